@@ -0,0 +1,196 @@
+//! Runs dummy hooks shaped by a policy file and prints how a shutdown goes.
+//!
+//! ```text
+//! cargo run --example shutdown-sim --features sim -- examples/shutdown-sim.policy
+//! ```
+//!
+//! Type a signal name (`INT`, `TERM`, `QUIT`, `PWR`) to send it to the
+//! process, or `rehearse [signal]` to run the hooks without shutting down.
+//! Signals sent with `kill` from another terminal work too.
+
+extern crate graceful;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use graceful::hooks::{self, Context};
+use graceful::{Signal, SignalGuard};
+
+#[macro_use]
+extern crate lazy_static;
+
+lazy_static! {
+    static ref STARTED: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let bad = || format!("bad duration: {}", value);
+    if let Some(millis) = value.strip_suffix("ms") {
+        millis.parse().map(Duration::from_millis).map_err(|_| bad())
+    } else if let Some(secs) = value.strip_suffix('s') {
+        secs.parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+            .ok_or_else(bad)
+    } else {
+        Err(bad())
+    }
+}
+
+fn parse_signal(name: &str) -> Result<Signal, String> {
+    match name.trim_start_matches("SIG").to_uppercase().as_str() {
+        "INT" => Ok(Signal::Interrupt),
+        "QUIT" => Ok(Signal::Quit),
+        "TERM" => Ok(Signal::Terminate),
+        "HUP" => Ok(Signal::Hangup),
+        "USR1" => Ok(Signal::User1),
+        "USR2" => Ok(Signal::User2),
+        "PWR" => Ok(Signal::Power),
+        _ => Err(format!("unknown signal: {}", name)),
+    }
+}
+
+/// Time since the shutdown or rehearsal started.
+fn elapsed() -> Duration {
+    let started = *STARTED.lock().unwrap();
+    started.map(|started| started.elapsed()).unwrap_or_default()
+}
+
+fn add_hook(phase: &str, name: &str, duration: Duration, priority: i32) {
+    let hook = name.to_owned();
+    hooks::phase(phase).hook_with_priority(name, priority, move |ctx: &Context| {
+        println!(
+            "{:>8.3?}  {}/{} started, {:?} left in the phase",
+            elapsed(),
+            ctx.phase(),
+            hook,
+            ctx.remaining()
+        );
+        thread::sleep(duration);
+        println!("{:>8.3?}  {}/{} done", elapsed(), ctx.phase(), hook);
+    });
+}
+
+fn load_policy(path: &str) -> Result<(), String> {
+    let policy = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let coordinator = hooks::coordinator();
+    for (number, line) in policy.lines().enumerate() {
+        let words: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(()),
+            ["grace-period", duration] => {
+                parse_duration(duration).map(|duration| coordinator.set_grace_period(duration))
+            }
+            ["signal-grace-period", signal, duration] => parse_signal(signal).and_then(|signal| {
+                parse_duration(duration)
+                    .map(|duration| coordinator.set_signal_grace_period(signal, duration))
+            }),
+            ["max-extension", duration] => {
+                parse_duration(duration).map(|duration| coordinator.set_max_extension(duration))
+            }
+            ["budget", phase, fraction] => match fraction.parse::<f64>() {
+                Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                    hooks::phase(phase).budget(fraction);
+                    Ok(())
+                }
+                _ => Err(format!("bad budget: {}", fraction)),
+            },
+            ["hook", phase, name, duration, rest @ ..] if rest.len() <= 1 => {
+                let priority = match rest.first() {
+                    Some(priority) => priority
+                        .parse()
+                        .map_err(|_| format!("bad priority: {}", priority))?,
+                    None => 0,
+                };
+                parse_duration(duration).map(|duration| add_hook(phase, name, duration, priority))
+            }
+            _ => Err(format!("cannot parse: {}", line.trim())),
+        };
+        result.map_err(|err| format!("{}:{}: {}", path, number + 1, err))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send(signal: Signal) {
+    if let Err(err) = graceful::process::raise(signal) {
+        println!("cannot send {}: {}", signal, err);
+    }
+}
+
+#[cfg(windows)]
+fn send(signal: Signal) {
+    println!(
+        "cannot send {} from here, press Ctrl+C or Ctrl+Break",
+        signal
+    );
+}
+
+/// Read commands from stdin until it is closed.
+fn prompt() {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.as_slice() {
+            [] => continue,
+            ["rehearse"] => Ok((true, Signal::Terminate)),
+            ["rehearse", signal] => parse_signal(signal).map(|signal| (true, signal)),
+            [signal] => parse_signal(signal).map(|signal| (false, signal)),
+            _ => Err(format!("cannot parse: {}", line.trim())),
+        };
+        match command {
+            Ok((true, signal)) => {
+                *STARTED.lock().unwrap() = Some(Instant::now());
+                println!("rehearsing {}", signal);
+                let report = hooks::coordinator().rehearse(signal);
+                println!("rehearsal: {}", report);
+            }
+            Ok((false, signal)) => {
+                *STARTED.lock().unwrap() = Some(Instant::now());
+                send(signal);
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+fn main() {
+    let signal_guard = SignalGuard::new();
+
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: shutdown-sim <policy>");
+            process::exit(2);
+        }
+    };
+    if let Err(err) = load_policy(&path) {
+        eprintln!("{}", err);
+        process::exit(2);
+    }
+
+    println!(
+        "pid {}, phases: {}",
+        process::id(),
+        hooks::coordinator().phases().join(", ")
+    );
+    println!("type INT, TERM, QUIT, PWR or rehearse [signal]");
+    thread::spawn(prompt);
+
+    let report = signal_guard.try_wait_report().unwrap();
+    println!("{}", report);
+    for phase in report.phases() {
+        println!("  {}: {:?}", phase.name(), phase.duration());
+    }
+    graceful::exit::exit_with(&report);
+}
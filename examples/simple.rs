@@ -1,33 +1,23 @@
 extern crate graceful;
 
-use std::sync::atomic::{AtomicBool, Ordering};
-
+use graceful::SignalGuard;
 use std::thread;
 use std::time::Duration;
-use graceful::SignalGuard;
-
-#[macro_use]
-extern crate lazy_static;
-
-lazy_static! {
-    static ref STOP: AtomicBool = AtomicBool::new(false);
-}
 
 fn main() {
     let signal_guard = SignalGuard::new();
+    let token = signal_guard.token();
 
-    let handle = thread::spawn(|| {
+    let handle = thread::spawn(move || {
         println!("Worker thread started. Type Ctrl+C to stop.");
-        while !STOP.load(Ordering::Acquire) {
+        while !token.wait_timeout(Duration::from_millis(500)) {
             println!("working...");
-            thread::sleep(Duration::from_millis(500));
         }
         println!("Bye.");
     });
 
     signal_guard.at_exit(move |sig| {
         println!("Signal {} received.", sig);
-        STOP.store(true, Ordering::Release);
         handle.join().unwrap();
     });
 }
@@ -0,0 +1,87 @@
+//! Attribute macros for [graceful](https://crates.io/crates/graceful).
+//!
+//! Use them through the re-exports in `graceful`, not from this crate.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{Error, Expr, Ident, ItemFn};
+
+/// Register a free function as a shutdown hook at link time.
+///
+/// See `graceful::hook`.
+#[proc_macro_attribute]
+pub fn hook(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut phase: Option<Expr> = None;
+    let mut priority: Option<Expr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("phase") {
+            phase = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("priority") {
+            priority = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `phase = ...` or `priority = ...`"))
+        }
+    });
+    syn::parse_macro_input!(args with parser);
+    let func = syn::parse_macro_input!(item as ItemFn);
+
+    match expand(phase, priority, func) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(
+    phase: Option<Expr>,
+    priority: Option<Expr>,
+    func: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &func.sig;
+    if sig.asyncness.is_some() {
+        return Err(Error::new_spanned(sig.asyncness, "hooks cannot be async"));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(&sig.generics, "hooks cannot be generic"));
+    }
+    let ident = &sig.ident;
+    let call = match sig.inputs.len() {
+        0 => quote!(#ident()),
+        1 => quote!(#ident(signal)),
+        _ => {
+            return Err(Error::new_spanned(
+                &sig.inputs,
+                "hooks take no arguments or a single `graceful::Signal`",
+            ))
+        }
+    };
+    let phase = phase.unwrap_or_else(|| syn::parse_quote!(::graceful::hooks::DEFAULT_PHASE));
+    let priority = priority.unwrap_or_else(|| syn::parse_quote!(0));
+    let wrapper = Ident::new(&format!("__graceful_hook_{}", ident), Span::call_site());
+
+    Ok(quote! {
+        #func
+
+        ::graceful::__private::inventory::submit! {
+            ::graceful::hooks::StaticHook::new(
+                concat!(module_path!(), "::", stringify!(#ident)),
+                #phase,
+                #priority,
+                {
+                    fn #wrapper(signal: ::graceful::Signal) {
+                        let _ = signal;
+                        #call;
+                    }
+                    #wrapper
+                },
+            )
+        }
+    })
+}
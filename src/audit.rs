@@ -0,0 +1,165 @@
+//! An append-only record of each shutdown.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::audit::AuditLog;
+//! use graceful::events;
+//!
+//! events::subscribe(AuditLog::open("/var/log/app/shutdown.log").unwrap());
+//! ```
+//!
+//! Every [event](../events/enum.Event.html) becomes one line, stamped with
+//! the seconds since the Unix epoch:
+//!
+//! ```text
+//! 1760431200.125 shutdown-started signal=SIGTERM pid=1 uid=0
+//! 1760431200.126 hook phase=drain hook=http duration=0.001s result=ok
+//! 1760431200.902 hook phase=flush hook=db duration=0.776s result="failed: disk full"
+//! 1760431200.903 shutdown-finished duration=0.778s failures=1 exit-code=1
+//! ```
+
+use std::fmt::Write as FmtWrite;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use events::{Event, Observer};
+use sync::lock;
+
+/// Writes every lifecycle event to a file, opened for appending only.
+///
+/// The file is synced to disk once the shutdown has finished. Write errors
+/// are ignored, there is nobody left to report them to.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    fn write(&self, line: &str) -> io::Result<()> {
+        lock(&self.file).write_all(line.as_bytes())
+    }
+}
+
+impl Observer for AuditLog {
+    fn on_event(&self, event: &Event) {
+        let _ = self.write(&format_event(event));
+        if let Event::ShutdownFinished(_) = *event {
+            let _ = lock(&self.file).sync_data();
+        }
+    }
+}
+
+fn format_event(event: &Event) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let stamp = format!("{}.{:03}", now.as_secs(), now.subsec_millis());
+    match *event {
+        Event::ShutdownStarted { signal, origin } => {
+            let mut line = format!(
+                "{} shutdown-started signal={}",
+                stamp,
+                quote(&signal.to_string())
+            );
+            if let Some(origin) = origin {
+                let _ = write!(line, " pid={} uid={}", origin.pid(), origin.uid());
+            }
+            line + "\n"
+        }
+        Event::PhaseFinished(phase) => phase
+            .hooks()
+            .map(|hook| {
+                let result = match hook.failure() {
+                    Some(failure) => failure.to_string(),
+                    None => "ok".to_owned(),
+                };
+                format!(
+                    "{} hook phase={} hook={} duration={} result={}\n",
+                    stamp,
+                    quote(phase.name()),
+                    quote(hook.name()),
+                    seconds(hook.duration()),
+                    quote(&result),
+                )
+            })
+            .collect(),
+        Event::ShutdownFinished(report) => format!(
+            "{} shutdown-finished duration={} failures={} exit-code={}\n",
+            stamp,
+            seconds(report.duration()),
+            report.failures().count(),
+            report.exit_code(),
+        ),
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_millis())
+}
+
+/// Quote `value` if it would not read back as a single token.
+fn quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        return value.to_owned();
+    }
+    format!("{:?}", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+    use hooks::{Context, Coordinator, CLOSE};
+    use signal::{Origin, Signal};
+
+    #[test]
+    fn writes_a_line_per_event() {
+        let path = env::temp_dir().join(format!("graceful-audit-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator
+            .phase(CLOSE)
+            .hook("db", |_: &Context| Err("disk full"));
+        let report = coordinator.rehearse(Signal::Terminate);
+
+        let log = AuditLog::open(&path).unwrap();
+        log.on_event(&Event::ShutdownStarted {
+            signal: Signal::Terminate,
+            origin: Some(Origin::new(1, 0)),
+        });
+        for phase in report.phases() {
+            log.on_event(&Event::PhaseFinished(phase));
+        }
+        log.on_event(&Event::ShutdownFinished(&report));
+        drop(log);
+        // Appended to, not truncated.
+        AuditLog::open(&path).unwrap().write("reopened\n").unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<Vec<&str>> = written
+            .lines()
+            .map(|line| line.splitn(2, ' ').collect())
+            .collect();
+        assert!(lines[..3].iter().all(|line| line[0].parse::<f64>().is_ok()));
+        assert_eq!(lines[0][1], "shutdown-started signal=SIGTERM pid=1 uid=0");
+        assert!(lines[1][1].starts_with("hook phase=close hook=db duration="));
+        assert!(lines[1][1].ends_with(r#" result="failed: disk full""#));
+        assert!(lines[2][1].starts_with("shutdown-finished duration="));
+        assert!(lines[2][1].ends_with(" failures=1 exit-code=1"));
+        assert_eq!(lines[3], ["reopened"]);
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -0,0 +1,302 @@
+//! A channel whose receiver can also wait for the shutdown.
+//!
+//! Threads that block on a `std::sync::mpsc::Receiver` cannot notice the
+//! shutdown until a message arrives. A [Receiver](struct.Receiver.html) from
+//! here can be waited on together with the shutdown with
+//! [ShutdownHandle::select](../struct.ShutdownHandle.html#method.select),
+//! with the same condition variable, so neither is polled:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::thread;
+//! use graceful::channel::{self, Selected};
+//! use graceful::{ShutdownHandle, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::new();
+//! let (sender, receiver) = channel::channel();
+//! let worker = thread::spawn(move || {
+//!     let shutdown = ShutdownHandle::new();
+//!     loop {
+//!         match shutdown.select(&receiver) {
+//!             Selected::Message(job) => println!("job {}", job),
+//!             Selected::Disconnected | Selected::Shutdown(_) => break,
+//!         }
+//!     }
+//! });
+//! sender.send(1).unwrap();
+//! signal_guard.at_exit(move |_| worker.join().unwrap());
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{RecvError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Wake, Waker};
+use std::time::Instant;
+
+use signal::Signal;
+use state::{self, SHARED};
+use sync::{lock, wait, wait_timeout};
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receiver: bool,
+}
+
+struct Inner<T> {
+    queue: Mutex<Queue<T>>,
+    cond: Condvar,
+}
+
+/// Wakes the receiver when the shutdown starts, without keeping the channel
+/// alive.
+struct Notify<T>(Weak<Inner<T>>);
+
+impl<T: Send + 'static> Wake for Notify<T> {
+    fn wake(self: Arc<Self>) {
+        if let Some(inner) = self.0.upgrade() {
+            // Taking the lock makes sure a receiver that just found no
+            // shutdown is already waiting.
+            let _queue = lock(&inner.queue);
+            inner.cond.notify_all();
+        }
+    }
+}
+
+/// The sending half of a [channel](fn.channel.html).
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The receiving half of a [channel](fn.channel.html).
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+    /// Registered with the shutdown until the receiver is dropped, unless
+    /// it started before the channel was made.
+    waker: Option<Waker>,
+}
+
+/// What [ShutdownHandle::select](../struct.ShutdownHandle.html#method.select)
+/// woke up for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Selected<T> {
+    Message(T),
+    /// Every sender is gone and no message is left.
+    Disconnected,
+    /// The shutdown has started, because of this signal.
+    Shutdown(Signal),
+}
+
+/// An unbounded channel.
+pub fn channel<T: Send + 'static>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        queue: Mutex::new(Queue {
+            items: VecDeque::new(),
+            senders: 1,
+            receiver: true,
+        }),
+        cond: Condvar::new(),
+    });
+    let mut history = lock(&SHARED.history);
+    let waker = if history.signals.is_empty() {
+        let waker = Waker::from(Arc::new(Notify(Arc::downgrade(&inner))));
+        history.wakers.push(waker.clone());
+        Some(waker)
+    } else {
+        None
+    };
+    drop(history);
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner, waker },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Send `value`, failing if the receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut queue = lock(&self.inner.queue);
+        if !queue.receiver {
+            return Err(SendError(value));
+        }
+        queue.items.push_back(value);
+        self.inner.cond.notify_all();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        lock(&self.inner.queue).senders += 1;
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut queue = lock(&self.inner.queue);
+        queue.senders -= 1;
+        if queue.senders == 0 {
+            self.inner.cond.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Sender { .. }")
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Block until a message arrives, ignoring the shutdown.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut queue = lock(&self.inner.queue);
+        loop {
+            if let Some(value) = queue.items.pop_front() {
+                return Ok(value);
+            }
+            if queue.senders == 0 {
+                return Err(RecvError);
+            }
+            queue = wait(&self.inner.cond, queue);
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut queue = lock(&self.inner.queue);
+        match queue.items.pop_front() {
+            Some(value) => Ok(value),
+            None if queue.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Wait for a message or the shutdown, whichever comes first, giving up
+    /// at `deadline` if there is one. The shutdown wins over messages
+    /// already queued, which can still be taken with `try_recv`.
+    pub(crate) fn select(&self, deadline: Option<Instant>) -> Option<Selected<T>> {
+        let mut queue = lock(&self.inner.queue);
+        loop {
+            if state::is_shutting_down() {
+                break;
+            }
+            if let Some(value) = queue.items.pop_front() {
+                return Some(Selected::Message(value));
+            }
+            if queue.senders == 0 {
+                return Some(Selected::Disconnected);
+            }
+            queue = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    wait_timeout(&self.inner.cond, queue, deadline - now)
+                }
+                None => wait(&self.inner.cond, queue),
+            };
+        }
+        // The shared history is locked before the queue when the shutdown
+        // is recorded, so let go of the queue first.
+        drop(queue);
+        Some(Selected::Shutdown(state::first_signal()))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        {
+            let mut queue = lock(&self.inner.queue);
+            queue.receiver = false;
+            queue.items.clear();
+        }
+        // Channels made and dropped before the shutdown leave nothing
+        // behind. The shutdown may have taken the waker already.
+        if let Some(ref waker) = self.waker {
+            lock(&SHARED.history)
+                .wakers
+                .retain(|registered| !registered.will_wake(waker));
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Receiver { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use handle::ShutdownHandle;
+
+    #[test]
+    fn selects_messages_in_order() {
+        let (sender, receiver) = channel();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        let shutdown = ShutdownHandle::new();
+        assert_eq!(shutdown.select(&receiver), Selected::Message(1));
+        assert_eq!(shutdown.select(&receiver), Selected::Message(2));
+    }
+
+    #[test]
+    fn selects_a_message_sent_while_waiting() {
+        let (sender, receiver) = channel();
+        let sending = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            sender.send("job").unwrap();
+        });
+        let shutdown = ShutdownHandle::new();
+        assert_eq!(
+            shutdown.select_timeout(&receiver, Duration::MAX),
+            Some(Selected::Message("job"))
+        );
+        sending.join().unwrap();
+        assert_eq!(shutdown.select(&receiver), Selected::Disconnected);
+    }
+
+    #[test]
+    fn gives_up_at_the_deadline() {
+        let (_sender, receiver) = channel::<()>();
+        let started = Instant::now();
+        assert_eq!(
+            receiver.select(Some(started + Duration::from_millis(20))),
+            None
+        );
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn fails_to_send_once_the_receiver_is_gone() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(1).unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn dropping_the_receiver_unregisters_its_waker() {
+        let (_sender, receiver) = channel::<()>();
+        let waker = receiver.waker.clone().expect("a waker");
+        let registered = |waker: &Waker| {
+            lock(&SHARED.history)
+                .wakers
+                .iter()
+                .any(|registered| registered.will_wake(waker))
+        };
+        assert!(registered(&waker));
+        drop(receiver);
+        assert!(!registered(&waker));
+    }
+}
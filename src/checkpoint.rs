@@ -0,0 +1,240 @@
+//! Saving the progress of long-running jobs at shutdown.
+//!
+//! A job registered here is written to its checkpoint file in the
+//! [FLUSH](../hooks/constant.FLUSH.html) phase, and can pick up where it
+//! left off on the next start with [restore](fn.restore.html). Jobs are
+//! checkpointed one at a time in the order they were registered; those not
+//! reached before the phase deadline are reported as timed out.
+//!
+//! The registry locks the job to checkpoint it, so a job should only hold
+//! the lock for one unit of work at a time.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::io::{Read, Write};
+//! use std::sync::{Arc, Mutex};
+//! use graceful::checkpoint::{self, Checkpointable};
+//! use graceful::BoxError;
+//!
+//! #[derive(Default)]
+//! struct Scan {
+//!     next: u64,
+//! }
+//!
+//! impl Checkpointable for Scan {
+//!     fn checkpoint(&mut self, out: &mut dyn Write) -> Result<(), BoxError> {
+//!         Ok(out.write_all(&self.next.to_le_bytes())?)
+//!     }
+//!
+//!     fn restore(&mut self, input: &mut dyn Read) -> Result<(), BoxError> {
+//!         let mut bytes = [0; 8];
+//!         input.read_exact(&mut bytes)?;
+//!         self.next = u64::from_le_bytes(bytes);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut scan = Scan::default();
+//! checkpoint::restore("scan.ckpt", &mut scan).unwrap();
+//! let scan = Arc::new(Mutex::new(scan));
+//! let registration = checkpoint::register("scan.ckpt", scan.clone());
+//! while !graceful::is_shutting_down() {
+//!     let mut scan = scan.lock().unwrap();
+//!     # if scan.next == 1000 { break; }
+//!     scan.next += 1;
+//! }
+//! # if !graceful::is_shutting_down() {
+//! // The scan is done, there is nothing to resume.
+//! registration.complete().unwrap();
+//! # }
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use error::{BoxError, Failure, FailureKind, ShutdownErrors};
+use hooks::{self, Context};
+use sync::lock;
+
+/// A computation whose progress can be saved and loaded again.
+pub trait Checkpointable: Send {
+    /// Write the progress made so far.
+    fn checkpoint(&mut self, out: &mut dyn Write) -> Result<(), BoxError>;
+
+    /// Continue from progress written by [checkpoint](#tymethod.checkpoint).
+    fn restore(&mut self, input: &mut dyn Read) -> Result<(), BoxError>;
+}
+
+trait Job: Send {
+    fn checkpoint(&self, out: &mut dyn Write) -> Result<(), BoxError>;
+}
+
+impl<T: Checkpointable> Job for Mutex<T> {
+    fn checkpoint(&self, out: &mut dyn Write) -> Result<(), BoxError> {
+        lock(self).checkpoint(out)
+    }
+}
+
+struct Entry {
+    id: usize,
+    path: PathBuf,
+    job: Arc<dyn Job + Sync>,
+}
+
+lazy_static! {
+    /// In registration order, which is the order they are checkpointed in.
+    static ref JOBS: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+static HOOK: Once = Once::new();
+
+/// Keeps a job registered until dropped.
+#[derive(Debug)]
+#[must_use = "the job is unregistered when this is dropped"]
+pub struct Registration {
+    id: usize,
+    path: PathBuf,
+}
+
+impl Registration {
+    /// Unregister a job that has finished, removing its checkpoint file so
+    /// the next start does not resume it.
+    pub fn complete(self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock(&JOBS).retain(|entry| entry.id != self.id);
+    }
+}
+
+/// Checkpoint `job` to `path` at shutdown.
+pub fn register<P, T>(path: P, job: Arc<Mutex<T>>) -> Registration
+where
+    P: AsRef<Path>,
+    T: Checkpointable + 'static,
+{
+    HOOK.call_once(|| {
+        hooks::phase(hooks::FLUSH).hook("graceful: checkpoints", checkpoint_all);
+    });
+    let path = path.as_ref().to_owned();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&JOBS).push(Entry {
+        id,
+        path: path.clone(),
+        job,
+    });
+    Registration { id, path }
+}
+
+/// Load the checkpoint at `path` into `job`, returning whether there was
+/// one.
+pub fn restore<P, T>(path: P, job: &mut T) -> Result<bool, BoxError>
+where
+    P: AsRef<Path>,
+    T: Checkpointable + ?Sized,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    job.restore(&mut BufReader::new(file))?;
+    Ok(true)
+}
+
+/// Write the file next to `path` and rename it into place once it is on
+/// disk, so a write cut short never replaces the previous file.
+pub(crate) fn write_atomically<F>(path: &Path, write: F) -> Result<(), BoxError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), BoxError>,
+{
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    let mut out = BufWriter::new(File::create(&partial)?);
+    write(&mut out)?;
+    out.into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn checkpoint_all(ctx: &Context) -> Result<(), ShutdownErrors> {
+    let jobs: Vec<(PathBuf, Arc<dyn Job + Sync>)> = lock(&JOBS)
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.job.clone()))
+        .collect();
+    let mut failures = Vec::new();
+    for (path, job) in jobs {
+        let name = path.display().to_string();
+        if ctx.remaining() == Some(Duration::from_secs(0)) {
+            failures.push(Failure::new(name, FailureKind::TimedOut));
+            continue;
+        }
+        if let Err(err) = write_atomically(&path, |out| job.checkpoint(out)) {
+            failures.push(Failure::new(name, FailureKind::Failed(err)));
+        }
+    }
+    ShutdownErrors::from_failures(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use hooks::{Coordinator, FLUSH};
+    use signal::Signal;
+
+    #[derive(Default)]
+    struct Scan {
+        next: u64,
+    }
+
+    impl Checkpointable for Scan {
+        fn checkpoint(&mut self, out: &mut dyn Write) -> Result<(), BoxError> {
+            Ok(out.write_all(&self.next.to_le_bytes())?)
+        }
+
+        fn restore(&mut self, input: &mut dyn Read) -> Result<(), BoxError> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            self.next = u64::from_le_bytes(bytes);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resumes_from_the_checkpoint_written_at_shutdown() {
+        let path = env::temp_dir().join(format!("graceful-scan-{}.ckpt", process::id()));
+        let mut scan = Scan::default();
+        assert!(!restore(&path, &mut scan).unwrap());
+        scan.next = 42;
+        let registration = register(&path, Arc::new(Mutex::new(scan)));
+
+        let coordinator = Coordinator::with_phases(&[FLUSH]);
+        coordinator.phase(FLUSH).hook("checkpoints", checkpoint_all);
+        assert!(coordinator.run(Signal::Terminate).is_clean());
+
+        let mut resumed = Scan::default();
+        assert!(restore(&path, &mut resumed).unwrap());
+        assert_eq!(resumed.next, 42);
+        registration.complete().unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,122 @@
+//! The time the shutdown deadlines are measured in.
+//!
+//! A [Coordinator](../hooks/struct.Coordinator.html) measures the grace
+//! period, the phase deadlines and the time its hooks take on a [Clock],
+//! the [SystemClock] unless
+//! [another is set](../hooks/struct.Coordinator.html#method.set_clock). A
+//! [ManualClock] lets tests check the timing of a shutdown without waiting
+//! for it:
+//!
+//! ```
+//! # extern crate graceful;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use graceful::clock::ManualClock;
+//! use graceful::hooks::{self, Coordinator};
+//! use graceful::{FailureKind, Signal};
+//!
+//! let coordinator = Coordinator::new();
+//! coordinator.set_clock(Arc::new(ManualClock::new()));
+//! coordinator.set_grace_period(Duration::from_secs(20));
+//! coordinator.phase(hooks::DRAIN).budget(0.5).hook("slow", |ctx| {
+//!     ctx.clock().sleep(Duration::from_secs(60));
+//! });
+//!
+//! let report = coordinator.rehearse(Signal::Terminate);
+//! let (_, hook) = report.failures().next().unwrap();
+//! assert!(matches!(hook.failure(), Some(FailureKind::TimedOut)));
+//! ```
+//!
+//! [Clock]: trait.Clock.html
+//! [SystemClock]: struct.SystemClock.html
+//! [ManualClock]: struct.ManualClock.html
+
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sync::lock;
+
+/// A source of time, and a way to let it pass.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Block until `duration` has passed on this clock.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The time of the system, `Instant::now`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+///
+/// [sleep](trait.Clock.html#tymethod.sleep) moves it forward and returns at
+/// once, so a hook that sleeps past its deadline is timed out without
+/// taking any time. A hook that blocks on anything else is only timed out
+/// once the clock is [advanced](#method.advance) past the deadline from
+/// another thread.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl ManualClock {
+    /// A clock starting at the current time of the system.
+    pub fn new() -> ManualClock {
+        ManualClock {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *lock(&self.now) += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *lock(&self.now)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_when_told() {
+        let clock = ManualClock::new();
+        let started = clock.now();
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(clock.now(), started);
+        clock.advance(Duration::from_secs(5));
+        clock.sleep(Duration::from_secs(60));
+        assert_eq!(clock.now() - started, Duration::from_secs(65));
+    }
+}
@@ -0,0 +1,92 @@
+//! Values dropped at shutdown, for cleanup done in `Drop`.
+
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+
+use error::{panic_message, BoxError};
+use hooks::{self, Context};
+use sync::lock;
+
+type Value = Box<dyn Any + Send>;
+
+lazy_static! {
+    static ref DEFERRED: Mutex<Vec<(usize, Value)>> = Mutex::new(Vec::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+static HOOK: Once = Once::new();
+
+/// A value handed to
+/// [SignalGuard::defer](struct.SignalGuard.html#method.defer), which can be
+/// taken back before the shutdown drops it.
+///
+/// Dropping this leaves the value to the shutdown.
+pub struct DeferGuard<T> {
+    id: usize,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> DeferGuard<T> {
+    /// Take the value back, unless it was dropped already.
+    pub fn take(self) -> Option<T> {
+        let mut deferred = lock(&DEFERRED);
+        let index = deferred.iter().position(|entry| entry.0 == self.id)?;
+        let (_, value) = deferred.remove(index);
+        value.downcast().ok().map(|value| *value)
+    }
+
+    /// Drop the value now, unless it was dropped already.
+    pub fn drop_now(self) {
+        drop(self.take());
+    }
+}
+
+impl<T> fmt::Debug for DeferGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeferGuard").field("id", &self.id).finish()
+    }
+}
+
+pub(crate) fn defer<T: Send + 'static>(value: T) -> DeferGuard<T> {
+    HOOK.call_once(|| {
+        hooks::phase(hooks::CLOSE).hook("graceful: deferred values", |ctx: &Context| {
+            if ctx.is_rehearsal() {
+                return Ok(());
+            }
+            match drop_all() {
+                Some(message) => Err(BoxError::from(format!("drop panicked: {}", message))),
+                None => Ok(()),
+            }
+        });
+    });
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&DEFERRED).push((id, Box::new(value)));
+    DeferGuard {
+        id,
+        _value: PhantomData,
+    }
+}
+
+/// Drop the deferred values, the last deferred first, including those
+/// deferred while dropping. Returns the message of the first drop that
+/// panicked; the others are dropped all the same.
+pub(crate) fn drop_all() -> Option<String> {
+    let mut first_panic = None;
+    loop {
+        let values = mem::take(&mut *lock(&DEFERRED));
+        if values.is_empty() {
+            return first_panic;
+        }
+        for (_, value) in values.into_iter().rev() {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| drop(value))) {
+                first_panic.get_or_insert_with(|| panic_message(&*payload));
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! GPU and other compute devices released at shutdown.
+//!
+//! A process killed while kernels are in flight can leave a device busy or
+//! its driver state inconsistent for the next start. Devices registered
+//! here are synchronized in the [DRAIN](../hooks/constant.DRAIN.html) phase,
+//! waiting for queued work to complete within a timeout, then released and
+//! dropped in the [CLOSE](../hooks/constant.CLOSE.html) phase.
+//!
+//! The crate does not depend on any GPU API; implement
+//! [Device](trait.Device.html) for a wrapper around a `wgpu::Device`, a CUDA
+//! context or similar:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::device::{self, Device};
+//! use graceful::BoxError;
+//!
+//! # struct Context;
+//! # impl Context { fn synchronize(&self) -> Result<(), BoxError> { Ok(()) } }
+//! struct Cuda(Context);
+//!
+//! impl Device for Cuda {
+//!     fn synchronize(&mut self) -> Result<(), BoxError> {
+//!         self.0.synchronize()
+//!     }
+//! }
+//!
+//! device::register("cuda:0", Duration::from_secs(5), Cuda(Context));
+//! ```
+
+use std::io;
+use std::sync::{Arc, Mutex, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::{panic_message, BoxError};
+use hooks::{self, Context};
+use sync::lock;
+use thread::{join_deadline, spawn_with};
+
+/// A device whose in-flight work can be waited for.
+pub trait Device: Send {
+    /// Block until all work submitted to the device has completed.
+    fn synchronize(&mut self) -> Result<(), BoxError>;
+
+    /// Release the device before it is dropped. The default does nothing,
+    /// for APIs that release on drop.
+    fn release(&mut self) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+impl<T: Device + ?Sized> Device for Box<T> {
+    fn synchronize(&mut self) -> Result<(), BoxError> {
+        (**self).synchronize()
+    }
+
+    fn release(&mut self) -> Result<(), BoxError> {
+        (**self).release()
+    }
+}
+
+type Slot = Arc<Mutex<Option<Box<dyn Device>>>>;
+
+/// Synchronize `device` at shutdown, waiting at most `timeout` and no
+/// longer than the phase allows, then release and drop it.
+///
+/// A device still synchronizing when the close phase starts is not
+/// released, as whatever it is stuck in holds it.
+pub fn register<D>(name: &str, timeout: Duration, device: D)
+where
+    D: Device + 'static,
+{
+    let slot: Slot = Arc::new(Mutex::new(Some(Box::new(device))));
+    let (drain, close) = (slot.clone(), slot);
+    let thread_name = format!("graceful: {} synchronize", name);
+    hooks::phase(hooks::DRAIN).hook(name, move |ctx: &Context| {
+        synchronize(&thread_name, &drain, timeout, ctx)
+    });
+    hooks::phase(hooks::CLOSE).hook_once(name, move |_: &Context| release(&close));
+}
+
+/// Synchronize on a thread that is abandoned at the timeout, since device
+/// APIs rarely offer one.
+fn synchronize(name: &str, slot: &Slot, timeout: Duration, ctx: &Context) -> Result<(), BoxError> {
+    let mut deadline = Instant::now().checked_add(timeout);
+    if let Some(phase_deadline) = ctx.deadline() {
+        deadline = Some(deadline.map_or(phase_deadline, |own| own.min(phase_deadline)));
+    }
+    let slot = slot.clone();
+    let builder = thread::Builder::new().name(name.to_owned());
+    let handle = spawn_with(builder, move || match *lock(&slot) {
+        Some(ref mut device) => device.synchronize(),
+        None => Ok(()),
+    })?;
+    let joined = match deadline {
+        Some(deadline) => join_deadline(handle, deadline),
+        None => Ok(handle.join()),
+    };
+    match joined {
+        Ok(Ok(result)) => result,
+        Ok(Err(payload)) => {
+            Err(format!("synchronize panicked: {}", panic_message(&*payload)).into())
+        }
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "device still busy").into()),
+    }
+}
+
+fn release(slot: &Slot) -> Result<(), BoxError> {
+    let device = match slot.try_lock() {
+        Ok(mut slot) => slot.take(),
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().take(),
+        Err(TryLockError::WouldBlock) => {
+            return Err("device still synchronizing, not released".into());
+        }
+    };
+    match device {
+        Some(mut device) => device.release(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hooks::{Coordinator, CLOSE, DRAIN};
+    use report::ShutdownReport;
+    use signal::Signal;
+
+    struct Gpu {
+        busy_for: Duration,
+        steps: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Device for Gpu {
+        fn synchronize(&mut self) -> Result<(), BoxError> {
+            thread::sleep(self.busy_for);
+            lock(&self.steps).push("synchronize");
+            Ok(())
+        }
+
+        fn release(&mut self) -> Result<(), BoxError> {
+            lock(&self.steps).push("release");
+            Ok(())
+        }
+    }
+
+    fn shut_down(busy_for: Duration) -> (ShutdownReport, Vec<&'static str>) {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let gpu = Gpu {
+            busy_for,
+            steps: steps.clone(),
+        };
+        let slot: Slot = Arc::new(Mutex::new(Some(Box::new(gpu))));
+        let (drain, close) = (slot.clone(), slot);
+        let coordinator = Coordinator::with_phases(&[DRAIN, CLOSE]);
+        coordinator.phase(DRAIN).hook("gpu", move |ctx: &Context| {
+            synchronize("gpu synchronize", &drain, Duration::from_millis(50), ctx)
+        });
+        coordinator
+            .phase(CLOSE)
+            .hook("gpu", move |_: &Context| release(&close));
+        let report = coordinator.run(Signal::Terminate);
+        let steps = lock(&steps).clone();
+        (report, steps)
+    }
+
+    #[test]
+    fn synchronizes_then_releases() {
+        let (report, steps) = shut_down(Duration::from_secs(0));
+        assert!(report.is_clean());
+        assert_eq!(steps, ["synchronize", "release"]);
+    }
+
+    #[test]
+    fn leaves_a_busy_device_alone() {
+        let (report, steps) = shut_down(Duration::from_secs(1));
+        let failures: Vec<String> = report
+            .failures()
+            .map(|(phase, hook)| format!("{}: {}", phase, hook.failure().unwrap()))
+            .collect();
+        assert_eq!(
+            failures,
+            [
+                "drain: failed: device still busy",
+                "close: failed: device still synchronizing, not released",
+            ]
+        );
+        assert!(steps.is_empty());
+    }
+}
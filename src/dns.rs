@@ -0,0 +1,112 @@
+//! Waiting out DNS caches before intake stops.
+//!
+//! A service found through DNS keeps receiving new connections after it
+//! leaves the record set, from clients and resolvers that cached the record
+//! for up to its TTL. Closing the listeners right away turns those into
+//! connection errors during every deploy. [delay_at_exit](fn.delay_at_exit.html)
+//! holds the shutdown in the [DNS_DELAY](constant.DNS_DELAY.html) phase for
+//! the TTL, capped so a long TTL cannot eat the whole grace period, before
+//! [stop-intake](../hooks/constant.STOP_INTAKE.html) closes the listeners.
+//!
+//! The record itself is best removed in the
+//! [RELINQUISH](../leadership/constant.RELINQUISH.html) phase, which runs
+//! just before:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//!
+//! # fn deregister() -> std::io::Result<()> { Ok(()) }
+//! graceful::leadership::relinquish_at_exit("consul", |_| deregister());
+//! graceful::dns::delay_at_exit(Duration::from_secs(30), Duration::from_secs(10));
+//! ```
+//!
+//! The delay is left out of
+//! [rehearsals](../hooks/struct.Coordinator.html#method.rehearse), and ends
+//! early when the phase runs out of time.
+
+use std::thread;
+use std::time::Duration;
+
+use hooks::{self, Context, Phase};
+use leadership;
+
+/// The phase the shutdown waits for DNS caches in.
+pub const DNS_DELAY: &str = "dns delay";
+
+/// The [DNS_DELAY](constant.DNS_DELAY.html) phase of the global
+/// [coordinator](../hooks/fn.coordinator.html), added after
+/// [RELINQUISH](../leadership/constant.RELINQUISH.html) and before
+/// [stop-intake](../hooks/constant.STOP_INTAKE.html) if needed.
+pub fn phase() -> Phase<'static> {
+    leadership::phase();
+    hooks::coordinator().phase_before(DNS_DELAY, hooks::STOP_INTAKE)
+}
+
+/// How long to wait for records with `ttl` to expire from caches, at most
+/// `cap`.
+pub fn drain_delay(ttl: Duration, cap: Duration) -> Duration {
+    ttl.min(cap)
+}
+
+/// Wait [drain_delay(ttl, cap)](fn.drain_delay.html) before the listeners
+/// are closed.
+pub fn delay_at_exit(ttl: Duration, cap: Duration) {
+    let delay = drain_delay(ttl, cap);
+    phase().hook_once("graceful: dns delay", move |ctx: &Context| {
+        wait_out(ctx, delay)
+    });
+}
+
+fn wait_out(ctx: &Context, delay: Duration) {
+    let delay = ctx
+        .remaining()
+        .map_or(delay, |remaining| remaining.min(delay));
+    thread::sleep(delay);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    use super::*;
+    use hooks::Coordinator;
+    use signal::Signal;
+
+    /// How long the delay hook of a coordinator with `grace_period` takes.
+    fn waited(delay: Duration, grace_period: Option<Duration>) -> Duration {
+        let (sender, waited) = mpsc::channel();
+        let coordinator = Coordinator::with_phases(&[DNS_DELAY]);
+        if let Some(grace_period) = grace_period {
+            coordinator.set_grace_period(grace_period);
+        }
+        coordinator
+            .phase(DNS_DELAY)
+            .hook_once("dns", move |ctx: &Context| {
+                let started = Instant::now();
+                wait_out(ctx, delay);
+                sender.send(started.elapsed()).unwrap();
+            });
+        coordinator.run(Signal::Terminate);
+        waited.recv().unwrap()
+    }
+
+    #[test]
+    fn caps_the_delay() {
+        let (ttl, cap) = (Duration::from_secs(300), Duration::from_secs(10));
+        assert_eq!(drain_delay(ttl, cap), cap);
+        assert_eq!(drain_delay(cap, ttl), cap);
+    }
+
+    #[test]
+    fn waits_out_the_delay() {
+        assert!(waited(Duration::from_millis(50), None) >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn ends_early_when_the_phase_runs_out_of_time() {
+        let delay = Duration::from_secs(3600);
+        assert!(waited(delay, Some(Duration::from_millis(50))) < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,74 @@
+//! Running the shutdown for a framework that handles signals itself.
+//!
+//! Nothing here blocks a signal or waits on the main thread: the framework
+//! keeps its own signal handling, or none at all, and calls
+//! [trigger](fn.trigger.html) from any thread once it decides to stop. From
+//! then on everything a [SignalGuard](../struct.SignalGuard.html) would
+//! drive works the same: [is_shutting_down](../fn.is_shutting_down.html)
+//! turns true, the [tokens](fn.token.html) move through the
+//! [phases](../enum.ShutdownPhase.html) of the shutdown, the registered
+//! [sockets](../net/index.html) and [threads](../park/index.html) are let
+//! go, the [observers](../events/index.html) are told, and the
+//! [hooks](../hooks/index.html) and [partitions](../hooks/fn.partition.html)
+//! run with their budgets.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # fn on_framework_signal<F: FnOnce(graceful::Signal) + Send + 'static>(_: F) {}
+//! use std::time::Duration;
+//!
+//! use graceful::{embedded, hooks};
+//!
+//! let framework = hooks::partition("my-framework");
+//! framework.set_grace_period(Duration::from_secs(10));
+//! framework.phase(hooks::DRAIN).hook("requests", |_| {});
+//!
+//! let token = embedded::token();
+//! on_framework_signal(|signal| {
+//!     if let Some(report) = embedded::trigger(signal) {
+//!         eprintln!("{}", report);
+//!     }
+//! });
+//! # let _ = token;
+//! ```
+//!
+//! Do not make a guard as well, or the shutdown runs twice.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use guard::{begin_shutdown, run_hooks};
+use report::ShutdownReport;
+use signal::{Origin, Signal};
+use state;
+use token::{self, ShutdownToken};
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// A token tripped when the shutdown starts, like
+/// [SignalGuard::token](../struct.SignalGuard.html#method.token).
+pub fn token() -> ShutdownToken {
+    token::guard_token()
+}
+
+/// Start the shutdown on `signal` and run the hooks, returning how they did.
+///
+/// Only the first call shuts down. Later ones, while the hooks run or
+/// after, are recorded in the [causes](../struct.ShutdownReport.html#method.causes)
+/// and move the shutdown to
+/// [Aborting](../enum.ShutdownPhase.html#variant.Aborting), as a second
+/// signal would, and return `None`.
+pub fn trigger(signal: Signal) -> Option<ShutdownReport> {
+    trigger_from(signal, None)
+}
+
+/// Like [trigger](fn.trigger.html), with the process that asked for the
+/// shutdown, if the framework knows it.
+pub fn trigger_from(signal: Signal, origin: Option<Origin>) -> Option<ShutdownReport> {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        state::record(signal, origin);
+        token::abort();
+        return None;
+    }
+    begin_shutdown(signal, origin);
+    Some(run_hooks(signal))
+}
@@ -0,0 +1,259 @@
+use std::any::Any;
+use std::error;
+use std::fmt;
+
+/// A boxed error returned by workers and hooks.
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+/// Return values accepted from workers and hooks.
+///
+/// Implemented for `()` and for `Result<(), E>` so infallible and fallible
+/// closures can be registered alike.
+pub trait IntoResult {
+    fn into_result(self) -> Result<(), BoxError>;
+}
+
+impl IntoResult for () {
+    fn into_result(self) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+impl<E: Into<BoxError>> IntoResult for Result<(), E> {
+    fn into_result(self) -> Result<(), BoxError> {
+        self.map_err(Into::into)
+    }
+}
+
+/// Why a worker or hook did not finish cleanly.
+#[derive(Debug)]
+pub enum FailureKind {
+    /// The worker panicked with the given message.
+    Panicked(String),
+    /// The worker returned an error.
+    Failed(BoxError),
+    /// The worker was still running when its time was up.
+    TimedOut,
+}
+
+/// A worker that did not finish cleanly.
+#[derive(Debug)]
+pub struct Failure {
+    name: String,
+    kind: FailureKind,
+}
+
+impl Failure {
+    pub(crate) fn new(name: String, kind: FailureKind) -> Failure {
+        Failure { name, kind }
+    }
+
+    /// The name the worker was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &FailureKind {
+        &self.kind
+    }
+
+    pub fn is_panic(&self) -> bool {
+        matches!(self.kind, FailureKind::Panicked(_))
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FailureKind::Panicked(ref msg) => write!(f, "panicked: {}", msg),
+            FailureKind::Failed(ref err) => write!(f, "failed: {}", err),
+            FailureKind::TimedOut => f.write_str("timed out"),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.kind)
+    }
+}
+
+/// Every failure collected while joining workers at shutdown.
+#[derive(Debug)]
+pub struct ShutdownErrors {
+    failures: Vec<Failure>,
+}
+
+impl ShutdownErrors {
+    pub(crate) fn from_failures(failures: Vec<Failure>) -> Result<(), ShutdownErrors> {
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ShutdownErrors { failures })
+        }
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, Failure> {
+        self.failures.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.failures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The exit code to report to a supervisor: `101` (the code of a
+    /// panicking Rust program) if any worker panicked, `1` otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.failures.iter().any(Failure::is_panic) {
+            101
+        } else {
+            1
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ShutdownErrors {
+    type Item = &'a Failure;
+    type IntoIter = ::std::slice::Iter<'a, Failure>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for ShutdownErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} worker(s) failed during shutdown",
+            self.failures.len()
+        )?;
+        for failure in &self.failures {
+            write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ShutdownErrors {}
+
+/// Render a panic payload as a message.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        (*msg).to_owned()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
+
+/// The category of an [Error](struct.Error.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Blocking the signals or installing the console handler failed.
+    Init,
+    /// Waiting for a signal failed.
+    Wait,
+}
+
+impl ErrorKind {
+    fn description(self) -> &'static str {
+        match self {
+            ErrorKind::Init => "failed to install signal handling",
+            ErrorKind::Wait => "failed to wait for a signal",
+        }
+    }
+}
+
+/// An operating system failure while handling signals.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    source: Option<BoxError>,
+}
+
+impl Error {
+    pub(crate) fn new<E: Into<BoxError>>(kind: ErrorKind, source: E) -> Error {
+        Error {
+            kind,
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error { kind, source: None }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.kind.description())?;
+        if let Some(ref source) = self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.source {
+            Some(ref source) => Some(&**source),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn errors_without_a_panic_exit_with_1() {
+        let failures = vec![
+            Failure::new("flush".to_owned(), FailureKind::Failed("disk full".into())),
+            Failure::new("upload".to_owned(), FailureKind::TimedOut),
+        ];
+        let errors = ShutdownErrors::from_failures(failures).unwrap_err();
+        assert_eq!(errors.exit_code(), 1);
+        assert_eq!(
+            errors.to_string(),
+            "2 worker(s) failed during shutdown\n  flush failed: disk full\n  upload timed out"
+        );
+        assert!(ShutdownErrors::from_failures(Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn panic_messages_are_rendered() {
+        assert_eq!(panic_message(&"static"), "static");
+        assert_eq!(panic_message(&"owned".to_owned()), "owned");
+        assert_eq!(panic_message(&7), "Box<dyn Any>");
+        let err: Result<(), io::Error> = Err(io::Error::other("disk full"));
+        assert_eq!(err.into_result().unwrap_err().to_string(), "disk full");
+    }
+
+    #[test]
+    fn errors_name_what_failed() {
+        let err = Error::new(ErrorKind::Wait, io::Error::other("bad mask"));
+        assert_eq!(err.kind(), ErrorKind::Wait);
+        assert_eq!(err.to_string(), "failed to wait for a signal: bad mask");
+        assert!(error::Error::source(&err).is_some());
+
+        let err = Error::from(ErrorKind::Init);
+        assert_eq!(err.to_string(), "failed to install signal handling");
+        assert!(error::Error::source(&err).is_none());
+    }
+}
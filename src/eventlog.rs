@@ -0,0 +1,123 @@
+//! Lifecycle entries for the Windows Event Log.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::eventlog::EventLog;
+//!
+//! let event_log = EventLog::register("MyService").unwrap();
+//! event_log.started();
+//! graceful::events::subscribe(event_log);
+//! ```
+
+extern crate winapi;
+
+use std::ffi::OsStr;
+use std::io;
+use std::iter;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+
+use self::winapi::shared::minwindef::WORD;
+use self::winapi::um::winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW};
+use self::winapi::um::winnt::{EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, HANDLE, LPCWSTR};
+
+use events::{Event, Observer};
+
+const EVENT_STARTED: u32 = 1;
+const EVENT_STOPPING: u32 = 2;
+const EVENT_STOPPED: u32 = 3;
+const EVENT_FAILED: u32 = 4;
+
+/// Writes an entry to the Application log when the program starts, when
+/// the shutdown begins and when it is complete.
+///
+/// | Event ID | Type        | Written                                        |
+/// |----------|-------------|------------------------------------------------|
+/// | 1        | Information | by [started](#method.started)                  |
+/// | 2        | Information | when the shutdown begins, with the event code  |
+/// | 3        | Information | when every hook completed, with the duration   |
+/// | 4        | Error       | when some hook failed, with the failures       |
+///
+/// No message file is registered for the source, so the Event Viewer shows
+/// the text of each entry as its inserted string.
+pub struct EventLog {
+    handle: HANDLE,
+}
+
+// The handle may be used from any thread.
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+impl EventLog {
+    /// Open the log under the event source `source`, usually the service
+    /// name.
+    pub fn register(source: &str) -> io::Result<EventLog> {
+        let source = wide(source);
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventLog { handle })
+    }
+
+    /// Record that the program has started.
+    pub fn started(&self) {
+        let _ = self.report(EVENTLOG_INFORMATION_TYPE, EVENT_STARTED, "started");
+    }
+
+    fn report(&self, kind: WORD, id: u32, message: &str) -> io::Result<()> {
+        let message = wide(message);
+        let mut strings: [LPCWSTR; 1] = [message.as_ptr()];
+        let ok = unsafe {
+            ReportEventW(
+                self.handle,
+                kind,
+                0,
+                id,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_mut_ptr(),
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Observer for EventLog {
+    fn on_event(&self, event: &Event) {
+        let _ = match *event {
+            Event::ShutdownStarted { signal, .. } => {
+                let code = signal
+                    .raw()
+                    .map_or_else(|| "none".to_owned(), |code| code.to_string());
+                let message = format!("stopping on {} (control code {})", signal, code);
+                self.report(EVENTLOG_INFORMATION_TYPE, EVENT_STOPPING, &message)
+            }
+            Event::ShutdownFinished(report) if report.is_clean() => {
+                let message = format!("stopped after {:?}", report.duration());
+                self.report(EVENTLOG_INFORMATION_TYPE, EVENT_STOPPED, &message)
+            }
+            Event::ShutdownFinished(report) => {
+                self.report(EVENTLOG_ERROR_TYPE, EVENT_FAILED, &report.to_string())
+            }
+            _ => Ok(()),
+        };
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        unsafe {
+            DeregisterEventSource(self.handle);
+        }
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(iter::once(0)).collect()
+}
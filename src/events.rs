@@ -0,0 +1,145 @@
+//! Observing the lifecycle of the shutdown.
+//!
+//! [Observers](trait.Observer.html) subscribed here are told about each
+//! transition as it happens: when termination starts, when each phase of
+//! [hooks](../hooks/index.html) has finished, and when the shutdown is
+//! done. They run on the thread driving the shutdown, so they should be
+//! quick; a panicking observer is skipped and the shutdown goes on.
+//!
+//! ```
+//! # extern crate graceful;
+//! use graceful::events::{self, Event};
+//!
+//! events::subscribe(|event: &Event| {
+//!     if let Event::ShutdownStarted { signal, .. } = *event {
+//!         eprintln!("stopping on {}", signal);
+//!     }
+//! });
+//! ```
+//!
+//! Every signal received by a guard, terminal or not, can also be followed
+//! as a [SignalEvent](struct.SignalEvent.html) from
+//! [SignalGuard::events](../struct.SignalGuard.html#method.events).
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use report::{PhaseReport, ShutdownReport};
+use signal::{Origin, Signal};
+use sync::lock;
+
+/// A transition of the shutdown.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// A terminal signal was received and the shutdown begins.
+    ShutdownStarted {
+        signal: Signal,
+        /// The process that sent the signal, if known.
+        origin: Option<Origin>,
+    },
+    /// Every hook of a phase has finished or timed out.
+    PhaseFinished(&'a PhaseReport),
+    /// Every phase has run; the handler is called next.
+    ShutdownFinished(&'a ShutdownReport),
+}
+
+/// A signal received by a guard, for finding out who stopped the program
+/// and when.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignalEvent {
+    signal: Signal,
+    received_at: SystemTime,
+    origin: Option<Origin>,
+}
+
+impl SignalEvent {
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// The process that sent the signal, where the platform tells (Linux).
+    pub fn origin(&self) -> Option<Origin> {
+        self.origin
+    }
+}
+
+/// Send `signal` to the `subscribers`, forgetting those whose receiver is
+/// gone.
+pub(crate) fn publish(
+    subscribers: &Mutex<Vec<Sender<SignalEvent>>>,
+    signal: Signal,
+    origin: Option<Origin>,
+) {
+    let event = SignalEvent {
+        signal,
+        received_at: SystemTime::now(),
+        origin,
+    };
+    lock(subscribers).retain(|subscriber| subscriber.send(event).is_ok());
+}
+
+/// Receives every [Event](enum.Event.html).
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> Observer for F {
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+lazy_static! {
+    static ref OBSERVERS: Mutex<Vec<Arc<dyn Observer>>> = Mutex::new(Vec::new());
+}
+
+/// Tell `observer` about every following event.
+pub fn subscribe<O: Observer + 'static>(observer: O) {
+    lock(&OBSERVERS).push(Arc::new(observer));
+}
+
+pub(crate) fn emit(event: &Event) {
+    // Not holding the lock, so observers may subscribe others.
+    let observers = lock(&OBSERVERS).clone();
+    for observer in observers {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| observer.on_event(event)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hooks::{Context, Coordinator};
+
+    #[test]
+    fn observers_see_the_phases_finish_despite_a_panicking_one() {
+        subscribe(|event: &Event| {
+            if let Event::PhaseFinished(phase) = *event {
+                if phase.name() == "observed" {
+                    panic!("observer failed");
+                }
+            }
+        });
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let observed = seen.clone();
+        subscribe(move |event: &Event| {
+            if let Event::PhaseFinished(phase) = *event {
+                if phase.name() == "observed" {
+                    let hooks = phase.hooks().map(|hook| hook.name().to_owned());
+                    lock(&observed).extend(hooks);
+                }
+            }
+        });
+        let coordinator = Coordinator::with_phases(&["observed"]);
+        coordinator.phase("observed").hook("hook", |_: &Context| {});
+        coordinator.run(Signal::Terminate);
+        assert_eq!(*lock(&seen), ["hook"]);
+    }
+}
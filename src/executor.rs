@@ -0,0 +1,108 @@
+//! Waiting on a future from a plain thread, such as a hook's.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Poll `future` on the current thread until it completes, or give up at
+/// `deadline`.
+///
+/// Whatever the future waits on has to be driven elsewhere, for example by
+/// the runtime of the application, this only polls.
+#[cfg_attr(not(any(feature = "quic", feature = "tokio")), allow(dead_code))]
+pub fn block_on<F: Future>(future: F, deadline: Option<Instant>) -> Option<F::Output> {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+            return Some(output);
+        }
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                thread::park_timeout(deadline - now);
+            }
+            None => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Pending until `ready` is set, waking the waiter from another thread.
+    struct Flag {
+        ready: Arc<AtomicBool>,
+        spawned: bool,
+    }
+
+    impl Future for Flag {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            if self.ready.load(Ordering::SeqCst) {
+                return Poll::Ready(7);
+            }
+            if !self.spawned {
+                self.spawned = true;
+                let (ready, waker) = (self.ready.clone(), cx.waker().clone());
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    ready.store(true, Ordering::SeqCst);
+                    waker.wake();
+                });
+            }
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn waits_until_the_future_is_woken() {
+        let flag = Flag {
+            ready: Arc::new(AtomicBool::new(false)),
+            spawned: false,
+        };
+        assert_eq!(block_on(flag, None), Some(7));
+    }
+
+    #[test]
+    fn gives_up_at_the_deadline() {
+        struct Never;
+
+        impl Future for Never {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+                Poll::Pending
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(30);
+        assert_eq!(block_on(Never, Some(deadline)), None);
+        assert!(Instant::now() >= deadline);
+    }
+}
@@ -0,0 +1,254 @@
+//! Conventional exit codes.
+//!
+//! Supervisors tell outcomes apart by the exit code, so a program should
+//! end with one that follows the usual conventions:
+//!
+//! * `0` after a clean shutdown,
+//! * `1`, or `101` as for a Rust panic, after a failed one, see
+//!   [ShutdownReport::exit_code](../struct.ShutdownReport.html#method.exit_code),
+//! * `128` plus the signal number to look as if killed by the signal, see
+//!   [signal_code](fn.signal_code.html),
+//! * a `sysexits.h` code when the program could not start, such as
+//!   [EX_CONFIG](constant.EX_CONFIG.html) for a bad configuration.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::{exit, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::new();
+//! let report = signal_guard.try_wait_report();
+//! exit::exit_with(&report);
+//! ```
+
+use std::io::{self, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+#[cfg(unix)]
+use std::ptr;
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use libc;
+
+use error::{Error, ShutdownErrors};
+use report::ShutdownReport;
+use signal::Signal;
+use sync::lock;
+
+type LastRite = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    static ref LAST_RITES: Mutex<Vec<LastRite>> = Mutex::new(Vec::new());
+}
+
+/// Successful termination.
+pub const EX_OK: i32 = 0;
+/// The command was used incorrectly.
+pub const EX_USAGE: i32 = 64;
+/// The input data was incorrect.
+pub const EX_DATAERR: i32 = 65;
+/// An input file did not exist or was not readable.
+pub const EX_NOINPUT: i32 = 66;
+/// A service is unavailable.
+pub const EX_UNAVAILABLE: i32 = 69;
+/// An internal software error.
+pub const EX_SOFTWARE: i32 = 70;
+/// An operating system error, such as failing to set up signal handling.
+pub const EX_OSERR: i32 = 71;
+/// An error while doing I/O.
+pub const EX_IOERR: i32 = 74;
+/// A temporary failure, the operation may succeed if retried.
+pub const EX_TEMPFAIL: i32 = 75;
+/// Insufficient permission.
+pub const EX_NOPERM: i32 = 77;
+/// Something was found in an unconfigured or misconfigured state.
+pub const EX_CONFIG: i32 = 78;
+
+/// `128` plus the signal number, the code a shell reports for a process
+/// killed by `signal`, or `None` if the signal has no number here.
+pub fn signal_code(signal: Signal) -> Option<i32> {
+    signal.raw().map(|raw| 128 + raw)
+}
+
+/// What [SignalGuard::at_exit](../struct.SignalGuard.html#method.at_exit)
+/// does once its handler has returned, see
+/// [SignalGuard::set_exit_policy](../struct.SignalGuard.html#method.set_exit_policy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitPolicy {
+    /// Return to the caller, which decides how to exit.
+    #[default]
+    Return,
+    /// End as if killed by the signal, so the supervisor or the parent
+    /// shell sees it, see [reraise](fn.reraise.html).
+    ReRaise,
+    /// Exit with this code, after flushing the standard streams.
+    ExitCode(i32),
+}
+
+/// Something that determines how the program should exit.
+pub trait ExitCode {
+    fn exit_code(&self) -> i32;
+}
+
+impl ExitCode for ShutdownReport {
+    fn exit_code(&self) -> i32 {
+        ShutdownReport::exit_code(self)
+    }
+}
+
+impl ExitCode for ShutdownErrors {
+    fn exit_code(&self) -> i32 {
+        ShutdownErrors::exit_code(self)
+    }
+}
+
+/// Errors of the guard itself mean signal handling could not be set up or
+/// used, so it is [EX_OSERR](constant.EX_OSERR.html).
+impl ExitCode for Error {
+    fn exit_code(&self) -> i32 {
+        EX_OSERR
+    }
+}
+
+impl<T: ExitCode, E: ExitCode> ExitCode for Result<T, E> {
+    fn exit_code(&self) -> i32 {
+        match *self {
+            Ok(ref ok) => ok.exit_code(),
+            Err(ref err) => err.exit_code(),
+        }
+    }
+}
+
+impl ExitCode for () {
+    fn exit_code(&self) -> i32 {
+        EX_OK
+    }
+}
+
+impl ExitCode for i32 {
+    fn exit_code(&self) -> i32 {
+        *self
+    }
+}
+
+/// Run `f` as the very last thing before the process exits, once the hooks
+/// and the handler are done, for what has to go last such as releasing a
+/// lock file or closing a log.
+///
+/// Unlike `libc::atexit`, the functions run on every way out of the guard:
+/// after the handler of [at_exit](../struct.SignalGuard.html#method.at_exit)
+/// and the like whatever the [exit policy](enum.ExitPolicy.html), at the
+/// end of [run](../fn.run.html), and in [exit_with](fn.exit_with.html) and
+/// [reraise](fn.reraise.html) before the process ends. They run once, in
+/// reverse order of registration, and a panic in one does not keep the
+/// others from running.
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use graceful::SignalGuard;
+///
+/// let signal_guard = SignalGuard::new();
+/// std::fs::write("/run/app.lock", b"").unwrap();
+/// graceful::atexit(|| {
+///     let _ = std::fs::remove_file("/run/app.lock");
+/// });
+/// signal_guard.at_exit(|_| {});
+/// ```
+///
+/// As a second signal may end the process with `reraise` while they run,
+/// they should be quick.
+pub fn atexit<F: FnOnce() + Send + 'static>(f: F) {
+    lock(&LAST_RITES).push(Box::new(f));
+}
+
+/// Run the functions added with `atexit` that have not run yet, including
+/// those they add themselves.
+pub(crate) fn run_last_rites() {
+    loop {
+        let rites = mem::take(&mut *lock(&LAST_RITES));
+        if rites.is_empty() {
+            return;
+        }
+        for rite in rites.into_iter().rev() {
+            let _ = panic::catch_unwind(AssertUnwindSafe(rite));
+        }
+    }
+}
+
+/// Flush the standard streams and exit with the code of `outcome`, after
+/// the functions added with [atexit](fn.atexit.html).
+pub fn exit_with<T: ExitCode + ?Sized>(outcome: &T) -> ! {
+    run_last_rites();
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    process::exit(outcome.exit_code())
+}
+
+/// End the process at once as if killed by `signal`, skipping the rest of
+/// the shutdown, for a cleanup that takes too long or a user who insists.
+///
+/// On Unix the signal is raised again with its default action, so the
+/// supervisor sees the process killed by it and `SIGQUIT` still dumps core.
+/// Where that does not end the process, as on Windows or for a signal
+/// ignored by default, it exits with [signal_code](fn.signal_code.html), or
+/// `1` if there is none. The functions added with [atexit](fn.atexit.html)
+/// that are not running already run first, then the standard streams are
+/// flushed.
+pub fn reraise(signal: Signal) -> ! {
+    run_last_rites();
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    #[cfg(unix)]
+    {
+        if let Some(signum) = signal.raw() {
+            unsafe {
+                libc::signal(signum, libc::SIG_DFL);
+                let mut set: libc::sigset_t = mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::sigaddset(&mut set, signum);
+                libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+                libc::raise(signum);
+            }
+        }
+    }
+    process::exit(signal_code(signal).unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorKind;
+
+    #[test]
+    #[cfg(unix)]
+    fn signal_codes_add_the_number_to_128() {
+        assert_eq!(signal_code(Signal::Interrupt), Some(130));
+        assert_eq!(signal_code(Signal::Terminate), Some(143));
+        assert_eq!(signal_code(Signal::Logoff), None);
+    }
+
+    #[test]
+    fn results_exit_with_the_code_of_either_side() {
+        let ok: Result<(), Error> = Ok(());
+        assert_eq!(ok.exit_code(), EX_OK);
+        let err: Result<(), Error> = Err(ErrorKind::Init.into());
+        assert_eq!(err.exit_code(), EX_OSERR);
+        let code: Result<i32, Error> = Ok(EX_CONFIG);
+        assert_eq!(code.exit_code(), 78);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exits_with_the_code_of_the_outcome() {
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            exit_with(&EX_TEMPFAIL);
+        }
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), EX_TEMPFAIL);
+    }
+}
@@ -0,0 +1,98 @@
+//! Leaving the shutdown report behind for tooling to collect.
+//!
+//! Once [enabled](fn.enable.html), every shutdown writes its
+//! [report](../struct.ShutdownReport.html#method.to_json) as JSON to a file
+//! once the hooks are done, just before the handler and the exit. The file
+//! is written next to its path and renamed over it, so a collector never
+//! reads half a report:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::SignalGuard;
+//!
+//! let signal_guard = SignalGuard::new();
+//! graceful::export::enable("/var/lib/app/shutdown.json");
+//! signal_guard.at_exit(|_| {});
+//! ```
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use checkpoint::write_atomically;
+use report::ShutdownReport;
+use sync::lock;
+
+lazy_static! {
+    static ref PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Write the report of the shutdown to `path`, replacing the report of the
+/// last run.
+pub fn enable<P: AsRef<Path>>(path: P) {
+    *lock(&PATH) = Some(path.as_ref().to_owned());
+}
+
+/// Stop writing the report.
+pub fn disable() {
+    *lock(&PATH) = None;
+}
+
+/// Write `report`, if enabled. A report that cannot be written is not a
+/// reason to stop the shutdown.
+pub(crate) fn write(report: &ShutdownReport) {
+    if let Some(ref path) = *lock(&PATH) {
+        let _ = write_atomically(path, |out| {
+            writeln!(out, "{}", report.to_json())?;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::time::Duration;
+
+    use super::*;
+    use error::FailureKind;
+    use report::{HookReport, PhaseReport};
+    use signal::Signal;
+
+    #[test]
+    fn writes_the_report_as_json() {
+        let upload = HookReport::new(
+            "upload".to_owned(),
+            Duration::from_millis(1500),
+            Some(FailureKind::Failed("said \"no\"".into())),
+            vec![("files".to_owned(), 3)],
+            2,
+        );
+        let phase = PhaseReport::new("flush".to_owned(), Duration::from_secs(2), vec![upload]);
+        let report = ShutdownReport::new(Signal::Terminate, vec![phase]);
+
+        let path = env::temp_dir().join(format!("graceful-report-{}.json", process::id()));
+        enable(&path);
+        write(&report);
+        let json = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        disable();
+        write(&report);
+        assert!(!path.exists());
+        assert_eq!(
+            json,
+            format!(
+                concat!(
+                    "{{\"signal\":\"{0}\",\"duration_ms\":2000,\"exit_code\":1,",
+                    "\"causes\":[{{\"signal\":\"{0}\",\"after_ms\":0}}],",
+                    "\"phases\":[{{\"name\":\"flush\",\"duration_ms\":2000,\"hooks\":[",
+                    "{{\"name\":\"upload\",\"duration_ms\":1500,\"attempts\":2,",
+                    "\"failure\":\"failed: said \\\"no\\\"\",\"counts\":{{\"files\":3}}}}]}}]}}\n"
+                ),
+                Signal::Terminate
+            )
+        );
+    }
+}
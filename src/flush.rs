@@ -0,0 +1,133 @@
+//! Flush profilers and buffered writers before the process exits.
+//!
+//! Tools like `tracing-flame`, coverage writers and custom profilers keep
+//! their data in memory and usually lose all of it when the process is
+//! terminated by a signal. [at_exit](fn.at_exit.html) registers a hook in the
+//! [FLUSH](../hooks/constant.FLUSH.html) phase that flushes the value and then
+//! drops it, so whatever it writes on drop is finalized too.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::fs::File;
+//! use std::io::BufWriter;
+//!
+//! let samples = BufWriter::new(File::create("samples.txt").unwrap());
+//! graceful::flush::at_exit("samples", samples);
+//! ```
+
+use std::fs::File;
+use std::io::{BufWriter, LineWriter, Stderr, Stdout, Write};
+
+#[cfg(feature = "tracing-flame")]
+use tracing_flame;
+
+use error::BoxError;
+use hooks;
+
+/// Something holding data that must be written out before exit.
+///
+/// Implement it for custom profilers, or wrap a closure in
+/// [FlushFn](struct.FlushFn.html).
+pub trait Flush {
+    fn flush(&mut self) -> Result<(), BoxError>;
+}
+
+impl<T: Flush + ?Sized> Flush for Box<T> {
+    fn flush(&mut self) -> Result<(), BoxError> {
+        (**self).flush()
+    }
+}
+
+macro_rules! impl_flush_for_writer {
+    ($($ty:ty),*) => {$(
+        impl Flush for $ty {
+            fn flush(&mut self) -> Result<(), BoxError> {
+                Write::flush(self).map_err(Into::into)
+            }
+        }
+    )*};
+}
+
+impl_flush_for_writer!(File, Stdout, Stderr);
+
+impl<W: Write> Flush for BufWriter<W> {
+    fn flush(&mut self) -> Result<(), BoxError> {
+        Write::flush(self).map_err(Into::into)
+    }
+}
+
+impl<W: Write> Flush for LineWriter<W> {
+    fn flush(&mut self) -> Result<(), BoxError> {
+        Write::flush(self).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "tracing-flame")]
+impl<W: Write + 'static> Flush for tracing_flame::FlushGuard<W> {
+    fn flush(&mut self) -> Result<(), BoxError> {
+        tracing_flame::FlushGuard::flush(self).map_err(Into::into)
+    }
+}
+
+/// A closure used as a [Flush](trait.Flush.html).
+pub struct FlushFn<F>(pub F);
+
+impl<F, E> Flush for FlushFn<F>
+where
+    F: FnMut() -> Result<(), E>,
+    E: Into<BoxError>,
+{
+    fn flush(&mut self) -> Result<(), BoxError> {
+        (self.0)().map_err(Into::into)
+    }
+}
+
+/// Flush and drop `value` in the [FLUSH](../hooks/constant.FLUSH.html)
+/// phase of the shutdown.
+pub fn at_exit<T>(name: &str, mut value: T)
+where
+    T: Flush + Send + 'static,
+{
+    hooks::phase(hooks::FLUSH).hook_once(name, move |_: &hooks::Context| value.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufWriter, Write};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flushes_buffered_writers() {
+        let written = Shared::default();
+        let mut buffered = BufWriter::new(written.clone());
+        buffered.write_all(b"samples").unwrap();
+        assert!(written.0.lock().unwrap().is_empty());
+        let mut flush: Box<dyn Flush> = Box::new(buffered);
+        flush.flush().unwrap();
+        assert_eq!(*written.0.lock().unwrap(), b"samples");
+    }
+
+    #[test]
+    fn closures_pass_on_their_errors() {
+        let mut flush = FlushFn(|| Err(io::Error::other("disk full")));
+        let err = flush.flush().unwrap_err();
+        assert_eq!(err.to_string(), "disk full");
+        let mut flush = FlushFn(|| Ok::<(), io::Error>(()));
+        assert!(flush.flush().is_ok());
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{self, Poll, Waker};
+use std::thread;
+
+use error::Error;
+use guard::SignalGuard;
+use signal::Signal;
+use sync::lock;
+
+#[derive(Default)]
+struct State {
+    done: Option<(Result<Signal, Error>, SignalGuard)>,
+    waker: Option<Waker>,
+}
+
+/// The shutdown of a [SignalGuard](struct.SignalGuard.html) running on its
+/// own thread, see [wait_async](struct.SignalGuard.html#method.wait_async).
+///
+/// Dropping it before it resolves leaves the thread waiting. Once it has
+/// resolved it keeps the guard, so on Windows the console handler is held
+/// back until it is dropped.
+pub struct ShutdownFuture {
+    state: Arc<Mutex<State>>,
+    guard: Option<SignalGuard>,
+}
+
+impl ShutdownFuture {
+    pub(crate) fn spawn(guard: SignalGuard) -> ShutdownFuture {
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        thread::Builder::new()
+            .name("graceful: signal wait".to_owned())
+            .spawn(move || {
+                let result = guard.try_wait();
+                let mut state = lock(&shared);
+                state.done = Some((result, guard));
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            })
+            .expect("failed to spawn thread");
+        ShutdownFuture { state, guard: None }
+    }
+
+    /// Like polling the future, but returns an error instead of panicking
+    /// if waiting for the signal failed.
+    pub fn poll_try(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<Signal, Error>> {
+        let mut state = lock(&self.state);
+        match state.done.take() {
+            Some((result, guard)) => {
+                self.guard = Some(guard);
+                Poll::Ready(result)
+            }
+            None if self.guard.is_some() => panic!("ShutdownFuture polled after completion"),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Future for ShutdownFuture {
+    type Output = Signal;
+
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal failed, as
+    /// [SignalGuard::wait](struct.SignalGuard.html#method.wait) does.
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Signal> {
+        self.get_mut().poll_try(cx).map(|result| match result {
+            Ok(signal) => signal,
+            Err(err) => panic!("graceful: {}", err),
+        })
+    }
+}
+
+impl fmt::Debug for ShutdownFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShutdownFuture")
+            .field("done", &self.guard.is_some())
+            .finish()
+    }
+}
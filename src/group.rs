@@ -0,0 +1,390 @@
+//! Taking the child processes down along with the program.
+
+#[cfg(windows)]
+extern crate winapi;
+
+use std::io;
+#[cfg(windows)]
+use std::mem;
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process::{Child, Command};
+#[cfg(windows)]
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(windows)]
+use self::winapi::shared::minwindef::{DWORD, FALSE, LPVOID};
+#[cfg(windows)]
+use self::winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+#[cfg(windows)]
+use self::winapi::um::handleapi::CloseHandle;
+#[cfg(windows)]
+use self::winapi::um::jobapi2::{
+    AssignProcessToJobObject, CreateJobObjectW, QueryInformationJobObject, SetInformationJobObject,
+    TerminateJobObject,
+};
+#[cfg(windows)]
+use self::winapi::um::processthreadsapi::{OpenProcess, TerminateProcess};
+#[cfg(windows)]
+use self::winapi::um::winbase::CREATE_NEW_PROCESS_GROUP;
+#[cfg(windows)]
+use self::winapi::um::wincon::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+#[cfg(windows)]
+use self::winapi::um::winnt::{
+    JobObjectBasicAccountingInformation, JobObjectExtendedLimitInformation, HANDLE,
+    JOBOBJECT_BASIC_ACCOUNTING_INFORMATION, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+    JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, PROCESS_TERMINATE,
+};
+#[cfg(unix)]
+use libc;
+
+use hooks::{self, Context};
+#[cfg(unix)]
+use process;
+use signal::Signal;
+use sync::lock;
+
+/// How often the children are checked while they are given time to exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Keeps track of the child processes of the program, so they can be
+/// stopped with it instead of being left behind.
+///
+/// On Unix the program becomes the leader of a process group of its own,
+/// which its children join unless they leave it, and
+/// [propagate](#method.propagate) signals the whole group. On Windows the
+/// children are put in a Job Object, which their own children join too,
+/// and which ends every process in it when the program exits, even if it
+/// crashes, or when the guard is dropped.
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use std::process::Command;
+/// use std::time::Duration;
+///
+/// use graceful::{ProcessGroupGuard, SignalGuard};
+///
+/// let signal_guard = SignalGuard::new();
+/// let children = ProcessGroupGuard::new().unwrap();
+/// children.spawn(Command::new("helper")).unwrap();
+///
+/// signal_guard.at_exit(move |_| {
+///     children.terminate_children(Duration::from_secs(5)).unwrap();
+/// });
+/// ```
+pub struct ProcessGroupGuard {
+    children: Mutex<Vec<u32>>,
+    #[cfg(windows)]
+    job: Job,
+}
+
+impl ProcessGroupGuard {
+    /// Make the program the leader of a process group of its own, unless
+    /// it already is, as when a shell with job control or a service manager
+    /// started it.
+    #[cfg(unix)]
+    pub fn new() -> io::Result<ProcessGroupGuard> {
+        unsafe {
+            if libc::getpgrp() != libc::getpid() && libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(ProcessGroupGuard {
+            children: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Create the Job Object for the children.
+    #[cfg(windows)]
+    pub fn new() -> io::Result<ProcessGroupGuard> {
+        Ok(ProcessGroupGuard {
+            children: Mutex::new(Vec::new()),
+            job: Job::new()?,
+        })
+    }
+
+    /// Start `command` as a child to stop with the program.
+    ///
+    /// On Unix the child starts with every signal unblocked, see
+    /// [process::unblock_signals](process/fn.unblock_signals.html). On
+    /// Windows it starts in a console process group of its own, so it can be
+    /// sent `Ctrl+Break` without the program receiving it too.
+    pub fn spawn(&self, mut command: Command) -> io::Result<Child> {
+        prepare(&mut command);
+        let child = command.spawn()?;
+        if let Err(err) = self.register(&child) {
+            let mut child = child;
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+        Ok(child)
+    }
+
+    /// Stop `child`, started some other way, with the program too.
+    pub fn register(&self, child: &Child) -> io::Result<()> {
+        #[cfg(windows)]
+        self.job.assign(child)?;
+        lock(&self.children).push(child.id());
+        Ok(())
+    }
+
+    /// Send `signal` to the children.
+    ///
+    /// On Unix it goes to the whole process group, including the children
+    /// of the children, but not to this process, see
+    /// [SignalGuard::signal_process_group](struct.SignalGuard.html#method.signal_process_group).
+    /// On Windows only `Ctrl+Break` can be sent to another process, so every
+    /// signal is sent as that, to the children started with
+    /// [spawn](#method.spawn).
+    pub fn propagate(&self, signal: Signal) -> io::Result<()> {
+        #[cfg(unix)]
+        return process::signal_process_group(signal);
+        #[cfg(windows)]
+        {
+            let _ = signal;
+            for &pid in lock(&self.children).iter() {
+                if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) } == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Send the children [Terminate](enum.Signal.html#variant.Terminate),
+    /// give them `grace` to exit, then kill those still running. Returns
+    /// how many had to be killed.
+    ///
+    /// On Windows a child that `Ctrl+Break` cannot be sent to, such as one
+    /// without a console, is killed at once.
+    ///
+    /// The children are not reaped, so their `Child` handles can still be
+    /// waited on.
+    pub fn terminate_children(&self, grace: Duration) -> io::Result<usize> {
+        let killed = self.ask_to_exit()?;
+        // A grace too long to add up is no limit at all.
+        let deadline = Instant::now().checked_add(grace);
+        while self.running()? > 0 {
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    return self.kill().map(|more| killed + more);
+                }
+                Some(deadline) => thread::sleep(POLL_INTERVAL.min(deadline - now)),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        Ok(killed)
+    }
+
+    /// [Terminate the children](#method.terminate_children) in the
+    /// [DRAIN](hooks/constant.DRAIN.html) phase of the shutdown, giving them
+    /// `grace` to exit or what is left of the phase, whichever is shorter.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::process::Command;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// use graceful::{ProcessGroupGuard, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// let children = Arc::new(ProcessGroupGuard::new().unwrap());
+    /// children.clone().terminate_at_shutdown(Duration::from_secs(5));
+    /// children.spawn(Command::new("helper")).unwrap();
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    pub fn terminate_at_shutdown(self: Arc<Self>, grace: Duration) {
+        hooks::phase(hooks::DRAIN).hook_once("graceful: child processes", move |ctx: &Context| {
+            let grace = ctx
+                .remaining()
+                .map_or(grace, |remaining| grace.min(remaining));
+            self.terminate_children(grace).map(|_| ())
+        });
+    }
+
+    #[cfg(unix)]
+    fn ask_to_exit(&self) -> io::Result<usize> {
+        self.propagate(Signal::Terminate).map(|()| 0)
+    }
+
+    /// Send `Ctrl+Break` to the children, killing those it cannot be sent
+    /// to. Returns how many were killed.
+    #[cfg(windows)]
+    fn ask_to_exit(&self) -> io::Result<usize> {
+        let mut killed = 0;
+        for &pid in lock(&self.children).iter() {
+            if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) } == 0 && terminate(pid)? {
+                killed += 1;
+            }
+        }
+        Ok(killed)
+    }
+
+    /// How many of the registered children have not exited.
+    #[cfg(unix)]
+    fn running(&self) -> io::Result<usize> {
+        let mut children = lock(&self.children);
+        children.retain(|&pid| is_running(pid));
+        Ok(children.len())
+    }
+
+    /// How many processes in the job have not exited.
+    #[cfg(windows)]
+    fn running(&self) -> io::Result<usize> {
+        self.job.active().map(|active| active as usize)
+    }
+
+    #[cfg(unix)]
+    fn kill(&self) -> io::Result<usize> {
+        let children = lock(&self.children);
+        for &pid in children.iter() {
+            if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+                let err = io::Error::last_os_error();
+                // It exited in the meantime.
+                if err.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(children.len())
+    }
+
+    /// End the job, or the children one by one if it cannot be ended.
+    #[cfg(windows)]
+    fn kill(&self) -> io::Result<usize> {
+        let active = self.job.active()?;
+        if unsafe { TerminateJobObject(self.job.0, 1) } != 0 {
+            return Ok(active as usize);
+        }
+        let mut killed = 0;
+        for &pid in lock(&self.children).iter() {
+            if terminate(pid)? {
+                killed += 1;
+            }
+        }
+        Ok(killed)
+    }
+}
+
+/// End the process `pid`, returning whether it was still there.
+#[cfg(windows)]
+fn terminate(pid: DWORD) -> io::Result<bool> {
+    let handle = unsafe { OpenProcess(PROCESS_TERMINATE, FALSE, pid) };
+    if handle.is_null() {
+        let err = io::Error::last_os_error();
+        // It exited and its last handle was closed.
+        if err.raw_os_error() == Some(ERROR_INVALID_PARAMETER as i32) {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+    let terminated = unsafe { TerminateProcess(handle, 1) };
+    let err = io::Error::last_os_error();
+    unsafe {
+        CloseHandle(handle);
+    }
+    if terminated == 0 {
+        return Err(err);
+    }
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn prepare(command: &mut Command) {
+    process::unblock_signals(command);
+}
+
+#[cfg(windows)]
+fn prepare(command: &mut Command) {
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Whether `pid` has not exited yet, leaving it to be reaped by its
+/// owner.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+    match unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, options) } {
+        // Nothing to report leaves the process ID zero.
+        0 => (unsafe { info.si_pid() }) == 0,
+        // Reaped already, or not a child.
+        _ => false,
+    }
+}
+
+/// A Job Object that ends its processes once its last handle is closed.
+#[cfg(windows)]
+struct Job(HANDLE);
+
+// The handle is only passed to thread-safe system calls.
+#[cfg(windows)]
+unsafe impl Send for Job {}
+#[cfg(windows)]
+unsafe impl Sync for Job {}
+
+#[cfg(windows)]
+impl Job {
+    fn new() -> io::Result<Job> {
+        let handle = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let job = Job(handle);
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { mem::zeroed() };
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let set = unsafe {
+            SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &mut limits as *mut _ as LPVOID,
+                mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as DWORD,
+            )
+        };
+        if set == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(job)
+    }
+
+    fn assign(&self, child: &Child) -> io::Result<()> {
+        if unsafe { AssignProcessToJobObject(self.0, child.as_raw_handle() as HANDLE) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn active(&self) -> io::Result<DWORD> {
+        let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { mem::zeroed() };
+        let queried = unsafe {
+            QueryInformationJobObject(
+                self.0,
+                JobObjectBasicAccountingInformation,
+                &mut info as *mut _ as LPVOID,
+                mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as DWORD,
+                ptr::null_mut(),
+            )
+        };
+        if queried == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(info.ActiveProcesses)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Job {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.0);
+        }
+    }
+}
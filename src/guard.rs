@@ -0,0 +1,1510 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(unix)]
+use libc;
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+
+use defer::{self, DeferGuard};
+use error::{Error, ErrorKind, IntoResult};
+use events::{self, Event, SignalEvent};
+use exit::{self, ExitPolicy};
+use export;
+#[cfg(feature = "async")]
+use future::ShutdownFuture;
+use hooks::{self, Context, Coordinator};
+use marker;
+use nested;
+use net;
+use panic_hook;
+use park;
+#[cfg(windows)]
+use platform;
+use platform::Guard;
+use priority;
+#[cfg(unix)]
+use process;
+use quiesce;
+#[cfg(unix)]
+use reaper::{self, ChildReaper};
+use report::{ShutdownOutcome, ShutdownReport};
+#[cfg(feature = "tokio")]
+use rt;
+#[cfg(windows)]
+use signal::ConsoleEvent;
+use signal::{Origin, Signal};
+use snapshot;
+use state;
+use sync::lock;
+#[cfg(all(unix, feature = "systemd"))]
+use systemd::{self, Watchdog};
+#[cfg(feature = "testing")]
+use testing;
+#[cfg(target_os = "linux")]
+use thread::{unmasked_threads, UnmaskedThread};
+use token::{self, ShutdownPhase, ShutdownToken};
+#[cfg(unix)]
+use wakeup;
+
+/// The signals handled by [SignalGuard::new](struct.SignalGuard.html#method.new).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const DEFAULT_SIGNALS: &[Signal] = &[
+    Signal::Interrupt,
+    Signal::Quit,
+    Signal::Terminate,
+    Signal::Power,
+];
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const DEFAULT_SIGNALS: &[Signal] = &[Signal::Interrupt, Signal::Quit, Signal::Terminate];
+
+/// Blocks the termination signals and runs a handler once one arrives.
+///
+/// On Windows, dropping the guard removes its console control handler, so
+/// guards can be created and dropped repeatedly in one process.
+pub struct SignalGuard {
+    guard: Guard,
+    signals: Vec<Signal>,
+    escalate: bool,
+    boost: bool,
+    observed: Vec<Signal>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<SignalEvent>>>>,
+    exit_policy: Mutex<ExitPolicy>,
+    handlers: Mutex<HashMap<Signal, Handler>>,
+    #[cfg(all(unix, feature = "systemd"))]
+    watchdog: Option<Watchdog>,
+}
+
+type Handler = Box<dyn FnMut(Signal) -> Flow + Send>;
+
+/// What to do after a handler added with
+/// [on_signal](struct.SignalGuard.html#method.on_signal) returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Go back to waiting, the signal did not end the process.
+    Continue,
+    /// Shut down as with any terminal signal.
+    Break,
+}
+
+/// Chooses the signals a [SignalGuard](struct.SignalGuard.html) handles.
+///
+/// It starts from the signals of [SignalGuard::new](struct.SignalGuard.html#method.new).
+/// Here `SIGHUP` is added and `SIGQUIT` left alone, so it still dumps core:
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use graceful::{Signal, SignalGuard};
+///
+/// let signal_guard = SignalGuard::builder()
+///     .signal(Signal::Hangup)
+///     .without(Signal::Quit)
+///     .build()
+///     .unwrap();
+/// assert!(!signal_guard.signals().contains(&Signal::Quit));
+/// ```
+///
+/// On Windows every console event reaches the handler whatever the set.
+#[derive(Clone, Debug)]
+pub struct SignalGuardBuilder {
+    signals: Vec<Signal>,
+    quiesce: Option<(Signal, Signal)>,
+    escalate: bool,
+    boost: bool,
+    observed: Vec<Signal>,
+    #[cfg(unix)]
+    reaper: Option<Arc<ChildReaper>>,
+    #[cfg(windows)]
+    shutdown_level: Option<u32>,
+}
+
+impl Default for SignalGuardBuilder {
+    fn default() -> SignalGuardBuilder {
+        SignalGuardBuilder {
+            signals: DEFAULT_SIGNALS.to_vec(),
+            quiesce: None,
+            escalate: false,
+            boost: false,
+            observed: Vec::new(),
+            #[cfg(unix)]
+            reaper: None,
+            #[cfg(windows)]
+            shutdown_level: None,
+        }
+    }
+}
+
+impl SignalGuardBuilder {
+    pub fn new() -> SignalGuardBuilder {
+        SignalGuardBuilder::default()
+    }
+
+    /// Also handle `signal`.
+    ///
+    /// It starts the shutdown like the others, unless it is taken by a
+    /// [NestedGuard](struct.NestedGuard.html) or by
+    /// [snapshot::on_signal](snapshot/fn.on_signal.html).
+    pub fn signal(mut self, signal: Signal) -> SignalGuardBuilder {
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+        }
+        self
+    }
+
+    /// Leave `signal` to its default action.
+    pub fn without(mut self, signal: Signal) -> SignalGuardBuilder {
+        self.signals.retain(|&other| other != signal);
+        self
+    }
+
+    /// Also handle `pause` and `resume`, which
+    /// [pause](quiesce/fn.pause.html) and [resume](quiesce/fn.resume.html)
+    /// the [workers](quiesce/index.html) instead of shutting down.
+    pub fn quiesce(self, pause: Signal, resume: Signal) -> SignalGuardBuilder {
+        let mut builder = self.signal(pause).signal(resume);
+        builder.quiesce = Some((pause, resume));
+        builder
+    }
+
+    /// Also handle `signal`, only sending it to the
+    /// [events](struct.SignalGuard.html#method.events) receivers and the
+    /// [on_signal](struct.SignalGuard.html#method.on_signal) handler,
+    /// without shutting down, for diagnostics such as dumping the state or
+    /// reopening log files on `SIGUSR1`.
+    pub fn observe(self, signal: Signal) -> SignalGuardBuilder {
+        let mut builder = self.signal(signal);
+        if !builder.observed.contains(&signal) {
+            builder.observed.push(signal);
+        }
+        builder
+    }
+
+    /// Also handle `SIGCHLD`, reaping the children that exit with `reaper`
+    /// instead of shutting down.
+    #[cfg(unix)]
+    pub fn reap_children(self, reaper: ChildReaper) -> SignalGuardBuilder {
+        let mut builder = self.signal(reaper::signal());
+        builder.reaper = Some(Arc::new(reaper));
+        builder
+    }
+
+    /// Whether a second signal during the shutdown ends the process at
+    /// once, as if killed by that signal, see
+    /// [exit::reraise](exit/fn.reraise.html). Off by default, where later
+    /// signals are only [recorded](struct.ShutdownHandle.html) and move the
+    /// shutdown to the [Aborting](enum.ShutdownPhase.html#variant.Aborting)
+    /// phase.
+    pub fn escalate_on_second_signal(mut self, escalate: bool) -> SignalGuardBuilder {
+        self.escalate = escalate;
+        self
+    }
+
+    /// Whether to raise the scheduling priority when the shutdown starts,
+    /// so the hooks finish within the grace period on a loaded host. Off
+    /// by default.
+    ///
+    /// On Unix the nice value is lowered by ten, which takes `CAP_SYS_NICE`
+    /// or, on Linux, a high enough `RLIMIT_NICE`; on Linux it is raised for
+    /// the thread running the shutdown and the hook threads, not for the
+    /// workers. On Windows the process is moved to `HIGH_PRIORITY_CLASS`.
+    /// The shutdown goes on as usual if the priority cannot be raised.
+    pub fn boost_priority(mut self, boost: bool) -> SignalGuardBuilder {
+        self.boost = boost;
+        self
+    }
+
+    /// Where the process comes in the order the system shuts processes
+    /// down in, from `0x100` to `0x3FF`, higher first; applications are at
+    /// `0x280` by default (Windows).
+    ///
+    /// The system gives every process a few seconds to exit after
+    /// `CTRL_SHUTDOWN_EVENT` before ending it, so a service that other
+    /// programs depend on stopping late can ask to be told earlier, see
+    /// `SetProcessShutdownParameters`.
+    #[cfg(windows)]
+    pub fn shutdown_level(mut self, level: u32) -> SignalGuardBuilder {
+        self.shutdown_level = Some(level);
+        self
+    }
+
+    /// Block the signals, see [SignalGuard::new](struct.SignalGuard.html#method.new).
+    ///
+    /// Fails with [ErrorKind::Init](enum.ErrorKind.html) if the set is
+    /// empty, if a signal has no number on this platform or cannot be
+    /// blocked (`SIGKILL`, `SIGSTOP`), if the quiesce signals are the
+    /// same, or if the shutdown level cannot be set (Windows).
+    pub fn build(self) -> Result<SignalGuard, Error> {
+        if self.signals.is_empty() {
+            return Err(Error::new(ErrorKind::Init, "no signals to handle"));
+        }
+        for &signal in &self.signals {
+            validate(signal)?;
+        }
+        if let Some((pause, resume)) = self.quiesce {
+            if pause == resume {
+                return Err(Error::new(
+                    ErrorKind::Init,
+                    format!("{} cannot both pause and resume", pause),
+                ));
+            }
+        }
+        #[cfg(windows)]
+        if let Some(level) = self.shutdown_level {
+            platform::set_shutdown_level(level).map_err(|err| Error::new(ErrorKind::Init, err))?;
+        }
+        let guard = Guard::new(&self.signals)?;
+        if let Some((pause, resume)) = self.quiesce {
+            quiesce::set_signals(pause, resume);
+        }
+        #[cfg(unix)]
+        if let Some(reaper) = self.reaper {
+            reaper::install(reaper);
+        }
+        Ok(SignalGuard {
+            guard,
+            signals: self.signals,
+            escalate: self.escalate,
+            boost: self.boost,
+            observed: self.observed,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            exit_policy: Mutex::new(ExitPolicy::Return),
+            handlers: Mutex::new(HashMap::new()),
+            #[cfg(all(unix, feature = "systemd"))]
+            watchdog: Watchdog::from_env(),
+        })
+    }
+}
+
+fn validate(signal: Signal) -> Result<(), Error> {
+    match signal.raw() {
+        None => Err(Error::new(
+            ErrorKind::Init,
+            format!("{:?} has no number on this platform", signal),
+        )),
+        #[cfg(unix)]
+        Some(libc::SIGKILL) | Some(libc::SIGSTOP) => Err(Error::new(
+            ErrorKind::Init,
+            format!("{} cannot be blocked", signal),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+impl Default for SignalGuard {
+    fn default() -> SignalGuard {
+        SignalGuard::new()
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        panic_hook::clear_waiter();
+        defer::drop_all();
+    }
+}
+
+impl SignalGuard {
+    /// Block necessary signals (`SIGINT`, `SIGQUIT` and `SIGTERM` on *nix,
+    /// and `SIGPWR` on Linux; `Ctrl+C` and `Ctrl+Break` on Windows).
+    ///
+    /// New threads should be spawned after this.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the signals cannot be blocked or the console handler
+    /// cannot be installed, see [try_new](#method.try_new).
+    pub fn new() -> SignalGuard {
+        match SignalGuard::try_new() {
+            Ok(guard) => guard,
+            Err(err) => panic!("graceful: {}", err),
+        }
+    }
+
+    /// Like [new](#method.new), but returns an error of kind
+    /// [Init](enum.ErrorKind.html#variant.Init) instead of panicking, for
+    /// programs that would rather carry on without graceful shutdown:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use graceful::SignalGuard;
+    ///
+    /// match SignalGuard::try_new() {
+    ///     Ok(signal_guard) => signal_guard.at_exit(|_| {}),
+    ///     Err(err) => eprintln!("{}", err),
+    /// }
+    /// ```
+    pub fn try_new() -> Result<SignalGuard, Error> {
+        SignalGuardBuilder::new().build()
+    }
+
+    /// Choose the signals to handle, see
+    /// [SignalGuardBuilder](struct.SignalGuardBuilder.html).
+    pub fn builder() -> SignalGuardBuilder {
+        SignalGuardBuilder::new()
+    }
+
+    /// Like [new](#method.new), also handling `signals`, see
+    /// [SignalGuardBuilder::signal](struct.SignalGuardBuilder.html#method.signal).
+    pub fn with_signals(signals: &[Signal]) -> Result<SignalGuard, Error> {
+        signals
+            .iter()
+            .fold(SignalGuard::builder(), |builder, &signal| {
+                builder.signal(signal)
+            })
+            .build()
+    }
+
+    /// Like [new](#method.new), also handling `pause` and `resume`, see
+    /// [SignalGuardBuilder::quiesce](struct.SignalGuardBuilder.html#method.quiesce).
+    pub fn with_quiesce(pause: Signal, resume: Signal) -> Result<SignalGuard, Error> {
+        SignalGuard::builder().quiesce(pause, resume).build()
+    }
+
+    /// Add a teardown step for a subsystem, such as a listener, a pool or a
+    /// metrics flusher, from anywhere before the wait.
+    ///
+    /// The steps run in the [CLOSE](hooks/constant.CLOSE.html) phase once a
+    /// signal arrives, those with a higher `priority` finishing before those
+    /// with a lower one start, and before the handler. A step that fails or
+    /// panics is [reported](struct.ShutdownReport.html) and does not keep the
+    /// others from running. See [hooks](hooks/index.html) for steps that
+    /// belong in an earlier phase.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # use graceful::SignalGuard;
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.register_hook(10, "http listener", |_| {});
+    /// signal_guard.register_hook(0, "database pool", |_| -> std::io::Result<()> { Ok(()) });
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    ///
+    /// The step may return `()` or any `Result<(), E>`.
+    pub fn register_hook<F, R>(&self, priority: i32, name: &str, hook: F)
+    where
+        F: FnOnce(Signal) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        hooks::phase(hooks::CLOSE)
+            .hook_once_with_priority(name, priority, move |ctx: &Context| hook(ctx.signal()));
+    }
+
+    /// Handle `signal` with `handler` on the waiting thread instead of
+    /// shutting down, for example to reload the configuration on `SIGHUP`.
+    /// Returning [Flow::Break](enum.Flow.html) shuts down after all.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn reload() -> std::io::Result<()> { Ok(()) }
+    /// use graceful::{Flow, Signal, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::builder()
+    ///     .signal(Signal::Hangup)
+    ///     .build()
+    ///     .unwrap();
+    /// signal_guard
+    ///     .on_signal(Signal::Hangup, |_| match reload() {
+    ///         Ok(()) => Flow::Continue,
+    ///         Err(_) => Flow::Break,
+    ///     })
+    ///     .unwrap();
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    ///
+    /// Replaces the handler added for `signal` before. The signal is ignored
+    /// once the shutdown has started. Fails with
+    /// [ErrorKind::Init](enum.ErrorKind.html) if the guard does not handle
+    /// `signal`, see [SignalGuardBuilder::signal](struct.SignalGuardBuilder.html#method.signal).
+    pub fn on_signal<F>(&self, signal: Signal, handler: F) -> Result<(), Error>
+    where
+        F: FnMut(Signal) -> Flow + Send + 'static,
+    {
+        if !self.signals.contains(&signal) {
+            return Err(Error::new(
+                ErrorKind::Init,
+                format!("{} is not handled by this guard", signal),
+            ));
+        }
+        lock(&self.handlers).insert(signal, Box::new(handler));
+        Ok(())
+    }
+
+    /// A token tripped as soon as the shutdown starts, before any hook or
+    /// the handler runs, for worker threads to stop on:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// let token = signal_guard.token();
+    /// let worker = thread::spawn(move || {
+    ///     while !token.wait_timeout(Duration::from_millis(500)) {
+    ///         println!("working...");
+    ///     }
+    /// });
+    /// signal_guard.at_exit(move |_| worker.join().unwrap());
+    /// ```
+    pub fn token(&self) -> ShutdownToken {
+        token::guard_token()
+    }
+
+    /// Like [token](#method.token), as a `tokio_util` token for async
+    /// servers, such as `with_graceful_shutdown` of axum and hyper:
+    ///
+    /// ```edition2018,no_run
+    /// # use graceful::SignalGuard;
+    /// # async fn serve(signal_guard: &SignalGuard) {
+    /// let token = signal_guard.cancellation_token();
+    /// // axum::serve(listener, app).with_graceful_shutdown(token.cancelled_owned())
+    /// token.cancelled().await;
+    /// # }
+    /// ```
+    ///
+    /// The token is a child of the one cancelled by the guard, so
+    /// cancelling it does not stop anything else. Something still has to
+    /// wait for the signal, such as [at_exit](#method.at_exit) or
+    /// [wait_async](#method.wait_async).
+    #[cfg(feature = "tokio")]
+    pub fn cancellation_token(&self) -> CancellationToken {
+        rt::token()
+    }
+
+    /// Like [cancellation_token](#method.cancellation_token), as a channel
+    /// that changes to the signal that started the shutdown.
+    #[cfg(feature = "tokio")]
+    pub fn signal_watch(&self) -> watch::Receiver<Option<Signal>> {
+        rt::subscribe()
+    }
+
+    /// The signals this guard blocks (Unix) or expects to be delivered
+    /// (Windows).
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    /// The phase of the shutdown, for example for the
+    /// [at_exit](#method.at_exit) handler to skip the slow cleanup once a
+    /// second signal has moved it to
+    /// [Aborting](enum.ShutdownPhase.html#variant.Aborting):
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn flush_caches() {}
+    /// use graceful::{ShutdownPhase, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.at_exit(|_| {
+    ///     if signal_guard.shutdown_phase() < ShutdownPhase::Aborting {
+    ///         flush_caches();
+    ///     }
+    /// });
+    /// ```
+    pub fn shutdown_phase(&self) -> ShutdownPhase {
+        token::shutdown_phase()
+    }
+
+    /// Tell systemd the program is set up, `READY=1`, see
+    /// [systemd](systemd/index.html). Does nothing if it was not started by
+    /// systemd.
+    ///
+    /// Requires the `systemd` feature.
+    #[cfg(all(unix, feature = "systemd"))]
+    pub fn notify_ready(&self) -> io::Result<()> {
+        systemd::notify_ready()
+    }
+
+    /// What [at_exit](#method.at_exit) and the like do once the handler has
+    /// returned. By default they return, and a program whose `main` then
+    /// returns exits with `0`; to have the parent shell or the supervisor
+    /// see it killed by the signal instead:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use graceful::exit::ExitPolicy;
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.set_exit_policy(ExitPolicy::ReRaise);
+    /// signal_guard.at_exit(|signal| println!("stopping on {}", signal));
+    /// ```
+    pub fn set_exit_policy(&self, policy: ExitPolicy) {
+        *lock(&self.exit_policy) = policy;
+    }
+
+    pub fn exit_policy(&self) -> ExitPolicy {
+        *lock(&self.exit_policy)
+    }
+
+    /// Block the running thread until a signal is received, then shut down
+    /// in the main thread:
+    ///
+    /// 1. [is_shutting_down](fn.is_shutting_down.html) turns true,
+    ///    [ShutdownHandle](struct.ShutdownHandle.html)s resolve, the
+    ///    [tokens](#method.token) are tripped and the
+    ///    [observers](events/index.html) are told,
+    /// 2. the registered [sockets](net/index.html) are shut down and the
+    ///    registered [parked threads](park/index.html) unparked,
+    /// 3. the registered [threads](wakeup/index.html) are woken up and the
+    ///    [shared flags](process/index.html) are set (Unix),
+    /// 4. the [snapshot](snapshot/index.html) is taken if it is to be, then
+    ///    the [hooks](hooks/index.html) run,
+    /// 5. `handler` is called, then the [exit policy](#method.set_exit_policy)
+    ///    applies.
+    ///
+    /// Do not put any code after this.
+    ///
+    /// Signals claimed by a [NestedGuard](struct.NestedGuard.html) are
+    /// handed to it and the wait goes on.
+    ///
+    /// The `handler` receives the [Signal](enum.Signal.html), the same on
+    /// every platform; its [raw](enum.Signal.html#method.raw) value is the
+    /// signal number (or console event code on Windows).
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use graceful::{Signal, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.at_exit(|signal| match signal {
+    ///     Signal::Interrupt => println!("interrupted"),
+    ///     other => println!("stopped by {}", other),
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal fails, see
+    /// [try_at_exit](#method.try_at_exit).
+    pub fn at_exit<F: FnOnce(Signal)>(&self, handler: F) {
+        let result = self.run(handler);
+        if let Err(err) = result {
+            panic!("graceful: {}", err);
+        }
+    }
+
+    /// Like [at_exit](#method.at_exit), but returns an error instead of
+    /// panicking if waiting for the signal fails. The `handler` is not
+    /// called in that case.
+    pub fn try_at_exit<F: FnOnce(Signal)>(&self, handler: F) -> Result<(), Error> {
+        self.run(handler)
+    }
+
+    /// Like [at_exit](#method.at_exit), but returns what `handler` returned
+    /// and how the shutdown went, for `main` to log why the process is
+    /// exiting and pick its exit code. The exit policy still applies, so
+    /// this only returns with [ExitPolicy::Return](exit/enum.ExitPolicy.html).
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use graceful::thread::Registry;
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// let workers = Registry::new();
+    /// // spawn workers...
+    /// let outcome = signal_guard.wait_and_shutdown(|_| workers.join_all());
+    /// eprintln!(
+    ///     "stopped by {} after {:?}",
+    ///     outcome.signal(),
+    ///     outcome.cleanup_duration()
+    /// );
+    /// let failed = outcome.escalated() || outcome.value().is_err();
+    /// std::process::exit(if failed { 1 } else { 0 });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal fails, see
+    /// [try_wait_and_shutdown](#method.try_wait_and_shutdown).
+    pub fn wait_and_shutdown<T, F: FnOnce(Signal) -> T>(&self, handler: F) -> ShutdownOutcome<T> {
+        match self.try_wait_and_shutdown(handler) {
+            Ok(outcome) => outcome,
+            Err(err) => panic!("graceful: {}", err),
+        }
+    }
+
+    /// Like [wait_and_shutdown](#method.wait_and_shutdown), but returns an
+    /// error instead of panicking if waiting for the signal fails. The
+    /// `handler` is not called in that case.
+    pub fn try_wait_and_shutdown<T, F: FnOnce(Signal) -> T>(
+        &self,
+        handler: F,
+    ) -> Result<ShutdownOutcome<T>, Error> {
+        let (signal, origin) = self.next_signal(None)?.expect("the wait has no deadline");
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+        let report = self.finish(signal, origin);
+        let mut value = None;
+        self.exit(signal, |signal| value = Some(handler(signal)));
+        let value = value.expect("the handler was called");
+        Ok(ShutdownOutcome::new(
+            value,
+            started_at,
+            started.elapsed(),
+            token::shutdown_phase() == ShutdownPhase::Aborting,
+            report,
+        ))
+    }
+
+    /// Like [try_at_exit](#method.try_at_exit), but if `handler` has not
+    /// returned `timeout` after it was called, the process ends as if
+    /// killed by the signal, see [exit::reraise](exit/fn.reraise.html).
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::time::Duration;
+    ///
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::builder()
+    ///     .escalate_on_second_signal(true)
+    ///     .build()
+    ///     .unwrap();
+    /// signal_guard.at_exit_with_timeout(Duration::from_secs(10), |signal| {
+    ///     println!("cleaning up after {}", signal);
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal fails, see
+    /// [try_at_exit_with_timeout](#method.try_at_exit_with_timeout).
+    pub fn at_exit_with_timeout<F: FnOnce(Signal)>(&self, timeout: Duration, handler: F) {
+        if let Err(err) = self.try_at_exit_with_timeout(timeout, handler) {
+            panic!("graceful: {}", err);
+        }
+    }
+
+    /// Like [at_exit_with_timeout](#method.at_exit_with_timeout), but
+    /// returns an error instead of panicking if waiting for the signal
+    /// fails. The `handler` is not called in that case.
+    pub fn try_at_exit_with_timeout<F: FnOnce(Signal)>(
+        &self,
+        timeout: Duration,
+        handler: F,
+    ) -> Result<(), Error> {
+        self.run(|signal| {
+            let (done, watchdog) = mpsc::channel::<()>();
+            let _ = thread::Builder::new()
+                .name("graceful: exit timeout".to_owned())
+                .spawn(move || {
+                    if let Err(RecvTimeoutError::Timeout) = watchdog.recv_timeout(timeout) {
+                        exit::reraise(signal);
+                    }
+                });
+            handler(signal);
+            drop(done);
+        })
+    }
+
+    /// Like [at_exit](#method.at_exit), also calling `tick` on the waiting
+    /// thread every `interval` until a signal arrives, for a heartbeat such
+    /// as flushing metrics or touching a watchdog file:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn touch_watchdog() {}
+    /// use std::time::Duration;
+    ///
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.at_exit_with_tick(Duration::from_secs(5), touch_watchdog, |signal| {
+    ///     println!("stopping on {}", signal);
+    /// });
+    /// ```
+    ///
+    /// Signals arriving while `tick` runs are taken once it returns. Ticks
+    /// missed because it took longer than `interval` are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero, or if waiting for the signal fails,
+    /// see [try_at_exit_with_tick](#method.try_at_exit_with_tick).
+    pub fn at_exit_with_tick<T, F>(&self, interval: Duration, tick: T, handler: F)
+    where
+        T: FnMut(),
+        F: FnOnce(Signal),
+    {
+        if let Err(err) = self.try_at_exit_with_tick(interval, tick, handler) {
+            panic!("graceful: {}", err);
+        }
+    }
+
+    /// Like [at_exit_with_tick](#method.at_exit_with_tick), but returns an
+    /// error instead of panicking if waiting for the signal fails. The
+    /// `handler` is not called in that case.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn try_at_exit_with_tick<T, F>(
+        &self,
+        interval: Duration,
+        mut tick: T,
+        handler: F,
+    ) -> Result<(), Error>
+    where
+        T: FnMut(),
+        F: FnOnce(Signal),
+    {
+        assert!(
+            interval > Duration::from_secs(0),
+            "tick interval must be positive"
+        );
+        // `None` once the next tick is too far off to represent, so the
+        // wait only ends at a signal.
+        let mut next = Instant::now().checked_add(interval);
+        let (signal, origin) = loop {
+            let due = match self.next_signal(next)? {
+                Some(received) => break received,
+                None => next.expect("only a deadline ends the wait without a signal"),
+            };
+            tick();
+            let now = Instant::now();
+            next = due
+                .checked_add(interval)
+                .filter(|&next| next > now)
+                .or_else(|| now.checked_add(interval));
+        };
+        self.finish(signal, origin);
+        self.exit(signal, handler);
+        Ok(())
+    }
+
+    /// Block the running thread until a signal is received and shut down as
+    /// [at_exit](#method.at_exit) does, then return the signal instead of
+    /// calling a handler, so the rest of `main` can carry on with ordinary
+    /// control flow:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # use graceful::SignalGuard;
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let signal_guard = SignalGuard::new();
+    ///     // spawn workers...
+    ///     let signal = signal_guard.try_wait()?;
+    ///     println!("stopped by {}", signal);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// On Windows the console handler is held back until the guard is
+    /// dropped, so keep the guard alive until cleanup is done.
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal fails, see
+    /// [try_wait](#method.try_wait).
+    pub fn wait(&self) -> Signal {
+        match self.try_wait() {
+            Ok(signal) => signal,
+            Err(err) => panic!("graceful: {}", err),
+        }
+    }
+
+    /// Like [wait](#method.wait), but returns an error instead of panicking
+    /// if waiting for the signal fails.
+    pub fn try_wait(&self) -> Result<Signal, Error> {
+        self.shut_down().map(|(signal, _)| signal)
+    }
+
+    /// Like [wait](#method.wait), but gives up after `timeout`, returning
+    /// `None` if no signal started the shutdown by then. Lets the main
+    /// thread do its own housekeeping between checks:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # use graceful::SignalGuard;
+    /// # fn rotate_logs() {}
+    /// use std::time::Duration;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// // spawn workers...
+    /// let signal = loop {
+    ///     match signal_guard.wait_timeout(Duration::from_secs(60)) {
+    ///         Some(signal) => break signal,
+    ///         None => rotate_logs(),
+    ///     }
+    /// };
+    /// println!("stopped by {}", signal);
+    /// ```
+    ///
+    /// Once a signal arrives the hooks run before this returns, as in
+    /// [wait](#method.wait). Signals taken by
+    /// [on_signal](#method.on_signal) handlers and the like do not end the
+    /// wait early.
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting for the signal fails, see
+    /// [try_wait_timeout](#method.try_wait_timeout).
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Signal> {
+        match self.try_wait_timeout(timeout) {
+            Ok(signal) => signal,
+            Err(err) => panic!("graceful: {}", err),
+        }
+    }
+
+    /// Like [wait_timeout](#method.wait_timeout), but returns an error
+    /// instead of panicking if waiting for the signal fails.
+    pub fn try_wait_timeout(&self, timeout: Duration) -> Result<Option<Signal>, Error> {
+        Ok(self
+            .next_signal(Instant::now().checked_add(timeout))?
+            .map(|(signal, origin)| {
+                self.finish(signal, origin);
+                signal
+            }))
+    }
+
+    /// Shut down if a terminal signal is pending, without blocking, as
+    /// [wait_timeout](#method.wait_timeout) does with no time to wait.
+    ///
+    /// # Panics
+    ///
+    /// Panics if checking for the signal fails.
+    pub fn poll(&self) -> Option<Signal> {
+        self.wait_timeout(Duration::from_secs(0))
+    }
+
+    /// If a terminal signal has arrived, shut down and end the process as
+    /// if killed by it, see [exit::reraise](exit/fn.reraise.html).
+    /// Otherwise return at once.
+    ///
+    /// Suits command line tools that do their work on the main thread: a
+    /// check between steps answers `Ctrl+C` promptly, still running the
+    /// hooks, and a shell running the tool in a loop sees it interrupted
+    /// and stops too.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn download() {}
+    /// # fn unpack() {}
+    /// # fn install() {}
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// download();
+    /// signal_guard.exit_if_signaled();
+    /// unpack();
+    /// signal_guard.exit_if_signaled();
+    /// install();
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if checking for the signal fails.
+    pub fn exit_if_signaled(&self) {
+        if let Some(signal) = self.poll() {
+            exit::reraise(signal);
+        }
+    }
+
+    /// Like [wait](#method.wait), but waits and shuts down on a thread of
+    /// its own, resolving once the hooks have run, so it can be awaited
+    /// inside an async runtime:
+    ///
+    /// ```edition2018,no_run
+    /// # use graceful::SignalGuard;
+    /// async fn serve() {
+    ///     let signal_guard = SignalGuard::new();
+    ///     // spawn tasks...
+    ///     let signal = signal_guard.wait_async().await;
+    ///     println!("stopped by {}", signal);
+    /// }
+    /// ```
+    ///
+    /// Tasks that only need to notice the shutdown can await a
+    /// [ShutdownHandle](struct.ShutdownHandle.html) instead.
+    #[cfg(feature = "async")]
+    pub fn wait_async(self) -> ShutdownFuture {
+        ShutdownFuture::spawn(self)
+    }
+
+    /// Like [try_wait](#method.try_wait), but returns how the hooks did,
+    /// for example to pick the [exit code](exit/index.html):
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # use graceful::{exit, SignalGuard};
+    /// let signal_guard = SignalGuard::new();
+    /// exit::exit_with(&signal_guard.try_wait_report());
+    /// ```
+    pub fn try_wait_report(&self) -> Result<ShutdownReport, Error> {
+        self.shut_down().map(|(_, report)| report)
+    }
+
+    /// Run the hooks as if `signal` had been received, without shutting
+    /// down, so the shutdown path can be tested in staging and timed. See
+    /// [Coordinator::rehearse](hooks/struct.Coordinator.html#method.rehearse)
+    /// for what is left out.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # use graceful::{SignalGuard, Signal};
+    /// let signal_guard = SignalGuard::new();
+    /// // register hooks...
+    /// let report = signal_guard.rehearse(Signal::Terminate);
+    /// println!("{}", report);
+    /// ```
+    pub fn rehearse(&self, signal: Signal) -> ShutdownReport {
+        hooks::run_all(signal, true)
+    }
+
+    /// Deliver `signal` to this guard as if the system had sent it, from
+    /// any thread, so the shutdown paths can be tested in ordinary `#[test]`
+    /// functions without sending real signals. Requires the `testing`
+    /// feature, which has the waiting guard check for such signals every
+    /// 10 ms.
+    ///
+    /// It goes through the same [handlers](#method.on_signal), claims and
+    /// [events](#method.events) as a signal from the system, without an
+    /// origin. During the shutdown it counts as a later signal.
+    ///
+    /// ```
+    /// # extern crate graceful;
+    /// # #[cfg(feature = "testing")]
+    /// # fn main() {
+    /// use std::sync::{Arc, Mutex};
+    /// use std::thread;
+    ///
+    /// use graceful::{hooks, Signal, SignalGuard};
+    ///
+    /// let signal_guard = Arc::new(SignalGuard::new());
+    /// let order = Arc::new(Mutex::new(Vec::new()));
+    /// for phase in &[hooks::DRAIN, hooks::CLOSE] {
+    ///     let order = order.clone();
+    ///     hooks::phase(phase).hook(phase, move |_| order.lock().unwrap().push(*phase));
+    /// }
+    ///
+    /// let trigger = signal_guard.clone();
+    /// thread::spawn(move || trigger.trigger(Signal::Terminate));
+    /// signal_guard.at_exit(|signal| assert_eq!(signal, Signal::Terminate));
+    /// assert_eq!(*order.lock().unwrap(), [hooks::DRAIN, hooks::CLOSE]);
+    /// # }
+    /// # #[cfg(not(feature = "testing"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "testing")]
+    pub fn trigger(&self, signal: Signal) {
+        if state::is_shutting_down() {
+            events::publish(&self.subscribers, signal, None);
+            record_later(signal, None, self.escalate, &self.later_ignored());
+        } else {
+            testing::inject(signal);
+        }
+    }
+
+    /// Shut down on the first panic of any thread, as if a signal had
+    /// arrived, with [Signal::Panic](enum.Signal.html#variant.Panic), so
+    /// the same hooks and handler run for both. The panic hook that was set
+    /// still runs first.
+    ///
+    /// Panics caught later count too, except during the shutdown, where a
+    /// panicking hook is only reported as failed.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::thread;
+    ///
+    /// use graceful::{Signal, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// signal_guard.shutdown_on_panic();
+    /// thread::spawn(|| panic!("worker failed"));
+    /// signal_guard.at_exit(|signal| {
+    ///     if signal == Signal::Panic {
+    ///         eprintln!("stopping after a panic");
+    ///     }
+    /// });
+    /// ```
+    pub fn shutdown_on_panic(&self) {
+        let wake = self.signals[0].raw().expect("the signals were validated");
+        panic_hook::install(wake);
+    }
+
+    /// Drop `value` at shutdown, in the [CLOSE](hooks/constant.CLOSE.html)
+    /// phase, or when the guard is dropped if no signal came, so cleanup
+    /// written as `Drop`, such as removing a temporary directory, a PID file
+    /// or a lock file, happens on either path. Values are dropped in the
+    /// reverse order they were deferred, and a drop that panics does not
+    /// keep the others from running.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::fs;
+    ///
+    /// use graceful::SignalGuard;
+    ///
+    /// struct PidFile(&'static str);
+    ///
+    /// impl Drop for PidFile {
+    ///     fn drop(&mut self) {
+    ///         let _ = fs::remove_file(self.0);
+    ///     }
+    /// }
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// fs::write("app.pid", std::process::id().to_string()).unwrap();
+    /// signal_guard.defer(PidFile("app.pid"));
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    pub fn defer<T: Send + 'static>(&self, value: T) -> DeferGuard<T> {
+        defer::defer(value)
+    }
+
+    /// The hook partition called `name`, for a framework to manage its
+    /// teardown apart from the application, see
+    /// [hooks::partition](hooks/fn.partition.html).
+    pub fn partition(&self, name: &str) -> &'static Coordinator {
+        hooks::partition(name)
+    }
+
+    /// Receive every signal this guard takes from now on, including those
+    /// it only [observes](struct.SignalGuardBuilder.html#method.observe)
+    /// and those during the shutdown, each with when it arrived and, on
+    /// Linux, who sent it:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::thread;
+    ///
+    /// use graceful::{Signal, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::builder()
+    ///     .observe(Signal::User1)
+    ///     .build()
+    ///     .unwrap();
+    /// let events = signal_guard.events();
+    /// thread::spawn(move || {
+    ///     for event in events {
+    ///         match event.origin() {
+    ///             Some(origin) => eprintln!("{} from {}", event.signal(), origin),
+    ///             None => eprintln!("{}", event.signal()),
+    ///         }
+    ///     }
+    /// });
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    pub fn events(&self) -> mpsc::Receiver<SignalEvent> {
+        let (sender, receiver) = mpsc::channel();
+        lock(&self.subscribers).push(sender);
+        receiver
+    }
+
+    /// Block the signals of this guard in the calling thread, for threads
+    /// started before the guard was made, such as the workers of a pool
+    /// created by another library. A signal of the set delivered to a
+    /// thread that does not block it kills the process, or runs another
+    /// handler, instead of reaching [at_exit](#method.at_exit).
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::sync::mpsc;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// use graceful::SignalGuard;
+    ///
+    /// let (sender, receiver) = mpsc::channel::<Arc<SignalGuard>>();
+    /// // Started before the guard, as a library might.
+    /// let worker = thread::spawn(move || {
+    ///     let signal_guard = receiver.recv().unwrap();
+    ///     signal_guard.apply_to_current_thread().unwrap();
+    ///     // ...
+    /// });
+    ///
+    /// let signal_guard = Arc::new(SignalGuard::new());
+    /// sender.send(signal_guard.clone()).unwrap();
+    /// signal_guard.at_exit(move |_| worker.join().unwrap());
+    /// ```
+    ///
+    /// Threads spawned with a
+    /// [MaskedThreadBuilder](thread/struct.MaskedThreadBuilder.html) start
+    /// with the set blocked. Does nothing on Windows, where console events
+    /// do not go to the threads of the program.
+    pub fn apply_to_current_thread(&self) -> io::Result<()> {
+        self.guard.block_current_thread()
+    }
+
+    /// The threads of the process that do not block every signal of this
+    /// guard, such as those started before it or by a C library (Linux).
+    ///
+    /// A signal of the set may be delivered to any of them instead of
+    /// reaching [at_exit](#method.at_exit), so the list should be empty.
+    /// Block the signals in them with
+    /// [apply_to_current_thread](#method.apply_to_current_thread).
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use graceful::SignalGuard;
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// for thread in signal_guard.unmasked_threads().unwrap() {
+    ///     eprintln!("warning: {}", thread);
+    /// }
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn unmasked_threads(&self) -> io::Result<Vec<UnmaskedThread>> {
+        unmasked_threads(self.guard.sigset())
+    }
+
+    /// The set of signals blocked by this guard, for composing with other
+    /// low-level code such as a custom `sigtimedwait` loop.
+    ///
+    /// Signals accepted elsewhere from this set never reach
+    /// [at_exit](#method.at_exit).
+    #[cfg(unix)]
+    pub fn raw_sigset(&self) -> &libc::sigset_t {
+        self.guard.sigset()
+    }
+
+    /// Send `signal` to the rest of the caller's process group, so pagers,
+    /// compressors and other helpers spawned by the program shut down too.
+    ///
+    /// This process does not receive the signal itself: it is discarded here,
+    /// or dropped by the guard listening during the shutdown, so it neither
+    /// starts another shutdown nor counts as a second signal. Call this from
+    /// the `handler`, a hook or a signal [handler](#method.on_signal), as
+    /// the guard waiting for signals on another thread would take it. Fails
+    /// with `InvalidInput` if `signal` has no number on this platform.
+    ///
+    /// Helpers inherit the blocked signals, start them with
+    /// [process::unblock_signals](process/fn.unblock_signals.html).
+    #[cfg(unix)]
+    pub fn signal_process_group(&self, signal: Signal) -> io::Result<()> {
+        process::signal_process_group(signal)
+    }
+
+    /// The console control handler installed by this guard, for example to
+    /// pass to `SetConsoleCtrlHandler` when composing with other handlers.
+    ///
+    /// The system stops calling later handlers once this one has taken an
+    /// event.
+    #[cfg(windows)]
+    pub fn raw_handler(&self) -> unsafe extern "system" fn(u32) -> i32 {
+        self.guard.handler()
+    }
+
+    /// The console event received by the handler that
+    /// [at_exit](#method.at_exit) has not picked up yet, if any.
+    #[cfg(windows)]
+    pub fn pending_event(&self) -> Option<ConsoleEvent> {
+        self.guard.pending().map(ConsoleEvent::from_raw)
+    }
+
+    fn run<F: FnOnce(Signal)>(&self, handler: F) -> Result<(), Error> {
+        let (signal, _) = self.shut_down()?;
+        self.exit(signal, handler);
+        Ok(())
+    }
+
+    /// Call `handler` after the shutdown on `signal`, then apply the exit
+    /// policy.
+    fn exit<F: FnOnce(Signal)>(&self, signal: Signal, handler: F) {
+        handler(signal);
+        exit::run_last_rites();
+        match self.exit_policy() {
+            ExitPolicy::Return => {}
+            ExitPolicy::ReRaise => exit::reraise(signal),
+            ExitPolicy::ExitCode(code) => exit::exit_with(&code),
+        }
+        self.release();
+    }
+
+    /// Stop taking console events after the handler (Windows).
+    pub(crate) fn release(&self) {
+        #[cfg(unix)]
+        panic_hook::clear_waiter();
+        self.guard.release();
+    }
+
+    /// Record the signals received during the rest of the shutdown for
+    /// [ShutdownHandle](struct.ShutdownHandle.html), or escalate on the
+    /// first one, leaving out those the process sent itself, such as with
+    /// [signal_process_group](#method.signal_process_group). Failing to
+    /// start that is not a reason to stop the shutdown.
+    fn keep_listening(&self) {
+        let listener = self.guard.listener();
+        let escalate = self.escalate;
+        let subscribers = self.subscribers.clone();
+        let ignored = self.later_ignored();
+        #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+        process::listen_for_self_sent(self.guard.sigset());
+        let _ = thread::Builder::new()
+            .name("graceful: signals".to_owned())
+            .spawn(move || {
+                while let Ok((raw, origin)) = listener.wait() {
+                    #[cfg(unix)]
+                    if process::is_self_sent(raw, origin) {
+                        continue;
+                    }
+                    let signal = Signal::from_raw(raw);
+                    events::publish(&subscribers, signal, origin);
+                    #[cfg(unix)]
+                    if reaper::claim(signal) {
+                        continue;
+                    }
+                    record_later(signal, origin, escalate, &ignored);
+                }
+            });
+    }
+
+    /// The signals that do not count once the shutdown has started: those
+    /// with a handler and those only observed.
+    fn later_ignored(&self) -> Vec<Signal> {
+        let mut ignored: Vec<Signal> = lock(&self.handlers).keys().cloned().collect();
+        ignored.extend_from_slice(&self.observed);
+        ignored
+    }
+
+    /// Accept a terminal signal if one is pending, without blocking or
+    /// shutting down, but marking the shutdown as started. Signals taken by
+    /// a handler or a claim are handed over as in the wait. Failures to
+    /// check count as nothing pending.
+    pub(crate) fn poll_interrupt(&self) -> Option<Signal> {
+        while let Ok(Some((raw, origin))) = self.guard.try_wait() {
+            let signal = Signal::from_raw(raw);
+            if self.handle(signal) || claim(signal) {
+                self.guard.resume();
+                continue;
+            }
+            state::record(signal, origin);
+            token::trip(signal);
+            return Some(signal);
+        }
+        None
+    }
+
+    /// Run the handler added for `signal`, returning whether it went on
+    /// waiting.
+    fn handle(&self, signal: Signal) -> bool {
+        match lock(&self.handlers).get_mut(&signal) {
+            Some(handler) => handler(signal) == Flow::Continue,
+            None => false,
+        }
+    }
+
+    /// Everything up to the handler: wait for the signal, then run the
+    /// hooks.
+    fn shut_down(&self) -> Result<(Signal, ShutdownReport), Error> {
+        let (signal, origin) = self.next_signal(None)?.expect("the wait has no deadline");
+        Ok((signal, self.finish(signal, origin)))
+    }
+
+    /// Wait for a signal that is not taken by a handler or a claim, until
+    /// `deadline` if there is one.
+    pub(crate) fn next_signal(
+        &self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<(Signal, Option<Origin>)>, Error> {
+        loop {
+            #[cfg(all(unix, feature = "systemd"))]
+            let wake = match self.watchdog {
+                Some(ref watchdog) => {
+                    Some(deadline.map_or(watchdog.due(), |deadline| deadline.min(watchdog.due())))
+                }
+                None => deadline,
+            };
+            #[cfg(not(all(unix, feature = "systemd")))]
+            let wake = deadline;
+            #[cfg(feature = "testing")]
+            let wake = {
+                if let Some(signal) = testing::take() {
+                    if self.accept(signal, None) {
+                        return Ok(Some((signal, None)));
+                    }
+                    continue;
+                }
+                Some(testing::wake_by(wake))
+            };
+            #[cfg(unix)]
+            panic_hook::set_waiter();
+            // A panic before the waiter was set does not wake it.
+            let received = match wake {
+                _ if panic_hook::is_pending() => Ok(None),
+                Some(wake) => {
+                    let timeout = wake.saturating_duration_since(Instant::now());
+                    self.guard.wait_timeout(timeout)
+                }
+                None => self.guard.wait().map(Some),
+            };
+            #[cfg(unix)]
+            panic_hook::clear_waiter();
+            if panic_hook::take() {
+                events::publish(&self.subscribers, Signal::Panic, None);
+                return Ok(Some((Signal::Panic, None)));
+            }
+            let (raw, origin) = match received? {
+                Some(received) => received,
+                None => {
+                    #[cfg(all(unix, feature = "systemd"))]
+                    if let Some(ref watchdog) = self.watchdog {
+                        watchdog.ping_if_due();
+                    }
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+            };
+            let signal = Signal::from_raw(raw);
+            if self.accept(signal, origin) {
+                return Ok(Some((signal, origin)));
+            }
+            self.guard.resume();
+        }
+    }
+
+    /// Tell the [events](#method.events) receivers about `signal` and hand
+    /// it to whatever takes it, returning whether it is left to start the
+    /// shutdown.
+    fn accept(&self, signal: Signal, origin: Option<Origin>) -> bool {
+        events::publish(&self.subscribers, signal, origin);
+        !self.handle(signal) && !claim(signal) && !self.observed.contains(&signal)
+    }
+
+    /// Shut down on `signal`, running the hooks.
+    pub(crate) fn finish(&self, signal: Signal, origin: Option<Origin>) -> ShutdownReport {
+        if self.boost {
+            let _ = priority::boost();
+        }
+        begin_shutdown(signal, origin);
+        self.keep_listening();
+        run_hooks(signal)
+    }
+}
+
+/// Run the snapshot and the hooks of the shutdown started by `signal`, then
+/// report how they did.
+pub(crate) fn run_hooks(signal: Signal) -> ShutdownReport {
+    let snapshot = snapshot::run_at_shutdown(signal);
+    let mut report = hooks::run_all(signal, false);
+    if let Some(snapshot) = snapshot {
+        report.prepend(snapshot);
+    }
+    report.set_causes(state::causes());
+    marker::finish(&report);
+    export::write(&report);
+    events::emit(&Event::ShutdownFinished(&report));
+    report
+}
+
+/// Record `signal`, received after the shutdown started, moving it to
+/// aborting, or escalate on it, unless it is `ignored` or taken by the
+/// quiescing or the snapshot.
+fn record_later(signal: Signal, origin: Option<Origin>, escalate: bool, ignored: &[Signal]) {
+    if quiesce::is_signal(signal) || snapshot::is_signal(signal) || ignored.contains(&signal) {
+        return;
+    }
+    state::record(signal, origin);
+    token::abort();
+    if escalate {
+        exit::reraise(signal);
+    }
+}
+
+/// Hand `signal` to whatever takes it instead of the shutdown, returning
+/// whether anything did.
+fn claim(signal: Signal) -> bool {
+    #[cfg(unix)]
+    if reaper::claim(signal) {
+        return true;
+    }
+    nested::claim(signal) || quiesce::claim(signal) || snapshot::claim(signal)
+}
+
+/// Tell everything waiting on the termination that it has started, before
+/// the hooks run.
+pub(crate) fn begin_shutdown(signal: Signal, origin: Option<Origin>) {
+    state::record(signal, origin);
+    token::trip(signal);
+    marker::write(signal);
+    #[cfg(all(unix, feature = "systemd"))]
+    let _ = systemd::notify_stopping();
+    events::emit(&Event::ShutdownStarted { signal, origin });
+    net::shutdown_all();
+    park::unpark_all();
+    #[cfg(unix)]
+    {
+        wakeup::wake_all();
+        process::set_flags();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(builder: SignalGuardBuilder) -> String {
+        match builder.build() {
+            Ok(_) => panic!("the signals were accepted"),
+            Err(err) => {
+                assert_eq!(err.kind(), ErrorKind::Init);
+                err.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn builders_add_and_remove_signals() {
+        let builder = SignalGuard::builder()
+            .signal(Signal::Hangup)
+            .signal(Signal::Hangup)
+            .without(Signal::Quit)
+            .quiesce(Signal::User1, Signal::User2);
+        let mut expected: Vec<Signal> = DEFAULT_SIGNALS
+            .iter()
+            .cloned()
+            .filter(|&signal| signal != Signal::Quit)
+            .collect();
+        expected.extend_from_slice(&[Signal::Hangup, Signal::User1, Signal::User2]);
+        assert_eq!(builder.signals, expected);
+    }
+
+    #[test]
+    fn builders_reject_signals_that_cannot_be_handled() {
+        let none = DEFAULT_SIGNALS
+            .iter()
+            .fold(SignalGuard::builder(), |builder, &signal| {
+                builder.without(signal)
+            });
+        assert_eq!(
+            rejection(none),
+            "failed to install signal handling: no signals to handle"
+        );
+        assert_eq!(
+            rejection(SignalGuard::builder().signal(Signal::Panic)),
+            "failed to install signal handling: Panic has no number on this platform"
+        );
+        assert!(
+            rejection(SignalGuard::builder().quiesce(Signal::User1, Signal::User1))
+                .ends_with("cannot both pause and resume")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn builders_reject_signals_that_cannot_be_blocked() {
+        let kill = SignalGuard::builder().signal(Signal::Other(libc::SIGKILL));
+        assert!(rejection(kill).ends_with("cannot be blocked"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn builders_reject_a_shutdown_level_out_of_range() {
+        // Levels below 0x100 are the system's.
+        rejection(SignalGuard::builder().shutdown_level(0x50));
+    }
+}
@@ -0,0 +1,253 @@
+use std::future::Future;
+use std::os::raw::c_void;
+use std::pin::Pin;
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+use tokio::task::JoinHandle;
+
+use channel::{Receiver, Selected};
+#[cfg(feature = "tokio")]
+use rt;
+use signal::Signal;
+use state::{self, SHARED};
+use sync::{lock, wait};
+use timer::{Interval, Sleep};
+
+/// Observes the shutdown from anywhere in the program.
+///
+/// It is a future resolving to the signal that started the shutdown, and
+/// iterates over that signal and every one received after it, such as a
+/// second `Ctrl+C` from an impatient user:
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use std::thread;
+///
+/// use graceful::{ShutdownHandle, SignalGuard};
+///
+/// let signal_guard = SignalGuard::new();
+/// thread::spawn(|| {
+///     for signal in ShutdownHandle::new().into_iter().skip(1) {
+///         eprintln!("still shutting down, {} ignored", signal);
+///     }
+/// });
+/// signal_guard.at_exit(|_| {});
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle(());
+
+impl ShutdownHandle {
+    pub fn new() -> ShutdownHandle {
+        ShutdownHandle::default()
+    }
+
+    /// Whether the shutdown has started, see
+    /// [is_shutting_down](fn.is_shutting_down.html).
+    pub fn is_shutting_down(&self) -> bool {
+        state::is_shutting_down()
+    }
+
+    /// A C function returning [is_shutting_down](fn.is_shutting_down.html),
+    /// for callbacks and threads driven by foreign code, such as an audio
+    /// or render loop, to check each frame. It is a single atomic load and
+    /// never blocks or allocates.
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # extern "C" fn set_stop_callback(_: extern "C" fn() -> bool) {}
+    /// use graceful::ShutdownHandle;
+    ///
+    /// set_stop_callback(ShutdownHandle::new().as_extern_poll());
+    /// ```
+    pub fn as_extern_poll(&self) -> extern "C" fn() -> bool {
+        extern_poll
+    }
+
+    /// Like [as_extern_poll](#method.as_extern_poll), for C APIs whose
+    /// callbacks take a context pointer. The pointer is ignored, so any may
+    /// be passed, including null.
+    pub fn as_extern_poll_with_context(&self) -> extern "C" fn(*mut c_void) -> bool {
+        extern_poll_with_context
+    }
+
+    /// Spawn `future` on the current tokio runtime, and have the
+    /// [DRAIN](hooks/constant.DRAIN.html) phase wait for it to finish, along
+    /// with every other task spawned this way:
+    ///
+    /// ```edition2018,no_run
+    /// # use graceful::{ShutdownHandle, SignalGuard};
+    /// # async fn flush_batches(_: tokio_util::sync::CancellationToken) {}
+    /// # fn run(signal_guard: &SignalGuard) {
+    /// let token = signal_guard.cancellation_token();
+    /// ShutdownHandle::new().spawn_tracked(flush_batches(token));
+    /// # }
+    /// ```
+    ///
+    /// The tasks are not stopped, only waited for, so they should watch a
+    /// [cancellation token](struct.SignalGuard.html#method.cancellation_token)
+    /// or the like. The phase waits until its deadline, or until the
+    /// [drain timeout](#method.set_drain_timeout) if that comes first, and
+    /// the runtime has to keep running until then.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called outside of a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn spawn_tracked<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        rt::spawn_tracked(future)
+    }
+
+    /// Wait at most `timeout` for the tasks spawned with
+    /// [spawn_tracked](#method.spawn_tracked) during the shutdown.
+    #[cfg(feature = "tokio")]
+    pub fn set_drain_timeout(&self, timeout: Duration) {
+        rt::set_drain_timeout(timeout);
+    }
+
+    /// The signal that started the shutdown, if it has.
+    pub fn signal(&self) -> Option<Signal> {
+        lock(&SHARED.history).signals.first().cloned()
+    }
+
+    /// Block until the shutdown starts, returning the signal that started
+    /// it.
+    pub fn wait(&self) -> Signal {
+        self.iter().next().expect("signals never end")
+    }
+
+    /// Block until a message arrives on `receiver` or the shutdown starts,
+    /// see [channel](channel/index.html). Once the shutdown has started it
+    /// wins over queued messages, which `try_recv` still returns.
+    pub fn select<T>(&self, receiver: &Receiver<T>) -> Selected<T> {
+        receiver.select(None).expect("no deadline")
+    }
+
+    /// Like [select](#method.select), giving up after `timeout`.
+    pub fn select_timeout<T>(
+        &self,
+        receiver: &Receiver<T>,
+        timeout: Duration,
+    ) -> Option<Selected<T>> {
+        receiver.select(Instant::now().checked_add(timeout))
+    }
+
+    /// The signals received, blocking until the next one arrives.
+    pub fn iter(&self) -> Signals {
+        Signals { next: 0 }
+    }
+
+    /// A future that waits for `duration`, or resolves early with the
+    /// signal once the shutdown starts, so periodic tasks need no select of
+    /// their own:
+    ///
+    /// ```edition2018,no_run
+    /// # use std::time::Duration;
+    /// # use graceful::ShutdownHandle;
+    /// # async fn refresh_cache() {}
+    /// async fn refresher(handle: ShutdownHandle) {
+    ///     while handle.sleep(Duration::from_secs(30)).await.is_ok() {
+    ///         refresh_cache().await;
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// It works under any runtime, a thread of its own keeps the time.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep::new(Instant::now().checked_add(duration))
+    }
+
+    /// Ticks every `period` until the shutdown starts:
+    ///
+    /// ```edition2018,no_run
+    /// # use std::time::Duration;
+    /// # use graceful::ShutdownHandle;
+    /// # async fn report_metrics() {}
+    /// async fn reporter(handle: ShutdownHandle) {
+    ///     let mut ticks = handle.interval(Duration::from_secs(10));
+    ///     while ticks.tick().await.is_ok() {
+    ///         report_metrics().await;
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn interval(&self, period: Duration) -> Interval {
+        Interval::new(period)
+    }
+}
+
+impl Future for ShutdownHandle {
+    type Output = Signal;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Signal> {
+        let mut history = lock(&SHARED.history);
+        if let Some(&signal) = history.signals.first() {
+            return Poll::Ready(signal);
+        }
+        if !history
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            history.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl IntoIterator for ShutdownHandle {
+    type Item = Signal;
+    type IntoIter = Signals;
+
+    fn into_iter(self) -> Signals {
+        self.iter()
+    }
+}
+
+impl IntoIterator for &ShutdownHandle {
+    type Item = Signal;
+    type IntoIter = Signals;
+
+    fn into_iter(self) -> Signals {
+        self.iter()
+    }
+}
+
+extern "C" fn extern_poll() -> bool {
+    state::is_shutting_down()
+}
+
+extern "C" fn extern_poll_with_context(_context: *mut c_void) -> bool {
+    state::is_shutting_down()
+}
+
+/// The terminal signals received, in order, starting with the one that
+/// started the shutdown. `next` blocks until there is another one, and never
+/// returns `None`.
+#[derive(Debug)]
+pub struct Signals {
+    next: usize,
+}
+
+impl Iterator for Signals {
+    type Item = Signal;
+
+    fn next(&mut self) -> Option<Signal> {
+        let mut history = lock(&SHARED.history);
+        loop {
+            if let Some(&signal) = history.signals.get(self.next) {
+                self.next += 1;
+                return Some(signal);
+            }
+            history = wait(&SHARED.cond, history);
+        }
+    }
+}
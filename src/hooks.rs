@@ -0,0 +1,1398 @@
+//! Shutdown hooks run after a signal is received, before the handler passed
+//! to `at_exit`.
+//!
+//! Shutdown is divided into named phases that run one after another, by
+//! default [stop-intake](constant.STOP_INTAKE.html),
+//! [drain](constant.DRAIN.html), [flush](constant.FLUSH.html) and
+//! [close](constant.CLOSE.html). The hooks of a phase run concurrently, each
+//! on its own thread, except that hooks with a higher priority finish before
+//! those with a lower one start. A failing or panicking hook does not keep
+//! the others from running.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::hooks;
+//!
+//! hooks::phase(hooks::STOP_INTAKE).hook("listener", |_| {
+//!     // stop accepting connections
+//! });
+//! hooks::phase(hooks::FLUSH)
+//!     .hook("metrics", |_| -> std::io::Result<()> { Ok(()) })
+//!     .hook("logs", |_| {});
+//! ```
+//!
+//! Tasks that are not hooks can still synchronize with the phase boundaries,
+//! for example with `hooks::phase(hooks::DRAIN).completed().await`.
+//!
+//! The whole sequence can be tried out without shutting down with
+//! [Coordinator::rehearse](struct.Coordinator.html#method.rehearse).
+//!
+//! # Time budget
+//!
+//! Once a [grace period](struct.Coordinator.html#method.set_grace_period) is
+//! set, each phase may claim a fraction of it with
+//! [budget](struct.Phase.html#method.budget); phases without one share what
+//! is left. Phase deadlines add up from the start of the shutdown, so time a
+//! phase leaves unused rolls forward to the following ones, while a slow
+//! phase is cut off at its deadline instead of starving the rest. Hooks still
+//! running at the deadline are abandoned and reported as timed out. The
+//! deadlines are measured on the [clock](../clock/index.html) of the
+//! coordinator.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::hooks;
+//!
+//! hooks::coordinator().set_grace_period(Duration::from_secs(30));
+//! hooks::phase(hooks::DRAIN).budget(0.6);
+//! hooks::phase(hooks::FLUSH).budget(0.3);
+//! hooks::phase(hooks::CLOSE).budget(0.1);
+//! ```
+//!
+//! # Partitions
+//!
+//! A framework can keep its teardown apart from the hooks of the
+//! application in a [partition](fn.partition.html) of its own, a
+//! coordinator with its own phases, budgets and grace period that the
+//! application's configuration does not touch. The partitions run after the
+//! application's hooks, the most recently created first, and their phases
+//! are reported as `partition:phase`.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::hooks;
+//!
+//! let framework = hooks::partition("my-framework");
+//! framework.set_grace_period(Duration::from_secs(5));
+//! framework.phase(hooks::CLOSE).hook("connection pool", |_| {});
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{self, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "static-hooks")]
+use __private::inventory;
+use clock::{Clock, SystemClock};
+use error::{panic_message, BoxError, FailureKind, IntoResult};
+use events::{self, Event};
+use report::{Cause, HookReport, PhaseReport, ShutdownReport};
+#[cfg(unix)]
+use sd_notify;
+use signal::Signal;
+use state;
+use sync::{lock, try_lock, wait};
+use thread::{join_deadline, spawn_with, JoinHandle};
+
+/// Stop accepting new work.
+pub const STOP_INTAKE: &str = "stop-intake";
+/// Wait for work in progress to finish.
+pub const DRAIN: &str = "drain";
+/// Write out buffered state.
+pub const FLUSH: &str = "flush";
+/// Release connections and other resources.
+pub const CLOSE: &str = "close";
+
+/// The phases of a [Coordinator](struct.Coordinator.html), in run order.
+pub const DEFAULT_PHASES: &[&str] = &[STOP_INTAKE, DRAIN, FLUSH, CLOSE];
+
+/// The phase of hooks registered without one.
+pub const DEFAULT_PHASE: &str = CLOSE;
+
+/// What a hook is told about the shutdown in progress.
+#[derive(Clone, Debug)]
+pub struct Context {
+    signal: Signal,
+    phase: String,
+    deadline: Option<Instant>,
+    extension: Arc<Extension>,
+    rehearsal: bool,
+    counts: Arc<Mutex<Vec<(String, u64)>>>,
+    attempt: Arc<AtomicU32>,
+    /// A clock set on the coordinator, or the time of the system.
+    clock: Option<Arc<dyn Clock>>,
+}
+
+/// The extensions granted during one run, shared by all its hooks.
+#[derive(Debug)]
+struct Extension {
+    granted: Mutex<Duration>,
+    max: Duration,
+}
+
+impl Context {
+    /// The signal that started the shutdown.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// Every signal received so far, starting with the one that started
+    /// the shutdown, see
+    /// [ShutdownReport::causes](../struct.ShutdownReport.html#method.causes).
+    /// Only that one in a rehearsal.
+    pub fn causes(&self) -> Vec<Cause> {
+        let causes = if self.rehearsal {
+            Vec::new()
+        } else {
+            state::causes()
+        };
+        if causes.is_empty() {
+            vec![Cause::new(self.signal, None, Duration::from_secs(0))]
+        } else {
+            causes
+        }
+    }
+
+    /// The name of the running phase.
+    pub fn phase(&self) -> &str {
+        &self.phase
+    }
+
+    /// When the running phase is cut off, if there is a grace period.
+    ///
+    /// [Extensions](#method.request_extension) push it back, and leave the
+    /// phase without a deadline if it is pushed back too far to represent.
+    pub fn deadline(&self) -> Option<Instant> {
+        let granted = *lock(&self.extension.granted);
+        self.deadline
+            .and_then(|deadline| deadline.checked_add(granted))
+    }
+
+    /// Whether this is a [rehearsal](struct.Coordinator.html#method.rehearse),
+    /// and the process is not actually shutting down.
+    pub fn is_rehearsal(&self) -> bool {
+        self.rehearsal
+    }
+
+    /// Add `n` to the count called `name`, reported for this hook in
+    /// [HookReport::counts](../struct.HookReport.html#method.counts).
+    pub fn count(&self, name: &str, n: u64) {
+        let mut counts = lock(&self.counts);
+        match counts.iter_mut().find(|count| count.0 == name) {
+            Some(count) => count.1 += n,
+            None => counts.push((name.to_owned(), n)),
+        }
+    }
+
+    /// A context for one hook, with counts of its own.
+    fn for_hook(&self) -> Context {
+        Context {
+            counts: Arc::default(),
+            attempt: Arc::new(AtomicU32::new(1)),
+            ..self.clone()
+        }
+    }
+
+    /// Which attempt of the hook this is, starting at `1`, see
+    /// [Phase::hook_with_retry](struct.Phase.html#method.hook_with_retry).
+    pub fn attempt(&self) -> u32 {
+        self.attempt.load(Ordering::Relaxed)
+    }
+
+    /// The time left until the [deadline](#method.deadline).
+    pub fn remaining(&self) -> Option<Duration> {
+        let now = self.clock().now();
+        self.deadline()
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// The clock the deadlines are measured on, see
+    /// [Coordinator::set_clock](struct.Coordinator.html#method.set_clock).
+    pub fn clock(&self) -> &dyn Clock {
+        match self.clock {
+            Some(ref clock) => &**clock,
+            None => &SystemClock,
+        }
+    }
+
+    /// Ask for more time before the running phase is cut off, for example
+    /// when a flush is nearly done. Returns the time granted, which may be
+    /// less than asked for.
+    ///
+    /// Extensions push back the deadlines of this and the following phases,
+    /// and all together stay within the
+    /// [maximum](struct.Coordinator.html#method.set_max_extension) of the
+    /// coordinator. Under systemd, the stop timeout of the service is
+    /// extended with `EXTEND_TIMEOUT_USEC=` to match, except in a rehearsal.
+    pub fn request_extension(&self, extension: Duration) -> Duration {
+        let granted = {
+            let mut granted = lock(&self.extension.granted);
+            let extension = extension.min(self.extension.max.saturating_sub(*granted));
+            *granted = granted.saturating_add(extension);
+            extension
+        };
+        #[cfg(unix)]
+        {
+            if granted > Duration::from_secs(0) && !self.rehearsal {
+                let timeout = self.remaining().unwrap_or(granted);
+                let _ = sd_notify::notify(&format!("EXTEND_TIMEOUT_USEC={}", timeout.as_micros()));
+            }
+        }
+        granted
+    }
+}
+
+type HookFn = Box<dyn FnMut(&Context) -> Result<(), BoxError> + Send>;
+
+/// How often a failing hook is tried again, see
+/// [Phase::hook_with_retry](struct.Phase.html#method.hook_with_retry).
+///
+/// The wait before each retry starts at the backoff and is multiplied by
+/// the multiplier after every attempt, up to the maximum backoff.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+    multiplier: f64,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Up to `attempts` attempts in all, the first retry after 100
+    /// milliseconds and each following one after twice as long as the
+    /// previous, but at most 5 seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `attempts` is zero.
+    pub fn new(attempts: u32) -> RetryPolicy {
+        assert!(attempts > 0, "a hook needs at least one attempt");
+        RetryPolicy {
+            attempts,
+            backoff: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// Wait `backoff` before the first retry.
+    pub fn backoff(self, backoff: Duration) -> RetryPolicy {
+        RetryPolicy { backoff, ..self }
+    }
+
+    /// Multiply the wait by `multiplier` after every retry, `1.0` for a
+    /// constant wait.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `multiplier` is less than `1.0`.
+    pub fn multiplier(self, multiplier: f64) -> RetryPolicy {
+        assert!(
+            multiplier >= 1.0,
+            "backoff multiplier must be at least 1.0, got {}",
+            multiplier
+        );
+        RetryPolicy { multiplier, ..self }
+    }
+
+    /// Never wait longer than `max_backoff` between attempts.
+    pub fn max_backoff(self, max_backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_backoff,
+            ..self
+        }
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+/// Run `f` until it succeeds or `policy` gives up. A retry is only
+/// started if the wait before it ends before the deadline.
+fn retry<F>(policy: RetryPolicy, ctx: &Context, f: &mut F) -> Result<(), BoxError>
+where
+    F: FnMut(&Context) -> Result<(), BoxError>,
+{
+    let mut backoff = policy.backoff;
+    loop {
+        let err = match f(ctx) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        let out_of_time = ctx
+            .remaining()
+            .is_some_and(|remaining| remaining <= backoff);
+        if ctx.attempt() >= policy.attempts || out_of_time {
+            return Err(err);
+        }
+        ctx.clock().sleep(backoff);
+        backoff = backoff.mul_f64(policy.multiplier).min(policy.max_backoff);
+        ctx.attempt.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Hook {
+    name: String,
+    priority: i32,
+    /// Left out of rehearsals.
+    once: bool,
+    func: Arc<Mutex<HookFn>>,
+}
+
+impl Hook {
+    fn new<F, R>(name: &str, priority: i32, mut f: F) -> Hook
+    where
+        F: FnMut(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        let func: HookFn = Box::new(move |ctx| f(ctx).into_result());
+        Hook {
+            name: name.to_owned(),
+            priority,
+            once: false,
+            func: Arc::new(Mutex::new(func)),
+        }
+    }
+}
+
+impl Clone for Hook {
+    fn clone(&self) -> Hook {
+        Hook {
+            name: self.name.clone(),
+            priority: self.priority,
+            once: self.once,
+            func: self.func.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct BarrierState {
+    completed: bool,
+    wakers: Vec<Waker>,
+}
+
+/// Released once every hook of a phase has returned.
+#[derive(Default)]
+struct Barrier {
+    state: Mutex<BarrierState>,
+    cond: Condvar,
+}
+
+impl Barrier {
+    fn complete(&self) {
+        let mut state = lock(&self.state);
+        state.completed = true;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.cond.notify_all();
+    }
+}
+
+#[derive(Clone)]
+struct PhaseEntry {
+    name: String,
+    hooks: Vec<Hook>,
+    budget: Option<f64>,
+    barrier: Arc<Barrier>,
+}
+
+impl PhaseEntry {
+    fn new(name: &str) -> PhaseEntry {
+        PhaseEntry {
+            name: name.to_owned(),
+            hooks: Vec::new(),
+            budget: None,
+            barrier: Arc::default(),
+        }
+    }
+}
+
+/// Runs shutdown hooks phase by phase.
+///
+/// [SignalGuard](../struct.SignalGuard.html) runs the global coordinator
+/// returned by [coordinator](fn.coordinator.html).
+pub struct Coordinator {
+    phases: Mutex<Vec<PhaseEntry>>,
+    grace_period: Mutex<Option<Duration>>,
+    signal_grace_periods: Mutex<HashMap<Signal, Duration>>,
+    max_extension: Mutex<Duration>,
+    clock: Mutex<Option<Arc<dyn Clock>>>,
+    /// Whether the hooks added with `#[graceful::hook]` run here too.
+    static_hooks: bool,
+}
+
+impl Default for Coordinator {
+    fn default() -> Coordinator {
+        Coordinator::with_phases(DEFAULT_PHASES)
+    }
+}
+
+impl Coordinator {
+    /// A coordinator with the [default phases](constant.DEFAULT_PHASES.html).
+    pub fn new() -> Coordinator {
+        Coordinator::default()
+    }
+
+    /// A coordinator running the given phases in order. Hooks added with
+    /// `#[graceful::hook]` only run in the global
+    /// [coordinator](fn.coordinator.html).
+    pub fn with_phases(names: &[&str]) -> Coordinator {
+        let phases = names.iter().map(|name| PhaseEntry::new(name)).collect();
+        Coordinator {
+            phases: Mutex::new(phases),
+            grace_period: Mutex::new(None),
+            signal_grace_periods: Mutex::new(HashMap::new()),
+            max_extension: Mutex::new(Duration::from_secs(0)),
+            clock: Mutex::new(None),
+            static_hooks: false,
+        }
+    }
+
+    /// Bound the time spent running hooks, see [the module
+    /// documentation](index.html#time-budget).
+    pub fn set_grace_period(&self, grace_period: Duration) {
+        *lock(&self.grace_period) = Some(grace_period);
+    }
+
+    pub fn grace_period(&self) -> Option<Duration> {
+        *lock(&self.grace_period)
+    }
+
+    /// Use `grace_period` instead when the shutdown is started by `signal`,
+    /// for example a shorter one on [Power](../enum.Signal.html#variant.Power)
+    /// while the UPS battery lasts:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// use std::time::Duration;
+    /// use graceful::{hooks, Signal};
+    ///
+    /// hooks::coordinator().set_grace_period(Duration::from_secs(60));
+    /// hooks::coordinator().set_signal_grace_period(Signal::Power, Duration::from_secs(5));
+    /// ```
+    pub fn set_signal_grace_period(&self, signal: Signal, grace_period: Duration) {
+        lock(&self.signal_grace_periods).insert(signal, grace_period);
+    }
+
+    /// The grace period that applies to a shutdown started by `signal`.
+    pub fn grace_period_for(&self, signal: Signal) -> Option<Duration> {
+        let specific = lock(&self.signal_grace_periods).get(&signal).cloned();
+        specific.or_else(|| self.grace_period())
+    }
+
+    /// Bound the total time hooks may add with
+    /// [request_extension](struct.Context.html#method.request_extension).
+    /// None is granted by default.
+    pub fn set_max_extension(&self, max: Duration) {
+        *lock(&self.max_extension) = max;
+    }
+
+    /// Measure the deadlines and the time hooks take on `clock` instead of
+    /// the time of the system, see [clock](../clock/index.html).
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *lock(&self.clock) = Some(clock);
+    }
+
+    /// The phase called `name`, appended after the existing phases if there
+    /// is none yet.
+    pub fn phase(&self, name: &str) -> Phase<'_> {
+        let barrier = entry(&mut lock(&self.phases), name).barrier.clone();
+        Phase {
+            coordinator: self,
+            name: name.to_owned(),
+            barrier,
+        }
+    }
+
+    /// The phase called `name`, inserted before the phase `before` if there
+    /// is none yet, or appended if there is no `before` either.
+    pub fn phase_before(&self, name: &str, before: &str) -> Phase<'_> {
+        {
+            let mut phases = lock(&self.phases);
+            if !phases.iter().any(|entry| entry.name == name) {
+                let index = phases
+                    .iter()
+                    .position(|entry| entry.name == before)
+                    .unwrap_or(phases.len());
+                phases.insert(index, PhaseEntry::new(name));
+            }
+        }
+        self.phase(name)
+    }
+
+    /// The names of the phases, in run order.
+    pub fn phases(&self) -> Vec<String> {
+        lock(&self.phases)
+            .iter()
+            .map(|phase| phase.name.clone())
+            .collect()
+    }
+
+    fn add(&self, phase: &str, hook: Hook) {
+        insert(&mut lock(&self.phases), phase, hook);
+    }
+
+    /// Run every phase for `signal`, waiting for the hooks to return or for
+    /// the grace period to run out.
+    pub fn run(&self, signal: Signal) -> ShutdownReport {
+        self.run_phases(signal, false)
+    }
+
+    /// Run the phases as [run](#method.run) does without shutting down, to
+    /// test the shutdown path and measure how long it takes.
+    ///
+    /// Hooks are told with [is_rehearsal](struct.Context.html#method.is_rehearsal),
+    /// hooks added with [hook_once](struct.Phase.html#method.hook_once) are
+    /// left out, the phases are not marked completed and no
+    /// [events](../events/index.html) are emitted. Hooks added with
+    /// `#[graceful::hook]` cannot tell and run as usual.
+    pub fn rehearse(&self, signal: Signal) -> ShutdownReport {
+        self.run_phases(signal, true)
+    }
+
+    pub(crate) fn run_phases(&self, signal: Signal, rehearsal: bool) -> ShutdownReport {
+        let clock = lock(&self.clock).clone();
+        let started = match clock {
+            Some(ref clock) => clock.now(),
+            None => Instant::now(),
+        };
+        let mut phases = lock(&self.phases).clone();
+        if self.static_hooks {
+            add_static_hooks(&mut phases);
+        }
+        if rehearsal {
+            for phase in &mut phases {
+                phase.hooks.retain(|hook| !hook.once);
+            }
+        }
+        let deadlines = match self.grace_period_for(signal) {
+            Some(grace_period) => deadlines(started, grace_period, &phases),
+            None => vec![None; phases.len()],
+        };
+        let extension = Arc::new(Extension {
+            granted: Mutex::new(Duration::from_secs(0)),
+            max: *lock(&self.max_extension),
+        });
+
+        let reports = phases
+            .into_iter()
+            .zip(deadlines)
+            .map(|(phase, deadline)| {
+                let ctx = Context {
+                    signal,
+                    phase: phase.name.clone(),
+                    deadline,
+                    extension: extension.clone(),
+                    rehearsal,
+                    counts: Arc::default(),
+                    attempt: Arc::default(),
+                    clock: clock.clone(),
+                };
+                let report = run_phase(&ctx, phase.hooks);
+                if !rehearsal {
+                    phase.barrier.complete();
+                    events::emit(&Event::PhaseFinished(&report));
+                }
+                report
+            })
+            .collect();
+        ShutdownReport::new(signal, reports)
+    }
+}
+
+/// A named phase of a [Coordinator](struct.Coordinator.html).
+pub struct Phase<'a> {
+    coordinator: &'a Coordinator,
+    name: String,
+    barrier: Arc<Barrier>,
+}
+
+impl<'a> Phase<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether every hook of this phase has returned.
+    pub fn is_completed(&self) -> bool {
+        lock(&self.barrier.state).completed
+    }
+
+    /// A future resolving once every hook of this phase has returned.
+    pub fn completed(&self) -> Completed {
+        Completed(self.barrier.clone())
+    }
+
+    /// Block until every hook of this phase has returned.
+    pub fn wait_completed(&self) {
+        let mut state = lock(&self.barrier.state);
+        while !state.completed {
+            state = wait(&self.barrier.cond, state);
+        }
+    }
+
+    /// Claim a fraction of the grace period for this phase, see [the module
+    /// documentation](index.html#time-budget).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fraction` is not between `0.0` and `1.0`.
+    pub fn budget(&self, fraction: f64) -> &Phase<'a> {
+        assert!(
+            (0.0..=1.0).contains(&fraction),
+            "phase budget must be between 0.0 and 1.0, got {}",
+            fraction
+        );
+        entry(&mut lock(&self.coordinator.phases), &self.name).budget = Some(fraction);
+        self
+    }
+
+    /// Add a hook with priority `0`.
+    ///
+    /// The hook may return `()` or any `Result<(), E>`.
+    pub fn hook<F, R>(&self, name: &str, f: F) -> &Phase<'a>
+    where
+        F: FnMut(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        self.hook_with_priority(name, 0, f)
+    }
+
+    /// Add a hook that finishes before the hooks of this phase with a lower
+    /// priority start.
+    pub fn hook_with_priority<F, R>(&self, name: &str, priority: i32, f: F) -> &Phase<'a>
+    where
+        F: FnMut(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        self.coordinator
+            .add(&self.name, Hook::new(name, priority, f));
+        self
+    }
+
+    /// Add a hook with priority `0` that is tried again as `policy` says
+    /// while it fails, for cleanup that depends on the network, such as
+    /// deregistering from service discovery:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn deregister() -> std::io::Result<()> { Ok(()) }
+    /// use std::time::Duration;
+    ///
+    /// use graceful::hooks::{self, RetryPolicy};
+    ///
+    /// let policy = RetryPolicy::new(3).backoff(Duration::from_millis(200));
+    /// hooks::phase(hooks::STOP_INTAKE).hook_with_retry("deregister", policy, |_| deregister());
+    /// ```
+    ///
+    /// Retries stay within the deadline of the phase: once the wait before
+    /// the next one would reach it, the last failure is reported instead.
+    /// A panic is not retried. The attempts made are reported in
+    /// [HookReport::attempts](../struct.HookReport.html#method.attempts).
+    pub fn hook_with_retry<F, R>(&self, name: &str, policy: RetryPolicy, mut f: F) -> &Phase<'a>
+    where
+        F: FnMut(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        let mut attempt = move |ctx: &Context| f(ctx).into_result();
+        self.hook(name, move |ctx: &Context| retry(policy, ctx, &mut attempt))
+    }
+
+    /// Add a hook with priority `0` that runs at most once, for cleanup that
+    /// consumes or destroys what it cleans up. It is left out of
+    /// [rehearsals](struct.Coordinator.html#method.rehearse).
+    pub fn hook_once<F, R>(&self, name: &str, f: F) -> &Phase<'a>
+    where
+        F: FnOnce(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        self.hook_once_with_priority(name, 0, f)
+    }
+
+    /// Like [hook_once](#method.hook_once), with a priority as in
+    /// [hook_with_priority](#method.hook_with_priority).
+    pub fn hook_once_with_priority<F, R>(&self, name: &str, priority: i32, f: F) -> &Phase<'a>
+    where
+        F: FnOnce(&Context) -> R + Send + 'static,
+        R: IntoResult,
+    {
+        let mut f = Some(f);
+        let mut hook = Hook::new(name, priority, move |ctx: &Context| match f.take() {
+            Some(f) => f(ctx).into_result(),
+            None => Ok(()),
+        });
+        hook.once = true;
+        self.coordinator.add(&self.name, hook);
+        self
+    }
+}
+
+lazy_static! {
+    static ref COORDINATOR: Coordinator = Coordinator {
+        static_hooks: true,
+        ..Coordinator::new()
+    };
+    /// The partitions, in creation order.
+    static ref PARTITIONS: Mutex<Vec<(String, &'static Coordinator)>> = Mutex::new(Vec::new());
+}
+
+/// The coordinator run by [SignalGuard](../struct.SignalGuard.html).
+pub fn coordinator() -> &'static Coordinator {
+    &COORDINATOR
+}
+
+/// The coordinator of the partition called `name`, created with the
+/// [default phases](constant.DEFAULT_PHASES.html) and no grace period if
+/// there is none yet, see [Partitions](index.html#partitions).
+///
+/// Hooks added with `#[graceful::hook]` stay with the application.
+pub fn partition(name: &str) -> &'static Coordinator {
+    let mut partitions = lock(&PARTITIONS);
+    if let Some(&(_, coordinator)) = partitions.iter().find(|entry| entry.0 == name) {
+        return coordinator;
+    }
+    // Partitions live as long as the program, like the global coordinator.
+    let coordinator: &'static Coordinator = Box::leak(Box::new(Coordinator::new()));
+    partitions.push((name.to_owned(), coordinator));
+    coordinator
+}
+
+/// Run the global coordinator, then the partitions, the most recently
+/// created first.
+pub(crate) fn run_all(signal: Signal, rehearsal: bool) -> ShutdownReport {
+    let mut report = COORDINATOR.run_phases(signal, rehearsal);
+    let partitions = lock(&PARTITIONS).clone();
+    for (name, coordinator) in partitions.into_iter().rev() {
+        report.append_partition(&name, coordinator.run_phases(signal, rehearsal));
+    }
+    report
+}
+
+/// A phase of the global [coordinator](fn.coordinator.html).
+pub fn phase(name: &str) -> Phase<'static> {
+    COORDINATOR.phase(name)
+}
+
+/// A future resolving once every hook of a phase has returned, see
+/// [Phase::completed](struct.Phase.html#method.completed).
+pub struct Completed(Arc<Barrier>);
+
+impl Future for Completed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<()> {
+        let mut state = lock(&self.0.state);
+        if state.completed {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// The phase called `name`, appended if there is none yet.
+fn entry<'a>(phases: &'a mut Vec<PhaseEntry>, name: &str) -> &'a mut PhaseEntry {
+    let index = match phases.iter().position(|entry| entry.name == name) {
+        Some(index) => index,
+        None => {
+            phases.push(PhaseEntry::new(name));
+            phases.len() - 1
+        }
+    };
+    &mut phases[index]
+}
+
+fn insert(phases: &mut Vec<PhaseEntry>, phase: &str, hook: Hook) {
+    entry(phases, phase).hooks.push(hook);
+}
+
+/// The deadline of every phase, cumulative from `started`.
+fn deadlines(
+    started: Instant,
+    grace_period: Duration,
+    phases: &[PhaseEntry],
+) -> Vec<Option<Instant>> {
+    let claimed: f64 = phases.iter().filter_map(|phase| phase.budget).sum();
+    let unclaimed = phases.iter().filter(|phase| phase.budget.is_none()).count();
+    let share = if unclaimed > 0 {
+        (1.0 - claimed).max(0.0) / unclaimed as f64
+    } else {
+        0.0
+    };
+
+    let mut elapsed = Duration::from_secs(0);
+    phases
+        .iter()
+        .map(|phase| {
+            // Too long a grace period to represent leaves the phase unbounded.
+            let fraction = phase.budget.unwrap_or(share);
+            let allotted = Duration::try_from_secs_f64(grace_period.as_secs_f64() * fraction)
+                .unwrap_or(grace_period);
+            elapsed = elapsed.saturating_add(allotted).min(grace_period);
+            started.checked_add(elapsed)
+        })
+        .collect()
+}
+
+fn run_phase(ctx: &Context, mut hooks: Vec<Hook>) -> PhaseReport {
+    let started = ctx.clock().now();
+    hooks.sort_by_key(|hook| Reverse(hook.priority));
+
+    let mut reports = Vec::with_capacity(hooks.len());
+    let mut hooks = hooks.into_iter().peekable();
+    while let Some(first) = hooks.next() {
+        let mut group = vec![first];
+        while hooks.peek().map(|hook| hook.priority) == Some(group[0].priority) {
+            group.extend(hooks.next());
+        }
+        if ctx.remaining() == Some(Duration::from_secs(0)) {
+            let skipped = group.into_iter().chain(hooks.by_ref());
+            reports.extend(skipped.map(|hook| {
+                HookReport::new(
+                    hook.name,
+                    Duration::from_secs(0),
+                    Some(FailureKind::TimedOut),
+                    Vec::new(),
+                    0,
+                )
+            }));
+            break;
+        }
+        reports.extend(run_group(ctx, group));
+    }
+    let elapsed = ctx.clock().now().saturating_duration_since(started);
+    PhaseReport::new(ctx.phase.clone(), elapsed, reports)
+}
+
+/// Run hooks of equal priority concurrently.
+fn run_group(ctx: &Context, group: Vec<Hook>) -> Vec<HookReport> {
+    let running: Vec<_> = group
+        .into_iter()
+        .map(|hook| {
+            let ctx = ctx.for_hook();
+            let counts = ctx.counts.clone();
+            let attempt = ctx.attempt.clone();
+            let name = hook.name.clone();
+            let started = ctx.clock().now();
+            let builder = thread::Builder::new().name(format!("graceful: {}", name));
+            let handle = spawn_with(builder, move || {
+                // A hook abandoned by an earlier run, after timing out,
+                // may still hold it.
+                let result = try_lock(&hook.func).map(|mut func| func(&ctx));
+                (result, ctx.clock().now())
+            });
+            (name, started, counts, attempt, handle)
+        })
+        .collect();
+
+    running
+        .into_iter()
+        .map(|(name, started, counts, attempt, handle)| {
+            let joined = handle.map(|handle| join_hook(ctx, handle));
+            let (failure, finished) = match joined {
+                Ok(Ok(Ok((Some(Ok(())), finished)))) => (None, Some(finished)),
+                Ok(Ok(Ok((Some(Err(err)), finished)))) => {
+                    (Some(FailureKind::Failed(err)), Some(finished))
+                }
+                Ok(Ok(Ok((None, _)))) => (Some(FailureKind::TimedOut), None),
+                Ok(Ok(Err(payload))) => {
+                    (Some(FailureKind::Panicked(panic_message(&*payload))), None)
+                }
+                Ok(Err(())) => (Some(FailureKind::TimedOut), None),
+                Err(err) => (Some(FailureKind::Failed(err.into())), None),
+            };
+            let finished = finished.unwrap_or_else(|| ctx.clock().now());
+            // On a clock of its own, a hook may have slept past the deadline
+            // before the wait noticed.
+            let late =
+                ctx.clock.is_some() && ctx.deadline().is_some_and(|deadline| finished > deadline);
+            let failure = if late {
+                Some(FailureKind::TimedOut)
+            } else {
+                failure
+            };
+            let counts = lock(&counts).clone();
+            let attempts = attempt.load(Ordering::Relaxed);
+            let duration = finished.saturating_duration_since(started);
+            HookReport::new(name, duration, failure, counts, attempts)
+        })
+        .collect()
+}
+
+/// Join a hook by the deadline, which extensions may push back while
+/// waiting.
+fn join_hook<T>(ctx: &Context, mut handle: JoinHandle<T>) -> Result<thread::Result<T>, ()> {
+    if ctx.clock.is_some() {
+        return join_hook_on_clock(ctx, handle);
+    }
+    loop {
+        let deadline = match ctx.deadline() {
+            Some(deadline) => deadline,
+            None => return Ok(handle.join()),
+        };
+        match join_deadline(handle, deadline) {
+            Ok(joined) => return Ok(joined),
+            Err(_) if ctx.deadline() == Some(deadline) => return Err(()),
+            Err(timeout) => handle = timeout.into_inner(),
+        }
+    }
+}
+
+/// Join a hook by the deadline on the clock of the coordinator, which only
+/// tells the time, so it is checked every millisecond.
+fn join_hook_on_clock<T>(ctx: &Context, handle: JoinHandle<T>) -> Result<thread::Result<T>, ()> {
+    loop {
+        if handle.is_finished() {
+            return Ok(handle.join());
+        }
+        match ctx.deadline() {
+            Some(deadline) if ctx.clock().now() >= deadline => return Err(()),
+            Some(_) => thread::sleep(Duration::from_millis(1)),
+            None => return Ok(handle.join()),
+        }
+    }
+}
+
+/// A hook registered at link time with the `#[graceful::hook]` attribute.
+///
+/// Requires the `static-hooks` feature.
+#[cfg(feature = "static-hooks")]
+pub struct StaticHook {
+    name: &'static str,
+    phase: &'static str,
+    priority: i32,
+    func: fn(Signal),
+}
+
+#[cfg(feature = "static-hooks")]
+impl StaticHook {
+    #[doc(hidden)]
+    pub const fn new(
+        name: &'static str,
+        phase: &'static str,
+        priority: i32,
+        func: fn(Signal),
+    ) -> StaticHook {
+        StaticHook {
+            name,
+            phase,
+            priority,
+            func,
+        }
+    }
+
+    /// The path of the annotated function.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn phase(&self) -> &'static str {
+        self.phase
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+#[cfg(feature = "static-hooks")]
+inventory::collect!(StaticHook);
+
+#[cfg(feature = "static-hooks")]
+fn add_static_hooks(phases: &mut Vec<PhaseEntry>) {
+    for hook in inventory::iter::<StaticHook> {
+        let func = hook.func;
+        let entry = Hook::new(hook.name, hook.priority, move |ctx: &Context| {
+            func(ctx.signal())
+        });
+        insert(phases, hook.phase, entry);
+    }
+}
+
+#[cfg(not(feature = "static-hooks"))]
+fn add_static_hooks(_phases: &mut Vec<PhaseEntry>) {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use clock::ManualClock;
+    use executor;
+
+    /// The report of the only hook of `report`.
+    fn only_hook(report: &ShutdownReport) -> &HookReport {
+        let mut hooks = report.phases().flat_map(|phase| phase.hooks());
+        let hook = hooks.next().expect("a hook");
+        assert!(hooks.next().is_none(), "more than one hook");
+        hook
+    }
+
+    fn on_manual_clock(coordinator: Coordinator) -> Coordinator {
+        coordinator.set_clock(Arc::new(ManualClock::new()));
+        coordinator
+    }
+
+    fn coordinator_now(coordinator: &Coordinator) -> Instant {
+        lock(&coordinator.clock).as_ref().unwrap().now()
+    }
+
+    #[test]
+    fn runs_the_phases_in_order() {
+        let coordinator = Coordinator::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for &phase in &[CLOSE, FLUSH, DRAIN, STOP_INTAKE, "after"] {
+            let order = order.clone();
+            coordinator.phase(phase).hook(phase, move |ctx: &Context| {
+                lock(&order).push(ctx.phase().to_owned())
+            });
+        }
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        assert_eq!(report.signal(), Signal::Terminate);
+        assert_eq!(*lock(&order), [STOP_INTAKE, DRAIN, FLUSH, CLOSE, "after"]);
+        let phases: Vec<&str> = report.phases().map(|phase| phase.name()).collect();
+        assert_eq!(phases, *lock(&order));
+    }
+
+    #[test]
+    fn runs_the_hooks_of_a_phase_by_priority() {
+        let coordinator = Coordinator::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for &(name, priority) in &[("low", -1), ("default", 0), ("high", 1)] {
+            let order = order.clone();
+            coordinator
+                .phase(DRAIN)
+                .hook_with_priority(name, priority, move |_| lock(&order).push(name));
+        }
+        assert!(coordinator.run(Signal::Terminate).is_clean());
+        assert_eq!(*lock(&order), ["high", "default", "low"]);
+        assert!(coordinator.phase(DRAIN).is_completed());
+    }
+
+    #[test]
+    fn reports_the_hooks_that_failed() {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator
+            .phase(CLOSE)
+            .hook("fails", |_| Err("broken pipe"))
+            .hook_with_priority("panics", 1, |_| -> () { panic!("out of disk") });
+        let report = coordinator.run(Signal::Terminate);
+        let failures: Vec<(&str, &str)> = report
+            .failures()
+            .map(|(phase, hook)| (phase, hook.name()))
+            .collect();
+        assert_eq!(failures, [(CLOSE, "panics"), (CLOSE, "fails")]);
+        assert_eq!(report.exit_code(), 101);
+    }
+
+    #[test]
+    fn completes_a_phase_before_the_next_one_starts() {
+        let coordinator = Coordinator::with_phases(&[DRAIN, CLOSE]);
+        let drained = coordinator.phase(DRAIN).completed();
+        assert!(!coordinator.phase(DRAIN).is_completed());
+        let (done, waited) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            executor::block_on(drained, None);
+            done.send(()).unwrap();
+        });
+        coordinator.phase(CLOSE).hook("close", move |_| {
+            waited
+                .recv_timeout(Duration::from_secs(5))
+                .map_err(|_| "drain did not complete")
+        });
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        waiter.join().unwrap();
+        coordinator.phase(CLOSE).wait_completed();
+    }
+
+    /// The time left in each phase when its hook runs, on a clock that
+    /// does not move.
+    fn remaining_by_phase(coordinator: &Coordinator) -> Vec<Option<Duration>> {
+        let remaining = Arc::new(Mutex::new(Vec::new()));
+        for phase in coordinator.phases() {
+            let remaining = remaining.clone();
+            coordinator
+                .phase(&phase)
+                .hook("remaining", move |ctx: &Context| {
+                    lock(&remaining).push(ctx.remaining())
+                });
+        }
+        coordinator.run(Signal::Terminate);
+        let remaining = lock(&remaining).clone();
+        remaining
+    }
+
+    #[test]
+    fn splits_the_grace_period_between_the_phases() {
+        let coordinator = on_manual_clock(Coordinator::with_phases(&["a", "b", "c"]));
+        coordinator.set_grace_period(Duration::from_secs(10));
+        coordinator.phase("b").budget(0.6);
+        assert_eq!(
+            remaining_by_phase(&coordinator),
+            [
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(8)),
+                Some(Duration::from_secs(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_hooks_of_a_phase_out_of_time() {
+        let coordinator = on_manual_clock(Coordinator::with_phases(&["a", "b"]));
+        coordinator.set_grace_period(Duration::from_secs(10));
+        coordinator
+            .phase("a")
+            .budget(1.0)
+            .hook("slow", |ctx: &Context| {
+                ctx.clock().sleep(Duration::from_secs(20));
+            });
+        coordinator.phase("b").hook("late", |_| {});
+        let report = coordinator.run(Signal::Terminate);
+        let failures: Vec<&str> = report.failures().map(|(_, hook)| hook.name()).collect();
+        assert_eq!(failures, ["slow", "late"]);
+        let late = report.phases().nth(1).unwrap().hooks().next().unwrap();
+        assert_eq!(late.attempts(), 0);
+    }
+
+    #[test]
+    fn rehearsals_leave_out_run_once_hooks() {
+        let coordinator = Coordinator::new();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let once = ran.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook_once("once", move |ctx: &Context| {
+                lock(&once).push(("once", ctx.is_rehearsal()));
+            });
+        let always = ran.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook("always", move |ctx: &Context| {
+                lock(&always).push(("always", ctx.is_rehearsal()));
+            });
+        coordinator.rehearse(Signal::Terminate);
+        assert_eq!(*lock(&ran), [("always", true)]);
+        assert!(!coordinator.phase(CLOSE).is_completed());
+    }
+
+    #[test]
+    fn uses_the_grace_period_of_the_signal() {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.set_grace_period(Duration::from_secs(10));
+        coordinator.set_signal_grace_period(Signal::Power, Duration::from_secs(3));
+        assert_eq!(
+            coordinator.grace_period_for(Signal::Power),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            coordinator.grace_period_for(Signal::Terminate),
+            Some(Duration::from_secs(10))
+        );
+        let coordinator = on_manual_clock(coordinator);
+        let remaining = Arc::new(Mutex::new(None));
+        let seen = remaining.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook("remaining", move |ctx: &Context| {
+                *lock(&seen) = ctx.remaining()
+            });
+        coordinator.run(Signal::Power);
+        assert_eq!(*lock(&remaining), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn extends_the_deadlines_up_to_the_maximum() {
+        let coordinator = on_manual_clock(Coordinator::with_phases(&["a", "b"]));
+        coordinator.set_grace_period(Duration::from_secs(10));
+        coordinator.set_max_extension(Duration::from_secs(3));
+        let granted = Arc::new(Mutex::new(Vec::new()));
+        let asked = granted.clone();
+        // Ahead of the hooks that look at the time left.
+        coordinator
+            .phase("a")
+            .hook_with_priority("flush", 1, move |ctx: &Context| {
+                let mut asked = lock(&asked);
+                asked.push(ctx.request_extension(Duration::from_secs(2)));
+                asked.push(ctx.request_extension(Duration::from_secs(2)));
+                asked.push(ctx.request_extension(Duration::from_secs(2)));
+            });
+        let remaining = remaining_by_phase(&coordinator);
+        assert_eq!(
+            *lock(&granted),
+            [
+                Duration::from_secs(2),
+                Duration::from_secs(1),
+                Duration::from_secs(0),
+            ]
+        );
+        // Both phases end 3 seconds later than they would have.
+        assert_eq!(
+            remaining,
+            [Some(Duration::from_secs(8)), Some(Duration::from_secs(13))]
+        );
+    }
+
+    #[test]
+    fn an_extension_too_long_to_represent_removes_the_deadline() {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.set_grace_period(Duration::from_secs(10));
+        coordinator.set_max_extension(Duration::MAX);
+        let seen = Arc::new(Mutex::new(None));
+        let at = seen.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook("extend", move |ctx: &Context| {
+                let granted = ctx.request_extension(Duration::MAX);
+                *lock(&at) = Some((granted, ctx.remaining()));
+            });
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        assert_eq!(*lock(&seen), Some((Duration::MAX, None)));
+    }
+
+    #[test]
+    fn a_grace_period_too_long_to_represent_sets_no_deadline() {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.set_grace_period(Duration::MAX);
+        let remaining = Arc::new(Mutex::new(Some(Duration::from_secs(0))));
+        let seen = remaining.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook("remaining", move |ctx: &Context| {
+                *lock(&seen) = ctx.remaining()
+            });
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        assert_eq!(*lock(&remaining), None);
+    }
+
+    #[test]
+    fn inserts_a_phase_before_another() {
+        let coordinator = Coordinator::new();
+        coordinator.phase_before("first", STOP_INTAKE);
+        coordinator.phase_before("first", CLOSE);
+        coordinator.phase_before("last", "missing");
+        assert_eq!(
+            coordinator.phases(),
+            ["first", STOP_INTAKE, DRAIN, FLUSH, CLOSE, "last"]
+        );
+    }
+
+    #[test]
+    fn counts_are_reported_per_hook() {
+        let coordinator = Coordinator::new();
+        coordinator.phase(DRAIN).hook("drain", |ctx: &Context| {
+            ctx.count("requests", 2);
+            ctx.count("requests", 3);
+            ctx.count("connections", 1);
+        });
+        let report = coordinator.run(Signal::Terminate);
+        let counts: Vec<(String, u64)> = only_hook(&report).counts().cloned().collect();
+        assert_eq!(
+            counts,
+            [("requests".to_owned(), 5), ("connections".to_owned(), 1)]
+        );
+        assert!(report
+            .to_string()
+            .ends_with("\n  drain/drain: 5 requests, 1 connections"));
+    }
+
+    #[test]
+    fn retries_a_failing_hook_until_it_succeeds() {
+        let coordinator = on_manual_clock(Coordinator::new());
+        let policy = RetryPolicy::new(5).backoff(Duration::from_millis(10));
+        coordinator
+            .phase(CLOSE)
+            .hook_with_retry("flaky", policy, |ctx: &Context| {
+                if ctx.attempt() < 3 {
+                    Err("not yet")
+                } else {
+                    Ok(())
+                }
+            });
+        let report = coordinator.run(Signal::Terminate);
+        let hook = only_hook(&report);
+        assert!(hook.is_ok());
+        assert_eq!(hook.attempts(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let coordinator = on_manual_clock(Coordinator::new());
+        let started = coordinator_now(&coordinator);
+        let policy = RetryPolicy::new(4)
+            .backoff(Duration::from_secs(1))
+            .multiplier(3.0)
+            .max_backoff(Duration::from_secs(5));
+        let finished = Arc::new(Mutex::new(None));
+        let at = finished.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook_with_retry("down", policy, move |ctx: &Context| {
+                *lock(&at) = Some(ctx.clock().now());
+                Err("down")
+            });
+        let report = coordinator.run(Signal::Terminate);
+        let hook = only_hook(&report);
+        assert!(matches!(hook.failure(), Some(FailureKind::Failed(_))));
+        assert_eq!(hook.attempts(), 4);
+        // Waits of 1, 3, then 5 rather than 9 seconds.
+        let finished = lock(&finished).unwrap();
+        assert_eq!(finished - started, Duration::from_secs(9));
+    }
+
+    #[test]
+    fn does_not_retry_past_the_deadline() {
+        let coordinator = on_manual_clock(Coordinator::with_phases(&[CLOSE]));
+        coordinator.set_grace_period(Duration::from_secs(10));
+        let policy = RetryPolicy::new(10).backoff(Duration::from_secs(4));
+        coordinator
+            .phase(CLOSE)
+            .hook_with_retry("down", policy, |_| Err("down"));
+        let report = coordinator.run(Signal::Terminate);
+        let hook = only_hook(&report);
+        assert!(matches!(hook.failure(), Some(FailureKind::Failed(_))));
+        // Retried after 4 and 8 seconds; the next wait would reach 12.
+        assert_eq!(hook.attempts(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1.0")]
+    fn rejects_a_shrinking_backoff() {
+        RetryPolicy::new(2).multiplier(0.5);
+    }
+
+    #[test]
+    fn does_not_wait_for_a_hook_abandoned_by_an_earlier_run() {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.set_grace_period(Duration::from_millis(50));
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        coordinator.phase(CLOSE).hook("stuck", move |_| {
+            let _ = lock(&released).recv();
+        });
+
+        let report = coordinator.rehearse(Signal::Terminate);
+        assert!(matches!(
+            only_hook(&report).failure(),
+            Some(FailureKind::TimedOut)
+        ));
+        // The first run still holds the hook.
+        let started = Instant::now();
+        let report = coordinator.rehearse(Signal::Terminate);
+        assert!(matches!(
+            only_hook(&report).failure(),
+            Some(FailureKind::TimedOut)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(release);
+    }
+}
@@ -0,0 +1,259 @@
+//! Background job queue workers that drain at shutdown.
+//!
+//! A [JobQueue](struct.JobQueue.html) runs worker threads that claim jobs
+//! from a queue through a [JobRunner](trait.JobRunner.html) and run them.
+//! When the shutdown starts, the workers stop claiming jobs in the
+//! [stop-intake](../hooks/constant.STOP_INTAKE.html) phase; the jobs in
+//! flight are given until the end of the [drain](../hooks/constant.DRAIN.html)
+//! phase to finish, and those that have not are put back on the queue. The
+//! drain hook [counts](../struct.HookReport.html#method.counts) the jobs
+//! `finished`, `requeued` and `lost`, the ones that could not be put back.
+//!
+//! A job put back may still be completed by its abandoned worker, so jobs
+//! are run at least once, and should be safe to run again.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::jobs::{JobQueue, JobRunner};
+//! use graceful::BoxError;
+//!
+//! # struct Redis;
+//! # impl Redis {
+//! #     fn pop(&self, _: Duration) -> Result<Option<String>, BoxError> { Ok(None) }
+//! #     fn push(&self, _: String) -> Result<(), BoxError> { Ok(()) }
+//! # }
+//! struct Emails(Redis);
+//!
+//! impl JobRunner for Emails {
+//!     type Job = String;
+//!
+//!     fn claim(&self) -> Result<Option<String>, BoxError> {
+//!         self.0.pop(Duration::from_secs(1))
+//!     }
+//!
+//!     fn run(&self, address: String) -> Result<(), BoxError> {
+//!         println!("mailing {}", address);
+//!         Ok(())
+//!     }
+//!
+//!     fn requeue(&self, address: String) -> Result<(), BoxError> {
+//!         self.0.push(address)
+//!     }
+//! }
+//!
+//! let _queue = JobQueue::start("emails", Emails(Redis), 4).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::BoxError;
+use hooks::{self, Context};
+use state;
+use sync::{lock, wait, wait_timeout};
+
+/// Claims jobs from a queue and runs them.
+pub trait JobRunner: Send + Sync + 'static {
+    /// A job, cloned so it can be put back while it is running.
+    type Job: Clone + Send + 'static;
+
+    /// The next job, if there is one. It may block for a short while, like
+    /// a long poll, and is not called anymore once the shutdown starts.
+    fn claim(&self) -> Result<Option<Self::Job>, BoxError>;
+
+    fn run(&self, job: Self::Job) -> Result<(), BoxError>;
+
+    /// Put a job that did not finish in time back on the queue.
+    fn requeue(&self, job: Self::Job) -> Result<(), BoxError>;
+}
+
+struct InFlight<J> {
+    jobs: HashMap<usize, J>,
+    /// Jobs finished since the workers stopped claiming.
+    finished: u64,
+}
+
+struct Inner<R: JobRunner> {
+    runner: R,
+    stopping: AtomicBool,
+    in_flight: Mutex<InFlight<R::Job>>,
+    cond: Condvar,
+    next_id: AtomicUsize,
+}
+
+/// Worker threads running jobs until the shutdown, see the
+/// [module](index.html) documentation.
+pub struct JobQueue<R: JobRunner> {
+    inner: Arc<Inner<R>>,
+}
+
+/// How long a worker waits before claiming again when there was no job or
+/// claiming failed.
+const IDLE: Duration = Duration::from_millis(100);
+
+impl<R: JobRunner> JobQueue<R> {
+    /// Start `workers` threads running jobs claimed through `runner`, and
+    /// add the hooks called `name` that drain them.
+    pub fn start(name: &str, runner: R, workers: usize) -> io::Result<JobQueue<R>> {
+        let inner = Arc::new(Inner {
+            runner,
+            stopping: AtomicBool::new(false),
+            in_flight: Mutex::new(InFlight {
+                jobs: HashMap::new(),
+                finished: 0,
+            }),
+            cond: Condvar::new(),
+            next_id: AtomicUsize::new(0),
+        });
+        for i in 0..workers {
+            let inner = inner.clone();
+            thread::Builder::new()
+                .name(format!("{} {}", name, i))
+                .spawn(move || work(&inner))?;
+        }
+        let stop = inner.clone();
+        hooks::phase(hooks::STOP_INTAKE).hook_once(name, move |_: &Context| {
+            stop.stopping.store(true, Ordering::Release);
+        });
+        let drain = inner.clone();
+        hooks::phase(hooks::DRAIN).hook_once(name, move |ctx: &Context| drain_jobs(&drain, ctx));
+        Ok(JobQueue { inner })
+    }
+
+    pub fn runner(&self) -> &R {
+        &self.inner.runner
+    }
+
+    /// How many jobs are running.
+    pub fn in_flight(&self) -> usize {
+        lock(&self.inner.in_flight).jobs.len()
+    }
+}
+
+fn work<R: JobRunner>(inner: &Inner<R>) {
+    while !inner.stopping.load(Ordering::Acquire) && !state::is_shutting_down() {
+        let job = match inner.runner.claim() {
+            Ok(Some(job)) => job,
+            Ok(None) | Err(_) => {
+                thread::sleep(IDLE);
+                continue;
+            }
+        };
+        let id = inner.next_id.fetch_add(1, Ordering::Relaxed);
+        lock(&inner.in_flight).jobs.insert(id, job.clone());
+        // Failed jobs are the runner's business, like retries.
+        let _ = inner.runner.run(job);
+        let mut in_flight = lock(&inner.in_flight);
+        // A job no longer in flight was put back by the drain.
+        if in_flight.jobs.remove(&id).is_some() && inner.stopping.load(Ordering::Acquire) {
+            in_flight.finished += 1;
+        }
+        inner.cond.notify_all();
+    }
+}
+
+fn drain_jobs<R: JobRunner>(inner: &Inner<R>, ctx: &Context) -> Result<(), BoxError> {
+    inner.stopping.store(true, Ordering::Release);
+    let mut in_flight = lock(&inner.in_flight);
+    while !in_flight.jobs.is_empty() {
+        in_flight = match ctx.deadline() {
+            Some(deadline) => {
+                // Leave a moment to put the rest back before the deadline.
+                let cutoff = deadline.checked_sub(IDLE).unwrap_or(deadline);
+                let now = Instant::now();
+                if now >= cutoff {
+                    break;
+                }
+                wait_timeout(&inner.cond, in_flight, cutoff - now)
+            }
+            None => wait(&inner.cond, in_flight),
+        };
+    }
+    let finished = in_flight.finished;
+    let left: Vec<R::Job> = in_flight.jobs.drain().map(|(_, job)| job).collect();
+    drop(in_flight);
+
+    let (mut requeued, mut lost) = (0, 0);
+    for job in left {
+        match inner.runner.requeue(job) {
+            Ok(()) => requeued += 1,
+            Err(_) => lost += 1,
+        }
+    }
+    ctx.count("finished", finished);
+    ctx.count("requeued", requeued);
+    if lost > 0 {
+        ctx.count("lost", lost);
+        return Err(format!("{} jobs could not be requeued", lost).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hooks::{Coordinator, DRAIN};
+    use signal::Signal;
+
+    /// Jobs sleeping for their duration.
+    #[derive(Default)]
+    struct Sleeps {
+        queue: Mutex<Vec<Duration>>,
+        requeued: Mutex<Vec<Duration>>,
+    }
+
+    impl JobRunner for Sleeps {
+        type Job = Duration;
+
+        fn claim(&self) -> Result<Option<Duration>, BoxError> {
+            Ok(lock(&self.queue).pop())
+        }
+
+        fn run(&self, job: Duration) -> Result<(), BoxError> {
+            thread::sleep(job);
+            Ok(())
+        }
+
+        fn requeue(&self, job: Duration) -> Result<(), BoxError> {
+            lock(&self.requeued).push(job);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn requeues_the_jobs_unfinished_at_the_drain_deadline() {
+        let (short, long) = (Duration::from_millis(100), Duration::from_secs(3));
+        let runner = Sleeps::default();
+        lock(&runner.queue).extend([long, short]);
+        let queue = JobQueue::start("sleeps", runner, 2).unwrap();
+        while queue.in_flight() < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let coordinator = Coordinator::with_phases(&[DRAIN]);
+        coordinator.set_grace_period(Duration::from_secs(1));
+        let inner = queue.inner.clone();
+        coordinator
+            .phase(DRAIN)
+            .hook("sleeps", move |ctx: &Context| drain_jobs(&inner, ctx));
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        let hook = report
+            .phases()
+            .flat_map(|phase| phase.hooks())
+            .next()
+            .unwrap();
+        let counts: Vec<(&str, u64)> = hook
+            .counts()
+            .map(|&(ref name, n)| (name.as_str(), n))
+            .collect();
+        assert_eq!(counts, [("finished", 1), ("requeued", 1)]);
+        assert_eq!(*lock(&queue.runner().requeued), [long]);
+        assert_eq!(queue.in_flight(), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//! Structured lifecycle entries for the systemd journal.
+//!
+//! Entries are sent with the native journal protocol, so their fields can be
+//! queried directly:
+//!
+//! ```text
+//! journalctl GRACEFUL_EVENT=shutdown-finished GRACEFUL_RESULT=failed
+//! ```
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! graceful::events::subscribe(graceful::journald::Journald::new().unwrap());
+//! ```
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use events::{Event, Observer};
+
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+const PRIORITY_WARNING: u8 = 4;
+const PRIORITY_NOTICE: u8 = 5;
+
+/// Sends an entry to the journal for every shutdown event, with these
+/// fields besides `MESSAGE` and `PRIORITY`:
+///
+/// * `GRACEFUL_EVENT`: `shutdown-started`, `phase-finished` or
+///   `shutdown-finished`,
+/// * `GRACEFUL_SIGNAL`: the signal that started the shutdown,
+/// * `GRACEFUL_PHASE`: the phase that finished,
+/// * `GRACEFUL_DURATION_MS`: how long the phase or the shutdown took,
+/// * `GRACEFUL_RESULT`: `ok`, or `failed` if any hook failed.
+pub struct Journald {
+    socket: UnixDatagram,
+}
+
+impl Journald {
+    pub fn new() -> io::Result<Journald> {
+        Ok(Journald {
+            socket: UnixDatagram::unbound()?,
+        })
+    }
+
+    fn send(&self, fields: &[(&str, &str)]) -> io::Result<()> {
+        let mut entry = Vec::new();
+        for &(key, value) in fields {
+            append_field(&mut entry, key, value);
+        }
+        self.socket.send_to(&entry, SOCKET).map(drop)
+    }
+}
+
+impl Observer for Journald {
+    /// Errors are ignored, the journal may just not be running.
+    fn on_event(&self, event: &Event) {
+        let _ = match *event {
+            Event::ShutdownStarted { signal, origin } => {
+                let signal = signal.to_string();
+                let message = match origin {
+                    Some(origin) => format!("shutdown started on {} from {}", signal, origin),
+                    None => format!("shutdown started on {}", signal),
+                };
+                self.send(&[
+                    ("MESSAGE", &message),
+                    ("PRIORITY", &PRIORITY_NOTICE.to_string()),
+                    ("GRACEFUL_EVENT", "shutdown-started"),
+                    ("GRACEFUL_SIGNAL", &signal),
+                ])
+            }
+            Event::PhaseFinished(phase) => {
+                let ok = phase.hooks().all(|hook| hook.is_ok());
+                let message = format!("shutdown phase {} finished", phase.name());
+                self.send(&[
+                    ("MESSAGE", &message),
+                    ("PRIORITY", &priority(ok).to_string()),
+                    ("GRACEFUL_EVENT", "phase-finished"),
+                    ("GRACEFUL_PHASE", phase.name()),
+                    ("GRACEFUL_DURATION_MS", &millis(phase.duration())),
+                    ("GRACEFUL_RESULT", result(ok)),
+                ])
+            }
+            Event::ShutdownFinished(report) => {
+                let ok = report.is_clean();
+                let signal = report.signal().to_string();
+                self.send(&[
+                    ("MESSAGE", &report.to_string()),
+                    ("PRIORITY", &priority(ok).to_string()),
+                    ("GRACEFUL_EVENT", "shutdown-finished"),
+                    ("GRACEFUL_SIGNAL", &signal),
+                    ("GRACEFUL_DURATION_MS", &millis(report.duration())),
+                    ("GRACEFUL_RESULT", result(ok)),
+                ])
+            }
+        };
+    }
+}
+
+fn priority(ok: bool) -> u8 {
+    if ok {
+        PRIORITY_NOTICE
+    } else {
+        PRIORITY_WARNING
+    }
+}
+
+fn result(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "failed"
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    duration.as_millis().to_string()
+}
+
+/// `KEY=value\n`, or the length-prefixed form for values spanning lines.
+fn append_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+    entry.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        entry.extend_from_slice(value.as_bytes());
+    } else {
+        entry.push(b'=');
+        entry.extend_from_slice(value.as_bytes());
+    }
+    entry.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiline_values_are_length_prefixed() {
+        let mut entry = Vec::new();
+        append_field(&mut entry, "GRACEFUL_EVENT", "shutdown-started");
+        append_field(&mut entry, "MESSAGE", "two\nlines");
+        let mut expected = b"GRACEFUL_EVENT=shutdown-started\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&[9, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(b"two\nlines\n");
+        assert_eq!(entry, expected);
+    }
+}
@@ -0,0 +1,158 @@
+//! Giving up leadership first thing at shutdown.
+//!
+//! A clustered service holding a lease or a lock, such as an etcd lease or
+//! a ZooKeeper ephemeral node, should let go of it as soon as it starts
+//! shutting down, so another instance takes over right away instead of
+//! after the lease expires. Hooks added with
+//! [relinquish_at_exit](fn.relinquish_at_exit.html) run in the
+//! [RELINQUISH](constant.RELINQUISH.html) phase, which comes before
+//! [stop-intake](../hooks/constant.STOP_INTAKE.html) and every other phase
+//! of the coordinator. Only a [snapshot](../snapshot/index.html) taken at
+//! shutdown runs before it.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # struct Lease;
+//! # impl Lease { fn revoke(self) -> std::io::Result<()> { Ok(()) } }
+//! # let lease = Lease;
+//! graceful::leadership::relinquish_at_exit("etcd lease", move |_| lease.revoke());
+//! ```
+//!
+//! # Consensus
+//!
+//! A member of a Raft or similar group that is the leader when it shuts
+//! down leaves the group without one until an election times out.
+//! [step_down_at_exit](fn.step_down_at_exit.html) transfers leadership to
+//! another member in the same phase, while the network layer is still up, so
+//! the group keeps serving through rolling restarts. Sockets of the
+//! consensus transport should not be [registered](../net/index.html) to be
+//! shut down when termination starts, as that happens before any phase.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::hooks::Context;
+//! use graceful::leadership::{self, StepDown};
+//! use graceful::BoxError;
+//!
+//! # struct Node;
+//! # impl Node {
+//! #     fn is_leader(&self) -> bool { true }
+//! #     fn transfer_leader(&self) -> Result<(), BoxError> { Ok(()) }
+//! # }
+//! struct Member(Node);
+//!
+//! impl StepDown for Member {
+//!     fn is_leader(&self) -> bool {
+//!         self.0.is_leader()
+//!     }
+//!
+//!     fn step_down(&mut self, _: &Context) -> Result<(), BoxError> {
+//!         self.0.transfer_leader()
+//!     }
+//! }
+//!
+//! leadership::step_down_at_exit("raft", Member(Node));
+//! ```
+//!
+//! The hooks run once, so they are left out of
+//! [rehearsals](../hooks/struct.Coordinator.html#method.rehearse). Give the
+//! phase a [budget](../hooks/struct.Phase.html#method.budget) to bound how
+//! long releasing may take.
+
+use error::{BoxError, IntoResult};
+use hooks::{self, Context, Phase};
+
+/// The phase leadership is given up in.
+pub const RELINQUISH: &str = "relinquish";
+
+/// The [RELINQUISH](constant.RELINQUISH.html) phase of the global
+/// [coordinator](../hooks/fn.coordinator.html), added before
+/// [stop-intake](../hooks/constant.STOP_INTAKE.html) if needed.
+pub fn phase() -> Phase<'static> {
+    hooks::coordinator().phase_before(RELINQUISH, hooks::STOP_INTAKE)
+}
+
+/// Release a lease or lock with `f` as soon as the shutdown starts.
+///
+/// The hook may return `()` or any `Result<(), E>`.
+pub fn relinquish_at_exit<F, R>(name: &str, f: F)
+where
+    F: FnOnce(&Context) -> R + Send + 'static,
+    R: IntoResult,
+{
+    phase().hook_once(name, f);
+}
+
+/// A member of a consensus group that can hand its leadership over.
+pub trait StepDown: Send {
+    fn is_leader(&self) -> bool;
+
+    /// Transfer leadership to another member, returning once it has taken
+    /// over. The [deadline](../hooks/struct.Context.html#method.deadline)
+    /// of `ctx` tells how long that may take.
+    fn step_down(&mut self, ctx: &Context) -> Result<(), BoxError>;
+}
+
+/// Transfer leadership away from `member` when the shutdown starts, if it
+/// is the leader by then.
+pub fn step_down_at_exit<S>(name: &str, mut member: S)
+where
+    S: StepDown + 'static,
+{
+    phase().hook_once(name, move |ctx: &Context| step_down(&mut member, ctx));
+}
+
+fn step_down<S: StepDown>(member: &mut S, ctx: &Context) -> Result<(), BoxError> {
+    if member.is_leader() {
+        member.step_down(ctx)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use hooks::Coordinator;
+    use signal::Signal;
+    use sync::lock;
+
+    struct Member {
+        leader: bool,
+        transfers: Arc<Mutex<usize>>,
+    }
+
+    impl StepDown for Member {
+        fn is_leader(&self) -> bool {
+            self.leader
+        }
+
+        fn step_down(&mut self, _: &Context) -> Result<(), BoxError> {
+            *lock(&self.transfers) += 1;
+            Ok(())
+        }
+    }
+
+    fn transfers(leader: bool) -> usize {
+        let transfers = Arc::new(Mutex::new(0));
+        let mut member = Member {
+            leader,
+            transfers: transfers.clone(),
+        };
+        let coordinator = Coordinator::with_phases(&[RELINQUISH]);
+        coordinator
+            .phase(RELINQUISH)
+            .hook_once("raft", move |ctx: &Context| step_down(&mut member, ctx));
+        assert!(coordinator.run(Signal::Terminate).is_clean());
+        let transfers = *lock(&transfers);
+        transfers
+    }
+
+    #[test]
+    fn only_the_leader_steps_down() {
+        assert_eq!(transfers(true), 1);
+        assert_eq!(transfers(false), 0);
+    }
+}
@@ -15,123 +15,226 @@
 //! 4. Register a handle to properly shutdown the application.
 //! 5. The main thread will be blocked until a signal is received.
 //! 6. The handler will run in the main thread.
-//! 7. On Windows the process will terminate after the handler returns (and
-//!    potentially any libc `atexit` handlers).
+//! 7. On Windows closing the console window is handled as well, and so are
+//!    logging off and shutting the system down in services. The program
+//!    goes on after the handler as on Unix, and the system waits for it to
+//!    exit until its time limit runs out.
 //!
 //! # Example
 //!
 //! ```no_run
 //! extern crate graceful;
 //!
-//! use std::sync::atomic::{ATOMIC_BOOL_INIT, AtomicBool, Ordering};
 //! use std::time::Duration;
 //! use std::thread;
 //!
 //! use graceful::SignalGuard;
 //!
-//! static STOP: AtomicBool = ATOMIC_BOOL_INIT;
-//!
 //! fn main() {
 //!     let signal_guard = SignalGuard::new();
+//!     let token = signal_guard.token();
 //!
-//! 	let handle = thread::spawn(|| {
+//!     let handle = thread::spawn(move || {
 //!         println!("Worker thread started. Type Ctrl+C to stop.");
-//!         while !STOP.load(Ordering::Acquire) {
+//!         while !token.wait_timeout(Duration::from_millis(500)) {
 //!             println!("working...");
-//!             thread::sleep(Duration::from_millis(500));
 //!         }
 //!         println!("Bye.");
 //!     });
 //!
-//! 	signal_guard.at_exit(move |sig| {
+//!     signal_guard.at_exit(move |sig| {
 //!         println!("Signal {} received.", sig);
-//!         STOP.store(true, Ordering::Release);
 //!         handle.join().unwrap();
 //!     });
 //! }
 //! ```
 //!
+//! # Features
+//!
+//! * `async`: await the shutdown inside an async runtime with
+//!   [SignalGuard::wait_async](struct.SignalGuard.html#method.wait_async).
+//! * `journald` (Unix): send structured entries to the systemd journal with
+//!   [journald::Journald](journald/struct.Journald.html).
+//! * `quic`: close `quinn` connections at shutdown with
+//!   [quic::drain_at_exit](quic/fn.drain_at_exit.html).
+//! * `scripting`: add shutdown hooks written in Rhai with
+//!   [script::load_dir](script/fn.load_dir.html).
+//! * `signal-safety-audit` (Unix): abort on allocations and locks inside
+//!   signal handlers, see [signal_safety](signal_safety/index.html).
+//! * `sim`: build the `shutdown-sim` example, which runs dummy hooks from a
+//!   policy file and takes signals typed on stdin.
+//! * `static-hooks`: register shutdown hooks at link time with
+//!   [`#[graceful::hook]`](attr.hook.html).
+//! * `syslog` (Unix): report the shutdown to the system log with
+//!   [syslog::Syslog](syslog/struct.Syslog.html).
+//! * `systemd` (Unix): tell systemd `STOPPING=1` and ping its watchdog, see
+//!   [systemd](systemd/index.html).
+//! * `testing`: deliver signals from tests without sending them, with
+//!   [SignalGuard::trigger](struct.SignalGuard.html#method.trigger).
+//! * `tokio`: stop async servers with a `CancellationToken` from
+//!   [SignalGuard::cancellation_token](struct.SignalGuard.html#method.cancellation_token),
+//!   and wait for tasks with
+//!   [ShutdownHandle::spawn_tracked](struct.ShutdownHandle.html#method.spawn_tracked).
+//! * `tracing-flame`: flush a `tracing_flame::FlushGuard` with
+//!   [flush::at_exit](flush/fn.at_exit.html).
+//! * `webhook`: post shutdown notifications as JSON with
+//!   [notify::Webhook](notify/struct.Webhook.html).
+//! * `websocket`: send Close frames to `tungstenite` sessions at shutdown with
+//!   [websocket::register](websocket/fn.register.html).
+//! * `windows-service` (Windows): shut down on the stop requests of the
+//!   service control manager with
+//!   [service::Service](service/struct.Service.html).
+//!
 
+#[cfg(feature = "static-hooks")]
+extern crate graceful_macros;
 #[cfg(unix)]
-mod platform {
-    extern crate nix;
-    use self::nix::sys::signal::{SigSet, SIGINT, SIGQUIT, SIGTERM};
-
-    pub struct SignalGuard(SigSet);
-
-    impl SignalGuard {
-        /// Block necessary signals (`SIGINT`, `SIGQUIT` and `SIGTERM` on *nix,
-        /// `Ctrl+C` and `Ctrl+Break` on Windows).
-        ///
-        /// New threads should be spawned after this.
-        pub fn new() -> SignalGuard {
-            let mut mask = SigSet::empty();
-            SignalGuard::init(&mut mask).unwrap();
-            SignalGuard(mask)
-        }
-
-        fn init(mask: &mut SigSet) -> nix::Result<()> {
-            mask.add(SIGINT);
-            mask.add(SIGQUIT);
-            mask.add(SIGTERM);
-            mask.thread_block()
-        }
-
-        /// Block the running thread until a signal is received. Then the
-        /// `handler` will be called in the main thread.
-        ///
-        /// Do not put any code after this.
-        pub fn at_exit<F: FnOnce(usize)>(&self, handler: F) {
-            let sig = self.0.wait().unwrap();
-            handler(sig as usize);
-        }
-    }
-}
-
-#[cfg(windows)]
+extern crate libc;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "quic")]
+extern crate quinn;
+#[cfg(feature = "scripting")]
+extern crate rhai;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate tokio_util;
+#[cfg(feature = "tracing-flame")]
+extern crate tracing_flame;
+#[cfg(feature = "websocket")]
+extern crate tungstenite;
+#[cfg(feature = "webhook")]
+extern crate ureq;
 
+pub mod audit;
+pub mod channel;
+pub mod checkpoint;
+pub mod clock;
+mod defer;
+pub mod device;
+pub mod dns;
+pub mod embedded;
+mod error;
 #[cfg(windows)]
-mod platform {
-    extern crate winapi;
-
-    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-    use std::sync::Mutex;
-
-    use self::winapi::shared::minwindef::{BOOL, DWORD, TRUE};
-    use self::winapi::um::consoleapi::SetConsoleCtrlHandler;
-
-    lazy_static! {
-        static ref CHAN: (SyncSender<DWORD>, Mutex<Receiver<DWORD>>) = {
-            let channel = sync_channel(0);
-            (channel.0, Mutex::new(channel.1))
-        };
-    }
+pub mod eventlog;
+pub mod events;
+mod executor;
+pub mod exit;
+pub mod export;
+pub mod flush;
+#[cfg(feature = "async")]
+mod future;
+mod group;
+mod guard;
+mod handle;
+pub mod hooks;
+pub mod jobs;
+#[cfg(all(unix, feature = "journald"))]
+pub mod journald;
+pub mod leadership;
+pub mod marker;
+#[cfg(unix)]
+pub mod mmap;
+mod nested;
+pub mod net;
+pub mod notify;
+pub mod outbound;
+mod panic_hook;
+pub mod park;
+mod priority;
+#[cfg(unix)]
+pub mod process;
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod quiesce;
+#[cfg(unix)]
+mod reaper;
+mod report;
+#[cfg(feature = "tokio")]
+mod rt;
+pub mod schedule;
+mod scoped;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(unix)]
+mod sd_notify;
+#[cfg(all(windows, feature = "windows-service"))]
+pub mod service;
+pub mod session;
+mod signal;
+#[cfg(all(unix, feature = "signal-safety-audit"))]
+pub mod signal_safety;
+pub mod snapshot;
+pub mod startup;
+mod state;
+mod sync;
+#[cfg(all(unix, feature = "syslog"))]
+pub mod syslog;
+#[cfg(all(unix, feature = "systemd"))]
+pub mod systemd;
+#[cfg(feature = "testing")]
+mod testing;
+pub mod thread;
+mod timer;
+mod token;
+pub mod transaction;
+#[cfg(unix)]
+pub mod trigger;
+#[cfg(unix)]
+pub mod wakeup;
+pub mod watch;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
-    unsafe extern "system" fn handler(event: DWORD) -> BOOL {
-        CHAN.0.send(event).unwrap();
-        CHAN.0.send(0).unwrap();
-        TRUE
-    }
+#[cfg(unix)]
+#[path = "unix.rs"]
+mod platform;
+#[cfg(windows)]
+#[path = "windows.rs"]
+mod platform;
 
-    pub struct SignalGuard;
+pub use defer::DeferGuard;
+pub use error::{BoxError, Error, ErrorKind, Failure, FailureKind, IntoResult, ShutdownErrors};
+pub use exit::atexit;
+#[cfg(feature = "async")]
+pub use future::ShutdownFuture;
+pub use group::ProcessGroupGuard;
+pub use guard::{Flow, SignalGuard, SignalGuardBuilder};
+pub use handle::{ShutdownHandle, Signals};
+pub use marker::previous_exit_was_unclean;
+pub use nested::NestedGuard;
+#[cfg(unix)]
+pub use reaper::ChildReaper;
+pub use report::{Cause, HookReport, PhaseReport, ShutdownOutcome, ShutdownReport};
+pub use scoped::{run, ExitReason};
+pub use signal::{ConsoleEvent, Origin, Signal};
+pub use state::is_shutting_down;
+pub use timer::{Interval, Sleep, Tick};
+pub use token::{shutdown_phase, ShutdownNotifier, ShutdownPhase, ShutdownToken};
 
-    impl SignalGuard {
-        pub fn new() -> SignalGuard {
-            unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) };
-            SignalGuard
-        }
+/// Register a free function as a shutdown hook at link time, so library
+/// crates can contribute hooks without access to the
+/// [SignalGuard](struct.SignalGuard.html).
+///
+/// The function takes no arguments or the received
+/// [Signal](enum.Signal.html). It joins the given `phase` (default
+/// [close](hooks/constant.CLOSE.html)) of the global
+/// [coordinator](hooks/fn.coordinator.html); within the phase hooks with a
+/// higher `priority` (default `0`) run first. Requires the `static-hooks`
+/// feature.
+///
+/// ```ignore
+/// #[graceful::hook(phase = "flush", priority = 10)]
+/// fn flush_metrics(signal: graceful::Signal) {
+///     println!("flushing metrics on {}", signal);
+/// }
+/// ```
+#[cfg(feature = "static-hooks")]
+pub use graceful_macros::hook;
 
-        pub fn at_exit<F: FnOnce(usize)>(&self, handler: F) {
-            let event = {
-                let receiver = CHAN.1.lock().unwrap();
-                receiver.recv().unwrap()
-            };
-            handler(event as usize);
-            CHAN.1.lock().unwrap().recv().unwrap();
-        }
-    }
+#[cfg(feature = "static-hooks")]
+#[doc(hidden)]
+pub mod __private {
+    pub extern crate inventory;
 }
-
-pub use platform::SignalGuard;
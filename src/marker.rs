@@ -0,0 +1,111 @@
+//! Noticing on startup that the last shutdown did not finish.
+//!
+//! Once [enabled](fn.enable.html), a marker file is written when the
+//! shutdown starts and removed once every hook has completed. A marker
+//! found at the next startup means that shutdown was killed, crashed or had
+//! hooks fail, and [previous_exit_was_unclean](../fn.previous_exit_was_unclean.html)
+//! tells the program to take its recovery path, such as replaying a journal
+//! or checking an index:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # fn recover() {}
+//! use graceful::SignalGuard;
+//!
+//! let signal_guard = SignalGuard::new();
+//! graceful::marker::enable("/var/lib/app/shutdown.marker").unwrap();
+//! if graceful::previous_exit_was_unclean() {
+//!     recover();
+//! }
+//! signal_guard.at_exit(|_| {});
+//! ```
+//!
+//! A process killed before its shutdown started leaves no marker.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use checkpoint::write_atomically;
+use report::ShutdownReport;
+use signal::Signal;
+use sync::lock;
+
+static UNCLEAN: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Keep the marker at `path`, and check for one left by the last run.
+///
+/// Fails if the existing marker cannot be inspected; the check is not
+/// repeated by calling this again.
+pub fn enable<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    match fs::metadata(path) {
+        Ok(_) => UNCLEAN.store(true, Ordering::Relaxed),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    *lock(&PATH) = Some(path.to_owned());
+    Ok(())
+}
+
+/// Whether the [marker](marker/index.html) of the last run was still there
+/// when it was enabled. Always false until then.
+pub fn previous_exit_was_unclean() -> bool {
+    UNCLEAN.load(Ordering::Relaxed)
+}
+
+/// Write the marker, if enabled. A marker that cannot be written is not a
+/// reason to stop the shutdown.
+pub(crate) fn write(signal: Signal) {
+    if let Some(ref path) = *lock(&PATH) {
+        let _ = write_atomically(path, |out| {
+            writeln!(out, "pid {} shutting down on {}", process::id(), signal)?;
+            Ok(())
+        });
+    }
+}
+
+/// Remove the marker, if enabled, when `report` is clean.
+pub(crate) fn finish(report: &ShutdownReport) {
+    if !report.is_clean() {
+        return;
+    }
+    if let Some(ref path) = *lock(&PATH) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use hooks::{Coordinator, CLOSE};
+
+    #[test]
+    fn the_marker_stays_after_an_unclean_shutdown() {
+        let path = env::temp_dir().join(format!("graceful-{}.marker", process::id()));
+        enable(&path).unwrap();
+        assert!(!previous_exit_was_unclean());
+
+        write(Signal::Terminate);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(&format!("pid {} shutting down", process::id())));
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.phase(CLOSE).hook("fails", |_| Err("disk full"));
+        finish(&coordinator.run(Signal::Terminate));
+        assert!(path.exists());
+
+        enable(&path).unwrap();
+        assert!(previous_exit_was_unclean());
+        finish(&Coordinator::with_phases(&[CLOSE]).run(Signal::Terminate));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,177 @@
+//! Memory-mapped regions written back at shutdown.
+//!
+//! Dirty pages of a shared file mapping reach the file eventually, but a
+//! process killed right after writing them gives no guarantee about when,
+//! and one that crashes the machine loses them. Regions registered here are
+//! `msync`'d in the [FLUSH](../hooks/constant.FLUSH.html) phase, and those
+//! registered with [unmap](fn.register.html) are `munmap`'d in the
+//! [CLOSE](../hooks/constant.CLOSE.html) phase.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # extern crate libc;
+//! use std::fs::OpenOptions;
+//! use std::os::unix::io::AsRawFd;
+//! use std::ptr;
+//!
+//! let file = OpenOptions::new().read(true).write(true).open("data.bin").unwrap();
+//! let len = file.metadata().unwrap().len() as usize;
+//! let addr = unsafe {
+//!     libc::mmap(
+//!         ptr::null_mut(),
+//!         len,
+//!         libc::PROT_READ | libc::PROT_WRITE,
+//!         libc::MAP_SHARED,
+//!         file.as_raw_fd(),
+//!         0,
+//!     )
+//! };
+//! assert_ne!(addr, libc::MAP_FAILED);
+//! let _registration = unsafe { graceful::mmap::register(addr, len, true) };
+//! ```
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+
+use libc;
+
+use hooks::{self, Context};
+use sync::lock;
+
+struct Region {
+    id: usize,
+    // Addresses are kept as integers so the registry is `Send`.
+    addr: usize,
+    len: usize,
+    unmap: bool,
+}
+
+lazy_static! {
+    /// In registration order, which is the order they are synced in.
+    static ref REGIONS: Mutex<Vec<Region>> = Mutex::new(Vec::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+static HOOKS: Once = Once::new();
+
+/// Keeps a region registered until dropped.
+#[derive(Debug)]
+#[must_use = "the region is unregistered when this is dropped"]
+pub struct Registration(usize);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock(&REGIONS).retain(|region| region.id != self.0);
+    }
+}
+
+/// Sync the mapping of `len` bytes at `addr` to its file at shutdown, and
+/// unmap it too if `unmap` is set.
+///
+/// # Safety
+///
+/// `addr` and `len` must describe a region returned by `mmap`. If `unmap`
+/// is set, nothing may use the region once the
+/// [CLOSE](../hooks/constant.CLOSE.html) phase has started; otherwise the
+/// region must stay mapped until the registration is dropped.
+pub unsafe fn register(addr: *mut libc::c_void, len: usize, unmap: bool) -> Registration {
+    HOOKS.call_once(|| {
+        hooks::phase(hooks::FLUSH).hook("graceful: msync", |_: &Context| sync_regions());
+        hooks::phase(hooks::CLOSE).hook_once("graceful: munmap", |_: &Context| unmap_regions());
+    });
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&REGIONS).push(Region {
+        id,
+        addr: addr as usize,
+        len,
+        unmap,
+    });
+    Registration(id)
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Sync every region, failing with the first error once all have been
+/// tried.
+fn sync_regions() -> io::Result<()> {
+    let mut result = Ok(());
+    for region in lock(&REGIONS).iter() {
+        let synced = check(unsafe {
+            libc::msync(region.addr as *mut libc::c_void, region.len, libc::MS_SYNC)
+        });
+        if result.is_ok() {
+            result = synced;
+        }
+    }
+    result
+}
+
+/// Unmap the regions that asked for it, unregistering them so they are
+/// never unmapped twice.
+fn unmap_regions() -> io::Result<()> {
+    let mut result = Ok(());
+    lock(&REGIONS).retain(|region| {
+        if !region.unmap {
+            return true;
+        }
+        let unmapped = check(unsafe { libc::munmap(region.addr as *mut libc::c_void, region.len) });
+        if result.is_ok() {
+            result = unmapped;
+        }
+        false
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+    use std::process;
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn syncs_then_unmaps_the_region() {
+        let path = env::temp_dir().join(format!("graceful-mmap-{}.bin", process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let len = 4096;
+        file.set_len(len as u64).unwrap();
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        assert_ne!(addr, libc::MAP_FAILED);
+        let _registration = unsafe { register(addr, len, true) };
+        unsafe { ptr::copy_nonoverlapping(b"dirty".as_ptr(), addr as *mut u8, 5) };
+
+        sync_regions().unwrap();
+        assert_eq!(&fs::read(&path).unwrap()[..5], b"dirty");
+        unmap_regions().unwrap();
+        // Unregistered, so it is never unmapped twice.
+        assert!(lock(&REGIONS).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
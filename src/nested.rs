@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use signal::Signal;
+use sync::{lock, wait, wait_timeout};
+
+struct Layer {
+    signals: Vec<Signal>,
+    received: Mutex<VecDeque<Signal>>,
+    cond: Condvar,
+}
+
+lazy_static! {
+    static ref STACK: Mutex<Vec<Arc<Layer>>> = Mutex::new(Vec::new());
+}
+
+/// A temporary policy on top of the [SignalGuard](struct.SignalGuard.html).
+///
+/// While it is alive, its signals are delivered to it instead of starting
+/// the shutdown; signals it does not list keep their outer meaning. Guards
+/// nest as a stack, the innermost one listing a signal gets it, and dropping
+/// a guard restores the policy that was in place before it.
+///
+/// Signals are only received while the outer guard is waiting in
+/// [at_exit](struct.SignalGuard.html#method.at_exit), so the code using the
+/// nested guard runs on another thread:
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use std::io::{self, BufRead};
+/// use std::thread;
+///
+/// use graceful::{NestedGuard, Signal, SignalGuard};
+///
+/// let signal_guard = SignalGuard::new();
+///
+/// let prompt = thread::spawn(|| {
+///     // Ctrl+C cancels the prompt instead of the program.
+///     let nested = NestedGuard::new(&[Signal::Interrupt]);
+///     let stdin = io::stdin();
+///     for line in stdin.lock().lines() {
+///         if nested.try_recv().is_some() {
+///             println!("prompt cancelled");
+///             break;
+///         }
+///         println!("> {}", line.unwrap());
+///     }
+/// });
+///
+/// signal_guard.at_exit(move |_| {
+///     let _ = prompt.join();
+/// });
+/// ```
+pub struct NestedGuard {
+    layer: Arc<Layer>,
+}
+
+impl NestedGuard {
+    /// Push a guard that receives `signals` until dropped.
+    pub fn new(signals: &[Signal]) -> NestedGuard {
+        let layer = Arc::new(Layer {
+            signals: signals.to_vec(),
+            received: Mutex::new(VecDeque::new()),
+            cond: Condvar::new(),
+        });
+        lock(&STACK).push(layer.clone());
+        NestedGuard { layer }
+    }
+
+    /// The next signal received, if any, without blocking.
+    pub fn try_recv(&self) -> Option<Signal> {
+        lock(&self.layer.received).pop_front()
+    }
+
+    /// Block until a signal is received.
+    pub fn recv(&self) -> Signal {
+        let mut received = lock(&self.layer.received);
+        loop {
+            if let Some(signal) = received.pop_front() {
+                return signal;
+            }
+            received = wait(&self.layer.cond, received);
+        }
+    }
+
+    /// Block until a signal is received or `timeout` has elapsed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Signal> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut received = lock(&self.layer.received);
+        loop {
+            if let Some(signal) = received.pop_front() {
+                return Some(signal);
+            }
+            received = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    wait_timeout(&self.layer.cond, received, deadline - now)
+                }
+                None => wait(&self.layer.cond, received),
+            };
+        }
+    }
+}
+
+impl Drop for NestedGuard {
+    fn drop(&mut self) {
+        lock(&STACK).retain(|layer| !Arc::ptr_eq(layer, &self.layer));
+    }
+}
+
+/// Hand `signal` to the innermost nested guard listing it. Returns `false`
+/// if there is none, and the signal keeps its outer meaning.
+pub(crate) fn claim(signal: Signal) -> bool {
+    let stack = lock(&STACK);
+    match stack
+        .iter()
+        .rev()
+        .find(|layer| layer.signals.contains(&signal))
+    {
+        Some(layer) => {
+            lock(&layer.received).push_back(signal);
+            layer.cond.notify_all();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn the_innermost_guard_listing_a_signal_gets_it() {
+        let outer = NestedGuard::new(&[Signal::Hangup, Signal::User1]);
+        let inner = NestedGuard::new(&[Signal::User1]);
+        assert!(claim(Signal::User1));
+        assert!(claim(Signal::Hangup));
+        assert_eq!(inner.try_recv(), Some(Signal::User1));
+        assert_eq!(inner.try_recv(), None);
+        assert_eq!(outer.try_recv(), Some(Signal::Hangup));
+
+        drop(inner);
+        assert!(claim(Signal::User1));
+        assert_eq!(outer.try_recv(), Some(Signal::User1));
+        drop(outer);
+        assert!(!claim(Signal::User1));
+    }
+
+    #[test]
+    fn receivers_wait_for_a_signal() {
+        let nested = NestedGuard::new(&[Signal::User2]);
+        assert_eq!(nested.recv_timeout(Duration::from_millis(10)), None);
+        let sender = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(20));
+            assert!(claim(Signal::User2));
+        });
+        assert_eq!(nested.recv(), Signal::User2);
+        sender.join().unwrap();
+        assert!(claim(Signal::User2));
+        assert_eq!(nested.recv_timeout(Duration::MAX), Some(Signal::User2));
+    }
+}
@@ -0,0 +1,507 @@
+//! Sockets shut down when termination starts.
+//!
+//! # Streams
+//!
+//! A thread blocked in `read()` on a socket does not notice the shutdown
+//! flag until data arrives. Streams registered here are shut down in both
+//! directions as soon as the signal is received, before any hook runs, so
+//! those reads return and the threads can leave their loops.
+//!
+//! Shutting down both directions at once may leave data the peer was still
+//! sending unread, which makes the kernel answer with a RST when the socket
+//! is closed. Use [DrainOptions](struct.DrainOptions.html) to half-close
+//! streams instead: the peer is sent a FIN, and once it closes its side
+//! too, the reads return; streams still open in the
+//! [CLOSE](../hooks/constant.CLOSE.html) phase are shut down in both
+//! directions. The options can also make closing the socket wait for
+//! unsent data with `SO_LINGER`.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::io::Read;
+//! use std::net::TcpStream;
+//!
+//! let mut stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+//! let _registration = graceful::net::register(&stream).unwrap();
+//! let mut buf = [0; 1024];
+//! while stream.read(&mut buf).unwrap() > 0 {
+//!     // ...
+//! }
+//! ```
+//!
+//! # Datagrams
+//!
+//! Datagram sockets have no stream to shut down, so a thread blocked in
+//! `recv()` is woken by an empty datagram sent to the socket instead; its
+//! loop has to check [is_shutting_down](../fn.is_shutting_down.html) after
+//! each datagram. Later, pending outbound packets are flushed in the
+//! [FLUSH](../hooks/constant.FLUSH.html) phase, and the sockets are closed
+//! in the order they were registered in the
+//! [CLOSE](../hooks/constant.CLOSE.html) phase.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::net::UdpSocket;
+//!
+//! let socket = UdpSocket::bind("0.0.0.0:5353").unwrap();
+//! let _registration = graceful::net::register_datagram(&socket).unwrap();
+//! let mut buf = [0; 1500];
+//! while let Ok((len, from)) = socket.recv_from(&mut buf) {
+//!     if graceful::is_shutting_down() {
+//!         break;
+//!     }
+//!     # let _ = (len, from);
+//!     // ...
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{self, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+#[cfg(unix)]
+use libc;
+
+use error::{BoxError, IntoResult};
+use hooks::{self, Context};
+use sync::lock;
+
+/// A stream that can be shut down from another thread.
+pub trait Socket: Send {
+    /// A second handle to the same socket, kept by the registry.
+    fn try_clone_socket(&self) -> io::Result<Box<dyn Socket>>;
+
+    /// Shut down both directions.
+    fn shutdown(&self) -> io::Result<()>;
+
+    /// Stop sending, so the peer reads the end of the stream. Shuts
+    /// down both directions unless implemented.
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown()
+    }
+
+    /// Set `SO_LINGER`. Does nothing unless implemented.
+    fn set_linger(&self, _linger: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Socket for TcpStream {
+    fn try_clone_socket(&self) -> io::Result<Box<dyn Socket>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, net::Shutdown::Both)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, net::Shutdown::Write)
+    }
+
+    /// Only on Unix.
+    fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            let value = libc::linger {
+                l_onoff: linger.is_some() as libc::c_int,
+                l_linger: linger.map_or(0, |linger| linger.as_secs().min(libc::c_int::MAX as u64))
+                    as libc::c_int,
+            };
+            let ret = unsafe {
+                libc::setsockopt(
+                    self.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_LINGER,
+                    &value as *const libc::linger as *const libc::c_void,
+                    ::std::mem::size_of::<libc::linger>() as libc::socklen_t,
+                )
+            };
+            if ret != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = linger;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixStream {
+    fn try_clone_socket(&self) -> io::Result<Box<dyn Socket>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, net::Shutdown::Both)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, net::Shutdown::Write)
+    }
+}
+
+/// How registered streams are shut down.
+///
+/// By default they are shut down in both directions when termination
+/// starts, and `SO_LINGER` is left alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DrainOptions {
+    half_close: bool,
+    linger: Option<Option<Duration>>,
+}
+
+impl DrainOptions {
+    pub fn new() -> DrainOptions {
+        DrainOptions::default()
+    }
+
+    /// Whether to half-close streams when termination starts, and shut
+    /// them down in both directions in the
+    /// [CLOSE](../hooks/constant.CLOSE.html) phase. Blocked reads then
+    /// return once the peer closes its side, rather than at once, so no
+    /// data it was still sending is left unread.
+    pub fn half_close(mut self, half_close: bool) -> DrainOptions {
+        self.half_close = half_close;
+        self
+    }
+
+    /// Set `SO_LINGER` when termination starts, so closing the socket
+    /// waits up to `linger` for unsent data to be delivered. A zero
+    /// `linger` makes the close send a RST, `None` turns lingering off.
+    /// Only TCP streams on Unix support it.
+    pub fn linger(mut self, linger: Option<Duration>) -> DrainOptions {
+        self.linger = Some(linger);
+        self
+    }
+}
+
+struct StreamEntry {
+    socket: Box<dyn Socket>,
+    options: DrainOptions,
+}
+
+lazy_static! {
+    static ref SOCKETS: Mutex<HashMap<usize, StreamEntry>> = Mutex::new(HashMap::new());
+    static ref DEFAULT_OPTIONS: Mutex<DrainOptions> = Mutex::new(DrainOptions::default());
+}
+
+static STREAM_HOOKS: Once = Once::new();
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps a socket registered until dropped.
+///
+/// The registry holds a duplicate of the socket's descriptor, so drop this
+/// when the connection is done with, or the socket stays open.
+#[derive(Debug)]
+#[must_use = "the socket is unregistered when this is dropped"]
+pub struct Registration(usize);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock(&SOCKETS).remove(&self.0);
+    }
+}
+
+/// Shut `socket` down when termination starts, with the
+/// [default options](fn.set_drain_options.html).
+pub fn register<S: Socket>(socket: &S) -> io::Result<Registration> {
+    let options = *lock(&DEFAULT_OPTIONS);
+    register_with(socket, options)
+}
+
+/// Like [register](fn.register.html), with `options` for this socket.
+pub fn register_with<S: Socket>(socket: &S, options: DrainOptions) -> io::Result<Registration> {
+    let socket = socket.try_clone_socket()?;
+    STREAM_HOOKS.call_once(|| {
+        hooks::phase(hooks::CLOSE)
+            .hook_once("graceful: stream close", |_: &Context| close_streams());
+    });
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&SOCKETS).insert(id, StreamEntry { socket, options });
+    Ok(Registration(id))
+}
+
+/// The options of the streams registered with
+/// [register](fn.register.html) from now on.
+pub fn set_drain_options(options: DrainOptions) {
+    *lock(&DEFAULT_OPTIONS) = options;
+}
+
+/// Shut down the streams left half-closed. Peers that already closed are
+/// not an error.
+fn close_streams() {
+    for entry in lock(&SOCKETS).values() {
+        if entry.options.half_close {
+            let _ = entry.socket.shutdown();
+        }
+    }
+}
+
+/// A datagram socket whose receivers can be woken from another thread.
+pub trait Datagram: Send {
+    /// A second handle to the same socket, kept by the registry.
+    fn try_clone_datagram(&self) -> io::Result<Box<dyn Datagram>>;
+
+    /// Send an empty datagram to this socket, so a thread blocked receiving
+    /// on it returns.
+    fn wake(&self) -> io::Result<()>;
+
+    /// Stop sending and receiving on the socket, where the platform allows.
+    fn close(&self) -> io::Result<()>;
+}
+
+impl Datagram for UdpSocket {
+    fn try_clone_datagram(&self) -> io::Result<Box<dyn Datagram>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn wake(&self) -> io::Result<()> {
+        let mut addr = self.local_addr()?;
+        let unspecified = match addr {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let sender = UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+        sender.send_to(&[], addr).map(drop)
+    }
+
+    /// Shuts the shared socket down on Unix, so sends fail and receives
+    /// return at once. Nothing can be done through a duplicate on Windows.
+    fn close(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            // Unconnected sockets report `ENOTCONN`, but are shut down all
+            // the same.
+            if unsafe { libc::shutdown(self.as_raw_fd(), libc::SHUT_RDWR) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ENOTCONN) {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Datagram for UnixDatagram {
+    fn try_clone_datagram(&self) -> io::Result<Box<dyn Datagram>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    /// Only sockets bound to a path can be woken.
+    fn wake(&self) -> io::Result<()> {
+        match self.local_addr()?.as_pathname() {
+            Some(path) => UnixDatagram::unbound()?.send_to(&[], path).map(drop),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&self) -> io::Result<()> {
+        UnixDatagram::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+type FlushFn = Box<dyn FnMut() -> Result<(), BoxError> + Send>;
+
+struct DatagramEntry {
+    id: usize,
+    socket: Box<dyn Datagram>,
+    flush: Option<FlushFn>,
+}
+
+lazy_static! {
+    /// In registration order, which is the order they are closed in.
+    static ref DATAGRAMS: Mutex<Vec<DatagramEntry>> = Mutex::new(Vec::new());
+}
+
+static DATAGRAM_HOOKS: Once = Once::new();
+
+/// Keeps a datagram socket registered until dropped.
+///
+/// As for [Registration](struct.Registration.html), the registry holds a
+/// duplicate of the socket's descriptor.
+#[derive(Debug)]
+#[must_use = "the socket is unregistered when this is dropped"]
+pub struct DatagramRegistration(usize);
+
+impl Drop for DatagramRegistration {
+    fn drop(&mut self) {
+        lock(&DATAGRAMS).retain(|entry| entry.id != self.0);
+    }
+}
+
+/// Stop the receive loop of `socket` when termination starts and close it
+/// at the end of the shutdown, see [Datagrams](index.html#datagrams).
+pub fn register_datagram<S: Datagram>(socket: &S) -> io::Result<DatagramRegistration> {
+    add_datagram(socket, None)
+}
+
+/// Like [register_datagram](fn.register_datagram.html), calling `flush` to
+/// send pending outbound packets before the socket is closed.
+pub fn register_datagram_with_flush<S, F, R>(
+    socket: &S,
+    mut flush: F,
+) -> io::Result<DatagramRegistration>
+where
+    S: Datagram,
+    F: FnMut() -> R + Send + 'static,
+    R: IntoResult,
+{
+    add_datagram(socket, Some(Box::new(move || flush().into_result())))
+}
+
+fn add_datagram<S: Datagram>(
+    socket: &S,
+    flush: Option<FlushFn>,
+) -> io::Result<DatagramRegistration> {
+    let socket = socket.try_clone_datagram()?;
+    DATAGRAM_HOOKS.call_once(|| {
+        hooks::phase(hooks::FLUSH)
+            .hook("graceful: datagram flush", |_: &Context| flush_datagrams());
+        hooks::phase(hooks::CLOSE)
+            .hook_once("graceful: datagram close", |_: &Context| close_datagrams());
+    });
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&DATAGRAMS).push(DatagramEntry { id, socket, flush });
+    Ok(DatagramRegistration(id))
+}
+
+/// Flush every socket, failing with the first error once all have been
+/// tried.
+fn flush_datagrams() -> Result<(), BoxError> {
+    let mut result = Ok(());
+    for entry in lock(&DATAGRAMS).iter_mut() {
+        if let Some(ref mut flush) = entry.flush {
+            let flushed = flush();
+            if result.is_ok() {
+                result = flushed;
+            }
+        }
+    }
+    result
+}
+
+fn close_datagrams() -> io::Result<()> {
+    let mut result = Ok(());
+    for entry in lock(&DATAGRAMS).iter() {
+        let closed = entry.socket.close();
+        if result.is_ok() {
+            result = closed;
+        }
+    }
+    result
+}
+
+/// Shut down or half-close every registered stream and wake every
+/// registered datagram socket. Sockets the peer already closed are not an
+/// error worth reporting at this point, so failures are ignored.
+pub(crate) fn shutdown_all() {
+    for entry in lock(&SOCKETS).values() {
+        if let Some(linger) = entry.options.linger {
+            let _ = entry.socket.set_linger(linger);
+        }
+        let _ = if entry.options.half_close {
+            entry.socket.shutdown_write()
+        } else {
+            entry.socket.shutdown()
+        };
+    }
+    for entry in lock(&DATAGRAMS).iter() {
+        let _ = entry.socket.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream, UdpSocket};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn connected() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn wakes_a_blocked_read_when_termination_starts() {
+        let (mut client, _server) = connected();
+        let _registration = register(&client).unwrap();
+        let reading = thread::spawn(move || client.read(&mut [0; 16]).unwrap());
+        thread::sleep(Duration::from_millis(20));
+        shutdown_all();
+        assert_eq!(reading.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn leaves_unregistered_streams_open() {
+        let (mut client, mut server) = connected();
+        drop(register(&client).unwrap());
+        shutdown_all();
+        server.write_all(b"still here").unwrap();
+        let mut received = [0; 10];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"still here");
+    }
+
+    #[test]
+    fn half_closed_streams_read_until_the_peer_closes() {
+        let (mut client, mut server) = connected();
+        let options = DrainOptions::new().half_close(true);
+        let _registration = register_with(&client, options).unwrap();
+        shutdown_all();
+        // The peer reads the end of the stream and can still send.
+        assert_eq!(server.read(&mut [0; 16]).unwrap(), 0);
+        server.write_all(b"bye").unwrap();
+        drop(server);
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"bye");
+    }
+
+    #[test]
+    fn wakes_flushes_and_closes_datagram_sockets() {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let flushes = flushed.clone();
+        let _registration = register_datagram_with_flush(&socket, move || {
+            flushes.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+        let receiver = socket.try_clone().unwrap();
+        let receiving = thread::spawn(move || receiver.recv(&mut [0; 16]).unwrap());
+        thread::sleep(Duration::from_millis(20));
+        shutdown_all();
+        // The empty datagram sent to wake the receiver.
+        assert_eq!(receiving.join().unwrap(), 0);
+
+        flush_datagrams().unwrap();
+        assert_eq!(flushed.load(Ordering::SeqCst), 1);
+        close_datagrams().unwrap();
+        #[cfg(unix)]
+        assert!(socket.send_to(b"late", "127.0.0.1:9").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Telling someone that the process is going down.
+//!
+//! A [ShutdownNotifier](trait.ShutdownNotifier.html) is called when the
+//! shutdown begins and when it is complete, for example to page the owners
+//! of a singleton service. With the `webhook` feature,
+//! [Webhook](struct.Webhook.html) posts both as JSON:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # #[cfg(feature = "webhook")]
+//! # fn main() {
+//! use graceful::notify::{self, Webhook};
+//!
+//! notify::subscribe(Webhook::new("https://hooks.example.com/services/T0/B0/X"));
+//! # }
+//! # #[cfg(not(feature = "webhook"))]
+//! # fn main() {}
+//! ```
+
+#[cfg(feature = "webhook")]
+use std::env;
+#[cfg(feature = "webhook")]
+use std::process;
+#[cfg(feature = "webhook")]
+use std::time::Duration;
+
+#[cfg(feature = "webhook")]
+use ureq;
+
+use events::{self, Event, Observer};
+#[cfg(feature = "webhook")]
+use report::json_string;
+use report::ShutdownReport;
+use signal::{Origin, Signal};
+
+/// Called at the start and at the end of the shutdown.
+///
+/// Both run on the thread driving the shutdown, before the hooks and after
+/// them, so a slow notifier delays the shutdown.
+pub trait ShutdownNotifier: Send + Sync {
+    fn shutdown_started(&self, signal: Signal, origin: Option<Origin>) {
+        let _ = (signal, origin);
+    }
+
+    fn shutdown_finished(&self, report: &ShutdownReport) {
+        let _ = report;
+    }
+}
+
+struct Notifier<N>(N);
+
+impl<N: ShutdownNotifier> Observer for Notifier<N> {
+    fn on_event(&self, event: &Event) {
+        match *event {
+            Event::ShutdownStarted { signal, origin } => self.0.shutdown_started(signal, origin),
+            Event::ShutdownFinished(report) => self.0.shutdown_finished(report),
+            _ => {}
+        }
+    }
+}
+
+/// Have `notifier` called for every following shutdown, see
+/// [events::subscribe](../events/fn.subscribe.html).
+pub fn subscribe<N: ShutdownNotifier + 'static>(notifier: N) {
+    events::subscribe(Notifier(notifier));
+}
+
+/// Posts a JSON document to a URL at the start and at the end of the
+/// shutdown.
+///
+/// ```text
+/// {"event":"shutdown-started","program":"app","pid":4242,"signal":"SIGTERM"}
+/// {"event":"shutdown-finished","program":"app","pid":4242,"signal":"SIGTERM",
+///  "duration_ms":778,"exit_code":1,"failures":[{"phase":"flush","hook":"db","failure":"failed: disk full"}]}
+/// ```
+///
+/// Requests that fail or take longer than the timeout are given up on.
+#[cfg(feature = "webhook")]
+pub struct Webhook {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "webhook")]
+impl Webhook {
+    /// A webhook with a timeout of 5 seconds.
+    pub fn new(url: &str) -> Webhook {
+        Webhook::with_timeout(url, Duration::from_secs(5))
+    }
+
+    pub fn with_timeout(url: &str, timeout: Duration) -> Webhook {
+        Webhook {
+            url: url.to_owned(),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    fn post(&self, body: &str) {
+        let _ = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(body);
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl ShutdownNotifier for Webhook {
+    fn shutdown_started(&self, signal: Signal, _origin: Option<Origin>) {
+        let mut body = header("shutdown-started", signal);
+        body.push('}');
+        self.post(&body);
+    }
+
+    fn shutdown_finished(&self, report: &ShutdownReport) {
+        let mut body = header("shutdown-finished", report.signal());
+        body.push_str(&format!(
+            ",\"duration_ms\":{},\"exit_code\":{},\"failures\":[",
+            report.duration().as_millis(),
+            report.exit_code()
+        ));
+        for (i, (phase, hook)) in report.failures().enumerate() {
+            let failure = hook.failure().map(ToString::to_string).unwrap_or_default();
+            if i > 0 {
+                body.push(',');
+            }
+            body.push_str(&format!(
+                "{{\"phase\":{},\"hook\":{},\"failure\":{}}}",
+                json_string(phase),
+                json_string(hook.name()),
+                json_string(&failure)
+            ));
+        }
+        body.push_str("]}");
+        self.post(&body);
+    }
+}
+
+/// The fields both documents start with, without the closing brace.
+#[cfg(feature = "webhook")]
+fn header(event: &str, signal: Signal) -> String {
+    let program = env::current_exe()
+        .ok()
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    format!(
+        "{{\"event\":{},\"program\":{},\"pid\":{},\"signal\":{}",
+        json_string(event),
+        json_string(&program),
+        process::id(),
+        json_string(&signal.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "webhook")]
+    use std::io::{BufRead, BufReader, Read, Write};
+    #[cfg(feature = "webhook")]
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    #[cfg(feature = "webhook")]
+    use std::thread;
+
+    use super::*;
+    use hooks::{Context, Coordinator, CLOSE};
+    use sync::lock;
+
+    #[derive(Default)]
+    struct Recorded(Mutex<Vec<String>>);
+
+    impl ShutdownNotifier for &Recorded {
+        fn shutdown_started(&self, signal: Signal, _origin: Option<Origin>) {
+            lock(&self.0).push(format!("started on {}", signal));
+        }
+
+        fn shutdown_finished(&self, report: &ShutdownReport) {
+            lock(&self.0).push(format!("finished with {}", report.exit_code()));
+        }
+    }
+
+    #[test]
+    fn notifiers_hear_of_the_start_and_the_end() {
+        let recorded = Recorded::default();
+        let notifier = Notifier(&recorded);
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator
+            .phase(CLOSE)
+            .hook("db", |_: &Context| Err("disk full"));
+        let report = coordinator.rehearse(Signal::Terminate);
+        notifier.on_event(&Event::ShutdownStarted {
+            signal: Signal::Terminate,
+            origin: None,
+        });
+        for phase in report.phases() {
+            notifier.on_event(&Event::PhaseFinished(phase));
+        }
+        notifier.on_event(&Event::ShutdownFinished(&report));
+        assert_eq!(
+            *lock(&recorded.0),
+            ["started on SIGTERM", "finished with 1"]
+        );
+    }
+
+    /// Accept one request on `listener` and return its body.
+    #[cfg(feature = "webhook")]
+    fn receive(listener: TcpListener) -> String {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_ascii_lowercase();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "webhook")]
+    fn webhooks_post_the_report() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || receive(listener));
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator
+            .phase(CLOSE)
+            .hook("db", |_: &Context| Err("disk full"));
+        let report = coordinator.rehearse(Signal::Terminate);
+        Webhook::new(&url).shutdown_finished(&report);
+
+        let body = server.join().unwrap();
+        assert!(body.starts_with(r#"{"event":"shutdown-finished","program":"#));
+        assert!(body.contains(&format!(r#","pid":{},"signal":"SIGTERM","#, process::id())));
+        assert!(body.ends_with(
+            r#","exit_code":1,"failures":[{"phase":"close","hook":"db","failure":"failed: disk full"}]}"#
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! Keeping outbound calls made by hooks within the grace period.
+//!
+//! A hook that deregisters from discovery or posts a final flush blocks on
+//! the network, and a call that hangs runs past the
+//! [deadline](../hooks/struct.Context.html#method.deadline) of its phase,
+//! where it is abandoned with nothing to show for it. A
+//! [CallBudget](struct.CallBudget.html) turns the time left into timeouts
+//! for such calls, keeping back a reserve to handle the response in, and
+//! refuses calls once nothing is left.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//!
+//! use graceful::hooks;
+//! use graceful::outbound::CallBudget;
+//!
+//! hooks::phase(hooks::STOP_INTAKE).hook("deregister", |ctx| {
+//!     let timeout = CallBudget::new(ctx).timeout(Duration::from_secs(5))?;
+//!     // DELETE /v1/agent/service/app with `timeout`
+//!     # let _ = timeout;
+//!     Ok::<(), graceful::outbound::Exhausted>(())
+//! });
+//! ```
+//!
+//! Without a grace period the calls only get the timeout asked for.
+
+use std::error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "webhook")]
+use ureq;
+
+use hooks::Context;
+
+/// The time kept back from each call by default.
+pub const DEFAULT_RESERVE: Duration = Duration::from_millis(100);
+
+/// The part of the grace period left for outbound calls of a hook.
+///
+/// Follows the deadline of the hook, including the
+/// [extensions](../hooks/struct.Context.html#method.request_extension)
+/// granted after it was made.
+#[derive(Clone, Debug)]
+pub struct CallBudget {
+    ctx: Context,
+    reserve: Duration,
+}
+
+impl CallBudget {
+    /// The budget of the hook given `ctx`, keeping back the
+    /// [default reserve](constant.DEFAULT_RESERVE.html).
+    pub fn new(ctx: &Context) -> CallBudget {
+        CallBudget {
+            ctx: ctx.clone(),
+            reserve: DEFAULT_RESERVE,
+        }
+    }
+
+    /// Keep back `reserve` before the deadline instead, for the hook to
+    /// finish in after its calls.
+    pub fn reserve(mut self, reserve: Duration) -> CallBudget {
+        self.reserve = reserve;
+        self
+    }
+
+    /// When calls have to be done by, if there is a grace period. Suits
+    /// clients that take an absolute deadline.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.ctx.deadline().map(|deadline| {
+            deadline
+                .checked_sub(self.reserve)
+                .unwrap_or_else(|| self.ctx.clock().now())
+        })
+    }
+
+    /// The time left for calls, if there is a grace period.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline()
+            .map(|deadline| deadline.saturating_duration_since(self.ctx.clock().now()))
+    }
+
+    /// The timeout of a call that would take at most `max` otherwise.
+    ///
+    /// Fails once no time is left, so the call is not started at all.
+    pub fn timeout(&self, max: Duration) -> Result<Duration, Exhausted> {
+        match self.remaining() {
+            Some(remaining) if remaining == Duration::from_secs(0) => Err(Exhausted),
+            Some(remaining) => Ok(remaining.min(max)),
+            None => Ok(max),
+        }
+    }
+
+    /// Like [timeout](#method.timeout), as the value of a `grpc-timeout`
+    /// header, so the server gives up when the caller does.
+    pub fn grpc_timeout(&self, max: Duration) -> Result<String, Exhausted> {
+        self.timeout(max).map(grpc_timeout)
+    }
+
+    /// Set the [timeout](#method.timeout) of `request`.
+    #[cfg(feature = "webhook")]
+    pub fn apply(&self, request: ureq::Request, max: Duration) -> Result<ureq::Request, Exhausted> {
+        self.timeout(max).map(|timeout| request.timeout(timeout))
+    }
+}
+
+/// No time is left for an outbound call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exhausted;
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no time left in the grace period for the call")
+    }
+}
+
+impl error::Error for Exhausted {}
+
+/// Render `timeout` in the finest unit that keeps it within the eight
+/// digits gRPC allows, rounding up.
+fn grpc_timeout(timeout: Duration) -> String {
+    let nanos = timeout.as_nanos();
+    let units: [(u128, char); 6] = [
+        (1, 'n'),
+        (1_000, 'u'),
+        (1_000_000, 'm'),
+        (1_000_000_000, 'S'),
+        (60_000_000_000, 'M'),
+        (3_600_000_000_000, 'H'),
+    ];
+    for &(size, unit) in &units {
+        let value = nanos.div_ceil(size);
+        if value < 100_000_000 {
+            return format!("{}{}", value, unit);
+        }
+    }
+    "99999999H".to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use clock::ManualClock;
+    use hooks::{Coordinator, CLOSE};
+    use signal::Signal;
+    use sync::lock;
+
+    /// What `call` returns given the budget of a hook that has
+    /// `grace_period`, on a clock that does not move.
+    fn in_hook<T, F>(grace_period: Option<Duration>, call: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(CallBudget) -> T + Send + 'static,
+    {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator.set_clock(Arc::new(ManualClock::new()));
+        if let Some(grace_period) = grace_period {
+            coordinator.set_grace_period(grace_period);
+        }
+        let result = Arc::new(Mutex::new(None));
+        let set = result.clone();
+        coordinator
+            .phase(CLOSE)
+            .hook_once("call", move |ctx: &Context| {
+                *lock(&set) = Some(call(CallBudget::new(ctx)))
+            });
+        coordinator.run(Signal::Terminate);
+        let result = lock(&result).take().unwrap();
+        result
+    }
+
+    #[test]
+    fn timeouts_end_before_the_reserve() {
+        let timeouts = in_hook(Some(Duration::from_secs(10)), |budget| {
+            (
+                budget.timeout(Duration::from_secs(5)),
+                budget.timeout(Duration::from_secs(60)),
+                budget
+                    .reserve(Duration::from_secs(10))
+                    .timeout(Duration::from_secs(5)),
+            )
+        });
+        assert_eq!(
+            timeouts,
+            (
+                Ok(Duration::from_secs(5)),
+                Ok(Duration::from_millis(9900)),
+                Err(Exhausted),
+            )
+        );
+    }
+
+    #[test]
+    fn calls_get_their_timeout_without_a_grace_period() {
+        let timeout = in_hook(None, |budget| {
+            assert_eq!(budget.remaining(), None);
+            budget.timeout(Duration::from_secs(60))
+        });
+        assert_eq!(timeout, Ok(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn grpc_timeouts_round_up_to_eight_digits() {
+        assert_eq!(grpc_timeout(Duration::from_nanos(1500)), "1500n");
+        assert_eq!(grpc_timeout(Duration::from_secs(1)), "1000000u");
+        assert_eq!(grpc_timeout(Duration::new(100_000, 1)), "100001S");
+        assert_eq!(grpc_timeout(Duration::MAX), "99999999H");
+    }
+}
@@ -0,0 +1,90 @@
+//! Shutting down when a thread panics.
+
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+#[cfg(unix)]
+use std::sync::Mutex;
+use std::sync::Once;
+
+#[cfg(unix)]
+use libc;
+
+#[cfg(windows)]
+use platform;
+use state;
+#[cfg(unix)]
+use sync::lock;
+
+static INSTALLED: Once = Once::new();
+
+/// Set by the first panic, so later ones do not start the shutdown again.
+static FIRED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the first panic until the guard takes it.
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// The raw signal, or console event, that wakes the guard.
+static WAKE: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+lazy_static! {
+    /// The thread waiting for signals, while it waits. Held while it is
+    /// signalled, so it cannot stop waiting and exit in the meantime.
+    static ref WAITER: Mutex<Option<libc::pthread_t>> = Mutex::new(None);
+}
+
+/// Have the first panic before the shutdown wake the guard with `wake`,
+/// after the panic hook that was set runs.
+pub(crate) fn install(wake: i32) {
+    WAKE.store(wake, Ordering::Relaxed);
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if !state::is_shutting_down() && !FIRED.swap(true, Ordering::SeqCst) {
+                PENDING.store(true, Ordering::SeqCst);
+                wake_guard(WAKE.load(Ordering::Relaxed));
+            }
+        }));
+    });
+}
+
+/// Remember the calling thread as the one waiting for signals, until
+/// [clear_waiter](fn.clear_waiter.html). Check [take](fn.take.html) after
+/// this and before waiting, as a panic before it does not wake the thread.
+#[cfg(unix)]
+pub(crate) fn set_waiter() {
+    *lock(&WAITER) = Some(unsafe { libc::pthread_self() });
+}
+
+/// Forget the thread waiting for signals, once it stopped waiting.
+#[cfg(unix)]
+pub(crate) fn clear_waiter() {
+    lock(&WAITER).take();
+}
+
+/// Whether a panic is waiting to be [taken](fn.take.html).
+pub(crate) fn is_pending() -> bool {
+    PENDING.load(Ordering::SeqCst)
+}
+
+/// Whether a panic is to be taken as the signal that starts the shutdown.
+pub(crate) fn take() -> bool {
+    PENDING.swap(false, Ordering::SeqCst)
+}
+
+/// Send the signal to the waiting thread only, so no other thread that
+/// accepts the signals counts it as another one.
+#[cfg(unix)]
+fn wake_guard(signum: i32) {
+    if let Some(waiter) = *lock(&WAITER) {
+        unsafe {
+            libc::pthread_kill(waiter, signum);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn wake_guard(event: i32) {
+    platform::deliver(event as u32);
+}
@@ -0,0 +1,199 @@
+//! Unpark registered threads when the shutdown starts.
+//!
+//! A worker waiting in `thread::park` for work that will not come any more
+//! would keep the shutdown from joining it. Every thread that
+//! [registered](fn.register.html) itself is unparked as the shutdown starts,
+//! so its loop gets to check whether to stop. A thread not parked at that
+//! moment returns from its next `park` at once.
+//!
+//! Workers pinned to a few cores, which may be quiesced or taken by other
+//! work during the teardown, can also be let run on every core once the
+//! shutdown starts with [release_affinity](fn.release_affinity.html)
+//! (Linux).
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # fn next_job() -> Option<u32> { None }
+//! use std::thread;
+//!
+//! use graceful::{is_shutting_down, park, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::new();
+//! park::release_affinity(true);
+//! let worker = thread::spawn(|| {
+//!     let _registration = park::register();
+//!     while !is_shutting_down() {
+//!         match next_job() {
+//!             Some(job) => println!("job {}", job),
+//!             None => thread::park(),
+//!         }
+//!     }
+//! });
+//! signal_guard.at_exit(move |_| worker.join().unwrap());
+//! ```
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+#[cfg(target_os = "linux")]
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, Thread};
+
+#[cfg(target_os = "linux")]
+use libc;
+
+use sync::lock;
+
+struct Entry {
+    thread: Thread,
+    /// The kernel ID of the thread, for its affinity.
+    #[cfg(target_os = "linux")]
+    tid: libc::pid_t,
+}
+
+lazy_static! {
+    static ref THREADS: Mutex<HashMap<usize, Entry>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+static RELEASE_AFFINITY: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the current thread registered until dropped.
+///
+/// It cannot be sent to another thread, so the registration ends on the
+/// thread it names.
+#[derive(Debug)]
+#[must_use = "the thread is unregistered when this is dropped"]
+pub struct Registration {
+    id: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock(&THREADS).remove(&self.id);
+    }
+}
+
+/// Have the current thread unparked when the shutdown starts.
+pub fn register() -> Registration {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let entry = Entry {
+        thread: thread::current(),
+        #[cfg(target_os = "linux")]
+        tid: unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t,
+    };
+    lock(&THREADS).insert(id, entry);
+    Registration {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+/// Whether to let the registered threads run on every core of the system
+/// when the shutdown starts, whatever their affinity was (Linux). Off by
+/// default; does nothing elsewhere.
+pub fn release_affinity(release: bool) {
+    RELEASE_AFFINITY.store(release, Ordering::Relaxed);
+}
+
+/// Release the affinity of every registered thread, if enabled, then
+/// unpark them. A thread that cannot be moved is left where it is.
+pub(crate) fn unpark_all() {
+    let release = RELEASE_AFFINITY.load(Ordering::Relaxed);
+    for entry in lock(&THREADS).values() {
+        if release {
+            #[cfg(target_os = "linux")]
+            reset_affinity(entry.tid);
+        }
+        entry.thread.unpark();
+    }
+}
+
+/// Allow the thread `tid` on every core. The kernel leaves out those its
+/// cpuset does not have.
+#[cfg(target_os = "linux")]
+fn reset_affinity(tid: libc::pid_t) {
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        for cpu in 0..libc::CPU_SETSIZE as usize {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        libc::sched_setaffinity(tid, mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn unparks_the_registered_threads() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready, registered) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+        let stopped = stop.clone();
+        let worker = thread::spawn(move || {
+            let registration = register();
+            ready.send(registration.id).unwrap();
+            while !stopped.load(Ordering::SeqCst) {
+                thread::park();
+            }
+            drop(registration);
+            done.send(()).unwrap();
+        });
+        let id = registered.recv().unwrap();
+        assert!(lock(&THREADS).contains_key(&id));
+
+        stop.store(true, Ordering::SeqCst);
+        unpark_all();
+        finished.recv_timeout(Duration::from_secs(10)).unwrap();
+        worker.join().unwrap();
+        assert!(!lock(&THREADS).contains_key(&id));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn releases_the_affinity_of_the_registered_threads() {
+        fn cpus() -> usize {
+            unsafe {
+                let mut set: libc::cpu_set_t = mem::zeroed();
+                assert_eq!(
+                    libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set),
+                    0
+                );
+                libc::CPU_COUNT(&set) as usize
+            }
+        }
+
+        let available = cpus();
+        let (ready, pinned) = mpsc::channel();
+        let (go, released) = mpsc::channel::<()>();
+        let worker = thread::spawn(move || {
+            let _registration = register();
+            unsafe {
+                let mut set: libc::cpu_set_t = mem::zeroed();
+                libc::CPU_SET(0, &mut set);
+                libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set);
+            }
+            ready.send(cpus()).unwrap();
+            released.recv().unwrap();
+            cpus()
+        });
+        assert_eq!(pinned.recv().unwrap(), 1);
+
+        release_affinity(true);
+        unpark_all();
+        release_affinity(false);
+        go.send(()).unwrap();
+        // The affinity of the test runner may be narrower than the cpuset.
+        assert!(worker.join().unwrap() >= available);
+    }
+}
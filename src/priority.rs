@@ -0,0 +1,76 @@
+//! Raising the scheduling priority for the shutdown.
+
+#[cfg(windows)]
+extern crate winapi;
+
+use std::io;
+
+#[cfg(windows)]
+use self::winapi::um::processthreadsapi::{GetCurrentProcess, SetPriorityClass};
+#[cfg(windows)]
+use self::winapi::um::winbase::HIGH_PRIORITY_CLASS;
+#[cfg(unix)]
+use libc;
+
+/// How many nice levels the shutdown is raised by.
+#[cfg(unix)]
+const BOOST: libc::c_int = 10;
+
+/// Lower the nice value of the calling thread by ten, down to `-20`.
+///
+/// On Linux the nice value is per thread, so this raises the thread running
+/// the shutdown and the hook threads it starts, not the workers already
+/// running. Fails without the privilege to lower it (`CAP_SYS_NICE`, or
+/// `RLIMIT_NICE` on Linux).
+#[cfg(unix)]
+pub(crate) fn boost() -> io::Result<()> {
+    // A nice value of -1 cannot be told from a failure, which only happens
+    // for a process that does not exist.
+    let current = unsafe { libc::getpriority(libc::PRIO_PROCESS as _, 0) };
+    let target = (current - BOOST).max(-20);
+    if target < current && unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, target) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Move the process to `HIGH_PRIORITY_CLASS`.
+#[cfg(windows)]
+pub(crate) fn boost() -> io::Result<()> {
+    if unsafe { SetPriorityClass(GetCurrentProcess(), HIGH_PRIORITY_CLASS) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io;
+    use std::thread;
+
+    use libc;
+
+    use super::boost;
+
+    fn nice() -> libc::c_int {
+        unsafe { libc::getpriority(libc::PRIO_PROCESS as _, 0) }
+    }
+
+    #[test]
+    fn raises_the_priority_or_fails_without_the_privilege() {
+        // On Linux the nice value is per thread, so the boost is kept to
+        // a thread of its own.
+        thread::spawn(|| {
+            let before = nice();
+            match boost() {
+                Ok(()) => assert_eq!(nice(), (before - 10).max(-20)),
+                Err(err) => {
+                    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+                    assert_eq!(nice(), before);
+                }
+            }
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,904 @@
+//! Shutting down a family of processes together.
+//!
+//! A [SharedFlag](struct.SharedFlag.html) lives in memory shared between a
+//! master and its workers. The master has it set when its guard receives a
+//! signal, and the workers check or wait on it, so they drain along with the
+//! master without each handling signals of their own. A
+//! [Prefork](struct.Prefork.html) manager forks and watches the workers
+//! itself, and replaces them as their
+//! [RecyclePolicy](struct.RecyclePolicy.html) retires them.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # extern crate libc;
+//! use std::time::Duration;
+//!
+//! use graceful::process::SharedFlag;
+//! use graceful::SignalGuard;
+//!
+//! let signal_guard = SignalGuard::new();
+//! let flag = SharedFlag::new().unwrap();
+//! flag.set_at_shutdown();
+//!
+//! if unsafe { libc::fork() } == 0 {
+//!     // The worker inherits the mapping.
+//!     while !flag.wait_timeout(Duration::from_secs(1)) {
+//!         // ...
+//!     }
+//!     std::process::exit(0);
+//! }
+//!
+//! signal_guard.at_exit(|_| {
+//!     unsafe { libc::wait(std::ptr::null_mut()) };
+//! });
+//! ```
+
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::Command;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use signal::{Origin, Signal};
+use state;
+use sync::lock;
+
+lazy_static! {
+    static ref AT_SHUTDOWN: Mutex<Vec<SharedFlag>> = Mutex::new(Vec::new());
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+lazy_static! {
+    static ref SELF_SENT: Mutex<Option<SelfSent>> = Mutex::new(None);
+}
+
+/// The signals accepted by the listener of the shutdown, and those sent by
+/// [signal_process_group](fn.signal_process_group.html) it has not accepted
+/// yet, where the sender of a signal is not known.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+struct SelfSent {
+    set: libc::sigset_t,
+    pending: Vec<libc::c_int>,
+}
+
+/// How often the flag is checked where the kernel cannot be asked to wait.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Mapping {
+    word: *const AtomicU32,
+    fd: Option<libc::c_int>,
+}
+
+// The mapping is only accessed through the atomic.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn map(fd: Option<libc::c_int>) -> io::Result<Mapping> {
+        let size = std::mem::size_of::<AtomicU32>();
+        let (flags, raw_fd) = match fd {
+            Some(fd) => (libc::MAP_SHARED, fd),
+            None => (libc::MAP_SHARED | libc::MAP_ANON, -1),
+        };
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                raw_fd,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            word: addr as *const AtomicU32,
+            fd,
+        })
+    }
+
+    fn word(&self) -> &AtomicU32 {
+        unsafe { &*self.word }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(
+                self.word as *mut libc::c_void,
+                std::mem::size_of::<AtomicU32>(),
+            );
+            if let Some(fd) = self.fd {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+/// A shutdown flag shared by a process and its children.
+///
+/// Clones refer to the same flag.
+#[derive(Clone)]
+pub struct SharedFlag(Arc<Mapping>);
+
+impl SharedFlag {
+    /// A flag in an anonymous shared mapping, inherited by processes forked
+    /// after this.
+    pub fn new() -> io::Result<SharedFlag> {
+        Mapping::map(None).map(|mapping| SharedFlag(Arc::new(mapping)))
+    }
+
+    /// A flag backed by a `memfd`, so it can also be handed to programs
+    /// started with `exec` through [as_raw_fd](#method.as_raw_fd).
+    ///
+    /// The descriptor is not close-on-exec.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn with_memfd() -> io::Result<SharedFlag> {
+        let name = b"graceful\0";
+        let fd = unsafe { libc::memfd_create(name.as_ptr() as *const libc::c_char, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let size = std::mem::size_of::<AtomicU32>() as libc::off_t;
+        if unsafe { libc::ftruncate(fd, size) } != 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        SharedFlag::map_fd(fd)
+    }
+
+    /// Map the flag of a descriptor created by
+    /// [with_memfd](#method.with_memfd), taking ownership of it.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open descriptor created by `with_memfd`, not owned by
+    /// anything else.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub unsafe fn from_raw_fd(fd: RawFd) -> io::Result<SharedFlag> {
+        SharedFlag::map_fd(fd)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn map_fd(fd: RawFd) -> io::Result<SharedFlag> {
+        match Mapping::map(Some(fd)) {
+            Ok(mapping) => Ok(SharedFlag(Arc::new(mapping))),
+            Err(err) => {
+                unsafe { libc::close(fd) };
+                Err(err)
+            }
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.word().load(Ordering::Acquire) != 0
+    }
+
+    /// Set the flag and wake every process waiting on it.
+    pub fn set(&self) {
+        self.0.word().store(1, Ordering::Release);
+        self.wake();
+    }
+
+    /// Have the flag [set](#method.set) when termination starts in this
+    /// process.
+    pub fn set_at_shutdown(&self) {
+        lock(&AT_SHUTDOWN).push(self.clone());
+    }
+
+    /// Block until the flag is set.
+    pub fn wait(&self) {
+        while !self.is_set() {
+            self.park(None);
+        }
+    }
+
+    /// Block until the flag is set or `timeout` has elapsed. Returns whether
+    /// it is set.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => {
+                self.wait();
+                return true;
+            }
+        };
+        while !self.is_set() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            self.park(Some(deadline - now));
+        }
+        true
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn park(&self, timeout: Option<Duration>) {
+        let timespec = timeout.map(|timeout| libc::timespec {
+            tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        });
+        let timespec = timespec
+            .as_ref()
+            .map_or(ptr::null(), |timespec| timespec as *const libc::timespec);
+        // Not FUTEX_PRIVATE_FLAG: the waiters are in other processes.
+        unsafe {
+            libc::syscall(libc::SYS_futex, self.0.word, libc::FUTEX_WAIT, 0, timespec);
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn park(&self, timeout: Option<Duration>) {
+        thread::sleep(timeout.map_or(POLL_INTERVAL, |timeout| timeout.min(POLL_INTERVAL)));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn wake(&self) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                self.0.word,
+                libc::FUTEX_WAKE,
+                libc::c_int::MAX,
+            );
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn wake(&self) {}
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl AsRawFd for SharedFlag {
+    /// The `memfd` behind the flag, or `-1` for an anonymous mapping.
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.fd.unwrap_or(-1)
+    }
+}
+
+/// Set every flag registered with
+/// [set_at_shutdown](struct.SharedFlag.html#method.set_at_shutdown).
+pub(crate) fn set_flags() {
+    for flag in lock(&AT_SHUTDOWN).iter() {
+        flag.set();
+    }
+}
+
+/// Send `signal` to this process, as if it came from outside, for example
+/// to start the shutdown from within the program.
+///
+/// On Linux it does not count as another signal once the shutdown has
+/// started, as the listener drops the signals this process sent itself.
+pub fn raise(signal: Signal) -> io::Result<()> {
+    let signum = signal.raw().ok_or_else(not_a_signal)?;
+    if unsafe { libc::kill(libc::getpid(), signum) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn not_a_signal() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "not a signal on this platform")
+}
+
+/// Send `signal` to every other process in the caller's process group.
+///
+/// The signal is ignored in this process while it is sent, and discarded if
+/// it became pending because it is blocked, so it does not start another
+/// shutdown here; one arriving from elsewhere in that moment is lost too.
+/// The listener of the shutdown can accept it before it is discarded, and
+/// drops it with [is_self_sent](fn.is_self_sent.html).
+pub(crate) fn signal_process_group(signal: Signal) -> io::Result<()> {
+    let signum = signal.raw().ok_or_else(not_a_signal)?;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        // The listener cannot tell who sent it here, so it is left to the
+        // listener to accept, counted as sent by this process.
+        let mut self_sent = lock(&SELF_SENT);
+        if let Some(ref mut self_sent) = *self_sent {
+            if unsafe { libc::sigismember(&self_sent.set, signum) } == 1 {
+                if unsafe { libc::kill(0, signum) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                self_sent.pending.push(signum);
+                return Ok(());
+            }
+        }
+    }
+    unsafe {
+        let mut ignore: libc::sigaction = std::mem::zeroed();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        libc::sigemptyset(&mut ignore.sa_mask);
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(signum, &ignore, &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let result = if libc::kill(0, signum) == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        };
+        // Setting `SIG_IGN` discards the signal if it is pending here.
+        libc::sigaction(signum, &ignore, ptr::null_mut());
+        libc::sigaction(signum, &previous, ptr::null_mut());
+        result
+    }
+}
+
+/// Start counting the signals of `set` sent by
+/// [signal_process_group](fn.signal_process_group.html) for the listener of
+/// the shutdown, which accepts them.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn listen_for_self_sent(set: &libc::sigset_t) {
+    *lock(&SELF_SENT) = Some(SelfSent {
+        set: *set,
+        pending: Vec::new(),
+    });
+}
+
+/// Whether the signal the listener of the shutdown accepted, `signum` from
+/// `origin`, was sent by this process, which does not count it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn is_self_sent(_: libc::c_int, origin: Option<Origin>) -> bool {
+    origin.is_some_and(|origin| origin.pid() == std::process::id())
+}
+
+/// Whether the signal the listener of the shutdown accepted, `signum`, is
+/// one sent by [signal_process_group](fn.signal_process_group.html), which
+/// this process does not count.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn is_self_sent(signum: libc::c_int, _: Option<Origin>) -> bool {
+    let mut self_sent = lock(&SELF_SENT);
+    let pending = match *self_sent {
+        Some(ref mut self_sent) => &mut self_sent.pending,
+        None => return false,
+    };
+    match pending.iter().position(|&sent| sent == signum) {
+        Some(index) => {
+            pending.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unblock every signal in the program started by `command`.
+///
+/// Children inherit the signal mask, so a helper spawned after the
+/// [SignalGuard](../struct.SignalGuard.html) was created has the termination
+/// signals blocked and would not react to
+/// [signal_process_group](../struct.SignalGuard.html#method.signal_process_group).
+pub fn unblock_signals(command: &mut Command) -> &mut Command {
+    unsafe {
+        command.pre_exec(|| {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            match libc::pthread_sigmask(libc::SIG_SETMASK, &set, ptr::null_mut()) {
+                0 => Ok(()),
+                err => Err(io::Error::from_raw_os_error(err)),
+            }
+        })
+    }
+}
+
+/// When a worker process should be retired and replaced.
+///
+/// Either limit retires the worker, whichever is reached first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecyclePolicy {
+    max_requests: Option<u64>,
+    max_lifetime: Option<Duration>,
+}
+
+impl RecyclePolicy {
+    /// A policy that never retires a worker.
+    pub fn new() -> RecyclePolicy {
+        RecyclePolicy::default()
+    }
+
+    /// Retire the worker after it handled `requests` requests.
+    pub fn max_requests(self, requests: u64) -> RecyclePolicy {
+        RecyclePolicy {
+            max_requests: Some(requests),
+            ..self
+        }
+    }
+
+    /// Retire the worker once it has been running for `lifetime`.
+    pub fn max_lifetime(self, lifetime: Duration) -> RecyclePolicy {
+        RecyclePolicy {
+            max_lifetime: Some(lifetime),
+            ..self
+        }
+    }
+}
+
+/// Tracks a worker against its [RecyclePolicy](struct.RecyclePolicy.html).
+///
+/// The worker records each request it handles, and once it
+/// [is_due](#method.is_due) its manager starts the replacement; workers of
+/// a [Prefork](struct.Prefork.html) manager have one already.
+///
+/// ```
+/// # extern crate graceful;
+/// use graceful::process::{RecyclePolicy, Recycler};
+///
+/// let recycler = Recycler::new(RecyclePolicy::new().max_requests(2));
+/// assert!(!recycler.record_request());
+/// assert!(recycler.record_request());
+/// assert!(recycler.is_due());
+/// ```
+#[derive(Debug)]
+pub struct Recycler {
+    policy: RecyclePolicy,
+    started: Instant,
+    requests: AtomicU64,
+}
+
+impl Recycler {
+    pub fn new(policy: RecyclePolicy) -> Recycler {
+        Recycler {
+            policy,
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a handled request. Returns whether the worker is now due to be
+    /// retired.
+    pub fn record_request(&self) -> bool {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.is_due()
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Whether either limit of the policy has been reached.
+    pub fn is_due(&self) -> bool {
+        let requests = self
+            .policy
+            .max_requests
+            .is_some_and(|max| self.requests() >= max);
+        let lifetime = self
+            .policy
+            .max_lifetime
+            .is_some_and(|max| self.started.elapsed() >= max);
+        requests || lifetime
+    }
+
+    /// The time left before the lifetime limit, if there is one.
+    pub fn remaining_lifetime(&self) -> Option<Duration> {
+        self.policy
+            .max_lifetime
+            .map(|max| max.saturating_sub(self.started.elapsed()))
+    }
+}
+
+/// How often the [Prefork](struct.Prefork.html) manager checks on its
+/// workers.
+const MANAGE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The flags a worker process shares with its manager.
+#[derive(Clone)]
+struct WorkerFlags {
+    /// Set by the worker once it is warmed up.
+    ready: SharedFlag,
+    /// Set by the worker once its policy retires it.
+    due: SharedFlag,
+    /// Set by the manager to have the worker drain and exit.
+    retire: SharedFlag,
+}
+
+impl WorkerFlags {
+    fn new() -> io::Result<WorkerFlags> {
+        Ok(WorkerFlags {
+            ready: SharedFlag::new()?,
+            due: SharedFlag::new()?,
+            retire: SharedFlag::new()?,
+        })
+    }
+}
+
+/// A worker process of a [Prefork](struct.Prefork.html) manager, as seen
+/// from inside it.
+pub struct Worker {
+    recycler: Recycler,
+    flags: WorkerFlags,
+}
+
+impl Worker {
+    /// Tell the manager the worker is warmed up and taking requests. The
+    /// worker it replaces, if any, is retired then.
+    pub fn ready(&self) {
+        self.flags.ready.set();
+    }
+
+    /// Count a handled request, and ask the manager for a replacement once
+    /// the policy retires the worker. Returns whether to stop taking
+    /// requests, see [is_retired](#method.is_retired).
+    pub fn record_request(&self) -> bool {
+        if self.recycler.record_request() {
+            self.flags.due.set();
+        }
+        self.is_retired()
+    }
+
+    /// Whether the manager has retired the worker, which drains what it
+    /// has taken and exits. Until then a worker its policy retires keeps
+    /// serving, while its replacement warms up.
+    pub fn is_retired(&self) -> bool {
+        self.flags.retire.is_set()
+    }
+
+    /// Block until the worker is retired or `timeout` has elapsed. Returns
+    /// whether it is retired.
+    pub fn wait_retired(&self, timeout: Duration) -> bool {
+        self.flags.retire.wait_timeout(timeout)
+    }
+
+    pub fn recycler(&self) -> &Recycler {
+        &self.recycler
+    }
+}
+
+/// Keeps a number of forked worker processes running, and replaces each
+/// one its [RecyclePolicy](struct.RecyclePolicy.html) retires without a gap
+/// in capacity: the replacement is started first, and the old worker is
+/// only retired, to drain and exit, once the replacement is
+/// [ready](struct.Worker.html#method.ready). Workers that exit on their
+/// own are replaced too.
+///
+/// [run](#method.run) manages the workers until the shutdown starts, then
+/// retires them all and waits for them to exit. Workers do not handle
+/// signals of their own, they leave once retired.
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use std::net::TcpListener;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// use graceful::process::{Prefork, RecyclePolicy};
+/// use graceful::SignalGuard;
+///
+/// let signal_guard = SignalGuard::new();
+/// let listener = TcpListener::bind("0.0.0.0:8080").unwrap();
+/// listener.set_nonblocking(true).unwrap();
+/// let policy = RecyclePolicy::new()
+///     .max_requests(10_000)
+///     .max_lifetime(Duration::from_secs(3600));
+/// let manager = thread::spawn(move || {
+///     Prefork::new(4).policy(policy).run(|worker| {
+///         // warm up...
+///         worker.ready();
+///         while !worker.is_retired() {
+///             match listener.accept() {
+///                 Ok((_stream, _)) => {
+///                     // ...
+///                     worker.record_request();
+///                 }
+///                 Err(_) => {
+///                     worker.wait_retired(Duration::from_millis(10));
+///                 }
+///             }
+///         }
+///     })
+/// });
+///
+/// signal_guard.at_exit(move |_| {
+///     manager.join().unwrap().unwrap();
+/// });
+/// ```
+///
+/// The manager reaps its workers itself, so do not also have the guard
+/// [reap children](../struct.SignalGuardBuilder.html#method.reap_children).
+/// Only the thread calling `run` lives on in a forked worker, so fork
+/// before other threads hold locks the worker needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Prefork {
+    workers: usize,
+    policy: RecyclePolicy,
+    warmup_timeout: Duration,
+    drain_timeout: Duration,
+}
+
+enum WorkerState {
+    /// Not ready yet, replacing the worker with this process ID if any.
+    WarmingUp(Option<libc::pid_t>),
+    /// Taking requests, while the replacement with this process ID warms
+    /// up if any.
+    Serving(Option<libc::pid_t>),
+    /// Draining since then.
+    Retiring(Instant),
+}
+
+struct WorkerProcess {
+    pid: libc::pid_t,
+    flags: WorkerFlags,
+    started: Instant,
+    state: WorkerState,
+}
+
+impl Prefork {
+    /// A manager of `workers` processes that are never recycled, that waits
+    /// 10 seconds for a replacement to warm up and 30 seconds for a retired
+    /// worker to drain.
+    pub fn new(workers: usize) -> Prefork {
+        Prefork {
+            workers,
+            policy: RecyclePolicy::new(),
+            warmup_timeout: Duration::from_secs(10),
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Retire and replace the workers by `policy`.
+    pub fn policy(self, policy: RecyclePolicy) -> Prefork {
+        Prefork { policy, ..self }
+    }
+
+    /// Retire the worker being replaced once its replacement has been
+    /// warming up for `timeout`, even if it is not ready yet.
+    pub fn warmup_timeout(self, timeout: Duration) -> Prefork {
+        Prefork {
+            warmup_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Kill a retired worker with `SIGKILL` once it has been draining for
+    /// `timeout`, also at the shutdown.
+    pub fn drain_timeout(self, timeout: Duration) -> Prefork {
+        Prefork {
+            drain_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Fork the workers, each running `worker` and exiting when it returns,
+    /// and manage them until the shutdown starts, then retire them all and
+    /// wait for them to exit.
+    ///
+    /// If a worker cannot be forked the others are retired the same way,
+    /// and the error is returned.
+    pub fn run<F: Fn(&Worker)>(&self, worker: F) -> io::Result<()> {
+        let mut workers = Vec::new();
+        let result = self.manage(&worker, &mut workers);
+        self.stop(workers);
+        result
+    }
+
+    fn manage<F: Fn(&Worker)>(
+        &self,
+        worker: &F,
+        workers: &mut Vec<WorkerProcess>,
+    ) -> io::Result<()> {
+        while !state::is_shutting_down() {
+            reap(workers);
+            let now = Instant::now();
+
+            // Start a replacement for every worker due.
+            for index in 0..workers.len() {
+                let process = &workers[index];
+                if let WorkerState::Serving(None) = process.state {
+                    if self.is_due(process, now) {
+                        let replacement = self.spawn(worker, Some(process.pid))?;
+                        workers[index].state = WorkerState::Serving(Some(replacement.pid));
+                        workers.push(replacement);
+                    }
+                }
+            }
+
+            // Let the replacements that warmed up take over.
+            let mut retired = Vec::new();
+            for process in workers.iter_mut() {
+                if let WorkerState::WarmingUp(replaces) = process.state {
+                    let warm =
+                        now.saturating_duration_since(process.started) >= self.warmup_timeout;
+                    if process.flags.ready.is_set() || warm {
+                        process.state = WorkerState::Serving(None);
+                        retired.extend(replaces);
+                    }
+                }
+            }
+            for process in workers.iter_mut() {
+                if retired.contains(&process.pid) {
+                    process.flags.retire.set();
+                    process.state = WorkerState::Retiring(now);
+                }
+            }
+
+            // Replace the workers that exited on their own.
+            let mut running = workers
+                .iter()
+                .filter(|process| match process.state {
+                    WorkerState::WarmingUp(replaces) => replaces.is_none(),
+                    WorkerState::Serving(_) => true,
+                    WorkerState::Retiring(_) => false,
+                })
+                .count();
+            while running < self.workers {
+                let process = self.spawn(worker, None)?;
+                workers.push(process);
+                running += 1;
+            }
+
+            for process in workers.iter() {
+                if let WorkerState::Retiring(since) = process.state {
+                    if now.saturating_duration_since(since) >= self.drain_timeout {
+                        unsafe { libc::kill(process.pid, libc::SIGKILL) };
+                    }
+                }
+            }
+            thread::sleep(MANAGE_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Whether the policy retires `process`. Its lifetime is checked here
+    /// too, so idle workers are replaced as well.
+    fn is_due(&self, process: &WorkerProcess, now: Instant) -> bool {
+        let lifetime = now.saturating_duration_since(process.started);
+        process.flags.due.is_set() || self.policy.max_lifetime.is_some_and(|max| lifetime >= max)
+    }
+
+    fn spawn<F: Fn(&Worker)>(
+        &self,
+        worker: &F,
+        replaces: Option<libc::pid_t>,
+    ) -> io::Result<WorkerProcess> {
+        let flags = WorkerFlags::new()?;
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => {
+                let handle = Worker {
+                    recycler: Recycler::new(self.policy),
+                    flags,
+                };
+                let code = match panic::catch_unwind(AssertUnwindSafe(|| worker(&handle))) {
+                    Ok(()) => 0,
+                    Err(_) => 101,
+                };
+                unsafe { libc::_exit(code) }
+            }
+            pid => Ok(WorkerProcess {
+                pid,
+                flags,
+                started: Instant::now(),
+                state: WorkerState::WarmingUp(replaces),
+            }),
+        }
+    }
+
+    /// Retire every worker and wait for them to exit, killing those still
+    /// draining after the drain timeout.
+    fn stop(&self, mut workers: Vec<WorkerProcess>) {
+        for process in &workers {
+            process.flags.retire.set();
+        }
+        let deadline = Instant::now().checked_add(self.drain_timeout);
+        loop {
+            reap(&mut workers);
+            if workers.is_empty() {
+                return;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            thread::sleep(MANAGE_INTERVAL);
+        }
+        for process in &workers {
+            unsafe {
+                libc::kill(process.pid, libc::SIGKILL);
+                libc::waitpid(process.pid, ptr::null_mut(), 0);
+            }
+        }
+    }
+}
+
+/// Drop the workers that exited, and forget them as replacements or as
+/// being replaced.
+fn reap(workers: &mut Vec<WorkerProcess>) {
+    let mut exited = Vec::new();
+    workers.retain(|process| {
+        // `ECHILD` if something else reaped it already.
+        let alive = unsafe { libc::waitpid(process.pid, ptr::null_mut(), libc::WNOHANG) } == 0;
+        if !alive {
+            exited.push(process.pid);
+        }
+        alive
+    });
+    for process in workers.iter_mut() {
+        let other = match process.state {
+            WorkerState::WarmingUp(ref mut other) | WorkerState::Serving(ref mut other) => other,
+            WorkerState::Retiring(_) => continue,
+        };
+        if other.is_some_and(|pid| exited.contains(&pid)) {
+            *other = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn shared_flag_wakes_waiters() {
+        let flag = SharedFlag::new().unwrap();
+        assert!(!flag.wait_timeout(Duration::from_millis(10)));
+        let setter = flag.clone();
+        let setting = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            setter.set();
+        });
+        assert!(flag.wait_timeout(Duration::MAX));
+        assert!(flag.is_set());
+        setting.join().unwrap();
+    }
+
+    #[test]
+    fn shared_flag_is_seen_across_a_fork() {
+        let flag = SharedFlag::new().unwrap();
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            flag.set();
+            unsafe { libc::_exit(0) };
+        }
+        assert!(flag.wait_timeout(Duration::from_secs(10)));
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn memfd_flags_can_be_mapped_again() {
+        let flag = SharedFlag::with_memfd().unwrap();
+        let fd = unsafe { libc::dup(flag.as_raw_fd()) };
+        let mapped = unsafe { SharedFlag::from_raw_fd(fd) }.unwrap();
+        assert!(!mapped.is_set());
+        flag.set();
+        assert!(mapped.is_set());
+    }
+
+    #[test]
+    fn recyclers_are_due_at_the_end_of_their_lifetime() {
+        let recycler = Recycler::new(RecyclePolicy::new().max_lifetime(Duration::from_millis(20)));
+        assert!(!recycler.record_request());
+        assert!(recycler.remaining_lifetime().unwrap() <= Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(30));
+        assert!(recycler.is_due());
+        assert_eq!(recycler.remaining_lifetime(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn the_default_policy_never_retires() {
+        let recycler = Recycler::new(RecyclePolicy::new());
+        for _ in 0..1000 {
+            assert!(!recycler.record_request());
+        }
+        assert_eq!(recycler.requests(), 1000);
+        assert_eq!(recycler.remaining_lifetime(), None);
+    }
+}
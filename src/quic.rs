@@ -0,0 +1,49 @@
+//! Closing QUIC connections at shutdown.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # extern crate quinn;
+//! # fn endpoint() -> quinn::Endpoint { unimplemented!() }
+//! let endpoint: quinn::Endpoint = endpoint();
+//! graceful::quic::drain_at_exit(endpoint, 0, b"server shutting down");
+//! ```
+
+use std::io;
+
+use quinn::{Endpoint, VarInt};
+
+use error::BoxError;
+use executor::block_on;
+use hooks::{self, Context};
+
+/// Close every connection of `endpoint` in the
+/// [DRAIN](../hooks/constant.DRAIN.html) phase, sending `CONNECTION_CLOSE`
+/// with `error_code` and `reason`, then wait for the peers to be told
+/// within the time left in the phase.
+///
+/// The runtime driving the endpoint has to keep running until the phase is
+/// over.
+///
+/// # Panics
+///
+/// Panics if `error_code` does not fit in a QUIC variable-length integer.
+pub fn drain_at_exit(endpoint: Endpoint, error_code: u64, reason: &[u8]) {
+    let error_code = VarInt::from_u64(error_code).expect("QUIC error code out of range");
+    let reason = reason.to_vec();
+    hooks::phase(hooks::DRAIN).hook_once("graceful: quic", move |ctx: &Context| {
+        drain(&endpoint, error_code, &reason, ctx)
+    });
+}
+
+fn drain(
+    endpoint: &Endpoint,
+    error_code: VarInt,
+    reason: &[u8],
+    ctx: &Context,
+) -> Result<(), BoxError> {
+    endpoint.close(error_code, reason);
+    match block_on(endpoint.wait_idle(), ctx.deadline()) {
+        Some(()) => Ok(()),
+        None => Err(io::Error::new(io::ErrorKind::TimedOut, "connections still draining").into()),
+    }
+}
@@ -0,0 +1,215 @@
+//! Pausing workers at a safe point without shutting down.
+//!
+//! Some maintenance, like taking a consistent backup snapshot, needs the
+//! workers to stop touching their data for a while and then carry on. A
+//! [Worker](struct.Worker.html) calls
+//! [safe_point](struct.Worker.html#method.safe_point) wherever it could
+//! stop, and is held there from [pause](fn.pause.html) until
+//! [resume](fn.resume.html). The two can also be triggered by signals with
+//! [SignalGuard::with_quiesce](../struct.SignalGuard.html#method.with_quiesce).
+//!
+//! When the shutdown starts, paused workers are let go so they can exit.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::thread;
+//! use std::time::Duration;
+//! use graceful::{quiesce, Signal, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::with_quiesce(Signal::User1, Signal::User2).unwrap();
+//! let worker = thread::spawn(|| {
+//!     let worker = quiesce::register();
+//!     while !graceful::is_shutting_down() {
+//!         worker.safe_point();
+//!         // write some data...
+//!     }
+//! });
+//!
+//! // Elsewhere, the backup:
+//! quiesce::pause();
+//! if quiesce::wait_paused(Duration::from_secs(10)) {
+//!     // snapshot the data...
+//! }
+//! quiesce::resume();
+//!
+//! signal_guard.at_exit(move |_| worker.join().unwrap());
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::task::{Wake, Waker};
+use std::time::{Duration, Instant};
+
+use signal::Signal;
+use state::{self, SHARED};
+use sync::{lock, wait, wait_timeout};
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    workers: usize,
+    /// Workers waiting in a safe point.
+    parked: usize,
+    /// The signals that pause and resume.
+    signals: Option<(Signal, Signal)>,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+    static ref COND: Condvar = Condvar::new();
+}
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+static SHUTDOWN_WAKER: Once = Once::new();
+
+/// Lets parked workers go when the shutdown starts.
+struct Release;
+
+impl Wake for Release {
+    fn wake(self: Arc<Self>) {
+        let _state = lock(&STATE);
+        COND.notify_all();
+    }
+}
+
+/// A thread that can be paused, registered until dropped.
+#[derive(Debug)]
+pub struct Worker(());
+
+impl Worker {
+    /// Block while the workers are paused, unless the shutdown has started.
+    pub fn safe_point(&self) {
+        if !PAUSED.load(Ordering::Acquire) {
+            return;
+        }
+        let mut state = lock(&STATE);
+        state.parked += 1;
+        COND.notify_all();
+        while state.paused && !state::is_shutting_down() {
+            state = wait(&COND, state);
+        }
+        state.parked -= 1;
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        lock(&STATE).workers -= 1;
+        COND.notify_all();
+    }
+}
+
+/// Register the calling thread, or whatever task owns the result, as a
+/// worker that [wait_paused](fn.wait_paused.html) waits for.
+pub fn register() -> Worker {
+    SHUTDOWN_WAKER.call_once(|| {
+        let mut history = lock(&SHARED.history);
+        if history.signals.is_empty() {
+            history.wakers.push(Waker::from(Arc::new(Release)));
+        }
+    });
+    lock(&STATE).workers += 1;
+    Worker(())
+}
+
+/// Ask every worker to stop at its next safe point.
+pub fn pause() {
+    lock(&STATE).paused = true;
+    PAUSED.store(true, Ordering::Release);
+}
+
+/// Let the workers carry on.
+pub fn resume() {
+    let mut state = lock(&STATE);
+    state.paused = false;
+    PAUSED.store(false, Ordering::Release);
+    COND.notify_all();
+}
+
+/// Whether the workers have been asked to pause.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Acquire)
+}
+
+/// Wait at most `timeout` for every registered worker to reach a safe
+/// point, returning whether they all did. Returns `false` at once if the
+/// workers are not paused.
+pub fn wait_paused(timeout: Duration) -> bool {
+    let deadline = Instant::now().checked_add(timeout);
+    let mut state = lock(&STATE);
+    loop {
+        if !state.paused {
+            return false;
+        }
+        if state.parked == state.workers {
+            return true;
+        }
+        state = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+                wait_timeout(&COND, state, deadline - now)
+            }
+            None => wait(&COND, state),
+        };
+    }
+}
+
+pub(crate) fn set_signals(pause: Signal, resume: Signal) {
+    lock(&STATE).signals = Some((pause, resume));
+}
+
+pub(crate) fn is_signal(signal: Signal) -> bool {
+    lock(&STATE)
+        .signals
+        .is_some_and(|(pause, resume)| signal == pause || signal == resume)
+}
+
+/// Pause or resume if `signal` is one of the quiesce signals. Returns
+/// `false` otherwise.
+pub(crate) fn claim(signal: Signal) -> bool {
+    let signals = lock(&STATE).signals;
+    match signals {
+        Some((pause_signal, _)) if signal == pause_signal => pause(),
+        Some((_, resume_signal)) if signal == resume_signal => resume(),
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn holds_workers_at_a_safe_point_until_resumed() {
+        let steps = Arc::new(AtomicUsize::new(0));
+        let worker = register();
+        assert!(!wait_paused(Duration::from_secs(1)));
+
+        set_signals(Signal::User1, Signal::User2);
+        assert!(!claim(Signal::Terminate));
+        assert!(claim(Signal::User1));
+        assert!(is_paused());
+        let working = {
+            let steps = steps.clone();
+            thread::spawn(move || {
+                worker.safe_point();
+                steps.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        assert!(wait_paused(Duration::from_secs(10)));
+        assert_eq!(steps.load(Ordering::SeqCst), 0);
+
+        assert!(claim(Signal::User2));
+        working.join().unwrap();
+        assert_eq!(steps.load(Ordering::SeqCst), 1);
+        assert!(!is_paused());
+    }
+}
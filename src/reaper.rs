@@ -0,0 +1,129 @@
+//! Reaping the child processes that exit, so they do not linger as zombies.
+
+use std::fmt;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+
+use libc;
+
+use signal::Signal;
+use sync::lock;
+
+type OnExit = Box<dyn FnMut(u32, ExitStatus) + Send>;
+
+lazy_static! {
+    static ref REAPER: Mutex<Option<Arc<ChildReaper>>> = Mutex::new(None);
+}
+
+/// Reaps every child process that exits, on the thread waiting for the
+/// signals, and hands its process ID and exit status to a callback.
+///
+/// Given to [SignalGuardBuilder::reap_children](struct.SignalGuardBuilder.html#method.reap_children),
+/// which has the guard also block `SIGCHLD` and take it instead of
+/// shutting down. The terminal signals still start the shutdown, and
+/// children are reaped during it too.
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use std::process::Command;
+/// use std::thread;
+///
+/// use graceful::{ChildReaper, SignalGuard};
+///
+/// let (reaper, exits) = ChildReaper::channel();
+/// let signal_guard = SignalGuard::builder()
+///     .reap_children(reaper)
+///     .build()
+///     .unwrap();
+/// thread::spawn(move || {
+///     for (pid, status) in exits {
+///         println!("worker {} exited with {}", pid, status);
+///     }
+/// });
+/// Command::new("worker").spawn().unwrap();
+/// signal_guard.at_exit(|_| {});
+/// ```
+///
+/// Every child is reaped, including those started with
+/// `std::process::Command`, whose `Child::wait` then fails as the status
+/// was taken already. Keep to one or the other.
+pub struct ChildReaper {
+    on_exit: Mutex<OnExit>,
+}
+
+impl ChildReaper {
+    /// Call `on_exit` with each child that exited.
+    ///
+    /// It runs on the waiting thread, or on the thread that listens for
+    /// signals during the shutdown, so it should not block.
+    pub fn new<F>(on_exit: F) -> ChildReaper
+    where
+        F: FnMut(u32, ExitStatus) + Send + 'static,
+    {
+        ChildReaper {
+            on_exit: Mutex::new(Box::new(on_exit)),
+        }
+    }
+
+    /// Send each child that exited to the returned receiver instead. Exits
+    /// are dropped once the receiver is.
+    pub fn channel() -> (ChildReaper, Receiver<(u32, ExitStatus)>) {
+        let (sender, receiver) = mpsc::channel();
+        let reaper = ChildReaper::new(move |pid, status| {
+            let _ = sender.send((pid, status));
+        });
+        (reaper, receiver)
+    }
+
+    /// Reap until no exited child is left.
+    fn reap(&self) {
+        let mut on_exit = lock(&self.on_exit);
+        loop {
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+            if pid > 0 {
+                on_exit(pid as u32, ExitStatus::from_raw(status));
+            } else if pid == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                // Zero while the rest are still running, `ECHILD` once there
+                // are no children.
+                return;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ChildReaper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChildReaper").finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn signal() -> Signal {
+    Signal::from_raw(libc::SIGCHLD)
+}
+
+/// Take over reaping, and reap the children that exited before `SIGCHLD`
+/// was blocked, which were not signalled.
+pub(crate) fn install(reaper: Arc<ChildReaper>) {
+    *lock(&REAPER) = Some(reaper.clone());
+    reaper.reap();
+}
+
+/// Reap if `signal` is `SIGCHLD` and a reaper is installed. Returns `false`
+/// otherwise.
+pub(crate) fn claim(signal: Signal) -> bool {
+    if signal != self::signal() {
+        return false;
+    }
+    let reaper = lock(&REAPER).clone();
+    match reaper {
+        Some(reaper) => {
+            reaper.reap();
+            true
+        }
+        None => false,
+    }
+}
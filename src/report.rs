@@ -0,0 +1,466 @@
+//! What happened during shutdown.
+
+use std::fmt;
+use std::slice;
+use std::time::{Duration, SystemTime};
+use std::vec;
+
+use error::FailureKind;
+use signal::{Origin, Signal};
+
+/// A signal received during a shutdown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cause {
+    signal: Signal,
+    origin: Option<Origin>,
+    after: Duration,
+}
+
+impl Cause {
+    pub(crate) fn new(signal: Signal, origin: Option<Origin>, after: Duration) -> Cause {
+        Cause {
+            signal,
+            origin,
+            after,
+        }
+    }
+
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// The process that sent the signal, where the platform tells.
+    pub fn origin(&self) -> Option<Origin> {
+        self.origin
+    }
+
+    /// How long after the signal that started the shutdown it arrived.
+    pub fn after(&self) -> Duration {
+        self.after
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.signal)?;
+        if let Some(origin) = self.origin {
+            write!(f, " from {}", origin)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a single hook.
+#[derive(Debug)]
+pub struct HookReport {
+    name: String,
+    duration: Duration,
+    failure: Option<FailureKind>,
+    counts: Vec<(String, u64)>,
+    attempts: u32,
+}
+
+impl HookReport {
+    pub(crate) fn new(
+        name: String,
+        duration: Duration,
+        failure: Option<FailureKind>,
+        counts: Vec<(String, u64)>,
+        attempts: u32,
+    ) -> HookReport {
+        HookReport {
+            name,
+            duration,
+            failure,
+            counts,
+            attempts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How long the hook ran.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Why the hook did not complete, if it failed.
+    pub fn failure(&self) -> Option<&FailureKind> {
+        self.failure.as_ref()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// How many times the hook was run: more than once if it was
+    /// [retried](hooks/struct.Phase.html#method.hook_with_retry), none if
+    /// it was skipped because the phase was out of time.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// What the hook counted with
+    /// [Context::count](hooks/struct.Context.html#method.count), in the
+    /// order the counts were first added to.
+    pub fn counts(&self) -> slice::Iter<'_, (String, u64)> {
+        self.counts.iter()
+    }
+}
+
+/// The hooks run during one phase.
+#[derive(Debug)]
+pub struct PhaseReport {
+    name: String,
+    duration: Duration,
+    hooks: Vec<HookReport>,
+}
+
+impl PhaseReport {
+    pub(crate) fn new(name: String, duration: Duration, hooks: Vec<HookReport>) -> PhaseReport {
+        PhaseReport {
+            name,
+            duration,
+            hooks,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn hooks(&self) -> slice::Iter<'_, HookReport> {
+        self.hooks.iter()
+    }
+}
+
+/// The outcome of every hook run for a shutdown, phase by phase.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    signal: Signal,
+    causes: Vec<Cause>,
+    phases: Vec<PhaseReport>,
+}
+
+impl ShutdownReport {
+    pub(crate) fn new(signal: Signal, phases: Vec<PhaseReport>) -> ShutdownReport {
+        ShutdownReport {
+            signal,
+            causes: vec![Cause::new(signal, None, Duration::from_secs(0))],
+            phases,
+        }
+    }
+
+    /// The signal that started the shutdown.
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    /// Every signal received until the hooks were done, starting with the
+    /// one that started the shutdown, such as a watchdog or an OOM notice
+    /// firing close after a `SIGTERM`.
+    pub fn causes(&self) -> slice::Iter<'_, Cause> {
+        self.causes.iter()
+    }
+
+    pub(crate) fn set_causes(&mut self, causes: Vec<Cause>) {
+        if !causes.is_empty() {
+            self.causes = causes;
+        }
+    }
+
+    pub fn phases(&self) -> slice::Iter<'_, PhaseReport> {
+        self.phases.iter()
+    }
+
+    pub(crate) fn into_phases(self) -> vec::IntoIter<PhaseReport> {
+        self.phases.into_iter()
+    }
+
+    pub(crate) fn prepend(&mut self, phase: PhaseReport) {
+        self.phases.insert(0, phase);
+    }
+
+    /// Add the phases of the hook partition `partition`, named
+    /// `partition:phase`.
+    pub(crate) fn append_partition(&mut self, partition: &str, report: ShutdownReport) {
+        self.phases
+            .extend(report.phases.into_iter().map(|mut phase| {
+                phase.name = format!("{}:{}", partition, phase.name);
+                phase
+            }));
+    }
+
+    /// Every hook that failed, with the name of its phase.
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &HookReport)> {
+        self.phases.iter().flat_map(|phase| {
+            phase
+                .hooks
+                .iter()
+                .filter(|hook| !hook.is_ok())
+                .map(move |hook| (phase.name(), hook))
+        })
+    }
+
+    /// Whether every hook completed.
+    pub fn is_clean(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The exit status that reflects this shutdown: `0` if it was clean,
+    /// otherwise as in [ShutdownErrors::exit_code](struct.ShutdownErrors.html#method.exit_code).
+    pub fn exit_code(&self) -> i32 {
+        let mut failures = self
+            .failures()
+            .filter_map(|(_, hook)| hook.failure())
+            .peekable();
+        if failures.peek().is_none() {
+            0
+        } else if failures.any(|failure| matches!(*failure, FailureKind::Panicked(_))) {
+            101
+        } else {
+            1
+        }
+    }
+
+    /// The total time spent running hooks.
+    pub fn duration(&self) -> Duration {
+        self.phases.iter().map(PhaseReport::duration).sum()
+    }
+
+    /// The report as a JSON object, for tooling that collects shutdowns
+    /// across machines, see [export](export/index.html):
+    ///
+    /// ```json
+    /// {
+    ///   "signal": "SIGTERM",
+    ///   "duration_ms": 1250,
+    ///   "exit_code": 1,
+    ///   "causes": [{"signal": "SIGTERM", "after_ms": 0, "pid": 1, "uid": 0}],
+    ///   "phases": [{
+    ///     "name": "drain",
+    ///     "duration_ms": 1250,
+    ///     "hooks": [{
+    ///       "name": "http",
+    ///       "duration_ms": 1250,
+    ///       "attempts": 1,
+    ///       "failure": "timed out",
+    ///       "counts": {"requests": 12}
+    ///     }]
+    ///   }]
+    /// }
+    /// ```
+    ///
+    /// `pid` and `uid` are left out where the sender is not known, and
+    /// `failure` is `null` for the hooks that completed.
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\"signal\":{},\"duration_ms\":{},\"exit_code\":{},\"causes\":[",
+            json_string(&self.signal.to_string()),
+            self.duration().as_millis(),
+            self.exit_code()
+        );
+        for (i, cause) in self.causes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "{{\"signal\":{},\"after_ms\":{}",
+                json_string(&cause.signal.to_string()),
+                cause.after.as_millis()
+            ));
+            if let Some(origin) = cause.origin {
+                out.push_str(&format!(
+                    ",\"pid\":{},\"uid\":{}",
+                    origin.pid(),
+                    origin.uid()
+                ));
+            }
+            out.push('}');
+        }
+        out.push_str("],\"phases\":[");
+        for (i, phase) in self.phases.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&format!(
+                "{{\"name\":{},\"duration_ms\":{},\"hooks\":[",
+                json_string(&phase.name),
+                phase.duration.as_millis()
+            ));
+            for (j, hook) in phase.hooks.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                let failure = hook.failure().map_or_else(
+                    || "null".to_owned(),
+                    |failure| json_string(&failure.to_string()),
+                );
+                out.push_str(&format!(
+                    "{{\"name\":{},\"duration_ms\":{},\"attempts\":{},\"failure\":{},\"counts\":{{",
+                    json_string(&hook.name),
+                    hook.duration.as_millis(),
+                    hook.attempts,
+                    failure
+                ));
+                for (k, &(ref name, n)) in hook.counts().enumerate() {
+                    if k > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&format!("{}:{}", json_string(name), n));
+                }
+                out.push_str("}}");
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "shutdown on {} took {:?}", self.signal, self.duration())?;
+        for cause in self.causes.iter().skip(1) {
+            write!(f, "\n  then {} after {:?}", cause, cause.after())?;
+        }
+        for (phase, hook) in self.failures() {
+            if let Some(failure) = hook.failure() {
+                write!(f, "\n  {}/{}: {}", phase, hook.name(), failure)?;
+                if hook.attempts > 1 {
+                    write!(f, " after {} attempts", hook.attempts)?;
+                }
+            }
+        }
+        for phase in &self.phases {
+            for hook in phase.hooks().filter(|hook| !hook.counts.is_empty()) {
+                write!(f, "\n  {}/{}: ", phase.name(), hook.name())?;
+                for (i, &(ref name, n)) in hook.counts().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{} {}", separator, n, name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a shutdown went, with what the handler returned, from
+/// [SignalGuard::wait_and_shutdown](struct.SignalGuard.html#method.wait_and_shutdown).
+#[derive(Debug)]
+pub struct ShutdownOutcome<T> {
+    value: T,
+    started_at: SystemTime,
+    cleanup_duration: Duration,
+    escalated: bool,
+    report: ShutdownReport,
+}
+
+impl<T> ShutdownOutcome<T> {
+    pub(crate) fn new(
+        value: T,
+        started_at: SystemTime,
+        cleanup_duration: Duration,
+        escalated: bool,
+        report: ShutdownReport,
+    ) -> ShutdownOutcome<T> {
+        ShutdownOutcome {
+            value,
+            started_at,
+            cleanup_duration,
+            escalated,
+            report,
+        }
+    }
+
+    /// The signal that started the shutdown.
+    pub fn signal(&self) -> Signal {
+        self.report.signal
+    }
+
+    /// When the signal was received.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// How long the hooks and the handler took together.
+    pub fn cleanup_duration(&self) -> Duration {
+        self.cleanup_duration
+    }
+
+    /// Whether another signal arrived during the shutdown, moving it to
+    /// [Aborting](enum.ShutdownPhase.html#variant.Aborting).
+    pub fn escalated(&self) -> bool {
+        self.escalated
+    }
+
+    /// How the hooks did.
+    pub fn report(&self) -> &ShutdownReport {
+        &self.report
+    }
+
+    /// What the handler returned.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+/// `s` as a quoted JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_the_signals_received_after_the_first() {
+        let mut report = ShutdownReport::new(Signal::Terminate, Vec::new());
+        report.set_causes(Vec::new());
+        assert_eq!(report.causes().count(), 1);
+        let first = format!("shutdown on {} took 0ns", Signal::Terminate);
+        assert_eq!(report.to_string(), first);
+
+        report.set_causes(vec![
+            Cause::new(Signal::Terminate, None, Duration::from_secs(0)),
+            Cause::new(
+                Signal::Interrupt,
+                Some(Origin::new(42, 0)),
+                Duration::from_millis(300),
+            ),
+        ]);
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "{}\n  then {} from pid 42 uid 0 after 300ms",
+                first,
+                Signal::Interrupt
+            )
+        );
+    }
+}
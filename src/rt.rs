@@ -0,0 +1,83 @@
+//! Handing the shutdown to tokio.
+
+use std::future::Future;
+use std::io;
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use error::BoxError;
+use executor::block_on;
+use hooks::{self, Context};
+use signal::Signal;
+use sync::lock;
+
+lazy_static! {
+    static ref TOKEN: CancellationToken = CancellationToken::new();
+    static ref SIGNAL: watch::Sender<Option<Signal>> = watch::channel(None).0;
+    static ref TRACKER: TaskTracker = TaskTracker::new();
+    /// How long the drain hook waits for tracked tasks, beyond the deadline
+    /// of the phase.
+    static ref TIMEOUT: Mutex<Option<Duration>> = Mutex::new(None);
+}
+
+static DRAIN_HOOK: Once = Once::new();
+
+/// A child of the token cancelled when the shutdown starts, so cancelling
+/// it does not stop anything else.
+pub(crate) fn token() -> CancellationToken {
+    TOKEN.child_token()
+}
+
+pub(crate) fn subscribe() -> watch::Receiver<Option<Signal>> {
+    SIGNAL.subscribe()
+}
+
+/// Cancel the tokens and publish `signal`, once.
+pub(crate) fn trip(signal: Signal) {
+    SIGNAL.send_if_modified(|current| {
+        if current.is_some() {
+            return false;
+        }
+        *current = Some(signal);
+        true
+    });
+    TOKEN.cancel();
+}
+
+/// Spawn `future` on the current runtime, to be waited for in the
+/// [DRAIN](../hooks/constant.DRAIN.html) phase.
+pub(crate) fn spawn_tracked<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    DRAIN_HOOK.call_once(|| {
+        hooks::phase(hooks::DRAIN).hook_once("graceful: tokio tasks", drain);
+    });
+    TRACKER.spawn(future)
+}
+
+pub(crate) fn set_drain_timeout(timeout: Duration) {
+    *lock(&TIMEOUT) = Some(timeout);
+}
+
+fn drain(ctx: &Context) -> Result<(), BoxError> {
+    TRACKER.close();
+    let timeout = lock(&TIMEOUT).and_then(|timeout| Instant::now().checked_add(timeout));
+    let deadline = match (ctx.deadline(), timeout) {
+        (Some(deadline), Some(timeout)) => Some(deadline.min(timeout)),
+        (deadline, timeout) => deadline.or(timeout),
+    };
+    match block_on(TRACKER.wait(), deadline) {
+        Some(()) => Ok(()),
+        None => {
+            let message = format!("{} task(s) still running", TRACKER.len());
+            Err(io::Error::new(io::ErrorKind::TimedOut, message).into())
+        }
+    }
+}
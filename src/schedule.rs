@@ -0,0 +1,187 @@
+//! Periodic tasks that stop with the shutdown.
+//!
+//! Tasks added with [every](fn.every.html) run one at a time on a single
+//! scheduler thread, instead of on timer threads of their own that keep
+//! going while the process shuts down. Once the shutdown starts no more
+//! ticks are run, and the task running at that moment, if any, is waited
+//! for in the [drain](../hooks/constant.DRAIN.html) phase, up to the phase
+//! deadline.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//!
+//! graceful::schedule::every("refresh cache", Duration::from_secs(60), || {
+//!     // ...
+//! });
+//! ```
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::task::{Wake, Waker};
+use std::thread as std_thread;
+use std::time::{Duration, Instant};
+
+use error::{BoxError, IntoResult};
+use hooks::{self, Context};
+use state::{self, SHARED};
+use sync::{lock, wait, wait_timeout};
+use thread::{join_deadline, spawn_with, JoinHandle};
+
+type TaskFn = Box<dyn FnMut() -> Result<(), BoxError> + Send>;
+
+struct Task {
+    name: String,
+    interval: Duration,
+    next: Instant,
+    /// Taken out while the task runs.
+    func: Option<TaskFn>,
+}
+
+#[derive(Default)]
+struct Tasks {
+    tasks: Vec<Task>,
+    running: Option<String>,
+}
+
+lazy_static! {
+    static ref TASKS: Mutex<Tasks> = Mutex::new(Tasks::default());
+    static ref COND: Condvar = Condvar::new();
+    static ref SCHEDULER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+static START: Once = Once::new();
+
+/// Wakes the scheduler when the shutdown starts.
+struct Stop;
+
+impl Wake for Stop {
+    fn wake(self: Arc<Self>) {
+        let _tasks = lock(&TASKS);
+        COND.notify_all();
+    }
+}
+
+/// Run `f` every `interval`, the first time one `interval` from now. Ticks
+/// missed while a task was running are skipped rather than run late.
+///
+/// The task may return `()` or any `Result<(), E>`; errors and panics do
+/// not stop it from running at the next tick. An `interval` too long to
+/// add to the current time never comes due.
+///
+/// # Panics
+///
+/// Panics if `interval` is zero, or if the scheduler thread cannot be
+/// started.
+pub fn every<F, R>(name: &str, interval: Duration, mut f: F)
+where
+    F: FnMut() -> R + Send + 'static,
+    R: IntoResult,
+{
+    assert!(
+        interval > Duration::from_secs(0),
+        "task interval must be positive"
+    );
+    START.call_once(start);
+    let next = match Instant::now().checked_add(interval) {
+        Some(next) => next,
+        None => return,
+    };
+    lock(&TASKS).tasks.push(Task {
+        name: name.to_owned(),
+        interval,
+        next,
+        func: Some(Box::new(move || f().into_result())),
+    });
+    COND.notify_all();
+}
+
+fn start() {
+    {
+        let mut history = lock(&SHARED.history);
+        if history.signals.is_empty() {
+            history.wakers.push(Waker::from(Arc::new(Stop)));
+        }
+    }
+    let builder = std_thread::Builder::new().name("graceful: scheduler".to_owned());
+    let handle = spawn_with(builder, run).expect("failed to spawn the scheduler thread");
+    *lock(&SCHEDULER) = Some(handle);
+    hooks::phase(hooks::DRAIN).hook_once("graceful: scheduler", finish);
+}
+
+fn run() {
+    let mut tasks = lock(&TASKS);
+    loop {
+        if state::is_shutting_down() {
+            return;
+        }
+        let now = Instant::now();
+        let due = tasks
+            .tasks
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, task)| task.next)
+            .map(|(index, task)| (index, task.next));
+        let index = match due {
+            Some((index, next)) if next <= now => index,
+            Some((_, next)) => {
+                tasks = wait_timeout(&COND, tasks, next - now);
+                continue;
+            }
+            None => {
+                tasks = wait(&COND, tasks);
+                continue;
+            }
+        };
+
+        let mut func = tasks.tasks[index].func.take().expect("task is not running");
+        tasks.running = Some(tasks.tasks[index].name.clone());
+        drop(tasks);
+        let _ = panic::catch_unwind(AssertUnwindSafe(&mut func));
+        tasks = lock(&TASKS);
+        tasks.running = None;
+
+        let task = &mut tasks.tasks[index];
+        task.func = Some(func);
+        let now = Instant::now();
+        while task.next <= now {
+            task.next += task.interval;
+        }
+    }
+}
+
+/// Wait for the task running when the shutdown started.
+fn finish(ctx: &Context) -> Result<(), BoxError> {
+    let handle = match lock(&SCHEDULER).take() {
+        Some(handle) => handle,
+        None => return Ok(()),
+    };
+    match ctx.deadline() {
+        Some(deadline) => match join_deadline(handle, deadline) {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                let running = lock(&TASKS).running.clone().unwrap_or_default();
+                let message = format!("task {} still running", running);
+                Err(io::Error::new(io::ErrorKind::TimedOut, message).into())
+            }
+        },
+        None => {
+            let _ = handle.join();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "task interval must be positive")]
+    fn rejects_a_zero_interval() {
+        every("busy", Duration::from_secs(0), || {});
+    }
+}
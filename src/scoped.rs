@@ -0,0 +1,106 @@
+//! A scoped entry point that sets up the guard before anything else runs.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::panic_message;
+use exit;
+use guard::SignalGuard;
+use report::ShutdownReport;
+use token::ShutdownToken;
+
+/// How often the waiting thread checks whether the work has returned.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How [run](fn.run.html) ended.
+#[derive(Debug)]
+pub enum ExitReason {
+    /// The work returned before any terminal signal arrived.
+    Completed,
+    /// A terminal signal arrived, and the shutdown ran as reported.
+    Signaled(ShutdownReport),
+    /// The work panicked, with the message of the panic.
+    Panicked(String),
+}
+
+impl ExitReason {
+    /// The exit status that reflects how the run ended: `0` once the work
+    /// completed, `101` if it panicked, and
+    /// [ShutdownReport::exit_code](struct.ShutdownReport.html#method.exit_code)
+    /// after a shutdown.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ExitReason::Completed => 0,
+            ExitReason::Signaled(ref report) => report.exit_code(),
+            ExitReason::Panicked(_) => 101,
+        }
+    }
+}
+
+/// Block the termination signals, then run `work` on a thread of its own
+/// and wait for it to return or for a signal, whichever comes first.
+///
+/// Once a signal arrives the token is tripped and the shutdown hooks run,
+/// then `work` is waited for. Threads it starts inherit the blocked
+/// signals, as the guard is made before it runs; those it starts in a
+/// `std::thread::scope` are joined along with it:
+///
+/// ```no_run
+/// # extern crate graceful;
+/// # fn handle_job(_: u32) {}
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// fn main() {
+///     let reason = graceful::run(|shutdown| {
+///         thread::scope(|scope| {
+///             for worker in 0..4 {
+///                 let shutdown = shutdown.clone();
+///                 scope.spawn(move || {
+///                     while !shutdown.wait_timeout(Duration::from_millis(100)) {
+///                         handle_job(worker);
+///                     }
+///                 });
+///             }
+///         });
+///     });
+///     std::process::exit(reason.exit_code());
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the signals cannot be blocked, see
+/// [SignalGuard::try_new](struct.SignalGuard.html#method.try_new), or
+/// waiting for them fails.
+pub fn run<F>(work: F) -> ExitReason
+where
+    F: FnOnce(ShutdownToken) + Send,
+{
+    let guard = SignalGuard::new();
+    let token = guard.token();
+    let reason = thread::scope(|scope| {
+        let worker = scope.spawn(move || work(token));
+        loop {
+            if worker.is_finished() {
+                return match worker.join() {
+                    Ok(()) => ExitReason::Completed,
+                    Err(payload) => ExitReason::Panicked(panic_message(&*payload)),
+                };
+            }
+            let received = guard
+                .next_signal(Some(Instant::now() + POLL_INTERVAL))
+                .unwrap_or_else(|err| panic!("graceful: {}", err));
+            if let Some((signal, origin)) = received {
+                let report = guard.finish(signal, origin);
+                return match worker.join() {
+                    Ok(()) => ExitReason::Signaled(report),
+                    Err(payload) => ExitReason::Panicked(panic_message(&*payload)),
+                };
+            }
+        }
+    });
+    exit::run_last_rites();
+    guard.release();
+    reason
+}
@@ -0,0 +1,151 @@
+//! Shutdown hooks written as Rhai scripts.
+//!
+//! [load_dir](fn.load_dir.html) adds every `*.rhai` file of a directory as a
+//! hook of a phase, so operators can add cleanup steps without rebuilding
+//! the service. Scripts run in a fresh engine each time, without access to
+//! the file system or to other modules, and are stopped once their timeout
+//! or the phase deadline passes. Each script sees the constants `signal`
+//! and `phase`, with the names of the signal and of the running phase, and
+//! `rehearsal`, true in a
+//! [rehearsal](../hooks/struct.Coordinator.html#method.rehearse).
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::hooks;
+//!
+//! graceful::script::load_dir("/etc/myservice/shutdown.d", hooks::CLOSE, Duration::from_secs(2))
+//!     .unwrap();
+//! ```
+//!
+//! with for example `/etc/myservice/shutdown.d/announce.rhai`:
+//!
+//! ```text
+//! print(`stopping on ${signal} in ${phase}`);
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Scope};
+
+use error::BoxError;
+use hooks::{self, Context};
+
+/// Add every `*.rhai` file in `dir` as a hook of `phase`, named after the
+/// file and run in file name order. Returns how many were added.
+///
+/// Scripts are read and checked for syntax errors now, so a broken script
+/// is reported at startup rather than during the shutdown.
+pub fn load_dir<P: AsRef<Path>>(dir: P, phase: &str, timeout: Duration) -> io::Result<usize> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "rhai") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut scripts = Vec::with_capacity(paths.len());
+    for path in paths {
+        let source = fs::read_to_string(&path)?;
+        if let Err(err) = engine(None).compile(&source) {
+            let message = format!("{}: {}", path.display(), err);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        scripts.push((name, source));
+    }
+
+    let count = scripts.len();
+    let phase = hooks::phase(phase);
+    for (name, source) in scripts {
+        phase.hook(&name, move |ctx: &Context| run(&source, timeout, ctx));
+    }
+    Ok(count)
+}
+
+/// A sandboxed engine, stopping scripts at `deadline` if there is one.
+fn engine(deadline: Option<Instant>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    if let Some(deadline) = deadline {
+        engine.on_progress(move |_| {
+            if Instant::now() >= deadline {
+                Some(Dynamic::UNIT)
+            } else {
+                None
+            }
+        });
+    }
+    engine
+}
+
+fn run(source: &str, timeout: Duration, ctx: &Context) -> Result<(), BoxError> {
+    let mut deadline = Instant::now().checked_add(timeout);
+    if let Some(phase_deadline) = ctx.deadline() {
+        deadline = Some(deadline.map_or(phase_deadline, |own| own.min(phase_deadline)));
+    }
+    let mut scope = Scope::new();
+    scope.push_constant("signal", ctx.signal().to_string());
+    scope.push_constant("phase", ctx.phase().to_owned());
+    scope.push_constant("rehearsal", ctx.is_rehearsal());
+    engine(deadline)
+        .run_with_scope(&mut scope, source)
+        .map_err(|err| err.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use hooks::{Coordinator, CLOSE};
+    use report::ShutdownReport;
+    use signal::Signal;
+
+    fn run_script(source: &'static str) -> ShutdownReport {
+        let coordinator = Coordinator::with_phases(&[CLOSE]);
+        coordinator
+            .phase(CLOSE)
+            .hook("script", move |ctx: &Context| {
+                run(source, Duration::from_millis(50), ctx)
+            });
+        coordinator.run(Signal::Terminate)
+    }
+
+    #[test]
+    fn scripts_see_the_signal_and_the_phase() {
+        let report = run_script(
+            r#"if signal != "SIGTERM" || phase != "close" || rehearsal { throw "wrong scope"; }"#,
+        );
+        assert!(report.is_clean());
+        assert!(!run_script(r#"throw "failed";"#).is_clean());
+    }
+
+    #[test]
+    fn stops_scripts_at_the_timeout() {
+        let report = run_script("loop {}");
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn rejects_broken_scripts_when_loading() {
+        let dir = env::temp_dir().join(format!("graceful-scripts-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("broken.rhai"), "let = ;").unwrap();
+        let err = load_dir(&dir, CLOSE, Duration::from_secs(1)).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("broken.rhai"), "{}", err);
+    }
+}
@@ -0,0 +1,160 @@
+//! Running as a Windows service.
+//!
+//! A service has no console, so the console handler of the
+//! [SignalGuard](../struct.SignalGuard.html) never runs. Registering the
+//! service's control handler with [Service::register](struct.Service.html#method.register)
+//! from its `ServiceMain` hands the stop requests of the service control
+//! manager to the guard instead, and the program shuts down along the same
+//! path as in a console:
+//!
+//! | Control                       | [Signal](../enum.Signal.html) |
+//! |-------------------------------|-------------------------------|
+//! | `SERVICE_CONTROL_STOP`        | `Terminate`                   |
+//! | `SERVICE_CONTROL_SHUTDOWN`    | `Shutdown`                    |
+//! | `SERVICE_CONTROL_PRESHUTDOWN` | `Shutdown`                    |
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use graceful::service::Service;
+//! use graceful::SignalGuard;
+//!
+//! // Called by `StartServiceCtrlDispatcherW`, for example through the
+//! // `windows-service` crate.
+//! fn service_main() {
+//!     let signal_guard = SignalGuard::new();
+//!     let service = Service::register("MyService").unwrap();
+//!     let report = signal_guard.try_wait_report();
+//!     let _ = service.stopped(graceful::exit::ExitCode::exit_code(&report) as u32);
+//! }
+//! # fn main() { service_main() }
+//! ```
+
+extern crate winapi;
+
+use std::ffi::OsStr;
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use self::winapi::shared::minwindef::{DWORD, LPVOID};
+use self::winapi::shared::winerror::{
+    ERROR_CALL_NOT_IMPLEMENTED, ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR,
+};
+use self::winapi::um::winnt::SERVICE_WIN32_OWN_PROCESS;
+use self::winapi::um::winsvc::{
+    RegisterServiceCtrlHandlerExW, SetServiceStatus, SERVICE_ACCEPT_PRESHUTDOWN,
+    SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_CONTROL_INTERROGATE,
+    SERVICE_CONTROL_PRESHUTDOWN, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_RUNNING,
+    SERVICE_STATUS, SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+};
+
+use hooks;
+use platform;
+use signal::{ConsoleEvent, Signal};
+use sync::lock;
+
+/// The wait hint sent with `SERVICE_STOP_PENDING` when there is no grace
+/// period.
+const DEFAULT_WAIT_HINT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    /// The status handle, for the control handler.
+    static ref HANDLE: Mutex<Option<usize>> = Mutex::new(None);
+}
+
+/// The service registered with the service control manager.
+#[derive(Debug)]
+pub struct Service(usize);
+
+impl Service {
+    /// Register the control handler of the service `name` and report it
+    /// running. Call it from the `ServiceMain` of the service, after the
+    /// guard has been created.
+    pub fn register(name: &str) -> io::Result<Service> {
+        let name: Vec<u16> = OsStr::new(name)
+            .encode_wide()
+            .chain(iter::once(0))
+            .collect();
+        let handle =
+            unsafe { RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control), ptr::null_mut()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let handle = handle as usize;
+        *lock(&HANDLE) = Some(handle);
+        set_status(handle, SERVICE_RUNNING, NO_ERROR, 0, Duration::from_secs(0))?;
+        Ok(Service(handle))
+    }
+
+    /// Report the service stopped, with `exit_code` as its service-specific
+    /// exit code unless it is `0`. The process may be ended once this has
+    /// been reported, so do it last.
+    pub fn stopped(&self, exit_code: u32) -> io::Result<()> {
+        let win32 = if exit_code == 0 {
+            NO_ERROR
+        } else {
+            ERROR_SERVICE_SPECIFIC_ERROR
+        };
+        set_status(
+            self.0,
+            SERVICE_STOPPED,
+            win32,
+            exit_code,
+            Duration::from_secs(0),
+        )
+    }
+}
+
+fn set_status(
+    handle: usize,
+    state: DWORD,
+    win32: DWORD,
+    specific: DWORD,
+    wait_hint: Duration,
+) -> io::Result<()> {
+    let mut status: SERVICE_STATUS = unsafe { mem::zeroed() };
+    status.dwServiceType = SERVICE_WIN32_OWN_PROCESS;
+    status.dwCurrentState = state;
+    status.dwControlsAccepted = match state {
+        SERVICE_RUNNING => {
+            SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN | SERVICE_ACCEPT_PRESHUTDOWN
+        }
+        _ => 0,
+    };
+    status.dwWin32ExitCode = win32;
+    status.dwServiceSpecificExitCode = specific;
+    status.dwWaitHint = wait_hint.as_millis().min(DWORD::MAX as u128) as DWORD;
+    if unsafe { SetServiceStatus(handle as SERVICE_STATUS_HANDLE, &mut status) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Runs on the dispatcher thread for every control, which has to return
+/// at once: the shutdown goes on on the guard's thread. Nothing in here can
+/// panic.
+unsafe extern "system" fn control(
+    control: DWORD,
+    _event_type: DWORD,
+    _event_data: LPVOID,
+    _context: LPVOID,
+) -> DWORD {
+    let event = match control {
+        SERVICE_CONTROL_STOP => ConsoleEvent::Close,
+        SERVICE_CONTROL_SHUTDOWN | SERVICE_CONTROL_PRESHUTDOWN => ConsoleEvent::Shutdown,
+        SERVICE_CONTROL_INTERROGATE => return NO_ERROR,
+        _ => return ERROR_CALL_NOT_IMPLEMENTED,
+    };
+    if let Some(handle) = *lock(&HANDLE) {
+        let wait_hint = hooks::coordinator()
+            .grace_period_for(Signal::from(event))
+            .unwrap_or(DEFAULT_WAIT_HINT);
+        let _ = set_status(handle, SERVICE_STOP_PENDING, NO_ERROR, 0, wait_hint);
+    }
+    platform::deliver(event.as_raw());
+    NO_ERROR
+}
@@ -0,0 +1,260 @@
+//! Keeping in-memory sessions and caches across restarts.
+//!
+//! [save_at_exit](fn.save_at_exit.html) writes records to a file in the
+//! [FLUSH](../hooks/constant.FLUSH.html) phase, and [load](fn.load.html)
+//! reads them back on the next start, so a restart does not log everyone
+//! out. A [Limits](struct.Limits.html) caps the size of the file and the time
+//! spent writing it; records past either cap are left out and the file
+//! stays readable. Records are opaque bytes, serialized however the
+//! application likes.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::collections::HashMap;
+//! use std::sync::{Arc, Mutex};
+//! use std::time::Duration;
+//! use graceful::session::{self, Limits};
+//!
+//! let sessions: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+//!
+//! session::load("sessions.bin", |record: &[u8]| {
+//!     let record = String::from_utf8_lossy(record);
+//!     if let Some((id, user)) = record.split_once('=') {
+//!         sessions.lock().unwrap().insert(id.to_owned(), user.to_owned());
+//!     }
+//! })
+//! .unwrap();
+//!
+//! let limits = Limits::new()
+//!     .max_bytes(64 << 20)
+//!     .max_time(Duration::from_secs(2));
+//! let saved = sessions.clone();
+//! session::save_at_exit("sessions.bin", limits, move |sink| -> std::io::Result<()> {
+//!     for (id, user) in saved.lock().unwrap().iter() {
+//!         if !sink.write(format!("{}={}", id, user).as_bytes())? {
+//!             break;
+//!         }
+//!     }
+//!     Ok(())
+//! });
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use checkpoint::write_atomically;
+use error::{BoxError, IntoResult};
+use hooks::{self, Context};
+
+/// Caps on what [save_at_exit](fn.save_at_exit.html) writes. None by
+/// default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    max_bytes: Option<u64>,
+    max_time: Option<Duration>,
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// Leave out the records that would make the file larger than `bytes`.
+    pub fn max_bytes(mut self, bytes: u64) -> Limits {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Stop writing records after `time`, or at the phase deadline if that
+    /// comes first.
+    pub fn max_time(mut self, time: Duration) -> Limits {
+        self.max_time = Some(time);
+        self
+    }
+}
+
+/// Where [save_at_exit](fn.save_at_exit.html) writes records.
+pub struct Sink<'a> {
+    out: &'a mut BufWriter<File>,
+    written: u64,
+    max_bytes: Option<u64>,
+    deadline: Option<Instant>,
+    full: bool,
+}
+
+impl<'a> Sink<'a> {
+    /// Write one record, returning `false` without writing it once a cap
+    /// has been reached.
+    pub fn write(&mut self, record: &[u8]) -> io::Result<bool> {
+        let len = 4 + record.len() as u64;
+        let over_size = self.max_bytes.is_some_and(|max| self.written + len > max);
+        let over_time = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if self.full || over_size || over_time || record.len() > u32::MAX as usize {
+            self.full = true;
+            return Ok(false);
+        }
+        self.out.write_all(&(record.len() as u32).to_le_bytes())?;
+        self.out.write_all(record)?;
+        self.written += len;
+        Ok(true)
+    }
+
+    /// Whether a cap has been reached, and no more records are written.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+}
+
+/// Save records to `path` at shutdown by calling `save` with a
+/// [Sink](struct.Sink.html), within `limits`. The previous file is only
+/// replaced once the new one is complete.
+///
+/// The function may return `()` or any `Result<(), E>`.
+pub fn save_at_exit<P, F, R>(path: P, limits: Limits, mut save: F)
+where
+    P: AsRef<Path>,
+    F: FnMut(&mut Sink) -> R + Send + 'static,
+    R: IntoResult,
+{
+    let path = path.as_ref().to_owned();
+    let name = format!("graceful: sessions {}", path.display());
+    hooks::phase(hooks::FLUSH).hook(&name, move |ctx: &Context| {
+        save_within(&path, limits, ctx, &mut save)
+    });
+}
+
+fn save_within<F, R>(
+    path: &Path,
+    limits: Limits,
+    ctx: &Context,
+    save: &mut F,
+) -> Result<(), BoxError>
+where
+    F: FnMut(&mut Sink) -> R,
+    R: IntoResult,
+{
+    let mut deadline = limits
+        .max_time
+        .and_then(|time| Instant::now().checked_add(time));
+    if let Some(phase_deadline) = ctx.deadline() {
+        deadline = Some(deadline.map_or(phase_deadline, |own| own.min(phase_deadline)));
+    }
+    write_atomically(path, |out| {
+        let mut sink = Sink {
+            out,
+            written: 0,
+            max_bytes: limits.max_bytes,
+            deadline,
+            full: false,
+        };
+        save(&mut sink).into_result()
+    })
+}
+
+/// Call `restore` with every record saved at `path`, returning how many
+/// there were, or `0` if there is no file.
+///
+/// The function may return `()` or any `Result<(), E>`, the first error
+/// stops the load.
+pub fn load<P, F, R>(path: P, mut restore: F) -> Result<usize, BoxError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[u8]) -> R,
+    R: IntoResult,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut input = BufReader::new(file);
+    let mut record = Vec::new();
+    let mut count = 0;
+    loop {
+        let mut len = [0; 4];
+        match input.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(count),
+            Err(err) => return Err(err.into()),
+        }
+        record.resize(u32::from_le_bytes(len) as usize, 0);
+        input.read_exact(&mut record)?;
+        restore(&record).into_result()?;
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+    use hooks::{Coordinator, FLUSH};
+    use signal::Signal;
+
+    fn save_and_load(name: &str, limits: Limits, sessions: &[&str]) -> Vec<String> {
+        let path = env::temp_dir().join(format!("graceful-{}-{}.bin", name, process::id()));
+        let sessions: Vec<String> = sessions.iter().map(|&session| session.to_owned()).collect();
+        let saved = path.clone();
+        let coordinator = Coordinator::with_phases(&[FLUSH]);
+        coordinator
+            .phase(FLUSH)
+            .hook("sessions", move |ctx: &Context| {
+                save_within(
+                    &saved,
+                    limits,
+                    ctx,
+                    &mut |sink: &mut Sink| -> io::Result<()> {
+                        for session in &sessions {
+                            if !sink.write(session.as_bytes())? {
+                                assert!(sink.is_full());
+                                break;
+                            }
+                        }
+                        Ok(())
+                    },
+                )
+            });
+        assert!(coordinator.run(Signal::Terminate).is_clean());
+
+        let mut loaded = Vec::new();
+        let count = load(&path, |record: &[u8]| {
+            loaded.push(String::from_utf8(record.to_owned()).unwrap())
+        })
+        .unwrap();
+        assert_eq!(count, loaded.len());
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn loads_the_sessions_saved_at_shutdown() {
+        let sessions = ["alice", "", "bob"];
+        assert_eq!(
+            save_and_load("sessions", Limits::new(), &sessions),
+            sessions
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_sessions_past_the_size_cap() {
+        let limits = Limits::new().max_bytes(2 * 4 + 10);
+        let loaded = save_and_load("capped-sessions", limits, &["alice", "bob", "carol"]);
+        assert_eq!(loaded, ["alice", "bob"]);
+    }
+
+    #[test]
+    fn loads_nothing_without_a_file() {
+        let path = env::temp_dir().join(format!("graceful-no-sessions-{}.bin", process::id()));
+        assert_eq!(
+            load(&path, |_: &[u8]| -> () { panic!("no records") }).unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+
+#[cfg(unix)]
+use libc;
+
+const CTRL_C_EVENT: u32 = 0;
+const CTRL_BREAK_EVENT: u32 = 1;
+const CTRL_CLOSE_EVENT: u32 = 2;
+const CTRL_LOGOFF_EVENT: u32 = 5;
+const CTRL_SHUTDOWN_EVENT: u32 = 6;
+
+/// A Windows console control event.
+///
+/// Defined on every platform so portable code can match on it without `cfg`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConsoleEvent {
+    /// `CTRL_C_EVENT`
+    CtrlC,
+    /// `CTRL_BREAK_EVENT`
+    CtrlBreak,
+    /// `CTRL_CLOSE_EVENT`, the console window is being closed.
+    Close,
+    /// `CTRL_LOGOFF_EVENT`, only received by services.
+    Logoff,
+    /// `CTRL_SHUTDOWN_EVENT`, only received by services.
+    Shutdown,
+    /// Any other event code.
+    Other(u32),
+}
+
+impl ConsoleEvent {
+    pub fn from_raw(event: u32) -> ConsoleEvent {
+        match event {
+            CTRL_C_EVENT => ConsoleEvent::CtrlC,
+            CTRL_BREAK_EVENT => ConsoleEvent::CtrlBreak,
+            CTRL_CLOSE_EVENT => ConsoleEvent::Close,
+            CTRL_LOGOFF_EVENT => ConsoleEvent::Logoff,
+            CTRL_SHUTDOWN_EVENT => ConsoleEvent::Shutdown,
+            other => ConsoleEvent::Other(other),
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            ConsoleEvent::CtrlC => CTRL_C_EVENT,
+            ConsoleEvent::CtrlBreak => CTRL_BREAK_EVENT,
+            ConsoleEvent::Close => CTRL_CLOSE_EVENT,
+            ConsoleEvent::Logoff => CTRL_LOGOFF_EVENT,
+            ConsoleEvent::Shutdown => CTRL_SHUTDOWN_EVENT,
+            ConsoleEvent::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for ConsoleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConsoleEvent::CtrlC => f.write_str("CTRL_C_EVENT"),
+            ConsoleEvent::CtrlBreak => f.write_str("CTRL_BREAK_EVENT"),
+            ConsoleEvent::Close => f.write_str("CTRL_CLOSE_EVENT"),
+            ConsoleEvent::Logoff => f.write_str("CTRL_LOGOFF_EVENT"),
+            ConsoleEvent::Shutdown => f.write_str("CTRL_SHUTDOWN_EVENT"),
+            ConsoleEvent::Other(other) => write!(f, "console event {}", other),
+        }
+    }
+}
+
+/// A portable name for the signals and console events that ask a process to
+/// stop, so one policy can be written for both platforms.
+///
+/// | `Signal`    | Unix      | Windows               |
+/// |-------------|-----------|-----------------------|
+/// | `Interrupt` | `SIGINT`  | `CTRL_C_EVENT`        |
+/// | `Quit`      | `SIGQUIT` | `CTRL_BREAK_EVENT`    |
+/// | `Terminate` | `SIGTERM` | `CTRL_CLOSE_EVENT`    |
+/// | `Hangup`    | `SIGHUP`  |                       |
+/// | `User1`     | `SIGUSR1` |                       |
+/// | `User2`     | `SIGUSR2` |                       |
+/// | `Power`     | `SIGPWR`  |                       |
+/// | `Logoff`    |           | `CTRL_LOGOFF_EVENT`   |
+/// | `Shutdown`  |           | `CTRL_SHUTDOWN_EVENT` |
+///
+/// Anything else is carried as `Other` with its raw value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// `SIGINT` or `Ctrl+C`.
+    Interrupt,
+    /// `SIGQUIT` or `Ctrl+Break`.
+    Quit,
+    /// `SIGTERM` or the console window being closed.
+    Terminate,
+    /// `SIGHUP`, the controlling terminal went away.
+    Hangup,
+    /// `SIGUSR1`
+    User1,
+    /// `SIGUSR2`
+    User2,
+    /// `SIGPWR`, the power is failing (Linux), typically sent by a UPS
+    /// daemon.
+    Power,
+    /// The user is logging off (Windows).
+    Logoff,
+    /// The system is shutting down (Windows).
+    Shutdown,
+    /// A thread panicked, once
+    /// [SignalGuard::shutdown_on_panic](struct.SignalGuard.html#method.shutdown_on_panic)
+    /// was called. It is no signal of the system, and cannot be handled by
+    /// a guard.
+    Panic,
+    /// Any other raw signal number or event code.
+    Other(i32),
+}
+
+impl Signal {
+    /// `Ctrl+C`, the same as [Interrupt](#variant.Interrupt).
+    pub const CTRL_C: Signal = Signal::Interrupt;
+    /// `Ctrl+Break`, the same as [Quit](#variant.Quit).
+    pub const CTRL_BREAK: Signal = Signal::Quit;
+
+    /// The console event this signal is delivered as on Windows, if any.
+    pub fn console_event(self) -> Option<ConsoleEvent> {
+        match self {
+            Signal::Interrupt => Some(ConsoleEvent::CtrlC),
+            Signal::Quit => Some(ConsoleEvent::CtrlBreak),
+            Signal::Terminate => Some(ConsoleEvent::Close),
+            Signal::Logoff => Some(ConsoleEvent::Logoff),
+            Signal::Shutdown => Some(ConsoleEvent::Shutdown),
+            _ => None,
+        }
+    }
+
+    /// Map a raw signal number (Unix) or console event code (Windows).
+    #[cfg(unix)]
+    pub fn from_raw(signum: i32) -> Signal {
+        match signum {
+            libc::SIGINT => Signal::Interrupt,
+            libc::SIGQUIT => Signal::Quit,
+            libc::SIGTERM => Signal::Terminate,
+            libc::SIGHUP => Signal::Hangup,
+            libc::SIGUSR1 => Signal::User1,
+            libc::SIGUSR2 => Signal::User2,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            libc::SIGPWR => Signal::Power,
+            other => Signal::Other(other),
+        }
+    }
+
+    /// Map a raw signal number (Unix) or console event code (Windows).
+    #[cfg(windows)]
+    pub fn from_raw(event: i32) -> Signal {
+        ConsoleEvent::from_raw(event as u32).into()
+    }
+
+    /// The raw signal number (Unix) or console event code (Windows), or
+    /// `None` if this platform has no equivalent.
+    #[cfg(unix)]
+    pub fn raw(self) -> Option<i32> {
+        match self {
+            Signal::Interrupt => Some(libc::SIGINT),
+            Signal::Quit => Some(libc::SIGQUIT),
+            Signal::Terminate => Some(libc::SIGTERM),
+            Signal::Hangup => Some(libc::SIGHUP),
+            Signal::User1 => Some(libc::SIGUSR1),
+            Signal::User2 => Some(libc::SIGUSR2),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Signal::Power => Some(libc::SIGPWR),
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Signal::Power => None,
+            Signal::Logoff | Signal::Shutdown | Signal::Panic => None,
+            Signal::Other(other) => Some(other),
+        }
+    }
+
+    /// The raw signal number (Unix) or console event code (Windows), or
+    /// `None` if this platform has no equivalent.
+    #[cfg(windows)]
+    pub fn raw(self) -> Option<i32> {
+        match self {
+            Signal::Other(event) => Some(event),
+            _ => self.console_event().map(|event| event.as_raw() as i32),
+        }
+    }
+}
+
+/// The process that sent a signal, where the platform tells (Linux).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Origin {
+    pid: u32,
+    uid: u32,
+}
+
+impl Origin {
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    pub(crate) fn new(pid: u32, uid: u32) -> Origin {
+        Origin { pid, uid }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// The real user ID of the sending process.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pid {} uid {}", self.pid, self.uid)
+    }
+}
+
+impl From<ConsoleEvent> for Signal {
+    fn from(event: ConsoleEvent) -> Signal {
+        match event {
+            ConsoleEvent::CtrlC => Signal::Interrupt,
+            ConsoleEvent::CtrlBreak => Signal::Quit,
+            ConsoleEvent::Close => Signal::Terminate,
+            ConsoleEvent::Logoff => Signal::Logoff,
+            ConsoleEvent::Shutdown => Signal::Shutdown,
+            ConsoleEvent::Other(other) => Signal::Other(other as i32),
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    #[cfg(unix)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Signal::Interrupt => f.write_str("SIGINT"),
+            Signal::Quit => f.write_str("SIGQUIT"),
+            Signal::Terminate => f.write_str("SIGTERM"),
+            Signal::Hangup => f.write_str("SIGHUP"),
+            Signal::User1 => f.write_str("SIGUSR1"),
+            Signal::User2 => f.write_str("SIGUSR2"),
+            Signal::Power => f.write_str("SIGPWR"),
+            Signal::Logoff => f.write_str("logoff"),
+            Signal::Shutdown => f.write_str("shutdown"),
+            Signal::Panic => f.write_str("panic"),
+            Signal::Other(other) => write!(f, "signal {}", other),
+        }
+    }
+
+    #[cfg(windows)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (*self, self.console_event()) {
+            (Signal::Other(other), _) => write!(f, "console event {}", other),
+            (Signal::Panic, _) => f.write_str("panic"),
+            (_, Some(event)) => event.fmt(f),
+            (signal, None) => write!(f, "{:?}", signal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_events_round_trip_their_codes() {
+        for code in 0..8 {
+            assert_eq!(ConsoleEvent::from_raw(code).as_raw(), code);
+        }
+        assert_eq!(ConsoleEvent::from_raw(1), ConsoleEvent::CtrlBreak);
+        assert_eq!(ConsoleEvent::from_raw(3), ConsoleEvent::Other(3));
+        assert_eq!(ConsoleEvent::Close.to_string(), "CTRL_CLOSE_EVENT");
+    }
+
+    #[test]
+    fn console_events_have_a_portable_signal() {
+        for code in 0..8 {
+            let event = ConsoleEvent::from_raw(code);
+            match Signal::from(event) {
+                Signal::Other(other) => assert_eq!(other as u32, code),
+                signal => assert_eq!(signal.console_event(), Some(event)),
+            }
+        }
+        assert_eq!(Signal::CTRL_C, Signal::from(ConsoleEvent::CtrlC));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn signals_round_trip_their_numbers() {
+        for signum in 1..32 {
+            assert_eq!(Signal::from_raw(signum).raw(), Some(signum));
+        }
+        assert_eq!(Signal::from_raw(libc::SIGTERM), Signal::Terminate);
+        assert_eq!(Signal::Terminate.to_string(), "SIGTERM");
+        assert_eq!(Signal::Logoff.raw(), None);
+        assert_eq!(Signal::Panic.raw(), None);
+    }
+}
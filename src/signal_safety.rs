@@ -0,0 +1,106 @@
+//! Checking that signal handlers stay async-signal-safe.
+//!
+//! The guard accepts signals synchronously, so hooks and handlers run on
+//! ordinary threads and may do anything. A handler installed with
+//! `sigaction`, such as the one [wakeup](../wakeup/index.html) installs,
+//! runs on top of whatever the interrupted thread was doing instead, and
+//! allocating or taking a lock in there can deadlock or corrupt the heap.
+//! Such bugs rarely show in testing, so with this feature the code run in
+//! those handlers is marked, and anything unsafe done there aborts the
+//! process with a message:
+//!
+//! * allocating, freeing or reallocating memory through
+//!   [AuditAllocator](struct.AuditAllocator.html), installed as the global
+//!   allocator of a test binary,
+//! * taking one of the crate's locks.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::alloc::System;
+//!
+//! use graceful::signal_safety::AuditAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: AuditAllocator = AuditAllocator(System);
+//! # fn main() {}
+//! ```
+//!
+//! Handlers of one's own can be marked with [enter](fn.enter.html).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use libc;
+
+thread_local! {
+    // Constant-initialized and without a destructor, so it is a plain
+    // thread-local load, safe in a signal handler.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether the current thread is running a signal handler marked with
+/// [enter](fn.enter.html).
+pub fn in_signal_handler() -> bool {
+    DEPTH.with(Cell::get) > 0
+}
+
+/// Mark the current thread as running a signal handler until the scope is
+/// dropped. Call it first thing in the handler.
+pub fn enter() -> HandlerScope {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    HandlerScope(PhantomData)
+}
+
+/// The signal handler marked by [enter](fn.enter.html).
+#[derive(Debug)]
+#[must_use = "the handler is only marked until this is dropped"]
+pub struct HandlerScope(PhantomData<*const ()>);
+
+impl Drop for HandlerScope {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Abort if a signal handler is running on this thread, naming `what` it
+/// was about to do.
+pub(crate) fn assert_safe(what: &str) {
+    if in_signal_handler() {
+        // `write` and `abort` are themselves async-signal-safe.
+        let prefix = b"graceful: not async-signal-safe in a signal handler: ";
+        unsafe {
+            libc::write(2, prefix.as_ptr() as *const libc::c_void, prefix.len());
+            libc::write(2, what.as_ptr() as *const libc::c_void, what.len());
+            libc::write(2, b"\n".as_ptr() as *const libc::c_void, 1);
+            libc::abort();
+        }
+    }
+}
+
+/// A global allocator that aborts when used in a marked signal handler,
+/// and otherwise forwards to the wrapped one.
+#[derive(Debug, Default)]
+pub struct AuditAllocator<A = System>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for AuditAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        assert_safe("allocating memory");
+        self.0.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        assert_safe("freeing memory");
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        assert_safe("allocating memory");
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        assert_safe("reallocating memory");
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,123 @@
+//! Snapshots of the program state, on demand and before shutting down.
+//!
+//! Snapshot hooks, such as a database dump or a state export, run together
+//! with their own timeout, separate from the grace period of the shutdown.
+//! They can be run at any time with [run](fn.run.html), when a signal is
+//! received with [on_signal](fn.on_signal.html), and as the first phase of
+//! the shutdown with [at_shutdown](fn.at_shutdown.html). Each run is
+//! reported to the [observers](../events/index.html) as a finished phase
+//! named [SNAPSHOT](constant.SNAPSHOT.html).
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//! use graceful::{snapshot, Signal, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::with_signals(&[Signal::User1]).unwrap();
+//! snapshot::register("state", || -> std::io::Result<()> {
+//!     std::fs::write("state.json", b"{}")
+//! });
+//! snapshot::set_timeout(Duration::from_secs(10));
+//! snapshot::on_signal(Signal::User1);
+//! snapshot::at_shutdown();
+//! signal_guard.at_exit(|_| {});
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use error::IntoResult;
+use hooks::{Context, Coordinator};
+use report::PhaseReport;
+use signal::Signal;
+use sync::lock;
+
+/// The name of the phase snapshots run in.
+pub const SNAPSHOT: &str = "snapshot";
+
+lazy_static! {
+    static ref SNAPSHOTS: Coordinator = Coordinator::with_phases(&[SNAPSHOT]);
+    static ref SIGNAL: Mutex<Option<Signal>> = Mutex::new(None);
+}
+
+static AT_SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Set while a run started by a signal is going on.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Add a snapshot hook. Hooks run concurrently.
+///
+/// The hook may return `()` or any `Result<(), E>`.
+pub fn register<F, R>(name: &str, mut f: F)
+where
+    F: FnMut() -> R + Send + 'static,
+    R: IntoResult,
+{
+    SNAPSHOTS.phase(SNAPSHOT).hook(name, move |_: &Context| f());
+}
+
+/// Bound the time a snapshot may take. Hooks still running then are
+/// abandoned and reported as timed out.
+pub fn set_timeout(timeout: Duration) {
+    SNAPSHOTS.set_grace_period(timeout);
+}
+
+/// Take a snapshot when `signal` is received, on a thread of its own,
+/// instead of shutting down. A signal received while that snapshot is
+/// still running is ignored.
+///
+/// The guard must be handling `signal`, see
+/// [SignalGuard::with_signals](../struct.SignalGuard.html#method.with_signals).
+pub fn on_signal(signal: Signal) {
+    *lock(&SIGNAL) = Some(signal);
+}
+
+/// Take a snapshot as the first phase of the shutdown, before the
+/// [hooks](../hooks/index.html) of the coordinator.
+pub fn at_shutdown() {
+    AT_SHUTDOWN.store(true, Ordering::Relaxed);
+}
+
+/// Take a snapshot now, blocking until it is done. The hooks see `signal`
+/// in their context.
+pub fn run(signal: Signal) -> PhaseReport {
+    SNAPSHOTS
+        .run(signal)
+        .into_phases()
+        .next()
+        .expect("the snapshot phase")
+}
+
+pub(crate) fn run_at_shutdown(signal: Signal) -> Option<PhaseReport> {
+    if AT_SHUTDOWN.load(Ordering::Relaxed) {
+        Some(run(signal))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_signal(signal: Signal) -> bool {
+    *lock(&SIGNAL) == Some(signal)
+}
+
+/// Start a snapshot if `signal` is the one that triggers it. Returns
+/// `false` otherwise.
+pub(crate) fn claim(signal: Signal) -> bool {
+    if !is_signal(signal) {
+        return false;
+    }
+    if !RUNNING.swap(true, Ordering::AcqRel) {
+        let spawned = thread::Builder::new()
+            .name("graceful: snapshot".to_owned())
+            .spawn(move || {
+                run(signal);
+                RUNNING.store(false, Ordering::Release);
+            });
+        if spawned.is_err() {
+            RUNNING.store(false, Ordering::Release);
+        }
+    }
+    true
+}
@@ -0,0 +1,314 @@
+//! Starting up in phases that the shutdown unwinds.
+//!
+//! A [Startup](struct.Startup.html) runs its steps phase by phase, in the
+//! order of [PHASES](constant.PHASES.html) unless other phases are added.
+//! The program is [ready](fn.is_ready.html), and the service manager is
+//! told `READY=1` (Unix), only once every step has succeeded.
+//!
+//! A step may come with an unwind, which undoes it. Unwinds are registered
+//! as the steps complete, and run in reverse order in the
+//! [CLOSE](../hooks/constant.CLOSE.html) phase of the shutdown, so a
+//! shutdown that starts half way through the startup only unwinds what was
+//! done. If a step fails, the steps done so far are unwound at once
+//! instead.
+//!
+//! Signals are only accepted once the guard waits for them, after the
+//! startup. [run_guarded](struct.Startup.html#method.run_guarded) checks
+//! for them between steps instead, and rolls back what was done without
+//! running the shutdown hooks against a half-built program:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # struct Pool;
+//! # impl Pool { fn close(self) {} }
+//! # fn connect() -> std::io::Result<Pool> { Ok(Pool) }
+//! use std::net::TcpListener;
+//! use std::sync::{Arc, Mutex};
+//!
+//! use graceful::startup::{self, Started, Startup};
+//! use graceful::SignalGuard;
+//!
+//! let signal_guard = SignalGuard::new();
+//! let pool = Arc::new(Mutex::new(None));
+//! let listener = Arc::new(Mutex::new(None));
+//!
+//! let mut steps = Startup::new();
+//! let (opened, closed) = (pool.clone(), pool.clone());
+//! steps.phase(startup::INIT).step_with_unwind(
+//!     "database",
+//!     move || connect().map(|pool| *opened.lock().unwrap() = Some(pool)),
+//!     move || {
+//!         if let Some(pool) = closed.lock().unwrap().take() {
+//!             pool.close();
+//!         }
+//!     },
+//! );
+//! let bound = listener.clone();
+//! steps.phase(startup::BIND).step("http", move || {
+//!     TcpListener::bind("0.0.0.0:8080").map(|socket| *bound.lock().unwrap() = Some(socket))
+//! });
+//! match steps.run_guarded(&signal_guard) {
+//!     Ok(Started::Ready) => signal_guard.at_exit(|_| {}),
+//!     Ok(Started::Interrupted(signal)) | Ok(Started::ShuttingDown(signal)) => {
+//!         println!("stopped by {} while starting", signal)
+//!     }
+//!     Err(errors) => {
+//!         eprintln!("{}", errors);
+//!         std::process::exit(graceful::exit::EX_UNAVAILABLE);
+//!     }
+//! }
+//! ```
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+
+use error::{panic_message, BoxError, Failure, FailureKind, IntoResult, ShutdownErrors};
+use guard::SignalGuard;
+use hooks::{self, Context};
+#[cfg(unix)]
+use sd_notify;
+use signal::Signal;
+use state;
+use sync::lock;
+
+/// Prepare what the rest needs, such as the configuration and pools.
+pub const INIT: &str = "init";
+/// Bind the listening sockets.
+pub const BIND: &str = "bind";
+/// Fill caches and run anything else that should be done before traffic.
+pub const WARMUP: &str = "warmup";
+/// Announce the service, such as registering it for discovery.
+pub const READY: &str = "ready";
+
+/// The phases of a [Startup](struct.Startup.html), in run order.
+pub const PHASES: &[&str] = &[INIT, BIND, WARMUP, READY];
+
+static IS_READY: AtomicBool = AtomicBool::new(false);
+
+type Unwind = Box<dyn FnOnce() -> Result<(), BoxError> + Send>;
+
+struct Step {
+    name: String,
+    start: Box<dyn FnOnce() -> Result<(), BoxError>>,
+    unwind: Option<Unwind>,
+}
+
+struct PhaseEntry {
+    name: String,
+    steps: Vec<Step>,
+}
+
+lazy_static! {
+    /// The unwinds of the completed steps, in completion order.
+    static ref UNWINDS: Mutex<Vec<(String, Unwind)>> = Mutex::new(Vec::new());
+}
+
+static UNWIND_HOOK: Once = Once::new();
+
+/// Whether a [Startup](struct.Startup.html) has run to completion and the
+/// shutdown has not started.
+pub fn is_ready() -> bool {
+    IS_READY.load(Ordering::Acquire) && !state::is_shutting_down()
+}
+
+/// How a startup ended when no step failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Started {
+    /// Every step succeeded.
+    Ready,
+    /// A terminal signal arrived first, and the steps done were unwound.
+    /// Only from [run_guarded](struct.Startup.html#method.run_guarded).
+    Interrupted(Signal),
+    /// The shutdown started on this signal first, and the remaining steps
+    /// were skipped. The shutdown unwinds the steps done.
+    ShuttingDown(Signal),
+}
+
+/// Startup steps grouped into named phases.
+pub struct Startup {
+    phases: Vec<PhaseEntry>,
+}
+
+impl Default for Startup {
+    fn default() -> Startup {
+        Startup::with_phases(PHASES)
+    }
+}
+
+impl Startup {
+    /// A startup with the [default phases](constant.PHASES.html).
+    pub fn new() -> Startup {
+        Startup::default()
+    }
+
+    /// A startup with `phases` instead, in run order.
+    pub fn with_phases(phases: &[&str]) -> Startup {
+        let phases = phases
+            .iter()
+            .map(|&name| PhaseEntry {
+                name: name.to_owned(),
+                steps: Vec::new(),
+            })
+            .collect();
+        Startup { phases }
+    }
+
+    /// The phase called `name`, appended after the others if there is none
+    /// yet.
+    pub fn phase(&mut self, name: &str) -> StartupPhase<'_> {
+        let index = match self.phases.iter().position(|phase| phase.name == name) {
+            Some(index) => index,
+            None => {
+                self.phases.push(PhaseEntry {
+                    name: name.to_owned(),
+                    steps: Vec::new(),
+                });
+                self.phases.len() - 1
+            }
+        };
+        StartupPhase(&mut self.phases[index])
+    }
+
+    /// Run every step, phase by phase and in the order they were added.
+    ///
+    /// Stops at the first step that fails or panics, unwinding the steps
+    /// completed so far in reverse order, and returns that failure along
+    /// with those of the unwinds. Stops likewise, without an error of its
+    /// own, once the shutdown has started, and returns
+    /// [ShuttingDown](enum.Started.html#variant.ShuttingDown) rather than
+    /// [Ready](enum.Started.html#variant.Ready); the shutdown unwinds what
+    /// was done.
+    pub fn run(self) -> Result<Started, ShutdownErrors> {
+        self.run_until(None)
+    }
+
+    /// Like [run](#method.run), also checking for a signal of `guard`
+    /// before each step. Once one arrives the shutdown is marked as
+    /// started, and the steps done so far are unwound in reverse order
+    /// right away; the shutdown hooks do not run. A signal arriving during
+    /// a step is noticed once it returns.
+    ///
+    /// Signals taken by [on_signal](../struct.SignalGuard.html#method.on_signal)
+    /// handlers and the like are handed to them as usual.
+    pub fn run_guarded(self, guard: &SignalGuard) -> Result<Started, ShutdownErrors> {
+        self.run_until(Some(guard))
+    }
+
+    fn run_until(self, guard: Option<&SignalGuard>) -> Result<Started, ShutdownErrors> {
+        UNWIND_HOOK.call_once(|| {
+            hooks::phase(hooks::CLOSE).hook_once("graceful: startup unwind", |_: &Context| {
+                let mut failures = Vec::new();
+                unwind_all(lock(&UNWINDS).drain(..).collect(), &mut failures);
+                ShutdownErrors::from_failures(failures)
+            });
+        });
+        let mut unwindable = 0;
+        for phase in self.phases {
+            for step in phase.steps {
+                if let Some(signal) = guard.and_then(SignalGuard::poll_interrupt) {
+                    let mut failures = Vec::new();
+                    unwind_all(take_unwinds(unwindable), &mut failures);
+                    ShutdownErrors::from_failures(failures)?;
+                    return Ok(Started::Interrupted(signal));
+                }
+                if state::is_shutting_down() {
+                    return Ok(Started::ShuttingDown(state::first_signal()));
+                }
+                let name = format!("{}/{}", phase.name, step.name);
+                let start = step.start;
+                let result = panic::catch_unwind(AssertUnwindSafe(start));
+                let kind = match result {
+                    Ok(Ok(())) => {
+                        if let Some(unwind) = step.unwind {
+                            lock(&UNWINDS).push((name, unwind));
+                            unwindable += 1;
+                        }
+                        continue;
+                    }
+                    Ok(Err(err)) => FailureKind::Failed(err),
+                    Err(payload) => FailureKind::Panicked(panic_message(&*payload)),
+                };
+                let mut failures = vec![Failure::new(name, kind)];
+                unwind_all(take_unwinds(unwindable), &mut failures);
+                return ShutdownErrors::from_failures(failures).map(|()| Started::Ready);
+            }
+        }
+        IS_READY.store(true, Ordering::Release);
+        #[cfg(unix)]
+        let _ = sd_notify::notify("READY=1");
+        Ok(Started::Ready)
+    }
+}
+
+/// A phase of a [Startup](struct.Startup.html), to add steps to.
+pub struct StartupPhase<'a>(&'a mut PhaseEntry);
+
+impl<'a> StartupPhase<'a> {
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Add a step that has nothing to undo.
+    ///
+    /// The step may return `()` or any `Result<(), E>`.
+    pub fn step<F, R>(&mut self, name: &str, start: F) -> &mut StartupPhase<'a>
+    where
+        F: FnOnce() -> R + 'static,
+        R: IntoResult,
+    {
+        self.add(name, start, None);
+        self
+    }
+
+    /// Add a step undone by `unwind` once it has completed, at shutdown or
+    /// when a later step fails.
+    ///
+    /// Both may return `()` or any `Result<(), E>`.
+    pub fn step_with_unwind<F, R, U, S>(
+        &mut self,
+        name: &str,
+        start: F,
+        unwind: U,
+    ) -> &mut StartupPhase<'a>
+    where
+        F: FnOnce() -> R + 'static,
+        R: IntoResult,
+        U: FnOnce() -> S + Send + 'static,
+        S: IntoResult,
+    {
+        self.add(name, start, Some(Box::new(move || unwind().into_result())));
+        self
+    }
+
+    fn add<F, R>(&mut self, name: &str, start: F, unwind: Option<Unwind>)
+    where
+        F: FnOnce() -> R + 'static,
+        R: IntoResult,
+    {
+        self.0.steps.push(Step {
+            name: name.to_owned(),
+            start: Box::new(move || start().into_result()),
+            unwind,
+        });
+    }
+}
+
+/// Take the last `count` unwinds back from the shutdown.
+fn take_unwinds(count: usize) -> Vec<(String, Unwind)> {
+    let mut unwinds = lock(&UNWINDS);
+    let first = unwinds.len().saturating_sub(count);
+    unwinds.drain(first..).collect()
+}
+
+/// Run `unwinds` in reverse order, adding their failures to `failures`.
+fn unwind_all(unwinds: Vec<(String, Unwind)>, failures: &mut Vec<Failure>) {
+    for (name, unwind) in unwinds.into_iter().rev() {
+        let kind = match panic::catch_unwind(AssertUnwindSafe(unwind)) {
+            Ok(Ok(())) => continue,
+            Ok(Err(err)) => FailureKind::Failed(err),
+            Err(payload) => FailureKind::Panicked(panic_message(&*payload)),
+        };
+        failures.push(Failure::new(name, kind));
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::task::Waker;
+use std::time::Instant;
+
+use report::Cause;
+use signal::{Origin, Signal};
+use sync::{lock, wait};
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Every terminal signal received, the one that started the shutdown first.
+#[derive(Default)]
+pub(crate) struct History {
+    pub signals: Vec<Signal>,
+    /// The same signals, with where and when each came from.
+    pub causes: Vec<Cause>,
+    pub started: Option<Instant>,
+    pub wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+pub(crate) struct Shared {
+    pub history: Mutex<History>,
+    pub cond: Condvar,
+}
+
+lazy_static! {
+    pub(crate) static ref SHARED: Shared = Shared::default();
+}
+
+/// Whether the shutdown has started.
+///
+/// A single atomic load, cheap enough for hot loops; see also
+/// [check_shutdown!](macro.check_shutdown.html).
+#[inline]
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Record a terminal signal; the first one starts the shutdown.
+pub(crate) fn record(signal: Signal, origin: Option<Origin>) {
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+    let mut history = lock(&SHARED.history);
+    let now = Instant::now();
+    let started = *history.started.get_or_insert(now);
+    history.signals.push(signal);
+    history
+        .causes
+        .push(Cause::new(signal, origin, now - started));
+    for waker in history.wakers.drain(..) {
+        waker.wake();
+    }
+    SHARED.cond.notify_all();
+}
+
+/// The signal that started the shutdown, which is recorded right after the
+/// flag is set.
+pub(crate) fn first_signal() -> Signal {
+    let mut history = lock(&SHARED.history);
+    loop {
+        if let Some(&signal) = history.signals.first() {
+            return signal;
+        }
+        history = wait(&SHARED.cond, history);
+    }
+}
+
+/// Every signal received since the shutdown started.
+pub(crate) fn causes() -> Vec<Cause> {
+    lock(&SHARED.history).causes.clone()
+}
+
+/// Leave the current function or loop once the shutdown has started.
+///
+/// * `check_shutdown!()` returns from a function returning `()`,
+/// * `check_shutdown!(return value)` returns `value`,
+/// * `check_shutdown!(break)` and `check_shutdown!(break 'label)` leave a
+///   loop,
+/// * `check_shutdown!(continue)` skips to the next iteration, for loops that
+///   check their own exit condition.
+///
+/// ```
+/// # #[macro_use] extern crate graceful;
+/// fn process(items: &[u32]) -> Result<u32, &'static str> {
+///     let mut sum = 0;
+///     for item in items {
+///         check_shutdown!(return Err("interrupted"));
+///         sum += item;
+///     }
+///     Ok(sum)
+/// }
+/// # fn main() {
+/// assert_eq!(process(&[1, 2, 3]), Ok(6));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! check_shutdown {
+    () => {
+        if $crate::is_shutting_down() {
+            return;
+        }
+    };
+    (return $value:expr) => {
+        if $crate::is_shutting_down() {
+            return $value;
+        }
+    };
+    (break) => {
+        if $crate::is_shutting_down() {
+            break;
+        }
+    };
+    (break $label:lifetime) => {
+        if $crate::is_shutting_down() {
+            break $label;
+        }
+    };
+    (continue) => {
+        if $crate::is_shutting_down() {
+            continue;
+        }
+    };
+}
@@ -0,0 +1,65 @@
+//! Poison-tolerant locking.
+//!
+//! Shutdown has to proceed even if some thread panicked while holding one of
+//! our locks, so poisoning is ignored everywhere: the protected state is kept
+//! consistent by never panicking while it is borrowed.
+
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::Duration;
+
+#[cfg(all(unix, feature = "signal-safety-audit"))]
+use signal_safety;
+
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    #[cfg(all(unix, feature = "signal-safety-audit"))]
+    signal_safety::assert_safe("taking a lock");
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Take the lock unless another thread holds it.
+pub fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    #[cfg(all(unix, feature = "signal-safety-audit"))]
+    signal_safety::assert_safe("taking a lock");
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+pub fn wait<'a, T>(cond: &Condvar, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+    cond.wait(guard).unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn wait_timeout<'a, T>(
+    cond: &Condvar,
+    guard: MutexGuard<'a, T>,
+    timeout: Duration,
+) -> MutexGuard<'a, T> {
+    match cond.wait_timeout(guard, timeout) {
+        Ok((guard, _)) => guard,
+        Err(poisoned) => poisoned.into_inner().0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn locks_survive_a_panicking_holder() {
+        let mutex = Arc::new(Mutex::new(1));
+        let holder = mutex.clone();
+        let _ = thread::spawn(move || {
+            let mut value = holder.lock().unwrap();
+            *value = 2;
+            panic!("poisoning the lock");
+        })
+        .join();
+        assert!(mutex.is_poisoned());
+        assert_eq!(*lock(&mutex), 2);
+    }
+}
@@ -0,0 +1,145 @@
+//! Lifecycle messages for the system log.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! graceful::events::subscribe(graceful::syslog::Syslog::new());
+//! ```
+
+use std::ffi::CString;
+
+use libc;
+
+use events::{Event, Observer};
+
+/// Sends a message with the `daemon` facility when the shutdown begins and
+/// when it is complete.
+///
+/// The messages go through `syslog(3)`, under the identity set by `openlog`
+/// if the program called it.
+#[derive(Debug, Default)]
+pub struct Syslog(());
+
+impl Syslog {
+    pub fn new() -> Syslog {
+        Syslog::default()
+    }
+}
+
+impl Observer for Syslog {
+    fn on_event(&self, event: &Event) {
+        let (priority, message) = match message(event) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if let Ok(message) = CString::new(message) {
+            let format = b"%s\0";
+            unsafe {
+                libc::syslog(
+                    libc::LOG_DAEMON | priority,
+                    format.as_ptr() as *const libc::c_char,
+                    message.as_ptr(),
+                );
+            }
+        }
+    }
+}
+
+/// The priority and the message logged for `event`, if any.
+fn message(event: &Event) -> Option<(libc::c_int, String)> {
+    match *event {
+        Event::ShutdownStarted { signal, origin } => {
+            let message = match origin {
+                Some(origin) => format!("shutdown started on {} from {}", signal, origin),
+                None => format!("shutdown started on {}", signal),
+            };
+            Some((libc::LOG_NOTICE, message))
+        }
+        Event::ShutdownFinished(report) => {
+            let failures = report.failures().count();
+            let message = format!(
+                "shutdown on {} finished in {:?} with {} failed hook(s)",
+                report.signal(),
+                report.duration(),
+                failures
+            );
+            let priority = if failures == 0 {
+                libc::LOG_NOTICE
+            } else {
+                libc::LOG_WARNING
+            };
+            Some((priority, message))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use error::FailureKind;
+    use report::{HookReport, PhaseReport, ShutdownReport};
+    use signal::{Origin, Signal};
+
+    #[test]
+    fn tells_where_the_shutdown_came_from() {
+        let started = Event::ShutdownStarted {
+            signal: Signal::Terminate,
+            origin: Some(Origin::new(42, 1000)),
+        };
+        assert_eq!(
+            message(&started),
+            Some((
+                libc::LOG_NOTICE,
+                format!(
+                    "shutdown started on {} from pid 42 uid 1000",
+                    Signal::Terminate
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn warns_of_the_hooks_that_failed() {
+        let hook = |failure| {
+            HookReport::new(
+                "upload".to_owned(),
+                Duration::from_secs(1),
+                failure,
+                vec![],
+                1,
+            )
+        };
+        let clean = ShutdownReport::new(
+            Signal::Interrupt,
+            vec![PhaseReport::new(
+                "flush".to_owned(),
+                Duration::from_secs(1),
+                vec![hook(None)],
+            )],
+        );
+        assert_eq!(
+            message(&Event::ShutdownFinished(&clean)),
+            Some((
+                libc::LOG_NOTICE,
+                format!(
+                    "shutdown on {} finished in 1s with 0 failed hook(s)",
+                    Signal::Interrupt
+                )
+            ))
+        );
+        let failed = ShutdownReport::new(
+            Signal::Interrupt,
+            vec![PhaseReport::new(
+                "flush".to_owned(),
+                Duration::from_secs(1),
+                vec![hook(Some(FailureKind::TimedOut))],
+            )],
+        );
+        assert_eq!(
+            message(&Event::ShutdownFinished(&failed)).map(|(priority, _)| priority),
+            Some(libc::LOG_WARNING)
+        );
+    }
+}
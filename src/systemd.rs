@@ -0,0 +1,99 @@
+//! Keeping systemd informed of the state of the service.
+//!
+//! With the `systemd` feature the guard tells the service manager
+//! `STOPPING=1` as soon as a terminal signal arrives, before the hooks and
+//! the handler run. If the unit has `WatchdogSec=` set, the waiting thread
+//! also sends `WATCHDOG=1` at half the interval systemd asked for, so a
+//! program hanging before the shutdown is restarted. Tell it the program is
+//! ready once set up with [SignalGuard::notify_ready](../struct.SignalGuard.html#method.notify_ready):
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # #[cfg(feature = "systemd")]
+//! # fn main() {
+//! use graceful::SignalGuard;
+//!
+//! let signal_guard = SignalGuard::new();
+//! // bind the sockets, spawn the workers...
+//! signal_guard.notify_ready().unwrap();
+//! signal_guard.at_exit(|_| {});
+//! # }
+//! # #[cfg(not(feature = "systemd"))]
+//! # fn main() {}
+//! ```
+//!
+//! Nothing is sent when the program was not started by systemd, that is if
+//! `NOTIFY_SOCKET` is not set.
+//!
+//! Requires the `systemd` feature.
+
+use std::env;
+use std::io;
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sd_notify;
+use sync::lock;
+
+/// Tell the service manager the program is ready, `READY=1`.
+pub fn notify_ready() -> io::Result<()> {
+    sd_notify::notify("READY=1")
+}
+
+/// Tell the service manager the program is stopping, `STOPPING=1`.
+pub fn notify_stopping() -> io::Result<()> {
+    sd_notify::notify("STOPPING=1")
+}
+
+/// How often to ping the watchdog: half of `WATCHDOG_USEC`, unless it is
+/// unset or meant for another process (`WATCHDOG_PID`).
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str().and_then(|pid| pid.parse().ok()) != Some(process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// Ping the watchdog, `WATCHDOG=1`.
+pub fn ping_watchdog() -> io::Result<()> {
+    sd_notify::notify("WATCHDOG=1")
+}
+
+/// The watchdog pings sent by the waiting thread.
+pub(crate) struct Watchdog {
+    interval: Duration,
+    last: Mutex<Instant>,
+}
+
+impl Watchdog {
+    /// A watchdog if systemd asked for one.
+    pub(crate) fn from_env() -> Option<Watchdog> {
+        watchdog_interval().map(|interval| Watchdog {
+            interval,
+            last: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// When the next ping is due.
+    pub(crate) fn due(&self) -> Instant {
+        *lock(&self.last) + self.interval
+    }
+
+    /// Ping if it is due. A ping that cannot be sent is tried again at the
+    /// next interval.
+    pub(crate) fn ping_if_due(&self) {
+        let now = Instant::now();
+        let mut last = lock(&self.last);
+        if now >= *last + self.interval {
+            let _ = ping_watchdog();
+            *last = now;
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! Signals injected by tests.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use signal::Signal;
+use sync::lock;
+
+/// How often a waiting guard checks for injected signals.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+lazy_static! {
+    static ref INJECTED: Mutex<VecDeque<Signal>> = Mutex::new(VecDeque::new());
+}
+
+pub(crate) fn inject(signal: Signal) {
+    lock(&INJECTED).push_back(signal);
+}
+
+/// The oldest injected signal not taken yet.
+pub(crate) fn take() -> Option<Signal> {
+    lock(&INJECTED).pop_front()
+}
+
+/// When to wake up to check for injected signals, at `wake` at the latest.
+pub(crate) fn wake_by(wake: Option<Instant>) -> Instant {
+    let poll = Instant::now() + POLL_INTERVAL;
+    wake.map_or(poll, |wake| wake.min(poll))
+}
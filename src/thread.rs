@@ -0,0 +1,595 @@
+//! Worker threads that are joined at shutdown.
+
+use std::error;
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::mem;
+#[cfg(unix)]
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use libc;
+
+use error::{panic_message, BoxError, Failure, FailureKind, IntoResult, ShutdownErrors};
+use guard::SignalGuard;
+#[cfg(target_os = "linux")]
+use signal::Signal;
+use sync::lock;
+
+#[derive(Default)]
+struct Completion {
+    done: AtomicBool,
+    waiter: Mutex<Option<Thread>>,
+}
+
+/// Marks the thread as finished when dropped, even while unwinding.
+struct Notify(Arc<Completion>);
+
+impl Drop for Notify {
+    fn drop(&mut self) {
+        self.0.done.store(true, Ordering::Release);
+        if let Some(waiter) = lock(&self.0.waiter).take() {
+            waiter.unpark();
+        }
+    }
+}
+
+/// An owned permission to join on a thread spawned by this module.
+///
+/// Unlike `std::thread::JoinHandle` it can be joined with a timeout, see
+/// [join_timeout](fn.join_timeout.html).
+pub struct JoinHandle<T> {
+    inner: thread::JoinHandle<T>,
+    completion: Arc<Completion>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn thread(&self) -> &Thread {
+        self.inner.thread()
+    }
+
+    /// Whether the thread has finished running its closure.
+    pub fn is_finished(&self) -> bool {
+        self.completion.done.load(Ordering::Acquire)
+    }
+
+    /// Wait for the thread to finish, see `std::thread::JoinHandle::join`.
+    pub fn join(self) -> thread::Result<T> {
+        self.inner.join()
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("thread", self.thread())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Spawn a thread whose handle supports [join_timeout](fn.join_timeout.html).
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with(thread::Builder::new(), f).expect("failed to spawn thread")
+}
+
+/// Like [spawn](fn.spawn.html), configured by a `std::thread::Builder`.
+pub fn spawn_with<F, T>(builder: thread::Builder, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let completion = Arc::new(Completion::default());
+    let notify = Notify(completion.clone());
+    let inner = builder.spawn(move || {
+        let _notify = notify;
+        f()
+    })?;
+    Ok(JoinHandle { inner, completion })
+}
+
+/// A `std::thread::Builder` whose threads start with the signals of a
+/// guard blocked, even when spawned from a thread that does not block them.
+///
+/// The signals are blocked in the spawning thread while the new thread is
+/// created, so it inherits them before any of its code runs, then the mask
+/// of the spawning thread is put back.
+///
+/// ```no_run
+/// # extern crate graceful;
+/// use graceful::thread::MaskedThreadBuilder;
+/// use graceful::SignalGuard;
+///
+/// let signal_guard = SignalGuard::new();
+/// let handle = MaskedThreadBuilder::new(&signal_guard)
+///     .name("flusher".to_owned())
+///     .spawn(|| { /* ... */ })
+///     .unwrap();
+/// signal_guard.at_exit(move |_| handle.join().unwrap());
+/// ```
+///
+/// On Windows it spawns as a plain `std::thread::Builder` would.
+pub struct MaskedThreadBuilder {
+    builder: thread::Builder,
+    #[cfg(unix)]
+    mask: libc::sigset_t,
+}
+
+impl MaskedThreadBuilder {
+    pub fn new(guard: &SignalGuard) -> MaskedThreadBuilder {
+        MaskedThreadBuilder::with_builder(guard, thread::Builder::new())
+    }
+
+    /// Spawn with the configuration of `builder`.
+    #[cfg_attr(windows, allow(unused_variables))]
+    pub fn with_builder(guard: &SignalGuard, builder: thread::Builder) -> MaskedThreadBuilder {
+        MaskedThreadBuilder {
+            builder,
+            #[cfg(unix)]
+            mask: *guard.raw_sigset(),
+        }
+    }
+
+    /// See `std::thread::Builder::name`.
+    pub fn name(mut self, name: String) -> MaskedThreadBuilder {
+        self.builder = self.builder.name(name);
+        self
+    }
+
+    /// See `std::thread::Builder::stack_size`.
+    pub fn stack_size(mut self, size: usize) -> MaskedThreadBuilder {
+        self.builder = self.builder.stack_size(size);
+        self
+    }
+
+    /// Spawn a thread whose handle supports
+    /// [join_timeout](fn.join_timeout.html).
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        #[cfg(unix)]
+        let previous = unsafe {
+            let mut previous: libc::sigset_t = mem::zeroed();
+            match libc::pthread_sigmask(libc::SIG_BLOCK, &self.mask, &mut previous) {
+                0 => previous,
+                err => return Err(io::Error::from_raw_os_error(err)),
+            }
+        };
+        let result = spawn_with(self.builder, f);
+        #[cfg(unix)]
+        unsafe {
+            libc::pthread_sigmask(libc::SIG_SETMASK, &previous, ptr::null_mut());
+        }
+        result
+    }
+}
+
+impl fmt::Debug for MaskedThreadBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaskedThreadBuilder")
+            .field("builder", &self.builder)
+            .finish()
+    }
+}
+
+/// A thread that does not block every signal of a guard, found by
+/// [SignalGuard::unmasked_threads](../struct.SignalGuard.html#method.unmasked_threads)
+/// (Linux).
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnmaskedThread {
+    tid: u32,
+    name: String,
+    missing: Vec<Signal>,
+}
+
+#[cfg(target_os = "linux")]
+impl UnmaskedThread {
+    /// The kernel ID of the thread.
+    pub fn tid(&self) -> u32 {
+        self.tid
+    }
+
+    /// The name of the thread as the kernel knows it, cut to 15 bytes.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The signals of the guard the thread does not block.
+    pub fn missing(&self) -> &[Signal] {
+        &self.missing
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl fmt::Display for UnmaskedThread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "thread {} ({}) does not block", self.tid, self.name)?;
+        for (i, signal) in self.missing.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, signal)?;
+        }
+        Ok(())
+    }
+}
+
+/// The threads of the process that leave a signal of `set` unblocked, from
+/// their `SigBlk` in `/proc/self/task/*/status`.
+#[cfg(target_os = "linux")]
+pub(crate) fn unmasked_threads(set: &libc::sigset_t) -> io::Result<Vec<UnmaskedThread>> {
+    let guarded: Vec<libc::c_int> = (1..=64)
+        .filter(|&signum| unsafe { libc::sigismember(set, signum) } == 1)
+        .collect();
+    let mut threads = Vec::new();
+    for entry in fs::read_dir("/proc/self/task")? {
+        let entry = entry?;
+        let status = match fs::read_to_string(entry.path().join("status")) {
+            Ok(status) => status,
+            // The thread has exited since the directory was read.
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        let tid = entry.file_name().to_str().and_then(|tid| tid.parse().ok());
+        let name = status_field(&status, "Name");
+        let blocked = status_field(&status, "SigBlk");
+        let (tid, name, blocked) = match (tid, name, blocked) {
+            (Some(tid), Some(name), Some(blocked)) => (tid, name, blocked),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected thread status",
+                ))
+            }
+        };
+        let missing: Vec<Signal> = guarded
+            .iter()
+            .filter(|&&signum| !is_blocked(blocked, signum))
+            .map(|&signum| Signal::from_raw(signum))
+            .collect();
+        if !missing.is_empty() {
+            threads.push(UnmaskedThread {
+                tid,
+                name: name.to_owned(),
+                missing,
+            });
+        }
+    }
+    Ok(threads)
+}
+
+#[cfg(target_os = "linux")]
+fn status_field<'a>(status: &'a str, field: &str) -> Option<&'a str> {
+    status.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key == field {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether `signum` is set in the hexadecimal `mask`, whose lowest bit is
+/// signal 1.
+#[cfg(target_os = "linux")]
+fn is_blocked(mask: &str, signum: libc::c_int) -> bool {
+    let bit = (signum - 1) as usize;
+    let digits = mask.as_bytes();
+    digits
+        .len()
+        .checked_sub(1 + bit / 4)
+        .and_then(|i| (digits[i] as char).to_digit(16))
+        .is_some_and(|digit| digit & (1 << (bit % 4)) != 0)
+}
+
+/// The thread did not finish within the timeout.
+///
+/// The handle is returned so the caller can wait again or give up on it.
+pub struct Timeout<T>(JoinHandle<T>);
+
+impl<T> Timeout<T> {
+    pub fn into_inner(self) -> JoinHandle<T> {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for Timeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Timeout").field(&self.0).finish()
+    }
+}
+
+impl<T> fmt::Display for Timeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("timed out waiting for thread to finish")
+    }
+}
+
+impl<T> error::Error for Timeout<T> {}
+
+/// Wait at most `timeout` for the thread to finish.
+///
+/// The outer `Result` tells whether the thread finished in time, the inner
+/// one whether it panicked, as in `std::thread::JoinHandle::join`.
+///
+/// ```
+/// # extern crate graceful;
+/// use std::time::Duration;
+///
+/// let handle = graceful::thread::spawn(|| 42);
+/// let result = graceful::thread::join_timeout(handle, Duration::from_secs(5));
+/// assert_eq!(result.unwrap().unwrap(), 42);
+/// ```
+pub fn join_timeout<T>(
+    handle: JoinHandle<T>,
+    timeout: Duration,
+) -> Result<thread::Result<T>, Timeout<T>> {
+    match Instant::now().checked_add(timeout) {
+        Some(deadline) => join_deadline(handle, deadline),
+        None => Ok(handle.join()),
+    }
+}
+
+pub(crate) fn join_deadline<T>(
+    handle: JoinHandle<T>,
+    deadline: Instant,
+) -> Result<thread::Result<T>, Timeout<T>> {
+    *lock(&handle.completion.waiter) = Some(thread::current());
+    loop {
+        if handle.is_finished() {
+            return Ok(handle.join());
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            lock(&handle.completion.waiter).take();
+            return Err(Timeout(handle));
+        }
+        thread::park_timeout(deadline - now);
+    }
+}
+
+struct Worker {
+    name: String,
+    handle: JoinHandle<Result<(), BoxError>>,
+}
+
+/// A set of named worker threads joined together at shutdown.
+///
+/// Panics and errors returned by the workers are collected into
+/// [ShutdownErrors](../struct.ShutdownErrors.html) instead of being lost, so
+/// the process can report a failed cleanup through its exit status:
+///
+/// ```no_run
+/// # extern crate graceful;
+/// # use graceful::SignalGuard;
+/// # use graceful::thread::Registry;
+/// let signal_guard = SignalGuard::new();
+/// let workers = Registry::new();
+///
+/// workers.spawn("flusher", || -> Result<(), std::io::Error> {
+///     Ok(())
+/// }).unwrap();
+///
+/// signal_guard.at_exit(move |_| {
+///     if let Err(errors) = workers.join_all() {
+///         eprintln!("{}", errors);
+///         std::process::exit(errors.exit_code());
+///     }
+/// });
+/// ```
+#[derive(Default)]
+pub struct Registry {
+    workers: Mutex<Vec<Worker>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Spawn a named worker thread.
+    ///
+    /// The worker may return `()` or any `Result<(), E>`.
+    pub fn spawn<F, R>(&self, name: &str, f: F) -> io::Result<()>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoResult,
+    {
+        let builder = thread::Builder::new().name(name.to_owned());
+        let handle = spawn_with(builder, move || f().into_result())?;
+        let worker = Worker {
+            name: name.to_owned(),
+            handle,
+        };
+        lock(&self.workers).push(worker);
+        Ok(())
+    }
+
+    /// Join every worker in the order they were spawned.
+    pub fn join_all(&self) -> Result<(), ShutdownErrors> {
+        self.join_until(None)
+    }
+
+    /// Join every worker, giving up on those still running once `timeout`
+    /// has elapsed. Workers left behind are reported as timed out.
+    pub fn join_all_timeout(&self, timeout: Duration) -> Result<(), ShutdownErrors> {
+        self.join_until(Instant::now().checked_add(timeout))
+    }
+
+    fn join_until(&self, deadline: Option<Instant>) -> Result<(), ShutdownErrors> {
+        let workers: Vec<Worker> = lock(&self.workers).drain(..).collect();
+        let mut failures = Vec::new();
+        for worker in workers {
+            let joined = match deadline {
+                Some(deadline) => join_deadline(worker.handle, deadline),
+                None => Ok(worker.handle.join()),
+            };
+            let kind = match joined {
+                Ok(Ok(Ok(()))) => continue,
+                Ok(Ok(Err(err))) => FailureKind::Failed(err),
+                Ok(Err(payload)) => FailureKind::Panicked(panic_message(&*payload)),
+                Err(_) => FailureKind::TimedOut,
+            };
+            failures.push(Failure::new(worker.name, kind));
+        }
+        ShutdownErrors::from_failures(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn join_timeout_gives_the_handle_back() {
+        let (release, released) = mpsc::channel::<()>();
+        let handle = spawn(move || {
+            let _ = released.recv();
+            7
+        });
+        let handle = match join_timeout(handle, Duration::from_millis(20)) {
+            Err(timeout) => timeout.into_inner(),
+            Ok(_) => panic!("joined a blocked thread"),
+        };
+        drop(release);
+        assert_eq!(join_timeout(handle, Duration::MAX).unwrap().unwrap(), 7);
+    }
+
+    #[test]
+    fn join_timeout_reports_a_panic() {
+        let handle = spawn(|| -> () { panic!("worker failed") });
+        assert!(join_timeout(handle, Duration::from_secs(5))
+            .unwrap()
+            .is_err());
+    }
+
+    /// What went wrong with each worker that failed.
+    fn failures(errors: &ShutdownErrors) -> Vec<(&str, String)> {
+        errors
+            .iter()
+            .map(|failure| (failure.name(), failure.kind().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn registry_collects_what_went_wrong() {
+        let workers = Registry::new();
+        workers.spawn("fine", || {}).unwrap();
+        workers
+            .spawn("failed", || -> Result<(), io::Error> {
+                Err(io::Error::other("disk full"))
+            })
+            .unwrap();
+        workers
+            .spawn("panicked", || -> () { panic!("oops") })
+            .unwrap();
+        let errors = workers.join_all().unwrap_err();
+        assert_eq!(
+            failures(&errors),
+            [
+                ("failed", "failed: disk full".to_owned()),
+                ("panicked", "panicked: oops".to_owned()),
+            ]
+        );
+        assert_eq!(errors.exit_code(), 101);
+        // Joined workers are not joined again.
+        assert!(workers.join_all().is_ok());
+    }
+
+    #[test]
+    fn registry_times_out_stuck_workers() {
+        let workers = Registry::new();
+        workers.spawn("fine", || {}).unwrap();
+        workers
+            .spawn("stuck", || thread::sleep(Duration::from_secs(1)))
+            .unwrap();
+        let errors = workers
+            .join_all_timeout(Duration::from_millis(200))
+            .unwrap_err();
+        assert_eq!(failures(&errors), [("stuck", "timed out".to_owned())]);
+        assert_eq!(errors.exit_code(), 1);
+    }
+
+    #[test]
+    fn registry_joins_without_a_limit() {
+        let workers = Registry::new();
+        workers
+            .spawn("slow", || thread::sleep(Duration::from_millis(20)))
+            .unwrap();
+        assert!(workers.join_all_timeout(Duration::MAX).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn masks_are_read_lowest_signal_last() {
+        assert!(is_blocked("0000000000004002", libc::SIGINT));
+        assert!(is_blocked("0000000000004002", libc::SIGTERM));
+        assert!(!is_blocked("0000000000004002", libc::SIGHUP));
+        assert!(!is_blocked("4002", 64));
+        assert_eq!(
+            status_field("Name:\tworker\nSigBlk:\t0002\n", "SigBlk"),
+            Some("0002")
+        );
+        assert_eq!(status_field("Name:\tworker\n", "SigBlk"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn finds_the_threads_that_leave_a_signal_unblocked() {
+        let set = unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGUSR2);
+            set
+        };
+        let probe = |name: &str, block: bool| {
+            let (ready, probed) = mpsc::channel();
+            let (done, finished) = mpsc::channel::<()>();
+            let handle = thread::Builder::new()
+                .name(name.to_owned())
+                .spawn(move || {
+                    if block {
+                        unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut()) };
+                    }
+                    ready.send(()).unwrap();
+                    let _ = finished.recv();
+                })
+                .unwrap();
+            probed.recv().unwrap();
+            let found = unmasked_threads(&set)
+                .unwrap()
+                .into_iter()
+                .find(|thread| thread.name() == name);
+            drop(done);
+            handle.join().unwrap();
+            found
+        };
+
+        let found = probe("unmasked-probe", false).unwrap();
+        assert_eq!(found.missing(), [Signal::from_raw(libc::SIGUSR2)]);
+        assert_eq!(
+            found.to_string(),
+            format!(
+                "thread {} (unmasked-probe) does not block {}",
+                found.tid(),
+                Signal::from_raw(libc::SIGUSR2)
+            )
+        );
+        assert_eq!(probe("masked-probe", true), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! Timers for async code that end early when the shutdown starts.
+//!
+//! A single thread keeps the deadlines of the pending timers and wakes their
+//! tasks, so they work under any runtime.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, Once};
+use std::task::{self, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use handle::ShutdownHandle;
+use signal::Signal;
+use sync::{lock, wait, wait_timeout};
+
+#[derive(Default)]
+struct Timers {
+    next_id: u64,
+    pending: HashMap<u64, (Instant, Waker)>,
+}
+
+#[derive(Default)]
+struct Shared {
+    timers: Mutex<Timers>,
+    cond: Condvar,
+}
+
+lazy_static! {
+    static ref SHARED: Shared = Shared::default();
+}
+
+static THREAD: Once = Once::new();
+
+/// Have `waker` woken at `deadline`, replacing what the timer `id` was
+/// given before. Returns the ID of the timer.
+fn schedule(id: Option<u64>, deadline: Instant, waker: &Waker) -> u64 {
+    THREAD.call_once(|| {
+        thread::Builder::new()
+            .name("graceful: timer".to_owned())
+            .spawn(run)
+            .expect("failed to spawn thread");
+    });
+    let mut timers = lock(&SHARED.timers);
+    let id = id.unwrap_or_else(|| {
+        timers.next_id += 1;
+        timers.next_id
+    });
+    timers.pending.insert(id, (deadline, waker.clone()));
+    SHARED.cond.notify_one();
+    id
+}
+
+fn cancel(id: u64) {
+    lock(&SHARED.timers).pending.remove(&id);
+}
+
+fn run() {
+    let mut timers = lock(&SHARED.timers);
+    loop {
+        let now = Instant::now();
+        timers.pending.retain(|_, &mut (deadline, ref waker)| {
+            if deadline <= now {
+                waker.wake_by_ref();
+            }
+            deadline > now
+        });
+        let next = timers.pending.values().map(|&(deadline, _)| deadline).min();
+        timers = match next {
+            Some(next) => wait_timeout(&SHARED.cond, timers, next - now),
+            None => wait(&SHARED.cond, timers),
+        };
+    }
+}
+
+/// Waits until a deadline, or until the shutdown starts, see
+/// [ShutdownHandle::sleep](struct.ShutdownHandle.html#method.sleep).
+///
+/// Resolves to `Ok(())` at the deadline, or to the signal that started the
+/// shutdown if it came first. A sleep too long for an `Instant` has no
+/// deadline and only ends at the shutdown.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Option<Instant>,
+    id: Option<u64>,
+    shutdown: ShutdownHandle,
+}
+
+impl Sleep {
+    pub(crate) fn new(deadline: Option<Instant>) -> Sleep {
+        Sleep {
+            deadline,
+            id: None,
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+
+    /// When the sleep ends, unless the shutdown starts first, or `None` if
+    /// only the shutdown ends it.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Wait until `deadline` instead.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+}
+
+impl Future for Sleep {
+    type Output = Result<(), Signal>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Result<(), Signal>> {
+        if let Poll::Ready(signal) = Pin::new(&mut self.shutdown).poll(cx) {
+            return Poll::Ready(Err(signal));
+        }
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+        if Instant::now() >= deadline {
+            if let Some(id) = self.id.take() {
+                cancel(id);
+            }
+            return Poll::Ready(Ok(()));
+        }
+        self.id = Some(schedule(self.id, deadline, cx.waker()));
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            cancel(id);
+        }
+    }
+}
+
+/// Ticks at a fixed period until the shutdown starts, see
+/// [ShutdownHandle::interval](struct.ShutdownHandle.html#method.interval).
+#[derive(Debug)]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    pub(crate) fn new(period: Duration) -> Interval {
+        assert!(
+            period > Duration::from_secs(0),
+            "interval period must be positive"
+        );
+        Interval {
+            period,
+            sleep: Sleep::new(Some(Instant::now())),
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Wait for the next tick, resolving to the time it was due, or to the
+    /// signal that started the shutdown. The first tick is due at once.
+    ///
+    /// Ticks missed because the task was busy are skipped rather than
+    /// caught up on.
+    pub fn tick(&mut self) -> Tick<'_> {
+        Tick(self)
+    }
+}
+
+/// The next tick of an [Interval](struct.Interval.html).
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Tick<'a>(&'a mut Interval);
+
+impl<'a> Future for Tick<'a> {
+    type Output = Result<Instant, Signal>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Result<Instant, Signal>> {
+        let interval = &mut *self.0;
+        match Pin::new(&mut interval.sleep).poll(cx) {
+            Poll::Ready(Ok(())) => {
+                let due = interval
+                    .sleep
+                    .deadline()
+                    .expect("an interval ticks at a deadline");
+                let now = Instant::now();
+                // A period too long for an `Instant` ticks only once.
+                interval.sleep.deadline = due
+                    .checked_add(interval.period)
+                    .filter(|&next| next > now)
+                    .or_else(|| now.checked_add(interval.period));
+                Poll::Ready(Ok(due))
+            }
+            Poll::Ready(Err(signal)) => Poll::Ready(Err(signal)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use executor::block_on;
+
+    fn soon() -> Option<Instant> {
+        Some(Instant::now() + Duration::from_millis(100))
+    }
+
+    #[test]
+    fn sleep_ends_at_the_deadline() {
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(20);
+        assert_eq!(block_on(Sleep::new(Some(deadline)), None), Some(Ok(())));
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn sleep_too_long_for_an_instant_waits_for_the_shutdown() {
+        let sleep = ShutdownHandle::new().sleep(Duration::MAX);
+        assert_eq!(sleep.deadline(), None);
+        assert_eq!(block_on(sleep, soon()), None);
+    }
+
+    #[test]
+    fn interval_ticks_once_per_period() {
+        let mut interval = ShutdownHandle::new().interval(Duration::from_millis(20));
+        let first = block_on(interval.tick(), None).unwrap().unwrap();
+        let second = block_on(interval.tick(), None).unwrap().unwrap();
+        assert_eq!(second - first, Duration::from_millis(20));
+        assert!(Instant::now() >= second);
+    }
+
+    #[test]
+    fn interval_skips_missed_ticks() {
+        let mut interval = ShutdownHandle::new().interval(Duration::from_millis(10));
+        let first = block_on(interval.tick(), None).unwrap().unwrap();
+        thread::sleep(Duration::from_millis(35));
+        let second = block_on(interval.tick(), None).unwrap().unwrap();
+        assert_eq!(second - first, Duration::from_millis(10));
+        let third = block_on(interval.tick(), None).unwrap().unwrap();
+        assert!(third - second > Duration::from_millis(20));
+    }
+
+    #[test]
+    fn interval_too_long_for_an_instant_ticks_once() {
+        let mut interval = ShutdownHandle::new().interval(Duration::MAX);
+        assert!(block_on(interval.tick(), None).unwrap().is_ok());
+        assert_eq!(block_on(interval.tick(), soon()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn interval_needs_a_period() {
+        Interval::new(Duration::from_secs(0));
+    }
+}
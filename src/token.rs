@@ -0,0 +1,286 @@
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+use rt;
+use signal::Signal;
+use sync::{lock, wait, wait_timeout};
+
+/// How far the shutdown has gone, for workers that stop differently in a
+/// soft and in a hard shutdown.
+///
+/// The first terminal signal starts the [Draining](#variant.Draining)
+/// phase, a second one the [Aborting](#variant.Aborting) phase. Phases only
+/// move forward, so they can be compared:
+///
+/// ```
+/// # extern crate graceful;
+/// use graceful::ShutdownPhase;
+///
+/// assert!(ShutdownPhase::Running < ShutdownPhase::Draining);
+/// assert!(ShutdownPhase::Draining < ShutdownPhase::Aborting);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShutdownPhase {
+    /// No shutdown yet.
+    #[default]
+    Running,
+    /// Stop accepting new work and finish what is in flight.
+    Draining,
+    /// Give up on the work in flight and stop as soon as possible.
+    Aborting,
+}
+
+impl fmt::Display for ShutdownPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShutdownPhase::Running => f.write_str("running"),
+            ShutdownPhase::Draining => f.write_str("draining"),
+            ShutdownPhase::Aborting => f.write_str("aborting"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    phase: Mutex<ShutdownPhase>,
+    cond: Condvar,
+}
+
+impl Inner {
+    /// Move on to `phase` unless the tokens are past it already.
+    fn advance(&self, phase: ShutdownPhase) {
+        let mut current = lock(&self.phase);
+        if *current < phase {
+            *current = phase;
+            self.cond.notify_all();
+        }
+    }
+}
+
+/// Tells the [ShutdownToken](struct.ShutdownToken.html)s made from it that
+/// their part of the program is to stop.
+///
+/// [SignalGuard::token](struct.SignalGuard.html#method.token) hands out
+/// tokens of a notifier the guard trips itself when the shutdown starts.
+/// One made here stops a subsystem on its own, for example to restart it or
+/// in a test:
+///
+/// ```
+/// # extern crate graceful;
+/// use std::thread;
+///
+/// use graceful::ShutdownNotifier;
+///
+/// let notifier = ShutdownNotifier::new();
+/// let token = notifier.token();
+/// let worker = thread::spawn(move || {
+///     while !token.is_shutdown() {
+///         // ...
+/// #       thread::yield_now();
+///     }
+/// });
+/// notifier.notify();
+/// worker.join().unwrap();
+/// ```
+#[derive(Default)]
+pub struct ShutdownNotifier(Arc<Inner>);
+
+impl ShutdownNotifier {
+    pub fn new() -> ShutdownNotifier {
+        ShutdownNotifier::default()
+    }
+
+    pub fn token(&self) -> ShutdownToken {
+        ShutdownToken(self.0.clone())
+    }
+
+    /// Trip every token, moving them to the
+    /// [Draining](enum.ShutdownPhase.html#variant.Draining) phase and waking
+    /// those waiting. Later calls do nothing.
+    pub fn notify(&self) {
+        self.0.advance(ShutdownPhase::Draining);
+    }
+
+    /// Move every token to the
+    /// [Aborting](enum.ShutdownPhase.html#variant.Aborting) phase, tripping
+    /// them if they are not yet.
+    pub fn abort(&self) {
+        self.0.advance(ShutdownPhase::Aborting);
+    }
+}
+
+impl fmt::Debug for ShutdownNotifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShutdownNotifier")
+            .field("phase", &*lock(&self.0.phase))
+            .finish()
+    }
+}
+
+/// A cheaply cloned handle worker threads check or wait on to learn that
+/// they are to stop, see [ShutdownNotifier](struct.ShutdownNotifier.html).
+#[derive(Clone)]
+pub struct ShutdownToken(Arc<Inner>);
+
+impl ShutdownToken {
+    /// Whether the token has been tripped.
+    pub fn is_shutdown(&self) -> bool {
+        self.phase() > ShutdownPhase::Running
+    }
+
+    /// The phase the token is in. For a token of
+    /// [SignalGuard::token](struct.SignalGuard.html#method.token) this is
+    /// the phase of the shutdown.
+    pub fn phase(&self) -> ShutdownPhase {
+        *lock(&self.0.phase)
+    }
+
+    /// Block until the token is tripped.
+    pub fn wait(&self) {
+        self.wait_for(ShutdownPhase::Draining);
+    }
+
+    /// Block until the token is tripped or `timeout` has elapsed. Returns
+    /// whether it is tripped.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_for_timeout(ShutdownPhase::Draining, timeout)
+    }
+
+    /// Block until the token has reached `phase`, for example a thread that
+    /// cancels the work in flight once the shutdown is aborting:
+    ///
+    /// ```no_run
+    /// # extern crate graceful;
+    /// # fn cancel_requests() {}
+    /// use std::thread;
+    ///
+    /// use graceful::{ShutdownPhase, SignalGuard};
+    ///
+    /// let signal_guard = SignalGuard::new();
+    /// let token = signal_guard.token();
+    /// thread::spawn(move || {
+    ///     token.wait_for(ShutdownPhase::Aborting);
+    ///     cancel_requests();
+    /// });
+    /// signal_guard.at_exit(|_| {});
+    /// ```
+    pub fn wait_for(&self, phase: ShutdownPhase) {
+        let mut current = lock(&self.0.phase);
+        while *current < phase {
+            current = wait(&self.0.cond, current);
+        }
+    }
+
+    /// Block until the token has reached `phase` or `timeout` has elapsed.
+    /// Returns whether it has reached it.
+    pub fn wait_for_timeout(&self, phase: ShutdownPhase, timeout: Duration) -> bool {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => {
+                self.wait_for(phase);
+                return true;
+            }
+        };
+        let mut current = lock(&self.0.phase);
+        while *current < phase {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            current = wait_timeout(&self.0.cond, current, deadline - now);
+        }
+        true
+    }
+}
+
+impl fmt::Debug for ShutdownToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShutdownToken")
+            .field("phase", &self.phase())
+            .finish()
+    }
+}
+
+lazy_static! {
+    static ref GUARD: ShutdownNotifier = ShutdownNotifier::new();
+}
+
+/// A token of the notifier tripped when the shutdown starts.
+pub(crate) fn guard_token() -> ShutdownToken {
+    GUARD.token()
+}
+
+/// The phase of the shutdown, [Running](enum.ShutdownPhase.html#variant.Running)
+/// until the first terminal signal, see [ShutdownPhase](enum.ShutdownPhase.html).
+pub fn shutdown_phase() -> ShutdownPhase {
+    GUARD.token().phase()
+}
+
+/// Move the tokens handed out by the guards to the
+/// [Aborting](enum.ShutdownPhase.html#variant.Aborting) phase, as another
+/// terminal signal arrived during the shutdown.
+pub(crate) fn abort() {
+    GUARD.abort();
+}
+
+/// Trip the tokens handed out by the guards, as the shutdown on `signal`
+/// starts.
+pub(crate) fn trip(signal: Signal) {
+    GUARD.notify();
+    #[cfg(feature = "tokio")]
+    rt::trip(signal);
+    #[cfg(not(feature = "tokio"))]
+    let _ = signal;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn tokens_follow_the_notifier() {
+        let notifier = ShutdownNotifier::new();
+        let token = notifier.token();
+        assert!(!token.is_shutdown());
+        assert!(!token.wait_timeout(Duration::from_millis(10)));
+        notifier.notify();
+        assert!(token.is_shutdown());
+        assert!(token.wait_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn waits_without_a_limit() {
+        let notifier = ShutdownNotifier::new();
+        let token = notifier.token();
+        let notifying = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            notifier.notify();
+        });
+        assert!(token.wait_timeout(Duration::MAX));
+        notifying.join().unwrap();
+    }
+
+    #[test]
+    fn tokens_move_on_to_aborting() {
+        let notifier = ShutdownNotifier::new();
+        let token = notifier.token();
+        assert_eq!(token.phase(), ShutdownPhase::Running);
+        notifier.notify();
+        assert_eq!(token.phase(), ShutdownPhase::Draining);
+        assert!(!token.wait_for_timeout(ShutdownPhase::Aborting, Duration::from_millis(10)));
+        let aborting = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            notifier.abort();
+            notifier.notify();
+        });
+        token.wait_for(ShutdownPhase::Aborting);
+        aborting.join().unwrap();
+        assert_eq!(token.phase(), ShutdownPhase::Aborting);
+        assert_eq!(token.phase().to_string(), "aborting");
+    }
+}
@@ -0,0 +1,207 @@
+//! Two-phase shutdown for applications that must not leave half-written
+//! data behind.
+//!
+//! A [Transaction](trait.Transaction.html) registered with
+//! [at_exit](fn.at_exit.html) is run in the
+//! [FLUSH](../hooks/constant.FLUSH.html) phase: the slow work is done by
+//! `prepare`, then made durable by `commit`, which should be short and
+//! atomic, like renaming a file or appending a commit record to a log. If
+//! either fails, or if `prepare` is still running when only the reserved
+//! time is left before the phase deadline, `rollback` is called instead so a
+//! clean marker can be written while there is still time.
+//!
+//! `rollback` may run while an abandoned `prepare` is still going on another
+//! thread, which is why the methods take `&self`.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::fs;
+//! use std::time::Duration;
+//! use graceful::hooks::Context;
+//! use graceful::transaction::{self, Transaction};
+//! use graceful::BoxError;
+//!
+//! struct Segment;
+//!
+//! impl Transaction for Segment {
+//!     fn prepare(&self, _: &Context) -> Result<(), BoxError> {
+//!         fs::write("wal/segment.tmp", b"...")?;
+//!         Ok(())
+//!     }
+//!
+//!     fn commit(&self, _: &Context) -> Result<(), BoxError> {
+//!         Ok(fs::rename("wal/segment.tmp", "wal/segment")?)
+//!     }
+//!
+//!     fn rollback(&self, _: &Context) -> Result<(), BoxError> {
+//!         Ok(fs::write("wal/ROLLBACK", b"")?)
+//!     }
+//! }
+//!
+//! transaction::at_exit("wal", Duration::from_millis(200), Segment);
+//! ```
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use error::{panic_message, BoxError};
+use hooks::{self, Context};
+use thread::{join_deadline, spawn_with};
+
+/// Work that is either completed or rolled back at shutdown.
+pub trait Transaction: Send + Sync {
+    /// Do the work, leaving it ready to be committed.
+    fn prepare(&self, ctx: &Context) -> Result<(), BoxError>;
+
+    /// Make the prepared work durable.
+    fn commit(&self, ctx: &Context) -> Result<(), BoxError>;
+
+    /// Record that the work was abandoned.
+    fn rollback(&self, ctx: &Context) -> Result<(), BoxError>;
+}
+
+/// Run `transaction` in the [FLUSH](../hooks/constant.FLUSH.html) phase,
+/// keeping `reserve` of the phase for `rollback`, see the
+/// [module](index.html) documentation.
+pub fn at_exit<T>(name: &str, reserve: Duration, transaction: T)
+where
+    T: Transaction + 'static,
+{
+    let transaction = Arc::new(transaction);
+    let thread_name = format!("graceful: {} prepare", name);
+    hooks::phase(hooks::FLUSH).hook(name, move |ctx: &Context| {
+        run(&thread_name, &transaction, reserve, ctx)
+    });
+}
+
+fn run<T>(
+    name: &str,
+    transaction: &Arc<T>,
+    reserve: Duration,
+    ctx: &Context,
+) -> Result<(), BoxError>
+where
+    T: Transaction + 'static,
+{
+    if let Err(err) = prepare(name, transaction, reserve, ctx) {
+        transaction.rollback(ctx)?;
+        return Err(err);
+    }
+    if let Err(err) = transaction.commit(ctx) {
+        transaction.rollback(ctx)?;
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Prepare on a thread that is abandoned once only `reserve` is left before
+/// the deadline.
+fn prepare<T>(
+    name: &str,
+    transaction: &Arc<T>,
+    reserve: Duration,
+    ctx: &Context,
+) -> Result<(), BoxError>
+where
+    T: Transaction + 'static,
+{
+    let cutoff = match ctx.deadline() {
+        Some(deadline) => deadline.checked_sub(reserve).unwrap_or(deadline),
+        None => return transaction.prepare(ctx),
+    };
+    let builder = ::std::thread::Builder::new().name(name.to_owned());
+    let (transaction, thread_ctx) = (transaction.clone(), ctx.clone());
+    let handle = spawn_with(builder, move || transaction.prepare(&thread_ctx))?;
+    match join_deadline(handle, cutoff) {
+        Ok(Ok(result)) => result,
+        Ok(Err(payload)) => Err(format!("prepare panicked: {}", panic_message(&*payload)).into()),
+        Err(_) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "prepare did not finish in time").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread;
+
+    use super::*;
+    use hooks::{Coordinator, FLUSH};
+    use report::ShutdownReport;
+    use signal::Signal;
+    use sync::lock;
+
+    #[derive(Default)]
+    struct Segment {
+        prepare_for: Duration,
+        fail_commit: bool,
+        steps: Mutex<Vec<&'static str>>,
+    }
+
+    impl Transaction for Segment {
+        fn prepare(&self, _: &Context) -> Result<(), BoxError> {
+            thread::sleep(self.prepare_for);
+            lock(&self.steps).push("prepare");
+            Ok(())
+        }
+
+        fn commit(&self, _: &Context) -> Result<(), BoxError> {
+            if self.fail_commit {
+                return Err("rename failed".into());
+            }
+            lock(&self.steps).push("commit");
+            Ok(())
+        }
+
+        fn rollback(&self, _: &Context) -> Result<(), BoxError> {
+            lock(&self.steps).push("rollback");
+            Ok(())
+        }
+    }
+
+    fn shut_down(segment: &Arc<Segment>, grace_period: Option<Duration>) -> ShutdownReport {
+        let coordinator = Coordinator::with_phases(&[FLUSH]);
+        if let Some(grace_period) = grace_period {
+            coordinator.set_grace_period(grace_period);
+        }
+        let segment = segment.clone();
+        coordinator.phase(FLUSH).hook("wal", move |ctx: &Context| {
+            run("wal prepare", &segment, Duration::from_millis(200), ctx)
+        });
+        coordinator.run(Signal::Terminate)
+    }
+
+    #[test]
+    fn commits_the_prepared_work() {
+        let segment = Arc::new(Segment::default());
+        assert!(shut_down(&segment, Some(Duration::from_secs(10))).is_clean());
+        assert_eq!(*lock(&segment.steps), ["prepare", "commit"]);
+    }
+
+    #[test]
+    fn rolls_back_a_failed_commit() {
+        let segment = Arc::new(Segment {
+            fail_commit: true,
+            ..Segment::default()
+        });
+        assert!(!shut_down(&segment, None).is_clean());
+        assert_eq!(*lock(&segment.steps), ["prepare", "rollback"]);
+    }
+
+    #[test]
+    fn rolls_back_while_there_is_time_left() {
+        let segment = Arc::new(Segment {
+            prepare_for: Duration::from_secs(1),
+            ..Segment::default()
+        });
+        let report = shut_down(&segment, Some(Duration::from_millis(300)));
+        let (_, hook) = report.failures().next().unwrap();
+        assert_eq!(
+            hook.failure().unwrap().to_string(),
+            "failed: prepare did not finish in time"
+        );
+        assert_eq!(*lock(&segment.steps), ["rollback"]);
+    }
+}
@@ -0,0 +1,215 @@
+//! Starting the shutdown from something other than a signal.
+//!
+//! Signals are handled by the [SignalGuard](../struct.SignalGuard.html)
+//! itself. Anything else that should start the shutdown, such as a
+//! deadline, the parent closing a pipe or a command on an admin socket, is
+//! a [TriggerSource](trait.TriggerSource.html) watched on a thread of its
+//! own by [spawn](fn.spawn.html). Once it fires, its signal is
+//! [raised](../process/fn.raise.html) in this process, so the guard shuts
+//! down as if it came from outside and every hook, report and observer sees
+//! it the same way.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::io::{self, BufRead, BufReader};
+//! use std::os::unix::net::UnixListener;
+//! use std::time::Duration;
+//!
+//! use graceful::trigger::{self, Eof, Timer, TriggerSource};
+//! use graceful::{BoxError, Signal, SignalGuard};
+//!
+//! struct AdminSocket(UnixListener);
+//!
+//! impl TriggerSource for AdminSocket {
+//!     fn wait(&mut self) -> Result<Option<Signal>, BoxError> {
+//!         for stream in self.0.incoming() {
+//!             let mut line = String::new();
+//!             BufReader::new(stream?).read_line(&mut line)?;
+//!             if line.trim() == "shutdown" {
+//!                 return Ok(Some(Signal::Terminate));
+//!             }
+//!         }
+//!         Ok(None)
+//!     }
+//! }
+//!
+//! let signal_guard = SignalGuard::new();
+//! trigger::spawn("max lifetime", Timer::after(Duration::from_secs(86400))).unwrap();
+//! trigger::spawn("parent gone", Eof::new(io::stdin())).unwrap();
+//! let admin = UnixListener::bind("/run/app/admin.sock").unwrap();
+//! trigger::spawn("admin socket", AdminSocket(admin)).unwrap();
+//! signal_guard.at_exit(|_| {});
+//! ```
+
+use std::io::{self, Read};
+use std::thread as std_thread;
+use std::time::{Duration, Instant};
+
+use error::BoxError;
+use process;
+use signal::Signal;
+use state;
+use thread::{self, JoinHandle};
+
+/// Something that can start the shutdown.
+pub trait TriggerSource: Send + 'static {
+    /// Block until the shutdown should start, and return the signal to
+    /// start it with. `Ok(None)` stops watching without starting it.
+    fn wait(&mut self) -> Result<Option<Signal>, BoxError>;
+}
+
+/// Watch `source` on a new thread, and start the shutdown once it fires.
+///
+/// Nothing is raised if the shutdown has started already. The handle
+/// returns the error of `source`, or of raising the signal.
+pub fn spawn<T: TriggerSource>(
+    name: &str,
+    mut source: T,
+) -> io::Result<JoinHandle<Result<(), BoxError>>> {
+    let builder = std_thread::Builder::new().name(format!("graceful: trigger {}", name));
+    thread::spawn_with(builder, move || {
+        if let Some(signal) = source.wait()? {
+            if !state::is_shutting_down() {
+                process::raise(signal)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Fires once a deadline has passed, for example to bound how long a
+/// process lives.
+#[derive(Clone, Copy, Debug)]
+pub struct Timer {
+    /// `None` if the delay was too long to add to the current time.
+    at: Option<Instant>,
+    signal: Signal,
+}
+
+impl Timer {
+    /// Fire with `SIGTERM` `delay` from now, or never if `delay` is too long
+    /// to add to the current time.
+    pub fn after(delay: Duration) -> Timer {
+        Timer {
+            at: Instant::now().checked_add(delay),
+            signal: Signal::Terminate,
+        }
+    }
+
+    /// Fire with `SIGTERM` at `at`.
+    pub fn at(at: Instant) -> Timer {
+        Timer {
+            at: Some(at),
+            signal: Signal::Terminate,
+        }
+    }
+
+    /// Fire with `signal` instead.
+    pub fn signal(mut self, signal: Signal) -> Timer {
+        self.signal = signal;
+        self
+    }
+}
+
+impl TriggerSource for Timer {
+    fn wait(&mut self) -> Result<Option<Signal>, BoxError> {
+        let at = match self.at {
+            Some(at) => at,
+            None => loop {
+                std_thread::park();
+            },
+        };
+        loop {
+            let now = Instant::now();
+            if now >= at {
+                return Ok(Some(self.signal));
+            }
+            std_thread::sleep(at - now);
+        }
+    }
+}
+
+/// Fires once a reader reaches its end or fails, for example when the
+/// parent process closes the pipe to standard input. What is read is
+/// discarded.
+#[derive(Debug)]
+pub struct Eof<R> {
+    reader: R,
+    signal: Signal,
+}
+
+impl<R: Read + Send + 'static> Eof<R> {
+    /// Fire with `SIGTERM` at the end of `reader`.
+    pub fn new(reader: R) -> Eof<R> {
+        Eof {
+            reader,
+            signal: Signal::Terminate,
+        }
+    }
+
+    /// Fire with `signal` instead.
+    pub fn signal(mut self, signal: Signal) -> Eof<R> {
+        self.signal = signal;
+        self
+    }
+}
+
+impl<R: Read + Send + 'static> TriggerSource for Eof<R> {
+    fn wait(&mut self) -> Result<Option<Signal>, BoxError> {
+        let mut buf = [0; 512];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(Some(self.signal)),
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return Ok(Some(self.signal)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers_fire_at_their_deadline() {
+        let at = Instant::now() + Duration::from_millis(20);
+        let signal = Timer::at(at).signal(Signal::Interrupt).wait().unwrap();
+        assert_eq!(signal, Some(Signal::Interrupt));
+        assert!(Instant::now() >= at);
+    }
+
+    #[test]
+    fn eof_fires_at_the_end_of_the_reader() {
+        let mut eof = Eof::new(&b"some input"[..]);
+        assert_eq!(eof.wait().unwrap(), Some(Signal::Terminate));
+    }
+
+    #[test]
+    fn spawned_sources_may_stop_without_a_signal() {
+        struct Never;
+
+        impl TriggerSource for Never {
+            fn wait(&mut self) -> Result<Option<Signal>, BoxError> {
+                Ok(None)
+            }
+        }
+
+        struct Broken;
+
+        impl TriggerSource for Broken {
+            fn wait(&mut self) -> Result<Option<Signal>, BoxError> {
+                Err("socket closed".into())
+            }
+        }
+
+        assert!(spawn("never", Never).unwrap().join().unwrap().is_ok());
+        let err = spawn("broken", Broken)
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "socket closed");
+    }
+}
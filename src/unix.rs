@@ -0,0 +1,357 @@
+extern crate nix;
+
+use std::io;
+use std::mem;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use libc;
+
+use self::nix::sys::signal::{self as nix_signal, SigSet};
+
+use error::{Error, ErrorKind};
+use signal::{Origin, Signal};
+
+pub struct Guard(SigSet);
+
+impl Guard {
+    pub fn new(signals: &[Signal]) -> Result<Guard, Error> {
+        let mut mask = SigSet::empty();
+        for signal in signals {
+            let signum = signal.raw().ok_or(ErrorKind::Init)?;
+            let signal = nix_signal::Signal::from_c_int(signum)
+                .map_err(|err| Error::new(ErrorKind::Init, io::Error::from(err)))?;
+            mask.add(signal);
+        }
+        mask.thread_block()
+            .map_err(|err| Error::new(ErrorKind::Init, io::Error::from(err)))?;
+        Ok(Guard(mask))
+    }
+
+    /// Accept the next pending signal of the set.
+    pub fn wait(&self) -> Result<(libc::c_int, Option<Origin>), Error> {
+        wait(&self.0)
+    }
+
+    /// Accept a signal of the set if one is pending, without blocking.
+    pub fn try_wait(&self) -> Result<Option<(libc::c_int, Option<Origin>)>, Error> {
+        wait_timeout(&self.0, Duration::from_secs(0))
+    }
+
+    /// Accept the next signal of the set, giving up after `timeout`.
+    pub fn wait_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<(libc::c_int, Option<Origin>)>, Error> {
+        wait_timeout(&self.0, timeout)
+    }
+
+    /// Receives the signals of this guard from another thread.
+    pub fn listener(&self) -> Listener {
+        Listener(self.0)
+    }
+
+    pub fn sigset(&self) -> &libc::sigset_t {
+        self.0.as_ref()
+    }
+
+    /// Block the set in the calling thread too.
+    pub fn block_current_thread(&self) -> io::Result<()> {
+        self.0.thread_block().map_err(io::Error::from)
+    }
+
+    /// Nothing is held back on Unix.
+    pub fn resume(&self) {}
+
+    /// Nothing is held back on Unix.
+    pub fn release(&self) {}
+}
+
+pub struct Listener(SigSet);
+
+impl Listener {
+    pub fn wait(&self) -> Result<(libc::c_int, Option<Origin>), Error> {
+        wait(&self.0)
+    }
+}
+
+/// Accept the next pending signal of the set, with the process that sent it
+/// where the platform tells.
+///
+/// Interrupted waits are retried and signals outside the set are ignored, so
+/// only a genuine failure of the wait is returned.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn wait(set: &SigSet) -> Result<(libc::c_int, Option<Origin>), Error> {
+    let set = set.as_ref();
+    loop {
+        let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+        let signum = unsafe { libc::sigwaitinfo(set, &mut info) };
+        if signum < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            return Err(Error::new(ErrorKind::Wait, err));
+        }
+        if unsafe { libc::sigismember(set, signum) } != 1 {
+            continue;
+        }
+        return Ok((signum, origin(&info)));
+    }
+}
+
+/// The process that sent the signal described by `info`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn origin(info: &libc::siginfo_t) -> Option<Origin> {
+    // Codes up to zero (`SI_USER`, `SI_QUEUE`, `SI_TKILL`) mean the signal
+    // was sent by a process rather than by the kernel.
+    if info.si_code <= 0 {
+        let (pid, uid) = unsafe { (info.si_pid(), info.si_uid()) };
+        Some(Origin::new(pid as u32, uid))
+    } else {
+        None
+    }
+}
+
+/// The sender is not known on this platform.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+)))]
+fn origin(_: &libc::siginfo_t) -> Option<Origin> {
+    None
+}
+
+/// Accept the next pending signal of the set. The sender is not known on
+/// this platform.
+///
+/// Interrupted waits are retried and signals outside the set are ignored, so
+/// only a genuine failure of `sigwait` is returned.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn wait(set: &SigSet) -> Result<(libc::c_int, Option<Origin>), Error> {
+    let set = set.as_ref();
+    loop {
+        let mut signum = 0;
+        match unsafe { libc::sigwait(set, &mut signum) } {
+            0 if unsafe { libc::sigismember(set, signum) } == 1 => return Ok((signum, None)),
+            0 | libc::EINTR => continue,
+            err => {
+                let err = io::Error::from_raw_os_error(err);
+                return Err(Error::new(ErrorKind::Wait, err));
+            }
+        }
+    }
+}
+
+/// The time left until `deadline` as a `timespec`.
+fn time_left(deadline: Instant) -> libc::timespec {
+    let left = deadline.saturating_duration_since(Instant::now());
+    libc::timespec {
+        tv_sec: left.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: left.subsec_nanos() as _,
+    }
+}
+
+/// Accept the next pending signal of the set, giving up after `timeout`.
+///
+/// Interrupted waits are retried and signals outside the set are ignored, as
+/// in `wait`.
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+)))]
+fn wait_timeout(
+    set: &SigSet,
+    timeout: Duration,
+) -> Result<Option<(libc::c_int, Option<Origin>)>, Error> {
+    let deadline = match Instant::now().checked_add(timeout) {
+        Some(deadline) => deadline,
+        None => return wait(set).map(Some),
+    };
+    let set = set.as_ref();
+    loop {
+        let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+        let left = time_left(deadline);
+        let signum = unsafe { libc::sigtimedwait(set, &mut info, &left) };
+        if signum < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EAGAIN) => return Ok(None),
+                Some(libc::EINTR) => continue,
+                _ => return Err(Error::new(ErrorKind::Wait, err)),
+            }
+        }
+        if unsafe { libc::sigismember(set, signum) } == 1 {
+            return Ok(Some((signum, origin(&info))));
+        }
+    }
+}
+
+/// Accept the next pending signal of the set, giving up after `timeout`.
+///
+/// There is no `sigtimedwait` everywhere here, so a kqueue is told about the
+/// signals instead, which notices them even though they are blocked, and
+/// `sigwait` accepts them once they are pending.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+fn wait_timeout(
+    set: &SigSet,
+    timeout: Duration,
+) -> Result<Option<(libc::c_int, Option<Origin>)>, Error> {
+    let queue = unsafe { libc::kqueue() };
+    if queue < 0 {
+        return Err(Error::new(ErrorKind::Wait, io::Error::last_os_error()));
+    }
+    let result = wait_kqueue(queue, set, timeout);
+    unsafe {
+        libc::close(queue);
+    }
+    result
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+fn wait_kqueue(
+    queue: libc::c_int,
+    set: &SigSet,
+    timeout: Duration,
+) -> Result<Option<(libc::c_int, Option<Origin>)>, Error> {
+    let mask = set.as_ref();
+    let changes: Vec<libc::kevent> = members(mask)
+        .map(|signum| {
+            let mut change: libc::kevent = unsafe { mem::zeroed() };
+            change.ident = signum as _;
+            change.filter = libc::EVFILT_SIGNAL;
+            change.flags = libc::EV_ADD;
+            change
+        })
+        .collect();
+    let registered = unsafe {
+        libc::kevent(
+            queue,
+            changes.as_ptr(),
+            changes.len() as _,
+            ptr::null_mut(),
+            0,
+            ptr::null(),
+        )
+    };
+    if registered < 0 {
+        return Err(Error::new(ErrorKind::Wait, io::Error::last_os_error()));
+    }
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        // Signals already pending before the queue was set up raise no
+        // event, so look for them every time round.
+        if is_pending(mask)? {
+            return wait(set).map(Some);
+        }
+        let left = deadline.map(time_left);
+        if let Some(left) = left {
+            if left.tv_sec == 0 && left.tv_nsec == 0 {
+                return Ok(None);
+            }
+        }
+        let mut event: libc::kevent = unsafe { mem::zeroed() };
+        let received = unsafe {
+            libc::kevent(
+                queue,
+                ptr::null(),
+                0,
+                &mut event,
+                1,
+                left.as_ref().map_or(ptr::null(), |left| left as *const _),
+            )
+        };
+        if received < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINTR) {
+                return Err(Error::new(ErrorKind::Wait, err));
+            }
+        }
+    }
+}
+
+/// The signals of `set`. Past the last signal of the platform `sigismember`
+/// fails, which is not membership either.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+fn members(set: &libc::sigset_t) -> impl Iterator<Item = libc::c_int> + '_ {
+    (1..=64).filter(move |&signum| unsafe { libc::sigismember(set, signum) } == 1)
+}
+
+/// Whether a signal of `set` is pending.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+fn is_pending(set: &libc::sigset_t) -> Result<bool, Error> {
+    let mut pending: libc::sigset_t = unsafe { mem::zeroed() };
+    if unsafe { libc::sigpending(&mut pending) } != 0 {
+        return Err(Error::new(ErrorKind::Wait, io::Error::last_os_error()));
+    }
+    Ok(members(set).any(|signum| unsafe { libc::sigismember(&pending, signum) } == 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn exposes_the_blocked_set() {
+        let guard = Guard::new(&[Signal::Interrupt, Signal::Terminate]).unwrap();
+        let set = guard.sigset();
+        let mut blocked: libc::sigset_t = unsafe { mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut blocked) },
+            0
+        );
+        for &signum in &[libc::SIGINT, libc::SIGTERM] {
+            assert_eq!(unsafe { libc::sigismember(set, signum) }, 1);
+            assert_eq!(unsafe { libc::sigismember(&blocked, signum) }, 1);
+        }
+        assert_eq!(unsafe { libc::sigismember(set, libc::SIGUSR1) }, 0);
+    }
+}
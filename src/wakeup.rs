@@ -0,0 +1,173 @@
+//! Interrupt blocking system calls in registered threads at shutdown.
+//!
+//! Once [enabled](fn.enable.html), every thread that
+//! [registered](fn.register.html) itself is sent the chosen signal when
+//! termination starts. Its handler does nothing and is installed without
+//! `SA_RESTART`, so a blocking `read()` or `accept()` in that thread fails
+//! with `EINTR` and the loop around it gets to check whether to stop. Note
+//! that some wrappers in `std`, like `TcpListener::accept` and
+//! `thread::sleep`, retry on `EINTR` themselves.
+//!
+//! The signal may arrive just before the thread enters the blocking call, in
+//! which case the call is not interrupted. Check the stop condition before
+//! blocking again, or combine this with a timeout.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::io::{ErrorKind, Read};
+//! use std::net::TcpStream;
+//! use std::thread;
+//!
+//! use graceful::{wakeup, Signal, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::new();
+//! wakeup::enable(Signal::User2).unwrap();
+//!
+//! let mut stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+//! let reader = thread::spawn(move || {
+//!     let _registration = wakeup::register();
+//!     let mut buf = [0; 1024];
+//!     loop {
+//!         match stream.read(&mut buf) {
+//!             Ok(0) => break,
+//!             Ok(_) => { /* ... */ }
+//!             Err(ref err) if err.kind() == ErrorKind::Interrupted => break,
+//!             Err(err) => panic!("{}", err),
+//!         }
+//!     }
+//! });
+//!
+//! signal_guard.at_exit(move |_| {
+//!     reader.join().unwrap();
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use libc;
+
+use error::{Error, ErrorKind};
+use signal::Signal;
+#[cfg(feature = "signal-safety-audit")]
+use signal_safety;
+use sync::lock;
+
+lazy_static! {
+    static ref THREADS: Mutex<HashMap<usize, libc::pthread_t>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The signal sent to registered threads, `0` until enabled.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn ignore(_: libc::c_int) {
+    #[cfg(feature = "signal-safety-audit")]
+    let _scope = signal_safety::enter();
+}
+
+/// Install a no-op handler for `signal` and send it to the registered
+/// threads at shutdown.
+///
+/// `signal` must not be one the [SignalGuard](../struct.SignalGuard.html)
+/// waits for, and nothing else in the process should rely on it.
+pub fn enable(signal: Signal) -> Result<(), Error> {
+    let signum = signal.raw().ok_or(ErrorKind::Init)?;
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // No `SA_RESTART`: interrupting the blocking call is the point.
+        action.sa_flags = 0;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signum, &action, ptr::null_mut()) != 0 {
+            return Err(Error::new(ErrorKind::Init, io::Error::last_os_error()));
+        }
+    }
+    SIGNAL.store(signum, Ordering::Release);
+    Ok(())
+}
+
+/// Keeps the current thread registered until dropped.
+///
+/// It cannot be sent to another thread, so the registration ends on the
+/// thread it names.
+#[derive(Debug)]
+#[must_use = "the thread is unregistered when this is dropped"]
+pub struct Registration {
+    id: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock(&THREADS).remove(&self.id);
+    }
+}
+
+/// Have the current thread interrupted when termination starts.
+pub fn register() -> Registration {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&THREADS).insert(id, unsafe { libc::pthread_self() });
+    Registration {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+/// Signal every registered thread, if enabled.
+pub(crate) fn wake_all() {
+    let signum = SIGNAL.load(Ordering::Acquire);
+    if signum == 0 {
+        return;
+    }
+    for &thread in lock(&THREADS).values() {
+        unsafe {
+            libc::pthread_kill(thread, signum);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn interrupts_a_blocking_read() {
+        enable(Signal::User2).unwrap();
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_fd, write_fd] = fds;
+        let (interrupted, reported) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            let _registration = register();
+            let mut buf = [0u8; 1];
+            let read = unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut _, 1) };
+            interrupted.send(io::Error::last_os_error().kind()).unwrap();
+            read
+        });
+        // The signal may come before the read starts, so keep sending it.
+        let kind = loop {
+            wake_all();
+            if let Ok(kind) = reported.recv_timeout(Duration::from_millis(10)) {
+                break kind;
+            }
+        };
+        assert_eq!(reader.join().unwrap(), -1);
+        assert_eq!(kind, io::ErrorKind::Interrupted);
+        assert!(lock(&THREADS).is_empty());
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+    }
+}
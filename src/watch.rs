@@ -0,0 +1,140 @@
+//! Restart when a new build of the program is deployed.
+//!
+//! [ExecutableWatcher](struct.ExecutableWatcher.html) checks the path the
+//! program was started from and calls back once the file there changes, for
+//! example to stop gracefully and have the supervisor or service manager
+//! start the new build:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # #[cfg(unix)]
+//! # fn main() {
+//! use std::time::Duration;
+//!
+//! use graceful::watch::ExecutableWatcher;
+//! use graceful::{Signal, SignalGuard};
+//!
+//! let signal_guard = SignalGuard::new();
+//! let _watcher = ExecutableWatcher::spawn(Duration::from_secs(5), || {
+//!     // Received by the guard like any other SIGTERM.
+//!     graceful::process::raise(Signal::Terminate).unwrap();
+//! })
+//! .unwrap();
+//!
+//! signal_guard.at_exit(|_| {});
+//! # }
+//! # #[cfg(not(unix))]
+//! # fn main() {}
+//! ```
+//!
+//! [process::raise](../process/fn.raise.html) is Unix only. On Windows,
+//! where the program cannot send the guard a console event, drive the
+//! shutdown with [embedded](../embedded/index.html) instead of a guard and
+//! start it from the callback, which works on every platform:
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! use std::time::Duration;
+//!
+//! use graceful::watch::ExecutableWatcher;
+//! use graceful::{embedded, Signal};
+//!
+//! let token = embedded::token();
+//! let _watcher = ExecutableWatcher::spawn(Duration::from_secs(5), || {
+//!     if let Some(report) = embedded::trigger(Signal::Terminate) {
+//!         eprintln!("{}", report);
+//!     }
+//! })
+//! .unwrap();
+//!
+//! token.wait();
+//! ```
+
+use std::env;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// What identifies one build of the file. Deploys usually rename a new file
+/// into place, which changes the inode even if nothing else differs.
+#[derive(PartialEq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    inode: (u64, u64),
+}
+
+impl Stamp {
+    fn of(path: &Path) -> io::Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(Stamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: (metadata.dev(), metadata.ino()),
+        })
+    }
+}
+
+/// Watches the running executable until dropped.
+pub struct ExecutableWatcher {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+}
+
+impl ExecutableWatcher {
+    /// Check the executable every `interval` and call `on_change` once it
+    /// has been replaced or modified.
+    ///
+    /// A file that is missing for a moment in the middle of a deploy is not
+    /// a change, the watcher waits for the new one to appear.
+    pub fn spawn<F>(interval: Duration, on_change: F) -> io::Result<ExecutableWatcher>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // Resolved now: once the file is replaced, `current_exe` may name
+        // the old, deleted one.
+        let path = env::current_exe()?.canonicalize()?;
+        let initial = Stamp::of(&path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let watched = path.clone();
+        let stopped = stop.clone();
+        thread::Builder::new()
+            .name("graceful: executable watcher".to_owned())
+            .spawn(move || {
+                while !stopped.load(Ordering::Acquire) {
+                    thread::sleep(interval);
+                    match Stamp::of(&watched) {
+                        Ok(ref stamp) if *stamp != initial => {
+                            if !stopped.load(Ordering::Acquire) {
+                                on_change();
+                            }
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+            })?;
+
+        Ok(ExecutableWatcher { path, stop })
+    }
+
+    /// The path being watched.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ExecutableWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
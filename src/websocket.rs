@@ -0,0 +1,216 @@
+//! Closing WebSocket sessions at shutdown.
+//!
+//! Sessions registered here are sent a Close frame with code `1001` (going
+//! away) when the [DRAIN](../hooks/constant.DRAIN.html) phase starts, and
+//! are given a moment to answer before their connections are shut down.
+//! The thread reading a session gets the peer's Close frame from `read()`,
+//! which then fails with `ConnectionClosed`; dropping the
+//! [Session](struct.Session.html) at that point is what counts as the
+//! acknowledgment.
+//!
+//! The Close frame is written by the registry, so the session's own thread
+//! should stop sending once [is_shutting_down](../fn.is_shutting_down.html)
+//! is true. Only server sessions are supported, as frames sent by a client
+//! have to be masked.
+//!
+//! ```no_run
+//! # extern crate graceful;
+//! # extern crate tungstenite;
+//! use std::net::TcpListener;
+//! use tungstenite::protocol::Role;
+//! use tungstenite::WebSocket;
+//!
+//! let listener = TcpListener::bind("127.0.0.1:8080").unwrap();
+//! for stream in listener.incoming() {
+//!     // After the handshake.
+//!     let mut socket = WebSocket::from_raw_socket(stream.unwrap(), Role::Server, None);
+//!     let _session = graceful::websocket::register(&socket).unwrap();
+//!     while let Ok(message) = socket.read() {
+//!         # let _ = message;
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{self, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::frame::{CloseFrame, Frame};
+use tungstenite::WebSocket;
+
+use error::BoxError;
+use hooks::{self, Context};
+use sync::{lock, wait, wait_timeout};
+
+/// A stream a WebSocket session can run over.
+pub trait Stream: Send {
+    /// A second handle to the same stream, kept by the registry.
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>>;
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        Write::write_all(self, buf)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn try_clone_stream(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        Write::write_all(self, buf)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, net::Shutdown::Both)
+    }
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<usize, Box<dyn Stream>>> = Mutex::new(HashMap::new());
+    /// Notified whenever a session is dropped.
+    static ref CLOSED: Condvar = Condvar::new();
+    static ref ACK_TIMEOUT: Mutex<Duration> = Mutex::new(Duration::from_secs(1));
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+static HOOK: Once = Once::new();
+
+/// Keeps a WebSocket session registered until dropped.
+///
+/// As for [net::Registration](../net/struct.Registration.html), the
+/// registry holds a duplicate of the stream's descriptor.
+#[derive(Debug)]
+#[must_use = "the session is unregistered when this is dropped"]
+pub struct Session(usize);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        lock(&SESSIONS).remove(&self.0);
+        CLOSED.notify_all();
+    }
+}
+
+/// Close `socket` cleanly at shutdown, see the [module](index.html)
+/// documentation.
+pub fn register<S: Stream>(socket: &WebSocket<S>) -> io::Result<Session> {
+    let stream = socket.get_ref().try_clone_stream()?;
+    HOOK.call_once(|| {
+        hooks::phase(hooks::DRAIN).hook_once("graceful: websocket close", close_sessions);
+    });
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&SESSIONS).insert(id, stream);
+    Ok(Session(id))
+}
+
+/// How long to wait for peers to answer the Close frame, within the phase's
+/// grace period. Defaults to one second.
+pub fn set_ack_timeout(timeout: Duration) {
+    *lock(&ACK_TIMEOUT) = timeout;
+}
+
+fn close_sessions(ctx: &Context) -> Result<(), BoxError> {
+    let mut deadline = Instant::now().checked_add(*lock(&ACK_TIMEOUT));
+    if let Some(phase_deadline) = ctx.deadline() {
+        deadline = Some(deadline.map_or(phase_deadline, |own| own.min(phase_deadline)));
+    }
+
+    let mut frame = Vec::new();
+    Frame::close(Some(CloseFrame {
+        code: CloseCode::Away,
+        reason: Cow::Borrowed("shutting down"),
+    }))
+    .format(&mut frame)?;
+
+    let mut sessions = lock(&SESSIONS);
+    // Peers that are already gone have nothing to acknowledge.
+    for stream in sessions.values_mut() {
+        let _ = stream.write_all(&frame);
+    }
+    while !sessions.is_empty() {
+        sessions = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                wait_timeout(&CLOSED, sessions, deadline - now)
+            }
+            None => wait(&CLOSED, sessions),
+        };
+    }
+
+    if sessions.is_empty() {
+        return Ok(());
+    }
+    for stream in sessions.values() {
+        let _ = stream.shutdown();
+    }
+    let message = format!("{} sessions did not acknowledge the close", sessions.len());
+    Err(io::Error::new(io::ErrorKind::TimedOut, message).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use tungstenite::protocol::Role;
+    use tungstenite::Message;
+
+    use super::*;
+    use hooks::{Coordinator, DRAIN};
+    use signal::Signal;
+
+    #[test]
+    fn closes_sessions_going_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let mut server = WebSocket::from_raw_socket(server, Role::Server, None);
+        let session = register(&server).unwrap();
+        let serving = thread::spawn(move || {
+            while server.read().is_ok() {}
+            drop(session);
+        });
+        let mut client = WebSocket::from_raw_socket(client, Role::Client, None);
+        let closing = thread::spawn(move || client.read().unwrap());
+
+        let coordinator = Coordinator::with_phases(&[DRAIN]);
+        coordinator.phase(DRAIN).hook("close", close_sessions);
+        let report = coordinator.run(Signal::Terminate);
+        assert!(report.is_clean());
+        serving.join().unwrap();
+        match closing.join().unwrap() {
+            Message::Close(Some(frame)) => {
+                assert_eq!(frame.code, CloseCode::Away);
+                assert_eq!(frame.reason, "shutting down");
+            }
+            message => panic!("not a close frame: {:?}", message),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+extern crate winapi;
+
+use std::io;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use self::winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use self::winapi::um::consoleapi::SetConsoleCtrlHandler;
+use self::winapi::um::processthreadsapi::SetProcessShutdownParameters;
+use self::winapi::um::wincon::{CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT};
+
+use error::{Error, ErrorKind};
+use signal::{Origin, Signal};
+use sync::{lock, wait, wait_timeout};
+
+#[derive(Default)]
+struct State {
+    /// The first event received, until the guard takes it.
+    event: Option<DWORD>,
+    /// Set once the guard's handler has returned.
+    released: bool,
+    /// Bumped to let the handlers waiting on the last event return without
+    /// releasing the next one.
+    generation: u64,
+    /// Set once a guard's handler has returned, and kept when the guard
+    /// starts over: from then on the handlers of events that end the
+    /// process never return.
+    finished: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+lazy_static! {
+    static ref SHARED: Shared = Shared::default();
+}
+
+/// Runs on a thread created by the system for every console event.
+///
+/// The system terminates the process once this returns from a close, logoff
+/// or shutdown event, so every call is held back until the guard's handler
+/// has finished, and those of the events that end the process for good
+/// after that: the program returns from `main` and exits on its own, unless
+/// the system stops waiting for it first. Nothing in here can panic.
+unsafe extern "system" fn handler(event: DWORD) -> BOOL {
+    let mut state = lock(&SHARED.state);
+    let generation = state.generation;
+    if state.event.is_none() && !state.released {
+        state.event = Some(event);
+        SHARED.cond.notify_all();
+    }
+    let ends_process = matches!(
+        event,
+        CTRL_CLOSE_EVENT | CTRL_LOGOFF_EVENT | CTRL_SHUTDOWN_EVENT
+    );
+    while (ends_process && state.finished) || (!state.released && state.generation == generation) {
+        state = wait(&SHARED.cond, state);
+    }
+    TRUE
+}
+
+/// Have the system ask this process to shut down at `level` of its
+/// shutdown order, see `SetProcessShutdownParameters`.
+pub fn set_shutdown_level(level: DWORD) -> io::Result<()> {
+    if unsafe { SetProcessShutdownParameters(level, 0) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Hand `event` to the guard as the console handler does, but without
+/// waiting for it to be handled, for callbacks that have to return at once
+/// such as a service control handler.
+pub fn deliver(event: DWORD) {
+    let mut state = lock(&SHARED.state);
+    if state.event.is_none() && !state.released {
+        state.event = Some(event);
+        SHARED.cond.notify_all();
+    }
+}
+
+pub type HandlerRoutine = unsafe extern "system" fn(DWORD) -> BOOL;
+
+pub struct Guard;
+
+impl Guard {
+    /// Every console event reaches the handler, so `_signals` only matters on
+    /// Unix.
+    pub fn new(_signals: &[Signal]) -> Result<Guard, Error> {
+        if unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) } == 0 {
+            return Err(Error::new(ErrorKind::Init, io::Error::last_os_error()));
+        }
+        Ok(Guard)
+    }
+
+    pub fn wait(&self) -> Result<(i32, Option<Origin>), Error> {
+        wait_event()
+    }
+
+    /// Take the event received by the handler, if any, without blocking.
+    pub fn try_wait(&self) -> Result<Option<(i32, Option<Origin>)>, Error> {
+        Ok(lock(&SHARED.state)
+            .event
+            .take()
+            .map(|event| (event as i32, None)))
+    }
+
+    /// Take the next event received by the handler, giving up after
+    /// `timeout`.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<Option<(i32, Option<Origin>)>, Error> {
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => return wait_event().map(Some),
+        };
+        let mut state = lock(&SHARED.state);
+        loop {
+            if let Some(event) = state.event.take() {
+                return Ok(Some((event as i32, None)));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = wait_timeout(&SHARED.cond, state, deadline - now);
+        }
+    }
+
+    /// Receives the events of this guard from another thread.
+    pub fn listener(&self) -> Listener {
+        Listener
+    }
+
+    pub fn handler(&self) -> HandlerRoutine {
+        handler
+    }
+
+    /// Console events are not delivered to the threads of the program.
+    pub fn block_current_thread(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The event received but not yet taken by `wait`, if any.
+    pub fn pending(&self) -> Option<u32> {
+        lock(&SHARED.state).event
+    }
+
+    /// Let the handlers holding back the last event return and accept the
+    /// next one. Returning from a `Ctrl+C` or `Ctrl+Break` handler does not
+    /// terminate the process.
+    pub fn resume(&self) {
+        let mut state = lock(&SHARED.state);
+        *state = State {
+            generation: state.generation.wrapping_add(1),
+            finished: state.finished,
+            ..State::default()
+        };
+        SHARED.cond.notify_all();
+    }
+
+    /// Stop accepting events once the guard's handler has returned. The
+    /// handlers of `Ctrl+C` and `Ctrl+Break` return, those of the events
+    /// that end the process are held until it exits.
+    pub fn release(&self) {
+        let mut state = lock(&SHARED.state);
+        state.released = true;
+        state.finished = true;
+        SHARED.cond.notify_all();
+    }
+}
+
+impl Drop for Guard {
+    /// Remove the handler and start over, so another guard can be created
+    /// later in the same process.
+    fn drop(&mut self) {
+        unsafe {
+            SetConsoleCtrlHandler(Some(handler), FALSE);
+        }
+        self.resume();
+    }
+}
+
+/// Receives events without holding back or releasing their handlers, which
+/// is up to the guard.
+pub struct Listener;
+
+impl Listener {
+    pub fn wait(&self) -> Result<(i32, Option<Origin>), Error> {
+        wait_event()
+    }
+}
+
+/// Receive the next console control event. Events do not tell where they came
+/// from.
+fn wait_event() -> Result<(i32, Option<Origin>), Error> {
+    let mut state = lock(&SHARED.state);
+    loop {
+        if let Some(event) = state.event.take() {
+            return Ok((event as i32, None));
+        }
+        state = wait(&SHARED.cond, state);
+    }
+}
@@ -0,0 +1,20 @@
+//! at_exit hands its handler the signal that started the shutdown.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use graceful::{process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::builder()
+        .signal(Signal::Hangup)
+        .build()
+        .unwrap();
+    process::raise(Signal::Hangup).unwrap();
+    let mut received = None;
+    signal_guard.at_exit(|signal| received = Some(signal));
+    assert_eq!(received, Some(Signal::Hangup));
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,38 @@
+//! The waiting thread ticks until a signal arrives, and a signal raised
+//! while a tick runs is taken once it returns.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::time::Duration;
+
+    use graceful::{process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::new();
+    let zero = panic::catch_unwind(AssertUnwindSafe(|| {
+        signal_guard.at_exit_with_tick(Duration::from_secs(0), || {}, |_| {})
+    }));
+    assert!(zero.is_err());
+    assert!(!graceful::is_shutting_down());
+
+    let mut ticks = 0;
+    let mut stopped_on = None;
+    signal_guard.at_exit_with_tick(
+        Duration::from_millis(10),
+        || {
+            ticks += 1;
+            if ticks == 3 {
+                process::raise(Signal::Terminate).unwrap();
+            }
+        },
+        |signal| stopped_on = Some(signal),
+    );
+    assert_eq!(ticks, 3);
+    assert_eq!(stopped_on, Some(Signal::Terminate));
+    assert!(graceful::is_shutting_down());
+}
+
+#[cfg(not(unix))]
+fn main() {}
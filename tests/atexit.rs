@@ -0,0 +1,36 @@
+//! The last rites run once the handler has returned, in reverse order of
+//! registration, once each, past a panic in one of them and including
+//! those they add.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::{Arc, Mutex};
+
+    use graceful::{process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let first = log.clone();
+    graceful::atexit(move || first.lock().unwrap().push("lock file"));
+    graceful::atexit(|| panic!("log already closed"));
+    let last = log.clone();
+    graceful::atexit(move || {
+        last.lock().unwrap().push("log");
+        let added = last.clone();
+        graceful::atexit(move || added.lock().unwrap().push("added"));
+    });
+
+    process::raise(Signal::Terminate).unwrap();
+    let handled = log.clone();
+    signal_guard.at_exit(move |_| handled.lock().unwrap().push("handler"));
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["handler", "log", "lock file", "added"]
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,52 @@
+//! check_shutdown! leaves functions and loops once the shutdown started.
+
+#[macro_use]
+extern crate graceful;
+
+use graceful::{embedded, Signal};
+
+fn sum(items: &[u32]) -> Option<u32> {
+    let mut sum = 0;
+    for item in items {
+        check_shutdown!(return None);
+        sum += item;
+    }
+    Some(sum)
+}
+
+fn visit(visited: &mut Vec<u32>) {
+    check_shutdown!();
+    visited.push(0);
+}
+
+/// The items visited by loops left with each form of the macro.
+fn loops() -> Vec<u32> {
+    let mut visited = Vec::new();
+    for item in 1..3 {
+        check_shutdown!(continue);
+        visited.push(item);
+    }
+    'outer: for item in 3..5 {
+        for _ in 0..1 {
+            check_shutdown!(break 'outer);
+        }
+        visited.push(item);
+    }
+    for item in 5..7 {
+        check_shutdown!(break);
+        visited.push(item);
+    }
+    visit(&mut visited);
+    visited
+}
+
+#[test]
+fn leaves_once_the_shutdown_started() {
+    assert_eq!(sum(&[1, 2, 3]), Some(6));
+    assert_eq!(loops(), [1, 2, 3, 4, 5, 6, 0]);
+
+    embedded::trigger(Signal::Terminate).unwrap();
+    assert!(graceful::is_shutting_down());
+    assert_eq!(sum(&[1, 2, 3]), None);
+    assert!(loops().is_empty());
+}
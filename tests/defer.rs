@@ -0,0 +1,66 @@
+//! Deferred values are dropped with the guard if no signal came, or in the
+//! CLOSE phase of the shutdown, the last deferred first and past a drop
+//! that panics, unless they were taken back.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::{Arc, Mutex};
+
+    use graceful::{hooks, process, Signal, SignalGuard};
+
+    struct Cleanup {
+        name: &'static str,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            self.log.lock().unwrap().push(self.name);
+            if self.name == "stuck" {
+                panic!("cannot remove the lock file");
+            }
+        }
+    }
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let cleanup = |name| Cleanup {
+        name,
+        log: log.clone(),
+    };
+
+    let signal_guard = SignalGuard::new();
+    signal_guard.defer(cleanup("pid file"));
+    let kept = signal_guard.defer(cleanup("kept"));
+    let early = signal_guard.defer(cleanup("early"));
+    signal_guard.defer(cleanup("temp dir"));
+    early.drop_now();
+    assert_eq!(kept.take().map(|kept| kept.name), Some("kept"));
+    assert_eq!(*log.lock().unwrap(), ["early", "kept"]);
+    drop(signal_guard);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["early", "kept", "temp dir", "pid file"]
+    );
+    log.lock().unwrap().clear();
+
+    let signal_guard = SignalGuard::new();
+    signal_guard.defer(cleanup("socket"));
+    signal_guard.defer(cleanup("stuck"));
+    let dropped = signal_guard.defer(cleanup("log"));
+    process::raise(Signal::Terminate).unwrap();
+    let outcome = signal_guard.wait_and_shutdown(|_| {});
+    assert_eq!(*log.lock().unwrap(), ["log", "stuck", "socket"]);
+    assert!(dropped.take().is_none());
+    let (phase, hook) = outcome.report().failures().next().unwrap();
+    assert_eq!(phase, hooks::CLOSE);
+    assert_eq!(hook.name(), "graceful: deferred values");
+    assert_eq!(
+        hook.failure().unwrap().to_string(),
+        "failed: drop panicked: cannot remove the lock file"
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,37 @@
+//! A framework driving the shutdown itself trips the tokens and runs the
+//! hooks on its first trigger, and a trigger while they run moves the
+//! shutdown to aborting instead of running them again.
+
+extern crate graceful;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use graceful::{embedded, hooks, ShutdownPhase, Signal};
+
+fn main() {
+    let token = embedded::token();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted = runs.clone();
+    hooks::phase(hooks::CLOSE).hook("pool", move |_| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        assert!(embedded::trigger(Signal::Interrupt).is_none());
+    });
+    assert!(!token.is_shutdown());
+
+    let report = thread::spawn(|| embedded::trigger(Signal::Terminate))
+        .join()
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.signal(), Signal::Terminate);
+    let causes: Vec<Signal> = report.causes().map(|cause| cause.signal()).collect();
+    assert_eq!(causes, [Signal::Terminate, Signal::Interrupt]);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert!(token.is_shutdown());
+    assert_eq!(token.phase(), ShutdownPhase::Aborting);
+    assert!(graceful::is_shutting_down());
+
+    assert!(embedded::trigger(Signal::Terminate).is_none());
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
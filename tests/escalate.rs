@@ -0,0 +1,101 @@
+//! A handler running past its timeout, a second signal, or a check with
+//! exit_if_signaled once one arrived, ends the process as if killed by the
+//! signal, while a handler done in time returns. Each
+//! case runs in a child process, this same binary started again with
+//! `ESCALATE` set.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::env;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, Stdio};
+    use std::thread;
+    use std::time::Duration;
+
+    use graceful::{hooks, process, Signal, SignalGuard};
+
+    match env::var("ESCALATE").as_ref().map(String::as_str) {
+        Ok("timeout") => {
+            let signal_guard = SignalGuard::new();
+            process::raise(Signal::Terminate).unwrap();
+            signal_guard.at_exit_with_timeout(Duration::from_millis(50), |_| {
+                thread::sleep(Duration::from_secs(30))
+            });
+            return;
+        }
+        Ok("in time") => {
+            let signal_guard = SignalGuard::try_new().unwrap();
+            process::raise(Signal::Terminate).unwrap();
+            signal_guard
+                .try_at_exit_with_timeout(Duration::from_secs(10), |_| {})
+                .unwrap();
+            return;
+        }
+        Ok("exit if signaled") => {
+            let signal_guard = SignalGuard::new();
+            hooks::phase(hooks::CLOSE).hook("close", |_| println!("closed"));
+            signal_guard.exit_if_signaled();
+            process::raise(Signal::Interrupt).unwrap();
+            signal_guard.exit_if_signaled();
+            unreachable!("still running after the signal");
+        }
+        Ok("second signal") => {
+            let signal_guard = SignalGuard::builder()
+                .escalate_on_second_signal(true)
+                .build()
+                .unwrap();
+            hooks::phase(hooks::CLOSE).hook("stuck", |_| {
+                println!("ready");
+                io::stdout().flush().unwrap();
+                thread::sleep(Duration::from_secs(30));
+            });
+            process::raise(Signal::Terminate).unwrap();
+            signal_guard.wait();
+            return;
+        }
+        _ => {}
+    }
+
+    let exe = env::current_exe().unwrap();
+    let status = Command::new(&exe)
+        .env("ESCALATE", "timeout")
+        .status()
+        .unwrap();
+    assert_eq!(status.signal(), Signal::Terminate.raw());
+    let status = Command::new(&exe)
+        .env("ESCALATE", "in time")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&exe)
+        .env("ESCALATE", "exit if signaled")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.signal(), Signal::Interrupt.raw());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "closed\n");
+
+    let mut child = Command::new(&exe)
+        .env("ESCALATE", "second signal")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line, "ready\n");
+    let killed = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert_eq!(child.wait().unwrap().signal(), Signal::Interrupt.raw());
+}
+
+#[cfg(not(unix))]
+fn main() {}
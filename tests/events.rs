@@ -0,0 +1,56 @@
+//! Every signal the guard takes is sent to the events receivers, with who
+//! sent it on Linux, and an observed signal does not shut down.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::process;
+    use std::thread;
+    use std::time::SystemTime;
+
+    use graceful::{Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::builder()
+        .observe(Signal::User1)
+        .build()
+        .unwrap();
+    let events = signal_guard.events();
+    drop(signal_guard.events());
+    let before = SystemTime::now();
+    let watcher = thread::spawn(move || {
+        let mut received = Vec::new();
+        for event in events {
+            if event.signal() == Signal::User1 {
+                assert!(!graceful::is_shutting_down());
+                graceful::process::raise(Signal::Terminate).unwrap();
+            }
+            received.push(event);
+            if event.signal() == Signal::Terminate {
+                return received;
+            }
+        }
+        panic!("the guard was dropped first");
+    });
+
+    graceful::process::raise(Signal::User1).unwrap();
+    let mut stopped_on = None;
+    signal_guard.at_exit(|signal| stopped_on = Some(signal));
+    assert_eq!(stopped_on, Some(Signal::Terminate));
+
+    let received = watcher.join().unwrap();
+    let signals: Vec<Signal> = received.iter().map(|event| event.signal()).collect();
+    assert_eq!(signals, [Signal::User1, Signal::Terminate]);
+    for event in received {
+        assert!(before <= event.received_at());
+        if cfg!(target_os = "linux") {
+            assert_eq!(
+                event.origin().map(|origin| origin.pid()),
+                Some(process::id())
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {}
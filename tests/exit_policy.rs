@@ -0,0 +1,48 @@
+//! Once the handler of at_exit has returned, the exit policy has the
+//! process killed by the signal, or exit with a code after flushing its
+//! output. Each case runs in a child process, this same binary started
+//! again with `EXIT_POLICY` set.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::env;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    use graceful::exit::ExitPolicy;
+    use graceful::{process, Signal, SignalGuard};
+
+    let policy = match env::var("EXIT_POLICY").as_ref().map(String::as_str) {
+        Ok("re-raise") => Some(ExitPolicy::ReRaise),
+        Ok("exit code") => Some(ExitPolicy::ExitCode(75)),
+        _ => None,
+    };
+    if let Some(policy) = policy {
+        let signal_guard = SignalGuard::new();
+        assert_eq!(signal_guard.exit_policy(), ExitPolicy::Return);
+        signal_guard.set_exit_policy(policy);
+        process::raise(Signal::Terminate).unwrap();
+        signal_guard.at_exit(|_| print!("handled"));
+        unreachable!("returned from at_exit");
+    }
+
+    let exe = env::current_exe().unwrap();
+    let output = Command::new(&exe)
+        .env("EXIT_POLICY", "re-raise")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.signal(), Signal::Terminate.raw());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "handled");
+
+    let output = Command::new(&exe)
+        .env("EXIT_POLICY", "exit code")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(75));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "handled");
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,22 @@
+//! The C poll functions of a ShutdownHandle turn true with the shutdown.
+
+extern crate graceful;
+
+use std::ptr;
+
+use graceful::{embedded, ShutdownHandle, Signal};
+
+#[test]
+fn polls_turn_true_once_the_shutdown_started() {
+    let handle = ShutdownHandle::new();
+    let (poll, poll_with_context) = (
+        handle.as_extern_poll(),
+        handle.as_extern_poll_with_context(),
+    );
+    assert!(!poll());
+    assert!(!poll_with_context(ptr::null_mut()));
+
+    embedded::trigger(Signal::Terminate).unwrap();
+    assert!(poll());
+    assert!(poll_with_context(ptr::null_mut()));
+}
@@ -0,0 +1,62 @@
+//! Threads started before the guard block its signals once they apply it,
+//! and threads spawned with a MaskedThreadBuilder start with them blocked
+//! while the spawning thread keeps its own mask.
+
+extern crate graceful;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+fn blocks_terminate() -> bool {
+    use std::{mem, ptr};
+
+    unsafe {
+        let mut mask: libc::sigset_t = mem::zeroed();
+        assert_eq!(
+            libc::pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut mask),
+            0
+        );
+        libc::sigismember(&mask, libc::SIGTERM) == 1
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+
+    use graceful::thread::MaskedThreadBuilder;
+    use graceful::{process, Signal, SignalGuard};
+
+    let (sender, receiver) = mpsc::channel::<Arc<SignalGuard>>();
+    let early = thread::spawn(move || {
+        let signal_guard = receiver.recv().unwrap();
+        assert!(!blocks_terminate());
+        let masked = MaskedThreadBuilder::new(&signal_guard)
+            .name("masked".to_owned())
+            .spawn(|| {
+                (
+                    thread::current().name().map(str::to_owned),
+                    blocks_terminate(),
+                )
+            })
+            .unwrap();
+        assert!(!blocks_terminate());
+        signal_guard.apply_to_current_thread().unwrap();
+        assert!(blocks_terminate());
+        masked
+    });
+
+    let signal_guard = Arc::new(SignalGuard::new());
+    assert!(blocks_terminate());
+    sender.send(signal_guard.clone()).unwrap();
+    let masked = early.join().unwrap();
+    assert_eq!(masked.join().unwrap(), (Some("masked".to_owned()), true));
+
+    process::raise(Signal::Terminate).unwrap();
+    signal_guard.at_exit(|_| {});
+}
+
+#[cfg(not(unix))]
+fn main() {}
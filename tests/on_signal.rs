@@ -0,0 +1,42 @@
+//! Signals handled with on_signal keep the process running until a handler
+//! breaks out of the wait.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use graceful::{process, ErrorKind, Flow, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::builder()
+        .signal(Signal::Hangup)
+        .signal(Signal::User1)
+        .build()
+        .unwrap();
+    let err = signal_guard
+        .on_signal(Signal::User2, |_| Flow::Continue)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Init);
+
+    let reloads = Arc::new(AtomicUsize::new(0));
+    let reloaded = reloads.clone();
+    signal_guard
+        .on_signal(Signal::Hangup, move |_| {
+            reloaded.fetch_add(1, Ordering::SeqCst);
+            process::raise(Signal::User1).unwrap();
+            Flow::Continue
+        })
+        .unwrap();
+    signal_guard
+        .on_signal(Signal::User1, |_| Flow::Break)
+        .unwrap();
+
+    process::raise(Signal::Hangup).unwrap();
+    assert_eq!(signal_guard.try_wait().unwrap(), Signal::User1);
+    assert_eq!(reloads.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,48 @@
+//! The hook partitions of frameworks run after the hooks of the
+//! application, the most recently created first, and their phases are
+//! reported under their name.
+
+extern crate graceful;
+
+use std::sync::{Arc, Mutex};
+
+use graceful::{embedded, hooks, Signal};
+
+fn main() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let first = hooks::partition("first");
+    assert!(std::ptr::eq(first, hooks::partition("first")));
+    let second = hooks::partition("second");
+
+    let closed = log.clone();
+    first
+        .phase(hooks::CLOSE)
+        .hook("pool", move |_| closed.lock().unwrap().push("first pool"));
+    let drained = log.clone();
+    second
+        .phase(hooks::DRAIN)
+        .hook("jobs", move |_| drained.lock().unwrap().push("second jobs"));
+    let app = log.clone();
+    hooks::phase(hooks::CLOSE).hook("app", move |_| app.lock().unwrap().push("app"));
+
+    let report = embedded::trigger(Signal::Terminate).unwrap();
+    assert_eq!(*log.lock().unwrap(), ["app", "second jobs", "first pool"]);
+    let phases: Vec<(&str, Vec<&str>)> = report
+        .phases()
+        .filter(|phase| phase.hooks().next().is_some())
+        .map(|phase| {
+            (
+                phase.name(),
+                phase.hooks().map(|hook| hook.name()).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        phases,
+        [
+            ("close", vec!["app"]),
+            ("second:drain", vec!["jobs"]),
+            ("first:close", vec!["pool"]),
+        ]
+    );
+}
@@ -0,0 +1,77 @@
+//! Workers are replaced as their policy retires them, the old one only
+//! retired once the replacement is ready, and all are retired at shutdown.
+
+#![cfg(unix)]
+
+extern crate graceful;
+extern crate libc;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::FromRawFd;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use graceful::process::{Prefork, RecyclePolicy};
+use graceful::{embedded, Signal};
+
+fn pipe() -> (File, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+/// Write `event` as a line in a single write, so the lines of the workers
+/// do not interleave.
+fn write_event(mut log: &File, event: String) {
+    log.write_all(format!("{}\n", event).as_bytes()).unwrap();
+}
+
+#[test]
+fn replaces_each_worker_before_retiring_it() {
+    let (events, log) = pipe();
+    let manager = thread::spawn(move || {
+        let policy = RecyclePolicy::new().max_requests(3);
+        Prefork::new(1)
+            .policy(policy)
+            .drain_timeout(Duration::from_secs(5))
+            .run(|worker| {
+                let pid = process::id();
+                write_event(&log, format!("start {}", pid));
+                thread::sleep(Duration::from_millis(20));
+                write_event(&log, format!("ready {}", pid));
+                worker.ready();
+                while !worker.record_request() {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                write_event(&log, format!("retired {}", pid));
+            })
+    });
+
+    let mut events = BufReader::new(events).lines().map(Result::unwrap);
+    let mut seen = Vec::new();
+    while seen.iter().filter(|event: &&String| event.starts_with("ready")).count() < 3 {
+        seen.push(events.next().unwrap());
+    }
+    embedded::trigger(Signal::Terminate);
+    manager.join().unwrap().unwrap();
+    // Every worker wrote its last line, and the manager's end is closed.
+    seen.extend(events);
+
+    let position = |event: String| seen.iter().position(|seen| *seen == event);
+    let workers: Vec<&str> = seen
+        .iter()
+        .filter_map(|event| event.strip_prefix("start "))
+        .collect();
+    assert!(workers.len() >= 3, "{:?}", seen);
+    // Those ready before the shutdown took over from the one before.
+    for (pid, replacement) in workers[..2].iter().zip(&workers[1..3]) {
+        let retired = position(format!("retired {}", pid)).expect("retired");
+        let ready = position(format!("ready {}", replacement)).expect("ready");
+        assert!(ready < retired, "{:?}", seen);
+    }
+    for pid in &workers {
+        assert!(position(format!("retired {}", pid)).is_some(), "{:?}", seen);
+    }
+}
@@ -0,0 +1,39 @@
+//! Children that ignore the termination signal are killed once their grace
+//! runs out.
+
+extern crate graceful;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+fn main() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use graceful::{ProcessGroupGuard, Signal};
+
+    // A group of its own, so the test runner is not signalled too.
+    let children = ProcessGroupGuard::new().unwrap();
+    let mut sleep = Command::new("sleep");
+    sleep.arg("30");
+    let mut polite = children.spawn(sleep).unwrap();
+    let mut stubborn = Command::new("sh");
+    stubborn.arg("-c").arg("trap '' TERM; exec sleep 30");
+    let mut stubborn = children.spawn(stubborn).unwrap();
+    // Give the shell the time to ignore the signal.
+    thread::sleep(Duration::from_millis(200));
+
+    assert_eq!(
+        children
+            .terminate_children(Duration::from_millis(200))
+            .unwrap(),
+        1
+    );
+    assert_eq!(polite.wait().unwrap().signal(), Signal::Terminate.raw());
+    assert_eq!(stubborn.wait().unwrap().signal(), Some(libc::SIGKILL));
+}
+
+#[cfg(not(unix))]
+fn main() {}
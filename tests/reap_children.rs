@@ -0,0 +1,36 @@
+//! The guard reaps exited children on SIGCHLD instead of shutting down.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::process::Command;
+    use std::time::Duration;
+
+    use graceful::{process, ChildReaper, Signal, SignalGuard};
+
+    let (reaper, exits) = ChildReaper::channel();
+    let signal_guard = SignalGuard::builder()
+        .reap_children(reaper)
+        .build()
+        .unwrap();
+    // Reaped by the guard, which leaves its `Child` nothing to wait for.
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg("exit 3")
+        .spawn()
+        .unwrap()
+        .id();
+
+    assert_eq!(signal_guard.wait_timeout(Duration::from_millis(500)), None);
+    let (pid, status) = exits.try_recv().unwrap();
+    assert_eq!(pid, child);
+    assert_eq!(status.code(), Some(3));
+    assert!(!graceful::is_shutting_down());
+
+    process::raise(Signal::Terminate).unwrap();
+    assert_eq!(signal_guard.wait(), Signal::Terminate);
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,45 @@
+//! Hooks registered on the guard run by priority before the handler, past
+//! one that fails.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::{Arc, Mutex};
+
+    use graceful::{process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let pool = order.clone();
+    signal_guard.register_hook(0, "database pool", move |signal| {
+        pool.lock().unwrap().push(format!("pool on {}", signal))
+    });
+    signal_guard.register_hook(5, "metrics", |_| Err("collector gone"));
+    let listener = order.clone();
+    signal_guard.register_hook(10, "http listener", move |_| {
+        listener.lock().unwrap().push("listener".to_owned())
+    });
+
+    process::raise(Signal::Interrupt).unwrap();
+    let handled = order.clone();
+    let outcome =
+        signal_guard.wait_and_shutdown(move |_| handled.lock().unwrap().push("handler".to_owned()));
+    assert_eq!(
+        *order.lock().unwrap(),
+        [
+            "listener".to_owned(),
+            format!("pool on {}", Signal::Interrupt),
+            "handler".to_owned(),
+        ]
+    );
+    let failures: Vec<&str> = outcome
+        .report()
+        .failures()
+        .map(|(_, hook)| hook.name())
+        .collect();
+    assert_eq!(failures, ["metrics"]);
+}
+
+#[cfg(not(unix))]
+fn main() {}
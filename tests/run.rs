@@ -0,0 +1,42 @@
+//! The scoped entry point tells apart work that returns, work that panics
+//! and work cut short by a signal.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::time::Duration;
+
+    use graceful::{process, ExitReason, Signal};
+
+    match graceful::run(|_| {}) {
+        ExitReason::Completed => {}
+        other => panic!("expected the work to complete, got {:?}", other),
+    }
+    assert!(!graceful::is_shutting_down());
+
+    let reason = graceful::run(|_| panic!("lost the database"));
+    match reason {
+        ExitReason::Panicked(ref message) => assert_eq!(message, "lost the database"),
+        ref other => panic!("expected the work to panic, got {:?}", other),
+    }
+    assert_eq!(reason.exit_code(), 101);
+    assert!(!graceful::is_shutting_down());
+
+    let reason = graceful::run(|shutdown| {
+        process::raise(Signal::Terminate).unwrap();
+        assert!(shutdown.wait_timeout(Duration::from_secs(10)));
+    });
+    match reason {
+        ExitReason::Signaled(ref report) => {
+            assert_eq!(report.signal(), Signal::Terminate);
+            assert!(report.is_clean());
+        }
+        ref other => panic!("expected a shutdown, got {:?}", other),
+    }
+    assert_eq!(reason.exit_code(), 0);
+    assert!(graceful::is_shutting_down());
+}
+
+#[cfg(not(unix))]
+fn main() {}
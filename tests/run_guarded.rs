@@ -0,0 +1,54 @@
+//! A signal arriving during a guarded startup unwinds the steps done so
+//! far, while handled signals leave it running.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::{Arc, Mutex};
+
+    use graceful::startup::{self, Started, Startup};
+    use graceful::{process, Flow, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::builder()
+        .signal(Signal::Hangup)
+        .build()
+        .unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let reloaded = log.clone();
+    signal_guard
+        .on_signal(Signal::Hangup, move |_| {
+            reloaded.lock().unwrap().push("reload");
+            Flow::Continue
+        })
+        .unwrap();
+
+    let mut steps = Startup::new();
+    let (opened, closed) = (log.clone(), log.clone());
+    steps.phase(startup::INIT).step_with_unwind(
+        "pool",
+        move || {
+            opened.lock().unwrap().push("pool");
+            process::raise(Signal::Hangup).unwrap();
+        },
+        move || closed.lock().unwrap().push("pool unwound"),
+    );
+    steps
+        .phase(startup::BIND)
+        .step("listener", || process::raise(Signal::Terminate).unwrap());
+    let warmed = log.clone();
+    steps
+        .phase(startup::WARMUP)
+        .step("cache", move || warmed.lock().unwrap().push("cache"));
+
+    assert_eq!(
+        steps.run_guarded(&signal_guard).unwrap(),
+        Started::Interrupted(Signal::Terminate)
+    );
+    assert_eq!(*log.lock().unwrap(), ["pool", "reload", "pool unwound"]);
+    assert!(graceful::is_shutting_down());
+    assert!(!startup::is_ready());
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,41 @@
+//! A hook sending the termination signal to the process group while the
+//! shutdown runs does not count as a second signal here.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use graceful::{hooks, process, ProcessGroupGuard, ShutdownPhase, Signal, SignalGuard};
+
+    // A group of its own, so the test runner is not signalled too.
+    let _group = ProcessGroupGuard::new().unwrap();
+    let signal_guard = Arc::new(
+        SignalGuard::builder()
+            .escalate_on_second_signal(true)
+            .build()
+            .unwrap(),
+    );
+    let sender = signal_guard.clone();
+    hooks::phase(hooks::CLOSE).hook("signal the group", move |_| {
+        sender.signal_process_group(Signal::Terminate).unwrap();
+        // Give the listener the time to take the signal, if it does.
+        thread::sleep(Duration::from_millis(200));
+    });
+
+    process::raise(Signal::Interrupt).unwrap();
+    let outcome = signal_guard.wait_and_shutdown(|signal| signal);
+    thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(*outcome.value(), Signal::Interrupt);
+    let causes: Vec<Signal> = outcome.report().causes().map(|cause| cause.signal()).collect();
+    assert_eq!(causes, [Signal::Interrupt]);
+    assert!(!outcome.escalated());
+    assert_eq!(signal_guard.shutdown_phase(), ShutdownPhase::Draining);
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,62 @@
+//! A ShutdownHandle resolves to the signal that started the shutdown and
+//! iterates over the signals received after it.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::process::{self, Command};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    use graceful::{Signal, SignalGuard, ShutdownHandle};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    let signal_guard = SignalGuard::new();
+    assert_eq!(ShutdownHandle::new().signal(), None);
+    let awaiting = thread::spawn(|| block_on(ShutdownHandle::new()));
+    let iterating = thread::spawn(|| ShutdownHandle::new().into_iter().take(2).collect::<Vec<_>>());
+
+    graceful::process::raise(Signal::Terminate).unwrap();
+    assert_eq!(signal_guard.wait(), Signal::Terminate);
+    assert_eq!(awaiting.join().unwrap(), Signal::Terminate);
+    assert_eq!(ShutdownHandle::new().signal(), Some(Signal::Terminate));
+
+    // From another process, as signals the process sends itself are not
+    // counted once the shutdown has started.
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(process::id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        iterating.join().unwrap(),
+        [Signal::Terminate, Signal::Interrupt]
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
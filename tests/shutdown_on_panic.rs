@@ -0,0 +1,31 @@
+//! A thread panicking while the guard waits starts the shutdown, and only
+//! the first panic does.
+
+extern crate graceful;
+
+use std::thread;
+use std::time::Duration;
+
+use graceful::{Signal, SignalGuard};
+
+#[test]
+fn first_panic_shuts_down() {
+    let signal_guard = SignalGuard::new();
+    signal_guard.shutdown_on_panic();
+    let worker = thread::spawn(|| -> () {
+        thread::sleep(Duration::from_millis(50));
+        panic!("worker failed");
+    });
+    let outcome = signal_guard.wait_and_shutdown(|signal| signal);
+    assert_eq!(*outcome.value(), Signal::Panic);
+    assert!(worker.join().is_err());
+
+    assert!(thread::spawn(|| -> () { panic!("again") }).join().is_err());
+    let causes: Vec<Signal> = outcome
+        .report()
+        .causes()
+        .map(|cause| cause.signal())
+        .collect();
+    assert_eq!(causes, [Signal::Panic]);
+    assert!(!outcome.escalated());
+}
@@ -0,0 +1,32 @@
+//! The signal sent to the process group reaches the helpers but not the
+//! process sending it.
+
+extern crate graceful;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+fn main() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use graceful::{process, Signal, SignalGuard};
+
+    // A group of its own, so the test runner is not signalled too.
+    assert_eq!(unsafe { libc::setpgid(0, 0) }, 0);
+    let signal_guard = SignalGuard::new();
+    let mut command = Command::new("sleep");
+    command.arg("30");
+    let mut helper = process::unblock_signals(&mut command).spawn().unwrap();
+
+    signal_guard.signal_process_group(Signal::Terminate).unwrap();
+
+    let status = helper.wait().unwrap();
+    assert_eq!(status.signal(), Signal::Terminate.raw());
+    assert_eq!(signal_guard.wait_timeout(Duration::from_millis(100)), None);
+    assert!(!graceful::is_shutting_down());
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,56 @@
+//! Allocating inside a marked signal handler aborts the process. The
+//! allocation runs in a child process, this same binary started again
+//! with `ALLOCATE_IN_HANDLER` set.
+
+extern crate graceful;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+use std::alloc::System;
+
+#[cfg(unix)]
+use graceful::signal_safety::AuditAllocator;
+
+#[cfg(unix)]
+#[global_allocator]
+static ALLOCATOR: AuditAllocator = AuditAllocator(System);
+
+#[cfg(unix)]
+fn main() {
+    use std::env;
+    use std::hint;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    use graceful::signal_safety::{self, in_signal_handler};
+
+    if env::var_os("ALLOCATE_IN_HANDLER").is_some() {
+        let _scope = signal_safety::enter();
+        hint::black_box(vec![0u8; 16]);
+        return;
+    }
+
+    assert!(!in_signal_handler());
+    {
+        let _outer = signal_safety::enter();
+        {
+            let _inner = signal_safety::enter();
+        }
+        assert!(in_signal_handler());
+    }
+    assert!(!in_signal_handler());
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("ALLOCATE_IN_HANDLER", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.signal(), Some(libc::SIGABRT));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "graceful: not async-signal-safe in a signal handler: allocating memory\n"
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,34 @@
+//! The drain phase waits for tasks spawned with spawn_tracked, up to the
+//! drain timeout.
+
+extern crate graceful;
+extern crate tokio;
+
+use std::future;
+use std::time::Duration;
+
+use graceful::{embedded, ShutdownHandle, Signal, SignalGuard};
+use tokio::runtime::Builder;
+
+#[test]
+fn drains_the_tracked_tasks() {
+    let signal_guard = SignalGuard::new();
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let _runtime = runtime.enter();
+    let handle = ShutdownHandle::new();
+    handle.set_drain_timeout(Duration::from_millis(200));
+    let flushed = handle.spawn_tracked(signal_guard.cancellation_token().cancelled_owned());
+    handle.spawn_tracked(future::pending::<()>());
+    let shutdown = runtime.spawn_blocking(|| embedded::trigger(Signal::Terminate));
+    let report = runtime.block_on(shutdown).unwrap().unwrap();
+
+    assert!(flushed.is_finished());
+    let failures: Vec<String> = report
+        .failures()
+        .map(|(phase, hook)| format!("{}/{}: {}", phase, hook.name(), hook.failure().unwrap()))
+        .collect();
+    assert_eq!(
+        failures,
+        ["drain/graceful: tokio tasks: failed: 1 task(s) still running"]
+    );
+}
@@ -0,0 +1,32 @@
+extern crate graceful;
+
+use std::sync::{Arc, Mutex};
+
+use graceful::startup::{self, Started, Startup};
+use graceful::{embedded, Signal};
+
+#[test]
+fn reports_a_shutdown_started_during_the_startup() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut steps = Startup::new();
+    let (opened, closed) = (log.clone(), log.clone());
+    steps.phase(startup::INIT).step_with_unwind(
+        "pool",
+        move || opened.lock().unwrap().push("pool"),
+        move || closed.lock().unwrap().push("pool unwound"),
+    );
+    steps.phase(startup::BIND).step("stop", || {
+        embedded::trigger(Signal::Terminate);
+    });
+    let warmed = log.clone();
+    steps
+        .phase(startup::WARMUP)
+        .step("cache", move || warmed.lock().unwrap().push("cache"));
+
+    assert_eq!(
+        steps.run().unwrap(),
+        Started::ShuttingDown(Signal::Terminate)
+    );
+    assert_eq!(*log.lock().unwrap(), ["pool", "pool unwound"]);
+    assert!(!startup::is_ready());
+}
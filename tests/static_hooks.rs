@@ -0,0 +1,61 @@
+extern crate graceful;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use graceful::hooks::{self, Coordinator};
+use graceful::{snapshot, Signal};
+
+static DRAINED: AtomicUsize = AtomicUsize::new(0);
+static CLOSED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// Rehearsals run every static hook, so tests counting calls take turns.
+static REHEARSING: Mutex<()> = Mutex::new(());
+
+#[graceful::hook]
+fn release() {
+    CLOSED.lock().unwrap().push("release".to_string());
+}
+
+#[graceful::hook(priority = 10)]
+fn flush(signal: Signal) {
+    CLOSED.lock().unwrap().push(format!("flush on {}", signal));
+}
+
+#[graceful::hook(phase = "drain")]
+fn drain() {
+    DRAINED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn static_hooks_do_not_run_in_a_snapshot() {
+    snapshot::register("state", || {});
+    let report = snapshot::run(Signal::User1);
+    let hooks: Vec<&str> = report.hooks().map(|hook| hook.name()).collect();
+    assert_eq!(hooks, ["state"]);
+}
+
+#[test]
+fn static_hooks_only_run_in_the_global_coordinator() {
+    let _turn = REHEARSING.lock().unwrap();
+    let before = DRAINED.load(Ordering::SeqCst);
+    let report = Coordinator::new().run(Signal::Terminate);
+    assert_eq!(report.phases().flat_map(|phase| phase.hooks()).count(), 0);
+    assert_eq!(DRAINED.load(Ordering::SeqCst), before);
+
+    let report = hooks::coordinator().rehearse(Signal::Terminate);
+    let drain = report.phases().find(|phase| phase.name() == hooks::DRAIN).unwrap();
+    let hooks: Vec<&str> = drain.hooks().map(|hook| hook.name()).collect();
+    assert_eq!(hooks, ["static_hooks::drain"]);
+    assert_eq!(DRAINED.load(Ordering::SeqCst), before + 1);
+}
+
+#[test]
+fn static_hooks_run_by_priority_with_the_signal() {
+    let _turn = REHEARSING.lock().unwrap();
+    CLOSED.lock().unwrap().clear();
+    let report = hooks::coordinator().rehearse(Signal::Interrupt);
+    let close = report.phases().find(|phase| phase.name() == hooks::DEFAULT_PHASE).unwrap();
+    let hooks: Vec<&str> = close.hooks().map(|hook| hook.name()).collect();
+    assert_eq!(hooks, ["static_hooks::flush", "static_hooks::release"]);
+    assert_eq!(*CLOSED.lock().unwrap(), ["flush on SIGINT", "release"]);
+}
@@ -0,0 +1,59 @@
+//! The guard tells systemd it is ready, pings the watchdog while waiting
+//! and tells it it is stopping once a signal arrives, over the socket in
+//! `NOTIFY_SOCKET`.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixDatagram;
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+
+    use graceful::{systemd, Signal, SignalGuard};
+
+    let path = env::temp_dir().join(format!("graceful-systemd-{}.sock", process::id()));
+    let _ = fs::remove_file(&path);
+    let manager = UnixDatagram::bind(&path).unwrap();
+    manager
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    env::set_var("NOTIFY_SOCKET", &path);
+    env::set_var("WATCHDOG_USEC", "100000");
+    env::set_var("WATCHDOG_PID", process::id().to_string());
+    assert_eq!(
+        systemd::watchdog_interval(),
+        Some(Duration::from_millis(50))
+    );
+
+    let signal_guard = SignalGuard::new();
+    signal_guard.notify_ready().unwrap();
+    let states = thread::spawn(move || {
+        let mut states = Vec::new();
+        let mut buf = [0; 64];
+        while states.last().map(String::as_str) != Some("STOPPING=1") {
+            let len = manager.recv(&mut buf).unwrap();
+            let state = String::from_utf8_lossy(&buf[..len]).into_owned();
+            if state == "WATCHDOG=1" && !states.contains(&state) {
+                graceful::process::raise(Signal::Terminate).unwrap();
+            }
+            states.push(state);
+        }
+        states
+    });
+    signal_guard.at_exit(|_| {});
+    let states = states.join().unwrap();
+    assert_eq!(states[0], "READY=1");
+    assert_eq!(states[1], "WATCHDOG=1");
+    assert_eq!(states.last().unwrap(), "STOPPING=1");
+    fs::remove_file(&path).unwrap();
+
+    env::set_var("WATCHDOG_PID", (process::id() + 1).to_string());
+    assert_eq!(systemd::watchdog_interval(), None);
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,45 @@
+//! Children terminated at shutdown get no more than what is left of the
+//! DRAIN phase to exit, however long their grace, before they are killed.
+
+extern crate graceful;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+fn main() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use graceful::{hooks, process, ProcessGroupGuard, Signal, SignalGuard};
+
+    // A group of its own, so the test runner is not signalled too.
+    let children = Arc::new(ProcessGroupGuard::new().unwrap());
+    let signal_guard = SignalGuard::new();
+    hooks::coordinator().set_grace_period(Duration::from_millis(500));
+    children
+        .clone()
+        .terminate_at_shutdown(Duration::from_secs(30));
+    let mut stubborn = Command::new("sh");
+    stubborn.arg("-c").arg("trap '' TERM; exec sleep 30");
+    let mut stubborn = children.spawn(stubborn).unwrap();
+    // Give the shell the time to ignore the signal.
+    thread::sleep(Duration::from_millis(200));
+
+    process::raise(Signal::Terminate).unwrap();
+    let outcome = signal_guard.wait_and_shutdown(|_| {});
+    assert_eq!(stubborn.wait().unwrap().signal(), Some(libc::SIGKILL));
+    assert!(outcome.cleanup_duration() < Duration::from_secs(5));
+    let hook = outcome
+        .report()
+        .phases()
+        .find(|phase| phase.name() == hooks::DRAIN)
+        .and_then(|phase| phase.hooks().next())
+        .unwrap();
+    assert_eq!(hook.name(), "graceful: child processes");
+}
+
+#[cfg(not(unix))]
+fn main() {}
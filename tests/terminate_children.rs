@@ -0,0 +1,45 @@
+//! The children are terminated in the DRAIN phase of a shutdown started by
+//! a real `SIGTERM`, without the signal they are sent counting here.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use graceful::{process, ProcessGroupGuard, Signal, SignalGuard};
+
+    // A group of its own, so the test runner is not signalled too.
+    let children = Arc::new(ProcessGroupGuard::new().unwrap());
+    let signal_guard = SignalGuard::builder()
+        .escalate_on_second_signal(true)
+        .build()
+        .unwrap();
+    children.clone().terminate_at_shutdown(Duration::from_secs(5));
+    let mut command = Command::new("sleep");
+    command.arg("30");
+    let mut child = children.spawn(command).unwrap();
+
+    process::raise(Signal::Terminate).unwrap();
+    let outcome = signal_guard.wait_and_shutdown(|signal| signal);
+
+    assert_eq!(*outcome.value(), Signal::Terminate);
+    let status = child.wait().unwrap();
+    assert_eq!(status.signal(), Signal::Terminate.raw());
+    let causes: Vec<Signal> = outcome.report().causes().map(|cause| cause.signal()).collect();
+    assert_eq!(causes, [Signal::Terminate]);
+    assert!(!outcome.escalated());
+    let hook = outcome
+        .report()
+        .phases()
+        .flat_map(|phase| phase.hooks())
+        .find(|hook| hook.name() == "graceful: child processes")
+        .unwrap();
+    assert!(hook.is_ok(), "{:?}", hook);
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,23 @@
+//! The tokio token is cancelled and the watch set once the shutdown starts.
+
+extern crate graceful;
+
+use graceful::{embedded, Signal, SignalGuard};
+
+#[test]
+fn cancels_the_token_and_publishes_the_signal() {
+    let signal_guard = SignalGuard::new();
+    let token = signal_guard.cancellation_token();
+    let watch = signal_guard.signal_watch();
+    assert!(!token.is_cancelled());
+    assert_eq!(*watch.borrow(), None);
+
+    signal_guard.cancellation_token().cancel();
+    assert!(!token.is_cancelled());
+
+    embedded::trigger(Signal::Terminate).unwrap();
+    assert!(token.is_cancelled());
+    assert_eq!(*watch.borrow(), Some(Signal::Terminate));
+    embedded::trigger(Signal::Interrupt);
+    assert_eq!(*signal_guard.signal_watch().borrow(), Some(Signal::Terminate));
+}
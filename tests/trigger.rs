@@ -0,0 +1,106 @@
+//! A whole shutdown driven by `SignalGuard::trigger`, without sending a
+//! signal, as the `testing` feature allows.
+
+extern crate graceful;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use graceful::channel::{self, Selected};
+use graceful::{hooks, Flow, ShutdownHandle, ShutdownPhase, Signal, SignalGuard};
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[test]
+fn triggered_shutdown() {
+    let signal_guard = Arc::new(SignalGuard::new());
+    let events = signal_guard.events();
+    let handled = Arc::new(AtomicUsize::new(0));
+    let counter = handled.clone();
+    signal_guard
+        .on_signal(Signal::Quit, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Flow::Continue
+        })
+        .unwrap();
+
+    let (_sender, receiver) = channel::channel::<()>();
+    let selecting = thread::spawn(move || ShutdownHandle::new().select(&receiver));
+    let sleeping = thread::spawn(|| block_on(ShutdownHandle::new().sleep(Duration::MAX)));
+    let ticking = thread::spawn(|| {
+        let mut ticks = ShutdownHandle::new().interval(Duration::from_millis(5));
+        let mut count = 0;
+        while block_on(ticks.tick()).is_ok() {
+            count += 1;
+        }
+        count
+    });
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let drained = order.clone();
+    let second = signal_guard.clone();
+    hooks::phase(hooks::DRAIN).hook("drain", move |_| {
+        drained.lock().unwrap().push(hooks::DRAIN);
+        second.trigger(Signal::Interrupt);
+        assert!(second
+            .token()
+            .wait_for_timeout(ShutdownPhase::Aborting, Duration::from_secs(5)));
+    });
+    let closed = order.clone();
+    hooks::phase(hooks::CLOSE).hook("close", move |_| closed.lock().unwrap().push(hooks::CLOSE));
+
+    let trigger = signal_guard.clone();
+    thread::spawn(move || {
+        trigger.trigger(Signal::Quit);
+        thread::sleep(Duration::from_millis(20));
+        trigger.trigger(Signal::Terminate);
+    });
+    let outcome = signal_guard.wait_and_shutdown(|signal| signal);
+
+    assert_eq!(*outcome.value(), Signal::Terminate);
+    assert_eq!(handled.load(Ordering::SeqCst), 1);
+    assert!(outcome.report().is_clean());
+    assert_eq!(*order.lock().unwrap(), [hooks::DRAIN, hooks::CLOSE]);
+    let causes: Vec<Signal> = outcome
+        .report()
+        .causes()
+        .map(|cause| cause.signal())
+        .collect();
+    assert_eq!(causes, [Signal::Terminate, Signal::Interrupt]);
+    assert!(outcome.escalated());
+
+    assert_eq!(
+        selecting.join().unwrap(),
+        Selected::Shutdown(Signal::Terminate)
+    );
+    assert_eq!(sleeping.join().unwrap(), Err(Signal::Terminate));
+    assert!(ticking.join().unwrap() >= 1);
+    let received: Vec<Signal> = events.try_iter().map(|event| event.signal()).collect();
+    assert_eq!(
+        received,
+        [Signal::Quit, Signal::Terminate, Signal::Interrupt]
+    );
+}
@@ -0,0 +1,24 @@
+//! wait runs the hooks and returns the signal that started the shutdown.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use graceful::{hooks, process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::new();
+    let closed = Arc::new(AtomicBool::new(false));
+    let close = closed.clone();
+    hooks::phase(hooks::CLOSE).hook("close", move |_| close.store(true, Ordering::SeqCst));
+
+    process::raise(Signal::Quit).unwrap();
+    assert_eq!(signal_guard.try_wait().unwrap(), Signal::Quit);
+    assert!(graceful::is_shutting_down());
+    assert!(closed.load(Ordering::SeqCst));
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,41 @@
+//! Shutting down with wait_and_shutdown hands back what the handler
+//! returned along with when the signal came and how long the cleanup took.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    use graceful::{hooks, process, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::new();
+    hooks::phase(hooks::FLUSH).hook("metrics", |_| thread::sleep(Duration::from_millis(20)));
+
+    let before = SystemTime::now();
+    process::raise(Signal::Terminate).unwrap();
+    let outcome = signal_guard.wait_and_shutdown(|signal| {
+        thread::sleep(Duration::from_millis(20));
+        format!("stopped on {}", signal)
+    });
+    assert_eq!(outcome.signal(), Signal::Terminate);
+    assert!(before <= outcome.started_at() && outcome.started_at() <= SystemTime::now());
+    assert!(outcome.cleanup_duration() >= Duration::from_millis(40));
+    assert!(!outcome.escalated());
+    assert!(outcome.report().is_clean());
+    let hooks: Vec<&str> = outcome
+        .report()
+        .phases()
+        .flat_map(|phase| phase.hooks())
+        .map(|hook| hook.name())
+        .collect();
+    assert_eq!(hooks, ["metrics"]);
+    assert_eq!(
+        outcome.into_value(),
+        format!("stopped on {}", Signal::Terminate)
+    );
+}
+
+#[cfg(not(unix))]
+fn main() {}
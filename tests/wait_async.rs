@@ -0,0 +1,46 @@
+//! wait_async resolves to the signal once the hooks have run.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+
+    use graceful::{hooks, process, Signal, SignalGuard};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let signal_guard = SignalGuard::new();
+    let closed = Arc::new(AtomicBool::new(false));
+    let close = closed.clone();
+    hooks::phase(hooks::CLOSE).hook("close", move |_| close.store(true, Ordering::SeqCst));
+
+    let mut shutdown = signal_guard.wait_async();
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    assert!(Pin::new(&mut shutdown).poll(&mut cx).is_pending());
+
+    process::raise(Signal::Quit).unwrap();
+    let signal = loop {
+        if let Poll::Ready(signal) = Pin::new(&mut shutdown).poll(&mut cx) {
+            break signal;
+        }
+        thread::park();
+    };
+    assert_eq!(signal, Signal::Quit);
+    assert!(closed.load(Ordering::SeqCst));
+}
+
+#[cfg(not(unix))]
+fn main() {}
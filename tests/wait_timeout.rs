@@ -0,0 +1,49 @@
+//! wait_timeout gives up without a terminal signal, and shuts down once one
+//! arrives.
+
+extern crate graceful;
+
+#[cfg(unix)]
+fn main() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use graceful::{hooks, process, Flow, Signal, SignalGuard};
+
+    let signal_guard = SignalGuard::builder()
+        .signal(Signal::Hangup)
+        .build()
+        .unwrap();
+    let reloads = Arc::new(AtomicUsize::new(0));
+    let reloaded = reloads.clone();
+    signal_guard
+        .on_signal(Signal::Hangup, move |_| {
+            reloaded.fetch_add(1, Ordering::SeqCst);
+            Flow::Continue
+        })
+        .unwrap();
+    let closed = Arc::new(AtomicBool::new(false));
+    let close = closed.clone();
+    hooks::phase(hooks::CLOSE).hook("close", move |_| close.store(true, Ordering::SeqCst));
+
+    assert_eq!(signal_guard.poll(), None);
+    let started = Instant::now();
+    assert_eq!(signal_guard.wait_timeout(Duration::from_millis(50)), None);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    process::raise(Signal::Hangup).unwrap();
+    assert_eq!(signal_guard.wait_timeout(Duration::from_millis(50)), None);
+    assert_eq!(reloads.load(Ordering::SeqCst), 1);
+    assert!(!graceful::is_shutting_down());
+
+    process::raise(Signal::Terminate).unwrap();
+    assert_eq!(
+        signal_guard.wait_timeout(Duration::from_secs(10)),
+        Some(Signal::Terminate)
+    );
+    assert!(closed.load(Ordering::SeqCst));
+}
+
+#[cfg(not(unix))]
+fn main() {}
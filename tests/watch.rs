@@ -0,0 +1,41 @@
+//! Replacing the executable while it runs starts the shutdown through
+//! `embedded::trigger`, which works on every platform.
+
+extern crate graceful;
+
+use std::env;
+use std::fs::{File, OpenOptions};
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use graceful::watch::ExecutableWatcher;
+use graceful::{embedded, Signal};
+
+/// The executable, opened to change its times while it runs.
+fn open_executable() -> File {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    // FILE_WRITE_ATTRIBUTES, as a running executable cannot be written.
+    #[cfg(windows)]
+    options.access_mode(0x100);
+    options.open(env::current_exe().unwrap()).unwrap()
+}
+
+fn main() {
+    let token = embedded::token();
+    let (reports, report) = mpsc::channel();
+    let _watcher = ExecutableWatcher::spawn(Duration::from_millis(10), move || {
+        reports.send(embedded::trigger(Signal::Terminate)).unwrap();
+    })
+    .unwrap();
+
+    open_executable()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+
+    assert!(token.wait_timeout(Duration::from_secs(5)));
+    let report = report.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    assert_eq!(report.signal(), Signal::Terminate);
+}